@@ -0,0 +1,183 @@
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+
+use crate::definition::{Struct, Enum, VariantKind, FieldAttrs, FieldMode};
+use crate::definition::{ChildMode, DecodeMode};
+
+
+/// How the value of the field is decoded, determines the trait bound
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Scalar,
+    Node,
+    Partial,
+}
+
+/// Types of fields along with the way they are decoded
+///
+/// Only fields whose bound can be derived from the attributes are listed,
+/// e.g. `str` fields need bounds that user can write themselves.
+fn struct_fields(s: &Struct) -> Vec<(&syn::Type, Role)> {
+    let mut fields = Vec::new();
+    let scalar = |decode: &DecodeMode| matches!(decode, DecodeMode::Normal);
+    for arg in &s.arguments {
+        if scalar(&arg.decode) {
+            fields.push((&arg.field.ty, Role::Scalar));
+        }
+    }
+    if let Some(var_args) = &s.var_args {
+        if scalar(&var_args.decode) {
+            fields.push((&var_args.field.ty, Role::Scalar));
+        }
+    }
+    for prop in &s.properties {
+        if prop.flatten {
+            fields.push((&prop.field.ty, Role::Partial));
+        } else if scalar(&prop.decode) {
+            fields.push((&prop.field.ty, Role::Scalar));
+        }
+    }
+    if let Some(var_props) = &s.var_props {
+        if scalar(&var_props.decode) {
+            fields.push((&var_props.field.ty, Role::Scalar));
+        }
+    }
+    for child in &s.children {
+        let role = match child.mode {
+            ChildMode::Bool => None,
+            ChildMode::Flatten => Some(Role::Partial),
+            ChildMode::Normal | ChildMode::Multi => {
+                unwrap_role(child.unwrap.as_deref())
+            }
+        };
+        if let Some(role) = role {
+            fields.push((&child.field.ty, role));
+        }
+    }
+    for var_children in s.var_children.iter().chain(&s.typed_children) {
+        if let Some(role) = unwrap_role(var_children.unwrap.as_deref()) {
+            fields.push((&var_children.field.ty, role));
+        }
+    }
+    fields
+}
+
+fn unwrap_role(unwrap: Option<&FieldAttrs>) -> Option<Role> {
+    let attrs = match unwrap {
+        Some(attrs) => attrs,
+        None => return Some(Role::Node),
+    };
+    match attrs.mode {
+        Some(FieldMode::Argument) | Some(FieldMode::Arguments)
+        | Some(FieldMode::Property { .. }) | Some(FieldMode::Properties)
+        => {
+            match attrs.decode {
+                None | Some(DecodeMode::Normal) => Some(Role::Scalar),
+                Some(_) => None,
+            }
+        }
+        Some(FieldMode::Child) | Some(FieldMode::Children { .. }) => {
+            unwrap_role(attrs.unwrap.as_deref())
+        }
+        _ => None,
+    }
+}
+
+fn enum_fields(e: &Enum) -> Vec<(&syn::Type, Role)> {
+    e.variants.iter()
+        .flat_map(|v| match &v.kind {
+            VariantKind::Tuple(s) | VariantKind::Named(s) => struct_fields(s),
+            VariantKind::Nested { ty, .. } => vec![(ty, Role::Node)],
+            VariantKind::Unit => Vec::new(),
+        })
+        .collect()
+}
+
+fn mentions(tokens: TokenStream, ident: &syn::Ident) -> bool {
+    tokens.into_iter().any(|tt| match tt {
+        TokenTree::Ident(i) => i == *ident,
+        TokenTree::Group(g) => mentions(g.stream(), ident),
+        _ => false,
+    })
+}
+
+/// Bounds for each type parameter used by the fields
+///
+/// Like in `serde`, the parameter itself is bounded rather than the field
+/// type, so `Vec<T>` and `Option<T>` fields require `T: DecodeScalar<S>`.
+fn bounds(generics: &syn::Generics, fields: &[(&syn::Type, Role)],
+          trait_for: impl Fn(Role) -> Option<TokenStream>)
+    -> Vec<syn::WherePredicate>
+{
+    let mut result = Vec::new();
+    for param in generics.type_params() {
+        let ident = &param.ident;
+        let mut roles = Vec::new();
+        for (ty, role) in fields {
+            if !roles.contains(role) &&
+                mentions(ty.to_token_stream(), ident)
+            {
+                roles.push(*role);
+            }
+        }
+        for role in roles {
+            if let Some(tr) = trait_for(role) {
+                result.push(syn::parse2(quote!(#ident: #tr)).unwrap());
+            }
+        }
+    }
+    result
+}
+
+fn decode_trait(span_ty: &TokenStream)
+    -> impl Fn(Role) -> Option<TokenStream> + '_
+{
+    move |role| Some(match role {
+        Role::Scalar => quote!(::knuffel::traits::DecodeScalar<#span_ty>),
+        Role::Node => quote!(::knuffel::traits::Decode<#span_ty>),
+        Role::Partial => quote!(::knuffel::traits::DecodePartial<#span_ty>),
+    })
+}
+
+fn encode_trait(role: Role) -> Option<TokenStream> {
+    match role {
+        Role::Scalar => Some(quote!(::knuffel::traits::EncodeScalar)),
+        Role::Node => Some(quote!(::knuffel::traits::Encode)),
+        Role::Partial => None,
+    }
+}
+
+pub fn decode_struct(s: &Struct, span_ty: &TokenStream)
+    -> Vec<syn::WherePredicate>
+{
+    bounds(&s.generics, &struct_fields(s), decode_trait(span_ty))
+}
+
+pub fn decode_enum(e: &Enum, span_ty: &TokenStream)
+    -> Vec<syn::WherePredicate>
+{
+    bounds(&e.generics, &enum_fields(e), decode_trait(span_ty))
+}
+
+pub fn encode_struct(s: &Struct) -> Vec<syn::WherePredicate> {
+    bounds(&s.generics, &struct_fields(s), encode_trait)
+}
+
+pub fn encode_enum(e: &Enum) -> Vec<syn::WherePredicate> {
+    bounds(&e.generics, &enum_fields(e), encode_trait)
+}
+
+/// Adds the span type parameter `S` to the generics
+///
+/// It goes after lifetimes but before other parameters, as older compilers
+/// require const parameters to be the last ones.
+pub fn add_span_param(generics: &mut syn::Generics) {
+    if generics.params.is_empty() {
+        generics.lt_token = Some(Default::default());
+        generics.gt_token = Some(Default::default());
+    }
+    let pos = generics.lifetimes().count();
+    generics.params.insert(pos, syn::parse2(quote!(S)).unwrap());
+    generics.make_where_clause().predicates.push(
+        syn::parse2(quote!(S: ::knuffel::traits::ErrorSpan)).unwrap());
+}
@@ -2,7 +2,7 @@ use std::mem;
 
 use proc_macro2::{TokenStream, Span};
 use proc_macro_error::emit_error;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::ext::IdentExt;
 use syn::parse::{Parse, ParseStream};
 use syn::punctuated::Punctuated;
@@ -10,6 +10,8 @@ use syn::spanned::Spanned;
 
 use crate::kw;
 
+const DURATION_UNITS: &[&str] = &["ns", "us", "ms", "s", "m", "h", "d"];
+
 pub enum Definition {
     UnitStruct(Struct),
     TupleStruct(Struct),
@@ -20,13 +22,14 @@ pub enum Definition {
 
 pub enum VariantKind {
     Unit,
-    Nested { option: bool },
+    Nested { option: bool, ty: syn::Type },
     Tuple(Struct),
     Named(Struct),
 }
 
 pub enum ArgKind {
     Value { option: bool },
+    Key,
 }
 
 #[derive(Debug, Clone)]
@@ -37,10 +40,16 @@ pub enum FieldMode {
     Properties,
     Children { name: Option<String> },
     Child,
+    PropertyOrChild,
     Flatten(Flatten),
     Span,
+    SpanBytes,
     NodeName,
+    NodeKey,
     TypeName,
+    TypeOf(syn::Ident),
+    DocComment,
+    FromContext,
 }
 
 pub enum FlattenItem {
@@ -58,7 +67,59 @@ pub struct Flatten {
 pub enum DecodeMode {
     Normal,
     Str,
-    Bytes,
+    Bytes { url_safe: bool },
+    FromU32,
+    SplitFlags,
+    Try(Vec<syn::Path>),
+    Units(syn::Path),
+    /// Optional bounds are in percents
+    Percent(Option<Box<Clamp>>),
+    /// Unit of the bare integers, one of `ns`, `us`, `ms`, `s`, `m`, `h`, `d`
+    Duration(String),
+    Path { expand_tilde: bool },
+    BoolWords,
+}
+
+/// Which of the children having the same name is kept by `dedup_by_name`
+#[derive(Debug, Clone, Copy)]
+pub enum Dedup {
+    First,
+    Last,
+}
+
+/// Literal kind required by `kind="..."`
+#[derive(Debug, Clone, Copy)]
+pub enum LiteralKind {
+    Int,
+    Decimal,
+    String,
+    Bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Clamp {
+    pub min: syn::Expr,
+    pub max: syn::Expr,
+}
+
+/// Casing of names derived from field or variant names
+#[derive(Debug, Clone, Copy)]
+pub enum RenameAll {
+    Kebab,
+    Snake,
+    Camel,
+    Pascal,
+    ScreamingSnake,
+    Lower,
+    Upper,
+}
+
+/// Group of fields of which at most one (or exactly one) may be present
+#[derive(Debug, Clone)]
+pub struct ExclusiveGroup {
+    pub fields: Vec<syn::Ident>,
+    pub exactly_one: bool,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -66,9 +127,27 @@ pub enum Attr {
     Skip,
     DecodeMode(DecodeMode),
     FieldMode(FieldMode),
-    Unwrap(FieldAttrs),
+    Unwrap(Box<FieldAttrs>),
     Default(Option<syn::Expr>),
+    OrderedBy(syn::Ident),
+    DedupByName(Dedup),
+    Merge(syn::Path),
+    Clamp(Box<Clamp>),
+    LiteralKind(LiteralKind),
+    NoneIf(String),
+    CaseInsensitive,
     SpanType(syn::Type),
+    Serde,
+    Tag(String),
+    ExposeName,
+    NodeNameConst(String),
+    TypeNameConst(String),
+    NonEmptyDocument,
+    StrictDocument,
+    FailFast,
+    RenameAll(RenameAll),
+    Exclusive(ExclusiveGroup),
+    ChildrenType(String),
 }
 
 #[derive(Debug, Clone)]
@@ -77,6 +156,16 @@ pub struct FieldAttrs {
     pub decode: Option<DecodeMode>,
     pub unwrap: Option<Box<FieldAttrs>>,
     pub default: Option<Option<syn::Expr>>,
+    pub ordered_by: Option<syn::Ident>,
+    pub dedup_by_name: Option<Dedup>,
+    pub merge: Option<syn::Path>,
+    pub clamp: Option<Clamp>,
+    pub literal_kind: Option<LiteralKind>,
+    pub type_name: Option<String>,
+    pub none_if: Option<String>,
+    pub case_insensitive: bool,
+    pub children_type: Option<String>,
+    pub skip: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -96,32 +185,57 @@ pub struct Field {
     pub span: Span,
     pub attr: AttrAccess,
     pub tmp_name: syn::Ident,
+    pub ty: syn::Type,
 }
 
 pub struct SpanField {
     pub field: Field,
+    /// Span is stored as a pair of byte offsets instead of the span type
+    pub bytes: bool,
 }
 
 pub struct NodeNameField {
     pub field: Field,
 }
 
+pub struct DocCommentField {
+    pub field: Field,
+    pub option: bool,
+}
+
 pub struct TypeNameField {
     pub field: Field,
     pub option: bool,
 }
 
+pub struct TypeOfField {
+    pub field: Field,
+    pub target: syn::Ident,
+    pub option: bool,
+}
+
 pub struct Arg {
     pub field: Field,
     pub kind: ArgKind,
     pub decode: DecodeMode,
     pub default: Option<Option<syn::Expr>>,
     pub option: bool,
+    pub clamp: Option<Clamp>,
+    pub literal_kind: Option<LiteralKind>,
+    /// Type annotation required by `type_name="..."`
+    pub type_name: Option<String>,
+    /// String value that is decoded as `None`
+    pub none_if: Option<String>,
 }
 
 pub struct VarArgs {
     pub field: Field,
     pub decode: DecodeMode,
+    /// Element type, when it can't be inferred from the collection
+    pub item: Option<syn::Type>,
+    /// Length of the fixed-size array `[T; N]`, i.e. exact number of
+    /// arguments
+    pub array_len: Option<syn::Expr>,
 }
 
 pub struct Prop {
@@ -131,6 +245,13 @@ pub struct Prop {
     pub decode: DecodeMode,
     pub flatten: bool,
     pub default: Option<Option<syn::Expr>>,
+    pub case_insensitive: bool,
+    pub clamp: Option<Clamp>,
+    pub literal_kind: Option<LiteralKind>,
+    /// Type annotation required by `type_name="..."`
+    pub type_name: Option<String>,
+    /// String value that is decoded as `None`
+    pub none_if: Option<String>,
 }
 
 pub struct VarProps {
@@ -152,15 +273,31 @@ pub struct Child {
     pub mode: ChildMode,
     pub unwrap: Option<Box<FieldAttrs>>,
     pub default: Option<Option<syn::Expr>>,
+    pub ordered_by: Option<syn::Ident>,
+    pub merge: Option<syn::Path>,
+    /// The value may also be specified as a property of the same name
+    pub or_property: bool,
 }
 
 pub struct VarChildren {
     pub field: Field,
     pub unwrap: Option<Box<FieldAttrs>>,
+    pub ordered_by: Option<syn::Ident>,
+    /// Only children having this type annotation are collected, `None`
+    /// collects everything that's not matched by other fields
+    pub type_filter: Option<String>,
+    /// Children are collected into a map keyed by node name
+    pub map: bool,
+    /// Only one of the children having the same name is decoded
+    pub dedup_by_name: Option<Dedup>,
 }
 
 pub enum ExtraKind {
     Auto,
+    /// Marked with `skip`, initialized using `Default` too
+    Skip,
+    /// Cloned from the value of the field type stored in `Context`
+    Context,
 }
 
 pub struct ExtraField {
@@ -172,6 +309,14 @@ pub struct ExtraField {
 #[derive(Clone)]
 pub struct TraitProps {
     pub span_type: Option<syn::Type>,
+    pub serde: bool,
+    pub expose_name: bool,
+    pub node_name: Option<String>,
+    pub non_empty_document: bool,
+    pub strict_document: bool,
+    pub fail_fast: bool,
+    pub rename_all: RenameAll,
+    pub exclusive: Vec<ExclusiveGroup>,
 }
 
 pub struct Struct {
@@ -181,6 +326,8 @@ pub struct Struct {
     pub spans: Vec<SpanField>,
     pub node_names: Vec<NodeNameField>,
     pub type_names: Vec<TypeNameField>,
+    pub type_ofs: Vec<TypeOfField>,
+    pub doc_comments: Vec<DocCommentField>,
     pub arguments: Vec<Arg>,
     pub var_args: Option<VarArgs>,
     pub properties: Vec<Prop>,
@@ -189,6 +336,7 @@ pub struct Struct {
     pub has_properties: bool,
     pub children: Vec<Child>,
     pub var_children: Option<VarChildren>,
+    pub typed_children: Vec<VarChildren>,
     pub extra_fields: Vec<ExtraField>,
 }
 
@@ -199,12 +347,15 @@ pub struct StructBuilder {
     pub spans: Vec<SpanField>,
     pub node_names: Vec<NodeNameField>,
     pub type_names: Vec<TypeNameField>,
+    pub type_ofs: Vec<TypeOfField>,
+    pub doc_comments: Vec<DocCommentField>,
     pub arguments: Vec<Arg>,
     pub var_args: Option<VarArgs>,
     pub properties: Vec<Prop>,
     pub var_props: Option<VarProps>,
     pub children: Vec<Child>,
     pub var_children: Option<VarChildren>,
+    pub typed_children: Vec<VarChildren>,
     pub extra_fields: Vec<ExtraField>,
 }
 
@@ -226,21 +377,62 @@ pub struct Enum {
     pub trait_props: TraitProps,
     pub generics: syn::Generics,
     pub variants: Vec<Variant>,
+    pub tag: Option<String>,
 }
 
 impl TraitProps {
     fn pick_from(attrs: &mut Vec<(Attr, Span)>) -> TraitProps {
         let mut props = TraitProps {
             span_type: None,
+            serde: false,
+            expose_name: false,
+            node_name: None,
+            non_empty_document: false,
+            strict_document: false,
+            fail_fast: false,
+            rename_all: RenameAll::Kebab,
+            exclusive: Vec::new(),
         };
+        let mut node_name_span = None;
         for attr in mem::replace(attrs, Vec::new()) {
             match attr.0 {
                 Attr::SpanType(ty) => {
                     props.span_type = Some(ty);
                 }
+                Attr::Serde => {
+                    props.serde = true;
+                }
+                Attr::ExposeName => {
+                    props.expose_name = true;
+                }
+                Attr::NodeNameConst(name) => {
+                    props.node_name = Some(name);
+                    node_name_span = Some(attr.1);
+                }
+                Attr::NonEmptyDocument => {
+                    props.non_empty_document = true;
+                }
+                Attr::StrictDocument => {
+                    props.strict_document = true;
+                }
+                Attr::FailFast => {
+                    props.fail_fast = true;
+                }
+                Attr::RenameAll(case) => {
+                    props.rename_all = case;
+                }
+                Attr::Exclusive(group) => {
+                    props.exclusive.push(group);
+                }
                 _ => attrs.push(attr),
             }
         }
+        if let Some(span) = node_name_span {
+            if !props.expose_name {
+                emit_error!(span,
+                    "`node_name = \"..\"` only makes sense with `expose_name`");
+            }
+        }
         return props;
     }
 }
@@ -266,6 +458,28 @@ fn is_option(ty: &syn::Type) -> bool {
     )
 }
 
+/// Returns `true` for `HashMap<K, V>` and `BTreeMap<K, V>`
+fn is_map(ty: &syn::Type) -> bool {
+    matches!(ty,
+        syn::Type::Path(syn::TypePath { qself: None, path })
+        if matches!(path.segments.last(),
+            Some(s) if s.ident == "HashMap" || s.ident == "BTreeMap")
+    )
+}
+
+/// Returns `T` for `BitFlags<T>`
+///
+/// Such fields are collected by `knuffel::decode::collect_flags`, which is
+/// only available with the `enumflags2` feature.
+fn bitflags_item(ty: &syn::Type) -> Option<&syn::Type> {
+    match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path })
+        if matches!(path.segments.last(), Some(s) if s.ident == "BitFlags")
+        => type_params(ty).first().copied(),
+        _ => None,
+    }
+}
+
 fn is_bool(ty: &syn::Type) -> bool {
     matches!(ty,
         syn::Type::Path(syn::TypePath { qself: None, path })
@@ -273,6 +487,34 @@ fn is_bool(ty: &syn::Type) -> bool {
     )
 }
 
+/// Returns type parameters of the last path segment, i.e. `[T]` for `Vec<T>`
+/// or `[K, V]` for `HashMap<K, V>`
+pub fn type_params(ty: &syn::Type) -> Vec<&syn::Type> {
+    match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => {
+            match path.segments.last().map(|s| &s.arguments) {
+                Some(syn::PathArguments::AngleBracketed(args)) => {
+                    args.args.iter().filter_map(|a| match a {
+                        syn::GenericArgument::Type(ty) => Some(ty),
+                        _ => None,
+                    }).collect()
+                }
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Returns element type of a collection field, also unwrapping `Option`
+pub fn item_type(ty: &syn::Type, option: bool) -> Option<&syn::Type> {
+    if option {
+        type_params(ty).first().and_then(|ty| item_type(ty, false))
+    } else {
+        type_params(ty).last().copied()
+    }
+}
+
 impl Variant {
     fn new(ident: syn::Ident, _attrs: VariantAttrs, kind: VariantKind)
         -> syn::Result<Self>
@@ -286,6 +528,164 @@ impl Variant {
     }
 }
 
+/// Value of the literal bound like `-1.5`, `NaN` for `f32::NAN`, `f64::NAN`
+///
+/// Returns `None` for the expressions that can't be evaluated at derive time.
+fn bound_value(expr: &syn::Expr) -> Option<f64> {
+    match expr {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(lit), .. }) => {
+            lit.base10_parse().ok()
+        }
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Float(lit), .. }) => {
+            lit.base10_parse().ok()
+        }
+        syn::Expr::Unary(syn::ExprUnary {
+            op: syn::UnOp::Neg(_), expr, ..
+        }) => bound_value(expr).map(|v| -v),
+        syn::Expr::Paren(syn::ExprParen { expr, .. }) => bound_value(expr),
+        syn::Expr::Group(syn::ExprGroup { expr, .. }) => bound_value(expr),
+        syn::Expr::Path(syn::ExprPath { path, .. })
+            if matches!(path.segments.last(), Some(s) if s.ident == "NAN")
+        => Some(f64::NAN),
+        _ => None,
+    }
+}
+
+impl Clamp {
+    /// Checks the bounds that can be evaluated at derive time
+    ///
+    /// Clamping panics if a bound is `NaN` or `min > max`.
+    fn new(attr: &str, range: &syn::ExprRange, min: syn::Expr, max: syn::Expr)
+        -> syn::Result<Clamp>
+    {
+        for bound in [&min, &max] {
+            if matches!(bound_value(bound), Some(v) if v.is_nan()) {
+                return Err(syn::Error::new(bound.span(),
+                    format!("`{}` bound must not be NaN", attr)));
+            }
+        }
+        if let (Some(lo), Some(hi)) = (bound_value(&min), bound_value(&max)) {
+            if lo > hi {
+                return Err(syn::Error::new(range.span(),
+                    format!("`{}` lower bound must not be greater \
+                             than the upper bound", attr)));
+            }
+        }
+        Ok(Clamp { min, max })
+    }
+    /// Clamps the value, which is `Option<T>` if the field is optional
+    pub fn apply(&self, value: &syn::Ident, option: bool) -> TokenStream {
+        let Clamp { min, max } = self;
+        if option {
+            quote!(#value.map(|v| v.clamp(#min, #max)))
+        } else {
+            quote!(#value.clamp(#min, #max))
+        }
+    }
+}
+
+impl DecodeMode {
+    /// Calls `try` functions in order until one succeeds
+    ///
+    /// The `value` is `&str` and result is `Result<T, String>` where error
+    /// contains messages from all the functions.
+    pub fn try_chain(fns: &[syn::Path], value: &syn::Ident) -> TokenStream {
+        let errors = (0..fns.len())
+            .map(|i| format_ident!("err_{}", i, span = Span::mixed_site()))
+            .collect::<Vec<_>>();
+        let names = fns.iter()
+            .map(|f| quote!(#f).to_string().replace(' ', ""))
+            .collect::<Vec<_>>();
+        let message = format!("none of the decoders succeeded: {}",
+            names.iter().map(|n| format!("`{}`: {{}}", n))
+                .collect::<Vec<_>>().join("; "));
+        let mut chain = quote! {
+            Err(format!(#message, #(#errors),*))
+        };
+        for (func, err) in fns.iter().zip(&errors).rev() {
+            chain = quote! {
+                match #func(#value) {
+                    Ok(v) => Ok(v),
+                    Err(#err) => #chain,
+                }
+            };
+        }
+        chain
+    }
+}
+
+impl RenameAll {
+    pub fn from_lit(case: &syn::LitStr) -> syn::Result<RenameAll> {
+        match &case.value()[..] {
+            "kebab-case" => Ok(RenameAll::Kebab),
+            "snake_case" => Ok(RenameAll::Snake),
+            "camelCase" => Ok(RenameAll::Camel),
+            "PascalCase" => Ok(RenameAll::Pascal),
+            "SCREAMING_SNAKE_CASE" => Ok(RenameAll::ScreamingSnake),
+            "lowercase" => Ok(RenameAll::Lower),
+            "UPPERCASE" => Ok(RenameAll::Upper),
+            _ => Err(syn::Error::new(case.span(),
+                "expected one of `\"kebab-case\"`, `\"snake_case\"`, \
+                 `\"camelCase\"`, `\"PascalCase\"`, \
+                 `\"SCREAMING_SNAKE_CASE\"`, `\"lowercase\"`, \
+                 `\"UPPERCASE\"`")),
+        }
+    }
+    /// Converts field or variant name to the name used in the source
+    pub fn apply(self, ident: &syn::Ident) -> String {
+        let name = ident.unraw().to_string();
+        match self {
+            RenameAll::Kebab => heck::ToKebabCase::to_kebab_case(&name[..]),
+            RenameAll::Snake => heck::ToSnakeCase::to_snake_case(&name[..]),
+            RenameAll::Camel => {
+                heck::ToLowerCamelCase::to_lower_camel_case(&name[..])
+            }
+            RenameAll::Pascal => {
+                heck::ToUpperCamelCase::to_upper_camel_case(&name[..])
+            }
+            RenameAll::ScreamingSnake => {
+                heck::ToShoutySnakeCase::to_shouty_snake_case(&name[..])
+            }
+            RenameAll::Lower => name.to_lowercase(),
+            RenameAll::Upper => name.to_uppercase(),
+        }
+    }
+}
+
+impl LiteralKind {
+    /// Pattern matching `Literal` of this kind
+    pub fn pattern(&self) -> TokenStream {
+        match self {
+            LiteralKind::Int => quote!(::knuffel::ast::Literal::Int(_)),
+            LiteralKind::Decimal => quote!(::knuffel::ast::Literal::Decimal(_)),
+            LiteralKind::String => quote!(::knuffel::ast::Literal::String(_)),
+            LiteralKind::Bool => quote!(::knuffel::ast::Literal::Bool(_)),
+        }
+    }
+    /// The `knuffel::decode::Kind` used in error messages
+    pub fn kind(&self) -> TokenStream {
+        match self {
+            LiteralKind::Int => quote!(::knuffel::decode::Kind::Int),
+            LiteralKind::Decimal => quote!(::knuffel::decode::Kind::Decimal),
+            LiteralKind::String => quote!(::knuffel::decode::Kind::String),
+            LiteralKind::Bool => quote!(::knuffel::decode::Kind::Bool),
+        }
+    }
+}
+
+impl Prop {
+    /// Pattern matching the property name in a `match` on `&str`
+    pub fn name_pattern(&self, name_str: &syn::Ident) -> TokenStream {
+        let name = &self.name;
+        if self.case_insensitive {
+            let lower = name.to_lowercase();
+            quote!(#name_str if #name_str.to_lowercase() == #lower)
+        } else {
+            quote!(#name)
+        }
+    }
+}
+
 impl Enum {
     fn new(ident: syn::Ident, attrs: Vec<syn::Attribute>,
            generics: syn::Generics,
@@ -294,11 +694,36 @@ impl Enum {
     {
         let mut attrs = parse_attr_list(&attrs);
         let trait_props = TraitProps::pick_from(&mut attrs);
-        if !attrs.is_empty() {
-            for (_, span) in attrs {
-                emit_error!(span, "unexpected container attribute");
+        let mut tag = None;
+        for (attr, span) in attrs {
+            match attr {
+                Attr::Tag(name) => {
+                    if tag.is_some() {
+                        emit_error!(span, "only single `tag` is allowed");
+                    }
+                    tag = Some(name);
+                }
+                _ => emit_error!(span, "unexpected container attribute"),
             }
         }
+        if trait_props.serde {
+            return Err(syn::Error::new(ident.span(),
+                "`serde` is not supported for enums yet"));
+        }
+        if trait_props.non_empty_document || trait_props.strict_document {
+            return Err(syn::Error::new(ident.span(),
+                "`non_empty_document` and `strict_document` are only \
+                 supported for structures"));
+        }
+        if trait_props.fail_fast {
+            return Err(syn::Error::new(ident.span(),
+                "`fail_fast` is only supported for structures"));
+        }
+        if let Some(group) = trait_props.exclusive.first() {
+            return Err(syn::Error::new(group.span,
+                "`one_of` and `at_most_one_of` are only \
+                 supported for structures"));
+        }
 
         let mut variants = Vec::new();
         for var in src_variants {
@@ -331,6 +756,7 @@ impl Enum {
                         // parser.
                         VariantKind::Nested {
                             option: tup.extra_fields[0].option,
+                            ty: tup.extra_fields[0].field.ty.clone(),
                         }
                     } else {
                         VariantKind::Tuple(tup)
@@ -347,6 +773,7 @@ impl Enum {
             trait_props,
             generics,
             variants,
+            tag,
         })
     }
 }
@@ -364,12 +791,15 @@ impl StructBuilder {
             spans: Vec::new(),
             node_names: Vec::new(),
             type_names: Vec::new(),
+            type_ofs: Vec::new(),
+            doc_comments: Vec::new(),
             arguments: Vec::new(),
             var_args: None::<VarArgs>,
             properties: Vec::new(),
             var_props: None::<VarProps>,
             children: Vec::new(),
             var_children: None::<VarChildren>,
+            typed_children: Vec::new(),
             extra_fields: Vec::new(),
         }
     }
@@ -381,16 +811,20 @@ impl StructBuilder {
             spans: self.spans,
             node_names: self.node_names,
             type_names: self.type_names,
+            type_ofs: self.type_ofs,
+            doc_comments: self.doc_comments,
             has_arguments:
                 !self.arguments.is_empty() || self.var_args.is_some(),
             has_properties:
-                !self.properties.is_empty() || self.var_props.is_some(),
+                !self.properties.is_empty() || self.var_props.is_some() ||
+                self.children.iter().any(|c| c.or_property),
             arguments: self.arguments,
             var_args: self.var_args,
             properties: self.properties,
             var_props: self.var_props,
             children: self.children,
             var_children: self.var_children,
+            typed_children: self.typed_children,
             extra_fields: self.extra_fields,
         }
     }
@@ -398,6 +832,71 @@ impl StructBuilder {
                      attrs: &FieldAttrs)
         -> syn::Result<&mut Self>
     {
+        if attrs.ordered_by.is_some() &&
+            !matches!(attrs.mode, Some(FieldMode::Children { .. }))
+        {
+            return Err(syn::Error::new(field.span,
+                "`ordered_by` is only allowed for `children`"));
+        }
+        if attrs.merge.is_some() &&
+            !matches!(attrs.mode, Some(FieldMode::Children { name: Some(_) }))
+        {
+            return Err(syn::Error::new(field.span,
+                "`merge` is only allowed for `children(name=\"..\")`"));
+        }
+        if attrs.children_type.is_some() &&
+            !matches!(attrs.mode, Some(FieldMode::Children { name: None }))
+        {
+            return Err(syn::Error::new(field.span,
+                "`type` is only allowed for `children` without `name`"));
+        }
+        if attrs.dedup_by_name.is_some() && (
+            !matches!(attrs.mode, Some(FieldMode::Children { name: None })) ||
+            attrs.children_type.is_some())
+        {
+            return Err(syn::Error::new(field.span,
+                "`dedup_by_name` is only allowed for catch all `children`"));
+        }
+        if attrs.merge.is_some() && is_option {
+            return Err(syn::Error::new(field.span,
+                "`merge` is not supported for optional children"));
+        }
+        if attrs.clamp.is_some() && !matches!(attrs.mode,
+            Some(FieldMode::Argument | FieldMode::Property { .. }))
+        {
+            return Err(syn::Error::new(field.span,
+                "`clamp` is only allowed for `argument` and `property`"));
+        }
+        if attrs.literal_kind.is_some() && !matches!(attrs.mode,
+            Some(FieldMode::Argument | FieldMode::Property { .. } |
+                 FieldMode::NodeKey))
+        {
+            return Err(syn::Error::new(field.span,
+                "`kind` is only allowed for `argument`, `node_key` and \
+                 `property`"));
+        }
+        if attrs.type_name.is_some() && !matches!(attrs.mode,
+            Some(FieldMode::Argument | FieldMode::Property { .. } |
+                 FieldMode::NodeKey))
+        {
+            return Err(syn::Error::new(field.span,
+                "`type_name=\"...\"` is only allowed for `argument`, \
+                 `node_key` and `property`; use plain `type_name` to \
+                 capture the type name of the node"));
+        }
+        if attrs.none_if.is_some() && (!is_option || !matches!(attrs.mode,
+            Some(FieldMode::Argument | FieldMode::Property { .. })))
+        {
+            return Err(syn::Error::new(field.span,
+                "`none_if` is only allowed for `argument` and `property` \
+                 of `Option` type"));
+        }
+        if attrs.case_insensitive &&
+            !matches!(attrs.mode, Some(FieldMode::Property { .. }))
+        {
+            return Err(syn::Error::new(field.span,
+                "`case_insensitive` is only allowed for `property`"));
+        }
         match &attrs.mode {
             Some(FieldMode::Argument) => {
                 if let Some(prev) = &self.var_args {
@@ -405,21 +904,71 @@ impl StructBuilder {
                         "extra `argument` after capture all `arguments`",
                         "capture all `arguments` is defined here"));
                 }
+                if is_option && attrs.default.is_some() {
+                    return Err(syn::Error::new(field.span,
+                        "optional argument is `None` when absent, \
+                         `default` can't be used with it; use a non-optional \
+                         type for a default value"));
+                }
                 self.arguments.push(Arg {
                     field,
                     kind: ArgKind::Value { option: is_option },
                     decode: attrs.decode.clone().unwrap_or(DecodeMode::Normal),
                     default: attrs.default.clone(),
                     option: is_option,
+                    clamp: attrs.clamp.clone(),
+                    literal_kind: attrs.literal_kind,
+                    type_name: attrs.type_name.clone(),
+                    none_if: attrs.none_if.clone(),
+                });
+            }
+            Some(FieldMode::NodeKey) => {
+                if is_option || attrs.default.is_some() {
+                    return Err(syn::Error::new(field.span,
+                        "`node_key` is always required, it can't be \
+                         optional or have a default"));
+                }
+                if let Some(prev) = self.arguments.first()
+                    .filter(|a| matches!(a.kind, ArgKind::Key))
+                {
+                    return Err(err_pair(&field, &prev.field,
+                        "only single `node_key` is allowed",
+                        "previous `node_key` is defined here"));
+                }
+                self.arguments.insert(0, Arg {
+                    field,
+                    kind: ArgKind::Key,
+                    decode: attrs.decode.clone().unwrap_or(DecodeMode::Normal),
+                    default: None,
+                    option: false,
+                    clamp: None,
+                    literal_kind: attrs.literal_kind,
+                    type_name: attrs.type_name.clone(),
+                    none_if: None,
                 });
             }
             Some(FieldMode::Arguments) => {
+                if is_option {
+                    return Err(syn::Error::new(field.span,
+                        "capture all `arguments` is always present (possibly \
+                         empty), so it can't be `Option`. Use a plain \
+                         collection like `Vec<T>`, or `argument` fields of \
+                         `Option<T>` for optional values"));
+                }
                 if let Some(prev) = &self.var_args {
                     return Err(err_pair(&field, &prev.field,
                         "only single `arguments` allowed",
                         "previous `arguments` is defined here"));
                 }
+                let (item, array_len) = match &field.ty {
+                    syn::Type::Array(arr) => {
+                        (Some((*arr.elem).clone()), Some(arr.len.clone()))
+                    }
+                    ty => (bitflags_item(ty).cloned(), None),
+                };
                 self.var_args = Some(VarArgs {
+                    item,
+                    array_len,
                     field,
                     decode: attrs.decode.clone().unwrap_or(DecodeMode::Normal),
                 });
@@ -433,7 +982,7 @@ impl StructBuilder {
                 let name = match (name, &field.attr) {
                     (Some(name), _) => name.clone(),
                     (None, AttrAccess::Named(name))
-                    => heck::ToKebabCase::to_kebab_case(&name.unraw().to_string()[..]),
+                    => self.trait_props.rename_all.apply(name),
                     (None, AttrAccess::Indexed(_)) => {
                         return Err(syn::Error::new(field.span,
                             "property must be named, try \
@@ -447,9 +996,22 @@ impl StructBuilder {
                     decode: attrs.decode.clone().unwrap_or(DecodeMode::Normal),
                     flatten: false,
                     default: attrs.default.clone(),
+                    case_insensitive: attrs.case_insensitive,
+                    clamp: attrs.clamp.clone(),
+                    literal_kind: attrs.literal_kind,
+                    type_name: attrs.type_name.clone(),
+                    none_if: attrs.none_if.clone(),
                 });
             }
             Some(FieldMode::Properties) => {
+                if is_option {
+                    return Err(syn::Error::new(field.span,
+                        "capture all `properties` is always present \
+                         (possibly empty), so it can't be `Option`. Use a \
+                         plain collection like `HashMap<K, V>`, or \
+                         `property` fields of `Option<T>` for optional \
+                         values"));
+                }
                 if let Some(prev) = &self.var_props {
                     return Err(err_pair(&field, &prev.field,
                         "only single `properties` is allowed",
@@ -467,9 +1029,7 @@ impl StructBuilder {
                         "capture all `children` is defined here"));
                 }
                 let name = match &field.attr {
-                    AttrAccess::Named(n) => {
-                        heck::ToKebabCase::to_kebab_case(&n.unraw().to_string()[..])
-                    }
+                    AttrAccess::Named(n) => self.trait_props.rename_all.apply(n),
                     AttrAccess::Indexed(_) => {
                         return Err(syn::Error::new(field.span,
                             "`child` is not allowed for tuple structs"));
@@ -486,6 +1046,39 @@ impl StructBuilder {
                     },
                     unwrap: attrs.unwrap.clone(),
                     default: attrs.default.clone(),
+                    ordered_by: None,
+                    merge: None,
+                    or_property: false,
+                });
+            }
+            Some(FieldMode::PropertyOrChild) => {
+                if let Some(prev) = &self.var_children {
+                    return Err(err_pair(&field, &prev.field,
+                        "extra `property_or_child` after capture all \
+                         `children`",
+                        "capture all `children` is defined here"));
+                }
+                let name = match &field.attr {
+                    AttrAccess::Named(n) => self.trait_props.rename_all.apply(n),
+                    AttrAccess::Indexed(_) => {
+                        return Err(syn::Error::new(field.span,
+                            "`property_or_child` is not allowed for tuple \
+                             structs"));
+                    }
+                };
+                let mut unwrap = FieldAttrs::new();
+                unwrap.mode = Some(FieldMode::Argument);
+                unwrap.decode = attrs.decode.clone();
+                self.children.push(Child {
+                    name,
+                    field,
+                    option: is_option,
+                    mode: ChildMode::Normal,
+                    unwrap: Some(Box::new(unwrap)),
+                    default: attrs.default.clone(),
+                    ordered_by: None,
+                    merge: None,
+                    or_property: true,
                 });
             }
             Some(FieldMode::Children { name: Some(name) }) => {
@@ -501,17 +1094,55 @@ impl StructBuilder {
                     mode: ChildMode::Multi,
                     unwrap: attrs.unwrap.clone(),
                     default: attrs.default.clone(),
+                    ordered_by: attrs.ordered_by.clone(),
+                    merge: attrs.merge.clone(),
+                    or_property: false,
+                });
+            }
+            Some(FieldMode::Children { name: None })
+            if attrs.children_type.is_some() => {
+                if is_option {
+                    return Err(syn::Error::new(field.span,
+                        "`children` filtered by `type` are always present \
+                         (possibly empty), so it can't be `Option`. Use a \
+                         plain collection like `Vec<T>`"));
+                }
+                let prev = self.typed_children.iter()
+                    .find(|c| c.type_filter == attrs.children_type);
+                if let Some(prev) = prev {
+                    return Err(err_pair(&field, &prev.field,
+                        "duplicate `children` for the same `type`",
+                        "previous `children` is defined here"));
+                }
+                self.typed_children.push(VarChildren {
+                    field,
+                    unwrap: attrs.unwrap.clone(),
+                    ordered_by: attrs.ordered_by.clone(),
+                    type_filter: attrs.children_type.clone(),
+                    map: false,
+                    dedup_by_name: None,
                 });
             }
             Some(FieldMode::Children { name: None }) => {
+                if is_option {
+                    return Err(syn::Error::new(field.span,
+                        "capture all `children` is always present (possibly \
+                         empty), so it can't be `Option`. Use a plain \
+                         collection like `Vec<T>`, or filtered \
+                         `children(name=\"..\")` which may be `Option`"));
+                }
                 if let Some(prev) = &self.var_children {
                     return Err(err_pair(&field, &prev.field,
                         "only single catch all `children` is allowed",
                         "previous `children` is defined here"));
                 }
                 self.var_children = Some(VarChildren {
+                    map: is_map(&field.ty),
                     field,
                     unwrap: attrs.unwrap.clone(),
+                    ordered_by: attrs.ordered_by.clone(),
+                    type_filter: None,
+                    dedup_by_name: attrs.dedup_by_name,
                 });
             }
             Some(FieldMode::Flatten(flatten)) => {
@@ -533,6 +1164,11 @@ impl StructBuilder {
                         decode: DecodeMode::Normal,
                         flatten: true,
                         default: None,
+                        case_insensitive: false,
+                        clamp: None,
+                        literal_kind: None,
+                        type_name: None,
+                        none_if: None,
                     });
                 }
                 if flatten.child {
@@ -549,25 +1185,63 @@ impl StructBuilder {
                         mode: ChildMode::Flatten,
                         unwrap: None,
                         default: None,
+                        ordered_by: None,
+                        merge: None,
+                        or_property: false,
                     });
                 }
             }
             Some(FieldMode::Span) => {
-                self.spans.push(SpanField { field });
+                self.spans.push(SpanField { field, bytes: false });
+            }
+            Some(FieldMode::SpanBytes) => {
+                self.spans.push(SpanField { field, bytes: true });
             }
             Some(FieldMode::NodeName) => {
                 self.node_names.push(NodeNameField { field });
             }
+            Some(FieldMode::FromContext) => {
+                self.extra_fields.push(ExtraField {
+                    field,
+                    kind: ExtraKind::Context,
+                    option: is_option,
+                });
+            }
+            Some(FieldMode::DocComment) => {
+                self.doc_comments.push(DocCommentField {
+                    field,
+                    option: is_option,
+                });
+            }
             Some(FieldMode::TypeName) => {
                 self.type_names.push(TypeNameField {
                     field,
                     option: is_option,
                 });
             }
+            Some(FieldMode::TypeOf(target)) => {
+                if let Some(prev) = self.type_ofs.iter()
+                    .find(|t| &t.target == target)
+                {
+                    return Err(err_pair(&field, &prev.field,
+                        "duplicate `type_of` for the same field",
+                        "previous `type_of` is defined here"));
+                }
+                self.type_ofs.push(TypeOfField {
+                    field,
+                    target: target.clone(),
+                    option: is_option,
+                });
+            }
             None => {
+                let kind = if attrs.skip {
+                    ExtraKind::Skip
+                } else {
+                    ExtraKind::Auto
+                };
                 self.extra_fields.push(ExtraField {
                     field,
-                    kind: ExtraKind::Auto,
+                    kind,
                     option: is_option,
                 });
             }
@@ -588,20 +1262,59 @@ impl Struct {
             let field = Field::new(&fld, idx);
             bld.add_field(field, is_option(&fld.ty), is_bool(&fld.ty), &attrs)?;
         }
-
-        Ok(bld.build())
+        let res = bld.build();
+        for type_of in &res.type_ofs {
+            if res.value_field(&type_of.target).is_none() {
+                return Err(syn::Error::new(type_of.target.span(),
+                    "`type_of` must refer to an `argument` or `property` \
+                     field"));
+            }
+        }
+        Ok(res)
+    }
+    /// Finds named `argument` or `property` field
+    fn value_field(&self, name: &syn::Ident) -> Option<&Field> {
+        let is_named = |f: &&Field| {
+            matches!(&f.attr, AttrAccess::Named(n) if n == name)
+        };
+        self.arguments.iter().map(|a| &a.field).find(is_named)
+            .or_else(|| {
+                self.properties.iter()
+                    .filter(|p| !p.flatten)
+                    .map(|p| &p.field)
+                    .find(is_named)
+            })
+    }
+    /// Returns `type_of` field for the `argument` or `property` field
+    pub fn type_of(&self, field: &Field) -> Option<&TypeOfField> {
+        self.type_ofs.iter().find(|t| {
+            matches!(&field.attr, AttrAccess::Named(n) if n == &t.target)
+        })
+    }
+    /// Whether the field is flattened for properties
+    ///
+    /// Such fields may be flattened for children too, in which case both
+    /// share the variable declared when decoding properties.
+    pub fn is_flat_property(&self, field: &Field) -> bool {
+        self.properties.iter()
+            .any(|p| p.flatten && p.field.tmp_name == field.tmp_name)
     }
     pub fn all_fields(&self) -> Vec<&Field> {
         let mut res = Vec::new();
         res.extend(self.spans.iter().map(|a| &a.field));
         res.extend(self.node_names.iter().map(|a| &a.field));
         res.extend(self.type_names.iter().map(|a| &a.field));
+        res.extend(self.type_ofs.iter().map(|a| &a.field));
+        res.extend(self.doc_comments.iter().map(|a| &a.field));
         res.extend(self.arguments.iter().map(|a| &a.field));
         res.extend(self.var_args.iter().map(|a| &a.field));
         res.extend(self.properties.iter().map(|p| &p.field));
         res.extend(self.var_props.iter().map(|p| &p.field));
-        res.extend(self.children.iter().map(|c| &c.field));
+        res.extend(self.children.iter()
+            .filter(|c| !self.is_flat_property(&c.field))
+            .map(|c| &c.field));
         res.extend(self.var_children.iter().map(|c| &c.field));
+        res.extend(self.typed_children.iter().map(|c| &c.field));
         res.extend(self.extra_fields.iter().map(|f| &f.field));
         return res;
     }
@@ -620,10 +1333,14 @@ impl Parse for Definition {
 
             let mut attrs = parse_attr_list(&attrs);
             let trait_props = TraitProps::pick_from(&mut attrs);
-            if !attrs.is_empty() {
-                for (_, span) in attrs {
-                    emit_error!(span, "unexpected container attribute");
-                }
+            let message = if matches!(item.fields, syn::Fields::Unit) {
+                "unexpected container attribute, unit structures have no \
+                 fields so field attributes are not allowed here"
+            } else {
+                "unexpected container attribute"
+            };
+            for (_, span) in attrs {
+                emit_error!(span, message);
             }
 
             match item.fields {
@@ -643,6 +1360,28 @@ impl Parse for Definition {
                         && tup.extra_fields.len() == 1
                         && matches!(tup.extra_fields[0].kind, ExtraKind::Auto)
                     {
+                        if trait_props.serde {
+                            return Err(syn::Error::new(item.ident.span(),
+                                "`serde` is not supported for \
+                                 newtype structures"));
+                        }
+                        if trait_props.non_empty_document ||
+                            trait_props.strict_document
+                        {
+                            return Err(syn::Error::new(item.ident.span(),
+                                "`non_empty_document` and `strict_document` \
+                                 are not supported for newtype structures"));
+                        }
+                        if trait_props.fail_fast {
+                            return Err(syn::Error::new(item.ident.span(),
+                                "`fail_fast` is not supported for \
+                                 newtype structures"));
+                        }
+                        if let Some(group) = trait_props.exclusive.first() {
+                            return Err(syn::Error::new(group.span,
+                                "`one_of` and `at_most_one_of` \
+                                 are not supported for newtype structures"));
+                        }
                         Ok(Definition::NewType(NewType {
                             ident: item.ident,
                             trait_props,
@@ -678,12 +1417,33 @@ impl FieldAttrs {
             decode: None,
             unwrap: None,
             default: None,
+            ordered_by: None,
+            dedup_by_name: None,
+            merge: None,
+            clamp: None,
+            literal_kind: None,
+            type_name: None,
+            none_if: None,
+            case_insensitive: false,
+            children_type: None,
+            skip: false,
         }
     }
     fn update(&mut self, attrs: impl IntoIterator<Item=(Attr, Span)>) {
         use Attr::*;
 
+        let mut skip_span = None;
+        let mut other_span = None;
         for (attr, span) in attrs {
+            if matches!(attr, Skip) {
+                if skip_span.is_some() {
+                    emit_error!(span, "only single `skip` is allowed");
+                }
+                skip_span = Some(span);
+                self.skip = true;
+                continue;
+            }
+            other_span.get_or_insert(span);
             match attr {
                 FieldMode(mode) => {
                     if self.mode.is_some() {
@@ -697,7 +1457,7 @@ impl FieldAttrs {
                     if self.unwrap.is_some() {
                         emit_error!(span, "`unwrap` specified twice");
                     }
-                    self.unwrap = Some(Box::new(val));
+                    self.unwrap = Some(val);
                 }
                 DecodeMode(mode) => {
                     if self.decode.is_some() {
@@ -715,10 +1475,71 @@ impl FieldAttrs {
                     }
                     self.default = Some(value);
                 }
+                OrderedBy(key) => {
+                    if self.ordered_by.is_some() {
+                        emit_error!(span,
+                            "only single `ordered_by` is allowed");
+                    }
+                    self.ordered_by = Some(key);
+                }
+                DedupByName(dedup) => {
+                    if self.dedup_by_name.is_some() {
+                        emit_error!(span,
+                            "only single `dedup_by_name` is allowed");
+                    }
+                    self.dedup_by_name = Some(dedup);
+                }
+                Clamp(clamp) => {
+                    if self.clamp.is_some() {
+                        emit_error!(span, "only single `clamp` is allowed");
+                    }
+                    self.clamp = Some(*clamp);
+                }
+                LiteralKind(kind) => {
+                    if self.literal_kind.is_some() {
+                        emit_error!(span, "only single `kind` is allowed");
+                    }
+                    self.literal_kind = Some(kind);
+                }
+                TypeNameConst(name) => {
+                    if self.type_name.is_some() {
+                        emit_error!(span,
+                            "only single `type_name` is allowed");
+                    }
+                    self.type_name = Some(name);
+                }
+                NoneIf(value) => {
+                    if self.none_if.is_some() {
+                        emit_error!(span, "only single `none_if` is allowed");
+                    }
+                    self.none_if = Some(value);
+                }
+                Merge(path) => {
+                    if self.merge.is_some() {
+                        emit_error!(span, "only single `merge` is allowed");
+                    }
+                    self.merge = Some(path);
+                }
+                CaseInsensitive => {
+                    self.case_insensitive = true;
+                }
+                ChildrenType(typ) => {
+                    if self.children_type.is_some() {
+                        emit_error!(span, "only single `type` is allowed");
+                    }
+                    self.children_type = Some(typ);
+                }
                 _ => emit_error!(span,
                     "this attribute is not supported on fields"),
             }
         }
+        if let (Some(skip), Some(other)) = (skip_span, other_span) {
+            let mut err = syn::Error::new(skip,
+                "`skip` cannot be combined with other attributes");
+            err.combine(syn::Error::new(other,
+                "this attribute conflicts with `skip`"));
+            emit_error!(err);
+        }
     }
 }
 
@@ -765,7 +1586,8 @@ fn parse_attrs(input: ParseStream)
 
 impl Attr {
     fn parse(input: ParseStream) -> syn::Result<(Self, Span)> {
-        Self::_parse(input).map(|a| (a, input.span()))
+        let span = input.span();
+        Self::_parse(input).map(|a| (a, span))
     }
     fn _parse(input: ParseStream) -> syn::Result<Self> {
         let lookahead = input.lookahead1();
@@ -815,6 +1637,9 @@ impl Attr {
         } else if lookahead.peek(kw::child) {
             let _kw: kw::child = input.parse()?;
             Ok(Attr::FieldMode(FieldMode::Child))
+        } else if lookahead.peek(kw::property_or_child) {
+            let _kw: kw::property_or_child = input.parse()?;
+            Ok(Attr::FieldMode(FieldMode::PropertyOrChild))
         } else if lookahead.peek(kw::unwrap) {
             let _kw: kw::unwrap = input.parse()?;
             let parens;
@@ -822,7 +1647,7 @@ impl Attr {
             let mut attrs = FieldAttrs::new();
             let chunk = parens.call(parse_attrs)?;
             attrs.update(chunk);
-            Ok(Attr::Unwrap(attrs))
+            Ok(Attr::Unwrap(Box::new(attrs)))
         } else if lookahead.peek(kw::skip) {
             let _kw: kw::skip = input.parse()?;
             Ok(Attr::Skip)
@@ -831,9 +1656,45 @@ impl Attr {
             Ok(Attr::DecodeMode(DecodeMode::Str))
         } else if lookahead.peek(kw::bytes) {
             let _kw: kw::bytes = input.parse()?;
-            Ok(Attr::DecodeMode(DecodeMode::Bytes))
+            let mut url_safe = false;
+            if !input.is_empty() && !input.lookahead1().peek(syn::Token![,]) {
+                let parens;
+                syn::parenthesized!(parens in input);
+                let _kw: kw::base64_url = parens.parse()?;
+                url_safe = true;
+            }
+            Ok(Attr::DecodeMode(DecodeMode::Bytes { url_safe }))
+        } else if lookahead.peek(kw::from_u32) {
+            let _kw: kw::from_u32 = input.parse()?;
+            Ok(Attr::DecodeMode(DecodeMode::FromU32))
+        } else if lookahead.peek(syn::Token![try]) {
+            let kw: syn::Token![try] = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let items;
+            syn::bracketed!(items in input);
+            let fns = Punctuated::<syn::Path, syn::Token![,]>::
+                parse_terminated(&items)?;
+            if fns.is_empty() {
+                return Err(syn::Error::new(kw.span,
+                    "`try` requires at least one function"));
+            }
+            Ok(Attr::DecodeMode(DecodeMode::Try(fns.into_iter().collect())))
+        } else if lookahead.peek(kw::units) {
+            let _kw: kw::units = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            Ok(Attr::DecodeMode(DecodeMode::Units(input.parse()?)))
+        } else if lookahead.peek(kw::split_flags) {
+            let _kw: kw::split_flags = input.parse()?;
+            Ok(Attr::DecodeMode(DecodeMode::SplitFlags))
         } else if lookahead.peek(kw::flatten) {
             let _kw: kw::flatten = input.parse()?;
+            if !input.lookahead1().peek(syn::token::Paren) {
+                // bare `flatten` forwards everything it can
+                return Ok(Attr::FieldMode(FieldMode::Flatten(Flatten {
+                    child: true,
+                    property: true,
+                })));
+            }
             let parens;
             syn::parenthesized!(parens in input);
             let items = Punctuated::<FlattenItem, syn::Token![,]>::
@@ -858,20 +1719,210 @@ impl Attr {
             } else {
                 Ok(Attr::Default(None))
             }
+        } else if lookahead.peek(kw::case_insensitive) {
+            let _kw: kw::case_insensitive = input.parse()?;
+            Ok(Attr::CaseInsensitive)
+        } else if lookahead.peek(kw::kind) {
+            let _kw: kw::kind = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let kind: syn::LitStr = input.parse()?;
+            match &kind.value()[..] {
+                "int" => Ok(Attr::LiteralKind(LiteralKind::Int)),
+                "decimal" => Ok(Attr::LiteralKind(LiteralKind::Decimal)),
+                "string" => Ok(Attr::LiteralKind(LiteralKind::String)),
+                "bool" => Ok(Attr::LiteralKind(LiteralKind::Bool)),
+                _ => Err(syn::Error::new(kind.span(),
+                    "expected one of `\"int\"`, `\"decimal\"`, \
+                     `\"string\"`, `\"bool\"`")),
+            }
+        } else if lookahead.peek(kw::none_if) {
+            let _kw: kw::none_if = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let value: syn::LitStr = input.parse()?;
+            Ok(Attr::NoneIf(value.value()))
+        } else if lookahead.peek(kw::clamp) {
+            let kw: kw::clamp = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let range: syn::ExprRange = input.parse()?;
+            match &range {
+                syn::ExprRange {
+                    from: Some(min),
+                    limits: syn::RangeLimits::Closed(_),
+                    to: Some(max),
+                    ..
+                } => {
+                    let clamp = Clamp::new("clamp", &range,
+                        (**min).clone(), (**max).clone())?;
+                    Ok(Attr::Clamp(Box::new(clamp)))
+                }
+                _ => Err(syn::Error::new(kw.span,
+                    "`clamp` requires an inclusive range like `0..=100`")),
+            }
+        } else if lookahead.peek(kw::percent) {
+            let kw: kw::percent = input.parse()?;
+            if !input.peek(syn::Token![=]) {
+                return Ok(Attr::DecodeMode(DecodeMode::Percent(None)));
+            }
+            let _eq: syn::Token![=] = input.parse()?;
+            let range: syn::ExprRange = input.parse()?;
+            match &range {
+                syn::ExprRange {
+                    from: Some(min),
+                    limits: syn::RangeLimits::Closed(_),
+                    to: Some(max),
+                    ..
+                } => {
+                    let clamp = Clamp::new("percent", &range,
+                        (**min).clone(), (**max).clone())?;
+                    Ok(Attr::DecodeMode(DecodeMode::Percent(
+                        Some(Box::new(clamp)))))
+                }
+                _ => Err(syn::Error::new(kw.span,
+                    "`percent` requires an inclusive range like `0..=100`")),
+            }
+        } else if lookahead.peek(kw::bool_words) {
+            let _kw: kw::bool_words = input.parse()?;
+            Ok(Attr::DecodeMode(DecodeMode::BoolWords))
+        } else if lookahead.peek(kw::path) {
+            let _kw: kw::path = input.parse()?;
+            Ok(Attr::DecodeMode(DecodeMode::Path { expand_tilde: false }))
+        } else if lookahead.peek(kw::expand_tilde) {
+            let _kw: kw::expand_tilde = input.parse()?;
+            Ok(Attr::DecodeMode(DecodeMode::Path { expand_tilde: true }))
+        } else if lookahead.peek(kw::duration) {
+            let _kw: kw::duration = input.parse()?;
+            let mut unit = String::from("s");
+            if !input.is_empty() && !input.lookahead1().peek(syn::Token![,]) {
+                let parens;
+                syn::parenthesized!(parens in input);
+                let _kw: kw::unit = parens.parse()?;
+                let _eq: syn::Token![=] = parens.parse()?;
+                let lit: syn::LitStr = parens.parse()?;
+                unit = lit.value();
+                if !DURATION_UNITS.contains(&&unit[..]) {
+                    return Err(syn::Error::new(lit.span(),
+                        "duration unit must be one of \
+                         `ns`, `us`, `ms`, `s`, `m`, `h`, `d`"));
+                }
+            }
+            Ok(Attr::DecodeMode(DecodeMode::Duration(unit)))
+        } else if lookahead.peek(kw::merge) {
+            let _kw: kw::merge = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let path: syn::Path = input.parse()?;
+            Ok(Attr::Merge(path))
+        } else if lookahead.peek(syn::Token![type]) {
+            let _kw: syn::Token![type] = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let typ: syn::LitStr = input.parse()?;
+            Ok(Attr::ChildrenType(typ.value()))
+        } else if lookahead.peek(kw::ordered_by) {
+            let _kw: kw::ordered_by = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let key: syn::Ident = input.parse()?;
+            Ok(Attr::OrderedBy(key))
+        } else if lookahead.peek(kw::dedup_by_name) {
+            let _kw: kw::dedup_by_name = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let policy: syn::LitStr = input.parse()?;
+            match &policy.value()[..] {
+                "first" => Ok(Attr::DedupByName(Dedup::First)),
+                "last" => Ok(Attr::DedupByName(Dedup::Last)),
+                _ => Err(syn::Error::new(policy.span(),
+                    "expected `\"first\"` or `\"last\"`")),
+            }
         } else if lookahead.peek(kw::span) {
             let _kw: kw::span = input.parse()?;
             Ok(Attr::FieldMode(FieldMode::Span))
+        } else if lookahead.peek(kw::span_bytes) {
+            let _kw: kw::span_bytes = input.parse()?;
+            Ok(Attr::FieldMode(FieldMode::SpanBytes))
         } else if lookahead.peek(kw::node_name) {
             let _kw: kw::node_name = input.parse()?;
-            Ok(Attr::FieldMode(FieldMode::NodeName))
+            if input.peek(syn::Token![=]) {
+                let _eq: syn::Token![=] = input.parse()?;
+                let name: syn::LitStr = input.parse()?;
+                Ok(Attr::NodeNameConst(name.value()))
+            } else {
+                Ok(Attr::FieldMode(FieldMode::NodeName))
+            }
+        } else if lookahead.peek(kw::node_key) {
+            let _kw: kw::node_key = input.parse()?;
+            Ok(Attr::FieldMode(FieldMode::NodeKey))
         } else if lookahead.peek(kw::type_name) {
             let _kw: kw::type_name = input.parse()?;
-            Ok(Attr::FieldMode(FieldMode::TypeName))
+            if input.peek(syn::Token![=]) {
+                let _eq: syn::Token![=] = input.parse()?;
+                let name: syn::LitStr = input.parse()?;
+                Ok(Attr::TypeNameConst(name.value()))
+            } else {
+                Ok(Attr::FieldMode(FieldMode::TypeName))
+            }
+        } else if lookahead.peek(kw::type_of) {
+            let _kw: kw::type_of = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let target: syn::Ident = input.parse()?;
+            Ok(Attr::FieldMode(FieldMode::TypeOf(target)))
         } else if lookahead.peek(kw::span_type) {
             let _kw: kw::span_type = input.parse()?;
             let _eq: syn::Token![=] = input.parse()?;
             let ty: syn::Type = input.parse()?;
             Ok(Attr::SpanType(ty))
+        } else if lookahead.peek(kw::serde) {
+            let _kw: kw::serde = input.parse()?;
+            Ok(Attr::Serde)
+        } else if lookahead.peek(kw::tag) {
+            let _kw: kw::tag = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let name: syn::LitStr = input.parse()?;
+            Ok(Attr::Tag(name.value()))
+        } else if lookahead.peek(kw::strict_document) {
+            let _kw: kw::strict_document = input.parse()?;
+            Ok(Attr::StrictDocument)
+        } else if lookahead.peek(kw::fail_fast) {
+            let _kw: kw::fail_fast = input.parse()?;
+            Ok(Attr::FailFast)
+        } else if lookahead.peek(kw::rename_all) {
+            let _kw: kw::rename_all = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let case: syn::LitStr = input.parse()?;
+            RenameAll::from_lit(&case).map(Attr::RenameAll)
+        } else if lookahead.peek(kw::non_empty_document) {
+            let _kw: kw::non_empty_document = input.parse()?;
+            Ok(Attr::NonEmptyDocument)
+        } else if lookahead.peek(kw::one_of) ||
+            lookahead.peek(kw::at_most_one_of)
+        {
+            let span = input.span();
+            let exactly_one = if input.peek(kw::one_of) {
+                let _kw: kw::one_of = input.parse()?;
+                true
+            } else {
+                let _kw: kw::at_most_one_of = input.parse()?;
+                false
+            };
+            let parens;
+            syn::parenthesized!(parens in input);
+            let fields = Punctuated::<syn::Ident, syn::Token![,]>::
+                parse_terminated(&parens)?;
+            if fields.len() < 2 {
+                return Err(syn::Error::new(span,
+                    "at least two fields are required in the group"));
+            }
+            Ok(Attr::Exclusive(ExclusiveGroup {
+                fields: fields.into_iter().collect(),
+                exactly_one,
+                span,
+            }))
+        } else if lookahead.peek(kw::doc_comment) {
+            let _kw: kw::doc_comment = input.parse()?;
+            Ok(Attr::FieldMode(FieldMode::DocComment))
+        } else if lookahead.peek(kw::from_context) {
+            let _kw: kw::from_context = input.parse()?;
+            Ok(Attr::FieldMode(FieldMode::FromContext))
+        } else if lookahead.peek(kw::expose_name) {
+            let _kw: kw::expose_name = input.parse()?;
+            Ok(Attr::ExposeName)
         } else {
             Err(lookahead.error())
         }
@@ -899,6 +1950,9 @@ impl Field {
             span: name.span(),
             attr: AttrAccess::Named(name.clone()),
             tmp_name: name.clone(),
+            ty: syn::Type::Infer(syn::TypeInfer {
+                underscore_token: Default::default(),
+            }),
         }
     }
     fn new(field: &syn::Field, idx: usize) -> Field {
@@ -907,6 +1961,7 @@ impl Field {
                 span: field.span(),
                 attr: AttrAccess::Named(id.clone()),
                 tmp_name: id.clone(),
+                ty: field.ty.clone(),
             })
             .unwrap_or_else(|| Field {
                 span: field.span(),
@@ -915,6 +1970,7 @@ impl Field {
                     &format!("field{}", idx),
                     Span::mixed_site(),
                 ),
+                ty: field.ty.clone(),
             })
     }
     pub fn from_self(&self) -> TokenStream {
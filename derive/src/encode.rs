@@ -0,0 +1,545 @@
+use proc_macro2::{TokenStream, Span};
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+
+use crate::bounds;
+use crate::definition::{Definition, Struct, NewType, Enum, VariantKind};
+use crate::definition::{Field, AttrAccess, ArgKind, DecodeMode, ChildMode};
+use crate::definition::{FieldAttrs, StructBuilder, TraitProps};
+
+
+fn unsupported(field: &Field, what: &str) -> syn::Error {
+    syn::Error::new(field.span,
+        format!("{} is not supported by `Encode`", what))
+}
+
+fn default_name(ident: &syn::Ident, trait_props: &TraitProps) -> String {
+    trait_props.node_name.clone().unwrap_or_else(|| {
+        heck::ToKebabCase::to_kebab_case(&ident.unraw().to_string()[..])
+    })
+}
+
+/// Pattern binding fields of `s` used for encoding to their `tmp_name`
+fn bindings(s: &Struct) -> TokenStream {
+    let mut fields = Vec::new();
+    fields.extend(s.node_names.iter().map(|f| &f.field));
+    fields.extend(s.type_names.iter().map(|f| &f.field));
+    fields.extend(s.type_ofs.iter().map(|t| &t.field));
+    fields.extend(s.arguments.iter().map(|a| &a.field));
+    fields.extend(s.var_args.iter().map(|a| &a.field));
+    fields.extend(s.properties.iter().map(|p| &p.field));
+    fields.extend(s.var_props.iter().map(|p| &p.field));
+    fields.extend(s.children.iter().map(|c| &c.field));
+    fields.extend(s.var_children.iter().map(|c| &c.field));
+    let mut seen = Vec::new();
+    let pairs = fields.into_iter().filter_map(|f| {
+        let tmp_name = &f.tmp_name;
+        if seen.contains(&tmp_name) {
+            // flatten fields are listed twice
+            return None;
+        }
+        seen.push(tmp_name);
+        Some(match &f.attr {
+            AttrAccess::Indexed(idx) => {
+                let idx = syn::Index::from(*idx);
+                quote!(#idx: #tmp_name)
+            }
+            AttrAccess::Named(name) if name == tmp_name => quote!(#name),
+            AttrAccess::Named(name) => quote!(#name: #tmp_name),
+        })
+    }).collect::<Vec<_>>();
+    quote!({ #(#pairs,)* .. })
+}
+
+/// Expression of type `Literal` for the `value` of type `&T`
+fn scalar(field: &Field, mode: &DecodeMode, value: TokenStream, option: bool)
+    -> syn::Result<TokenStream>
+{
+    match mode {
+        // durations are written with a unit, so the default unit is
+        // irrelevant, and paths are written as resolved
+        DecodeMode::Normal | DecodeMode::Duration(_)
+        | DecodeMode::Path { .. } | DecodeMode::BoolWords => {
+            Ok(quote!(::knuffel::traits::EncodeScalar::encode_scalar(#value)))
+        }
+        DecodeMode::Str if option => Ok(quote! {
+            match #value {
+                Some(v) => ::knuffel::encode::display(v),
+                None => ::knuffel::ast::Literal::Null,
+            }
+        }),
+        DecodeMode::Str => Ok(quote!(::knuffel::encode::display(#value))),
+        DecodeMode::Bytes { .. } => Err(unsupported(field, "`bytes`")),
+        DecodeMode::FromU32 => Err(unsupported(field, "`from_u32`")),
+        DecodeMode::SplitFlags => Err(unsupported(field, "`split_flags`")),
+        DecodeMode::Try(_) => Err(unsupported(field, "`try`")),
+        DecodeMode::Units(_) => Err(unsupported(field, "`units`")),
+        DecodeMode::Percent(_) => Err(unsupported(field, "`percent`")),
+    }
+}
+
+/// Uses the type name of the `type_of` field for `field` when it's present
+///
+/// The `typed` closure receives the expression of the type name.
+fn with_type_of(s: &Struct, field: &Field, plain: TokenStream,
+                typed: impl FnOnce(TokenStream) -> TokenStream)
+    -> TokenStream
+{
+    let type_of = match s.type_of(field) {
+        Some(type_of) => type_of,
+        None => return plain,
+    };
+    let fld = &type_of.field.tmp_name;
+    let typ = syn::Ident::new("typ", Span::mixed_site());
+    let value = if type_of.option {
+        quote!(#fld.as_ref())
+    } else {
+        quote!(Some(#fld))
+    };
+    let typed = typed(quote!(&::knuffel::encode::type_name(#typ)));
+    quote! {
+        match #value {
+            Some(#typ) => { #typed }
+            None => { #plain }
+        }
+    }
+}
+
+fn encode_args(s: &Struct, writer: &syn::Ident) -> syn::Result<TokenStream> {
+    let mut out = Vec::new();
+    for arg in &s.arguments {
+        let fld = &arg.field.tmp_name;
+        let option = matches!(arg.kind, ArgKind::Value { option: true });
+        let value = scalar(&arg.field, &arg.decode, quote!(#fld), option)?;
+        let plain = match &arg.type_name {
+            Some(type_name) => quote! {
+                #writer.typed_argument(#type_name, &#value);
+            },
+            None => quote! {
+                #writer.argument(&#value);
+            },
+        };
+        out.push(with_type_of(s, &arg.field, plain, |type_name| quote! {
+            #writer.typed_argument(#type_name, &#value);
+        }));
+    }
+    if let Some(var_args) = &s.var_args {
+        let fld = &var_args.field.tmp_name;
+        let value = scalar(&var_args.field, &var_args.decode,
+                           quote!(item), false)?;
+        out.push(quote! {
+            for item in #fld.iter() {
+                #writer.argument(&#value);
+            }
+        });
+    }
+    Ok(quote!(#(#out)*))
+}
+
+fn encode_props(s: &Struct, writer: &syn::Ident) -> syn::Result<TokenStream> {
+    let mut out = Vec::new();
+    for prop in &s.properties {
+        if prop.flatten {
+            return Err(unsupported(&prop.field, "`flatten`"));
+        }
+        let fld = &prop.field.tmp_name;
+        let name = &prop.name;
+        let write = |value: TokenStream| {
+            let plain = match &prop.type_name {
+                Some(type_name) => quote! {
+                    #writer.typed_property(#name, #type_name, &#value);
+                },
+                None => quote! {
+                    #writer.property(#name, &#value);
+                },
+            };
+            with_type_of(s, &prop.field, plain, |type_name| quote! {
+                #writer.typed_property(#name, #type_name, &#value);
+            })
+        };
+        if prop.option {
+            let value = scalar(&prop.field, &prop.decode, quote!(v), false)?;
+            let write = write(value);
+            out.push(quote! {
+                if let Some(v) = #fld {
+                    #write
+                }
+            });
+        } else {
+            let value = scalar(&prop.field, &prop.decode, quote!(#fld),
+                               false)?;
+            out.push(write(value));
+        }
+    }
+    if let Some(var_props) = &s.var_props {
+        let fld = &var_props.field.tmp_name;
+        let value = scalar(&var_props.field, &var_props.decode,
+                           quote!(value), false)?;
+        out.push(quote! {
+            let mut props = #fld.iter()
+                .map(|(name, value)| (name.to_string(), #value))
+                .collect::<Vec<_>>();
+            props.sort_by(|a, b| a.0.cmp(&b.0));
+            for (name, value) in &props {
+                #writer.property(name, value);
+            }
+        });
+    }
+    Ok(quote!(#(#out)*))
+}
+
+/// Writes `value` as a node with the field described by `attrs` inside
+fn encode_unwrapped(parent: &Struct, field: &Field, attrs: &FieldAttrs,
+                    value: TokenStream, name: TokenStream,
+                    writer: &syn::Ident)
+    -> syn::Result<TokenStream>
+{
+    let fld = &field.tmp_name;
+    let mut bld = StructBuilder::new(
+        format_ident!("Wrap_{}", fld, span = Span::mixed_site()),
+        parent.trait_props.clone(),
+        parent.generics.clone(),
+    );
+    bld.add_field(Field::new_named(fld), false, false, attrs)?;
+    let object = bld.build();
+    let node = encode_node(&object, name, writer)?;
+    Ok(quote! {
+        {
+            let #fld = #value;
+            #node
+        }
+    })
+}
+
+fn encode_children(s: &Struct, writer: &syn::Ident)
+    -> syn::Result<TokenStream>
+{
+    let mut out = Vec::new();
+    for child in &s.children {
+        let fld = &child.field.tmp_name;
+        let name = &child.name;
+        let single = |value: TokenStream| match &child.unwrap {
+            Some(unwrap) => encode_unwrapped(s, &child.field, unwrap,
+                                             value, quote!(#name), writer),
+            None => Ok(quote! {
+                ::knuffel::traits::Encode::encode_node(
+                    #value, Some(#name), #writer);
+            }),
+        };
+        match child.mode {
+            ChildMode::Flatten => {
+                return Err(unsupported(&child.field, "`flatten`"));
+            }
+            ChildMode::Bool => {
+                out.push(quote! {
+                    if *#fld {
+                        #writer.start_node(#name);
+                        #writer.end_node();
+                    }
+                });
+            }
+            ChildMode::Multi if child.merge.is_none() => {
+                let item = single(quote!(item))?;
+                let items = if child.option {
+                    quote!(#fld.iter().flatten())
+                } else {
+                    quote!(#fld.iter())
+                };
+                out.push(quote! {
+                    for item in #items {
+                        #item
+                    }
+                });
+            }
+            ChildMode::Normal | ChildMode::Multi if child.option => {
+                let value = single(quote!(v))?;
+                out.push(quote! {
+                    if let Some(v) = #fld {
+                        #value
+                    }
+                });
+            }
+            ChildMode::Normal | ChildMode::Multi => {
+                out.push(single(quote!(#fld))?);
+            }
+        }
+    }
+    if let Some(var) = &s.var_children {
+        let fld = &var.field.tmp_name;
+        if var.map {
+            let item = match &var.unwrap {
+                Some(unwrap) => encode_unwrapped(s, &var.field, unwrap,
+                    quote!(value), quote!(name), writer)?,
+                None => quote! {
+                    ::knuffel::traits::Encode::encode_node(
+                        value, Some(name), #writer);
+                },
+            };
+            out.push(quote! {
+                let mut items = #fld.iter()
+                    .map(|(name, value)| (name.to_string(), value))
+                    .collect::<Vec<_>>();
+                items.sort_by(|a, b| a.0.cmp(&b.0));
+                for (name, value) in &items {
+                    let name = &name[..];
+                    #item
+                }
+            });
+        } else {
+            if var.unwrap.is_some() {
+                return Err(unsupported(&var.field,
+                    "`unwrap` for `children` without `name`"));
+            }
+            out.push(quote! {
+                for item in #fld.iter() {
+                    ::knuffel::traits::Encode::encode_node(
+                        item, None, #writer);
+                }
+            });
+        }
+    }
+    if let Some(typed) = s.typed_children.first() {
+        return Err(unsupported(&typed.field, "`children(type=...)`"));
+    }
+    Ok(quote!(#(#out)*))
+}
+
+/// Writes the whole node, `name` is an expression of type `&str`
+fn encode_node(s: &Struct, name: TokenStream, writer: &syn::Ident)
+    -> syn::Result<TokenStream>
+{
+    encode_tagged_node(s, name, quote!(), writer)
+}
+
+/// Writes the whole node with `tag` written right after the node name
+fn encode_tagged_node(s: &Struct, name: TokenStream, tag: TokenStream,
+                      writer: &syn::Ident)
+    -> syn::Result<TokenStream>
+{
+    let name = match s.node_names.first() {
+        Some(node_name) => {
+            let fld = &node_name.field.tmp_name;
+            quote!(&#fld.to_string()[..])
+        }
+        None => name,
+    };
+    let start = match s.type_names.first() {
+        Some(type_name) if type_name.option => {
+            let fld = &type_name.field.tmp_name;
+            quote! {
+                match #fld {
+                    Some(type_name) => {
+                        #writer.start_typed_node(
+                            &type_name.to_string(), #name);
+                    }
+                    None => #writer.start_node(#name),
+                }
+            }
+        }
+        Some(type_name) => {
+            let fld = &type_name.field.tmp_name;
+            quote! {
+                #writer.start_typed_node(&#fld.to_string(), #name);
+            }
+        }
+        None => quote!(#writer.start_node(#name);),
+    };
+    let args = encode_args(s, writer)?;
+    let props = encode_props(s, writer)?;
+    let children = if s.children.is_empty() && s.var_children.is_none() {
+        quote!()
+    } else {
+        let children = encode_children(s, writer)?;
+        quote! {
+            #writer.start_children();
+            #children
+            #writer.end_children();
+        }
+    };
+    Ok(quote! {
+        #start
+        #tag
+        #args
+        #props
+        #children
+        #writer.end_node();
+    })
+}
+
+fn emit_struct(s: &Struct) -> syn::Result<TokenStream> {
+    let s_name = &s.ident;
+    let name = syn::Ident::new("name", Span::mixed_site());
+    let writer = syn::Ident::new("writer", Span::mixed_site());
+    let mut generics = s.generics.clone();
+    generics.make_where_clause().predicates
+        .extend(bounds::encode_struct(s));
+    let (impl_gen, type_gen, bounds) = generics.split_for_impl();
+    let default_name = default_name(&s.ident, &s.trait_props);
+    let bindings = bindings(s);
+    let node = encode_node(s, quote!(#name.unwrap_or(#default_name)),
+                           &writer)?;
+    let mut extra_traits = Vec::new();
+    if !s.has_arguments && !s.has_properties &&
+        s.spans.is_empty() && s.node_names.is_empty() &&
+        s.type_names.is_empty() && s.doc_comments.is_empty()
+    {
+        let children = encode_children(s, &writer)?;
+        extra_traits.push(quote! {
+            impl #impl_gen ::knuffel::traits::EncodeChildren
+                for #s_name #type_gen
+                #bounds
+            {
+                fn encode_children(&self,
+                    #writer: &mut ::knuffel::encode::Writer)
+                {
+                    let Self #bindings = self;
+                    #children
+                }
+            }
+        });
+    }
+    Ok(quote! {
+        #(#extra_traits)*
+        impl #impl_gen ::knuffel::Encode for #s_name #type_gen
+            #bounds
+        {
+            fn encode_node(&self, #name: Option<&str>,
+                           #writer: &mut ::knuffel::encode::Writer)
+            {
+                let Self #bindings = self;
+                #node
+            }
+        }
+    })
+}
+
+fn emit_new_type(s: &NewType) -> syn::Result<TokenStream> {
+    let s_name = &s.ident;
+    let name = syn::Ident::new("name", Span::mixed_site());
+    let writer = syn::Ident::new("writer", Span::mixed_site());
+    let (impl_gen, type_gen, bounds) = s.generics.split_for_impl();
+    let default_name = default_name(&s.ident, &s.trait_props);
+    let body = if s.option {
+        quote! {
+            match &self.0 {
+                Some(value) => {
+                    ::knuffel::traits::Encode::encode_node(
+                        value, #name, #writer);
+                }
+                None => {
+                    #writer.start_node(#name.unwrap_or(#default_name));
+                    #writer.end_node();
+                }
+            }
+        }
+    } else {
+        quote! {
+            ::knuffel::traits::Encode::encode_node(&self.0, #name, #writer);
+        }
+    };
+    Ok(quote! {
+        impl #impl_gen ::knuffel::Encode for #s_name #type_gen
+            #bounds
+        {
+            fn encode_node(&self, #name: Option<&str>,
+                           #writer: &mut ::knuffel::encode::Writer)
+            {
+                #body
+            }
+        }
+    })
+}
+
+fn emit_enum(e: &Enum) -> syn::Result<TokenStream> {
+    let e_name = &e.ident;
+    let name = syn::Ident::new("name", Span::mixed_site());
+    let writer = syn::Ident::new("writer", Span::mixed_site());
+    let mut generics = e.generics.clone();
+    generics.make_where_clause().predicates
+        .extend(bounds::encode_enum(e));
+    let (impl_gen, type_gen, bounds) = generics.split_for_impl();
+    let default_name = default_name(&e.ident, &e.trait_props);
+    let mut branches = Vec::with_capacity(e.variants.len());
+    for var in &e.variants {
+        let var_ident = &var.ident;
+        let var_name = &var.name;
+        // with a `tag` the node name is chosen by the parent and the variant
+        // is written as a property
+        let (node_name, tag) = match &e.tag {
+            Some(tag) => (
+                quote!(#name.unwrap_or(#default_name)),
+                quote!(#writer.property(#tag,
+                    &::knuffel::ast::Literal::String(#var_name.into()));),
+            ),
+            None => (quote!(#var_name), quote!()),
+        };
+        match &var.kind {
+            VariantKind::Unit => {
+                branches.push(quote! {
+                    #e_name::#var_ident => {
+                        #writer.start_node(#node_name);
+                        #tag
+                        #writer.end_node();
+                    }
+                });
+            }
+            VariantKind::Nested { .. } if e.tag.is_some() => {
+                return Err(syn::Error::new(var_ident.span(),
+                    "`tag` with single-field tuple variants is not \
+                     supported by `Encode`"));
+            }
+            VariantKind::Nested { option: false, .. } => {
+                branches.push(quote! {
+                    #e_name::#var_ident(value) => {
+                        ::knuffel::traits::Encode::encode_node(
+                            value, Some(#var_name), #writer);
+                    }
+                });
+            }
+            VariantKind::Nested { option: true, .. } => {
+                branches.push(quote! {
+                    #e_name::#var_ident(Some(value)) => {
+                        ::knuffel::traits::Encode::encode_node(
+                            value, Some(#var_name), #writer);
+                    }
+                    #e_name::#var_ident(None) => {
+                        #writer.start_node(#var_name);
+                        #writer.end_node();
+                    }
+                });
+            }
+            VariantKind::Tuple(s) | VariantKind::Named(s) => {
+                let bindings = bindings(s);
+                let node = encode_tagged_node(s, node_name, tag, &writer)?;
+                branches.push(quote! {
+                    #e_name::#var_ident #bindings => { #node }
+                });
+            }
+        }
+    }
+    Ok(quote! {
+        impl #impl_gen ::knuffel::Encode for #e_name #type_gen
+            #bounds
+        {
+            fn encode_node(&self, #name: Option<&str>,
+                           #writer: &mut ::knuffel::encode::Writer)
+            {
+                match self {
+                    #(#branches)*
+                    // skipped variants are never decoded, so write nothing
+                    #[allow(unreachable_patterns)]
+                    _ => {}
+                }
+            }
+        }
+    })
+}
+
+pub fn emit_encoder(def: &Definition) -> syn::Result<TokenStream> {
+    match def {
+        Definition::Struct(s) | Definition::TupleStruct(s)
+        | Definition::UnitStruct(s) => emit_struct(s),
+        Definition::NewType(s) => emit_new_type(s),
+        Definition::Enum(e) => emit_enum(e),
+    }
+}
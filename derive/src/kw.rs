@@ -1,17 +1,55 @@
+syn::custom_keyword!(alias);
 syn::custom_keyword!(argument);
 syn::custom_keyword!(arguments);
+syn::custom_keyword!(at_most_one_of);
+syn::custom_keyword!(base64_url);
+syn::custom_keyword!(bool);
+syn::custom_keyword!(bool_words);
 syn::custom_keyword!(bytes);
+syn::custom_keyword!(case_insensitive);
 syn::custom_keyword!(child);
 syn::custom_keyword!(children);
+syn::custom_keyword!(clamp);
+syn::custom_keyword!(dedup_by_name);
 syn::custom_keyword!(default);
+syn::custom_keyword!(doc_comment);
+syn::custom_keyword!(duration);
+syn::custom_keyword!(expand_tilde);
+syn::custom_keyword!(expose_name);
+syn::custom_keyword!(fail_fast);
 syn::custom_keyword!(flatten);
+syn::custom_keyword!(from_context);
+syn::custom_keyword!(from_u32);
+syn::custom_keyword!(kind);
+syn::custom_keyword!(merge);
 syn::custom_keyword!(name);
+syn::custom_keyword!(node_key);
 syn::custom_keyword!(node_name);
+syn::custom_keyword!(non_empty_document);
+syn::custom_keyword!(none_if);
+syn::custom_keyword!(normalize);
+syn::custom_keyword!(one_of);
+syn::custom_keyword!(ordered_by);
+syn::custom_keyword!(path);
+syn::custom_keyword!(percent);
 syn::custom_keyword!(properties);
 syn::custom_keyword!(property);
+syn::custom_keyword!(property_or_child);
+syn::custom_keyword!(raw_fallback);
+syn::custom_keyword!(rename);
+syn::custom_keyword!(rename_all);
+syn::custom_keyword!(serde);
 syn::custom_keyword!(skip);
 syn::custom_keyword!(span);
+syn::custom_keyword!(span_bytes);
 syn::custom_keyword!(span_type);
+syn::custom_keyword!(split_flags);
 syn::custom_keyword!(str);
+syn::custom_keyword!(strict_document);
+syn::custom_keyword!(tag);
 syn::custom_keyword!(type_name);
+syn::custom_keyword!(type_of);
+syn::custom_keyword!(unit);
+syn::custom_keyword!(units);
 syn::custom_keyword!(unwrap);
+syn::custom_keyword!(value);
@@ -1,22 +1,68 @@
+#![forbid(unsafe_code)]
+// lints that disagree with the style of the existing code
+#![allow(clippy::needless_return, clippy::wrong_self_convention)]
+#![allow(clippy::mem_replace_with_default, clippy::needless_borrow)]
+
 use proc_macro2::TokenStream;
+use quote::quote;
+use syn::ext::IdentExt;
 
+mod bounds;
 mod definition;
+mod encode;
 mod kw;
 mod node;
 mod scalar;
+mod serde;
 mod variants;
 
-use definition::Definition;
+use definition::{Definition, TraitProps};
 use scalar::{Scalar, emit_scalar};
 
 
 fn emit_decoder(def: &Definition) -> syn::Result<TokenStream> {
+    let mut stream = match def {
+        Definition::Struct(s) => node::emit_struct(s, true)?,
+        Definition::NewType(s) => node::emit_new_type(s)?,
+        Definition::TupleStruct(s) => node::emit_struct(s, false)?,
+        Definition::UnitStruct(s) => node::emit_struct(s, true)?,
+        Definition::Enum(e) => variants::emit_enum(e)?,
+    };
     match def {
-        Definition::Struct(s) => node::emit_struct(s, true),
-        Definition::NewType(s) => node::emit_new_type(s),
-        Definition::TupleStruct(s) => node::emit_struct(s, false),
-        Definition::UnitStruct(s) => node::emit_struct(s, true),
-        Definition::Enum(e) => variants::emit_enum(e),
+        Definition::Struct(s) | Definition::UnitStruct(s)
+        if s.trait_props.serde => {
+            stream.extend(serde::emit_serde(s, true)?);
+        }
+        Definition::TupleStruct(s) if s.trait_props.serde => {
+            stream.extend(serde::emit_serde(s, false)?);
+        }
+        _ => {}
+    }
+    let (ident, generics, trait_props) = match def {
+        Definition::Struct(s) | Definition::UnitStruct(s)
+        | Definition::TupleStruct(s) => (&s.ident, &s.generics, &s.trait_props),
+        Definition::NewType(s) => (&s.ident, &s.generics, &s.trait_props),
+        Definition::Enum(e) => (&e.ident, &e.generics, &e.trait_props),
+    };
+    if trait_props.expose_name {
+        stream.extend(emit_node_name(ident, generics, trait_props));
+    }
+    Ok(stream)
+}
+
+fn emit_node_name(ident: &syn::Ident, generics: &syn::Generics,
+                  trait_props: &TraitProps)
+    -> TokenStream
+{
+    let name = trait_props.node_name.clone().unwrap_or_else(|| {
+        heck::ToKebabCase::to_kebab_case(&ident.unraw().to_string()[..])
+    });
+    let (impl_gen, type_gen, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_gen #ident #type_gen #where_clause {
+            /// Name of the node this type is expected to be decoded from
+            pub const NODE_NAME: &'static str = #name;
+        }
     }
 }
 
@@ -45,3 +91,29 @@ pub fn decode_scalar_derive(input: proc_macro::TokenStream)
         Err(e) => e.to_compile_error().into(),
     }
 }
+
+#[proc_macro_error::proc_macro_error]
+#[proc_macro_derive(Encode, attributes(knuffel))]
+#[doc = include_str!("../derive_encode.md")]
+pub fn encode_derive(input: proc_macro::TokenStream)
+    -> proc_macro::TokenStream
+{
+    let item = syn::parse_macro_input!(input as Definition);
+    match encode::emit_encoder(&item) {
+        Ok(stream) => stream.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_error::proc_macro_error]
+#[proc_macro_derive(EncodeScalar, attributes(knuffel))]
+#[doc = include_str!("../derive_encode_scalar.md")]
+pub fn encode_scalar_derive(input: proc_macro::TokenStream)
+    -> proc_macro::TokenStream
+{
+    let item = syn::parse_macro_input!(input as Scalar);
+    match scalar::emit_encode_scalar(&item) {
+        Ok(stream) => stream.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
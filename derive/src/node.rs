@@ -2,8 +2,12 @@ use proc_macro2::{TokenStream, Span};
 use quote::{format_ident, quote, ToTokens};
 use syn::ext::IdentExt;
 
+use crate::bounds;
 use crate::definition::{Struct, StructBuilder, ArgKind, FieldAttrs, DecodeMode};
 use crate::definition::{Child, Field, NewType, ExtraKind, ChildMode};
+use crate::definition::{AttrAccess, Clamp, Dedup, TypeOfField, VarChildren};
+use crate::definition::LiteralKind;
+use crate::definition::{item_type, type_params};
 
 
 pub(crate) struct Common<'a> {
@@ -15,7 +19,12 @@ pub(crate) struct Common<'a> {
 fn child_can_partial(child: &Child) -> bool {
     use ChildMode::*;
 
-    child.option || matches!(child.mode, Bool | Flatten)
+    !child.or_property && (child.option || matches!(child.mode, Bool | Flatten))
+}
+
+/// Name of the variable holding the property of `property_or_child` field
+fn or_property_var(child: &Child) -> syn::Ident {
+    format_ident!("prop_{}", child.field.tmp_name, span = Span::mixed_site())
 }
 
 pub fn emit_struct(s: &Struct, named: bool) -> syn::Result<TokenStream> {
@@ -30,15 +39,11 @@ pub fn emit_struct(s: &Struct, named: bool) -> syn::Result<TokenStream> {
     if let Some(ty) = s.trait_props.span_type.as_ref() {
         span_ty = quote!(#ty);
     } else {
-        if common_generics.params.is_empty() {
-            common_generics.lt_token = Some(Default::default());
-            common_generics.gt_token = Some(Default::default());
-        }
-        common_generics.params.push(syn::parse2(quote!(S)).unwrap());
+        bounds::add_span_param(&mut common_generics);
         span_ty = quote!(S);
-        common_generics.make_where_clause().predicates.push(
-            syn::parse2(quote!(S: ::knuffel::traits::ErrorSpan)).unwrap());
     };
+    common_generics.make_where_clause().predicates
+        .extend(bounds::decode_struct(s, &span_ty));
     let trait_gen = quote!(<#span_ty>);
     let (impl_gen, _, bounds) = common_generics.split_for_impl();
 
@@ -53,7 +58,9 @@ pub fn emit_struct(s: &Struct, named: bool) -> syn::Result<TokenStream> {
     let decode_props = decode_props(&common, &node)?;
     let decode_children_normal = decode_children(
         &common, &children, Some(quote!(#node.span())))?;
-    let assign_extra = assign_extra(&common)?;
+    let check_exclusive_normal = check_exclusive(
+        &common, Some(&node), &children)?;
+    let assign_extra_normal = assign_extra(&common, Some(&node))?;
 
     let all_fields = s.all_fields();
     let struct_val = if named {
@@ -71,16 +78,23 @@ pub fn emit_struct(s: &Struct, named: bool) -> syn::Result<TokenStream> {
         let assignments = fields.iter().map(|(_, v)| v);
         quote!{ #s_name(#(#assignments),*) }
     };
+    let finish = finish_struct(s, struct_val);
     let mut extra_traits = Vec::new();
     let partial_compatible = s.spans.is_empty() &&
         s.node_names.is_empty() &&
         s.type_names.is_empty() &&
+        s.type_ofs.is_empty() &&
+        s.doc_comments.is_empty() &&
+        s.trait_props.exclusive.is_empty() &&
+        s.extra_fields.iter()
+            .all(|f| matches!(f.kind, ExtraKind::Auto | ExtraKind::Skip)) &&
         !s.has_arguments && (
             s.properties.iter().all(|x| x.option || x.flatten) &&
             s.var_props.is_none()
         ) && (
             s.children.iter().all(child_can_partial) &&
-            s.var_children.is_none()
+            s.var_children.is_none() &&
+            s.typed_children.is_empty()
         );
     if partial_compatible {
         let node = syn::Ident::new("node", Span::mixed_site());
@@ -112,9 +126,24 @@ pub fn emit_struct(s: &Struct, named: bool) -> syn::Result<TokenStream> {
         });
     }
     if !s.has_arguments && !s.has_properties &&
-        s.spans.is_empty() && s.node_names.is_empty() && s.type_names.is_empty()
+        s.spans.is_empty() && s.node_names.is_empty() &&
+        s.type_names.is_empty() && s.doc_comments.is_empty()
     {
         let decode_children = decode_children(&common, &children, None)?;
+        let check_exclusive = check_exclusive(&common, None, &children)?;
+        let assign_extra = assign_extra(&common, None)?;
+        let check_empty = if s.trait_props.non_empty_document {
+            quote! {
+                if #children.is_empty() {
+                    return Err(::knuffel::errors::DecodeError::MissingNode {
+                        message: "document is empty; \
+                                  expected at least one node".into(),
+                    });
+                }
+            }
+        } else {
+            quote!()
+        };
         extra_traits.push(quote! {
             impl #impl_gen ::knuffel::traits::DecodeChildren #trait_gen
                 for #s_name #type_gen
@@ -125,12 +154,20 @@ pub fn emit_struct(s: &Struct, named: bool) -> syn::Result<TokenStream> {
                     #ctx: &mut ::knuffel::decode::Context<#span_ty>)
                     -> Result<Self, ::knuffel::errors::DecodeError<#span_ty>>
                 {
+                    #check_empty
                     #decode_children
+                    #check_exclusive
                     #assign_extra
-                    Ok(#struct_val)
+                    #finish
                 }
             }
         });
+    } else if s.trait_props.non_empty_document ||
+        s.trait_props.strict_document
+    {
+        return Err(syn::Error::new(s.ident.span(),
+            "`non_empty_document` and `strict_document` require a structure \
+             that can be decoded as a document, i.e. having only children"));
     }
     Ok(quote! {
         #(#extra_traits)*
@@ -147,12 +184,47 @@ pub fn emit_struct(s: &Struct, named: bool) -> syn::Result<TokenStream> {
                 let #children = #node.children.as_ref()
                     .map(|lst| &lst[..]).unwrap_or(&[]);
                 #decode_children_normal
-                #assign_extra
-                Ok(#struct_val)
+                #check_exclusive_normal
+                #assign_extra_normal
+                #finish
             }
         }
     })
 }
+/// Builds the structure, then folds `merge` children into it
+fn finish_struct(s: &Struct, struct_val: TokenStream) -> TokenStream {
+    let merges = s.children.iter()
+        .filter_map(|c| c.merge.as_ref().map(|path| (c, path)))
+        .collect::<Vec<_>>();
+    if merges.is_empty() {
+        return quote!(Ok(#struct_val));
+    }
+    let result = syn::Ident::new("result", Span::mixed_site());
+    let merged = syn::Ident::new("merged", Span::mixed_site());
+    let item = syn::Ident::new("item", Span::mixed_site());
+    let apply = merges.iter().map(|(child, path)| {
+        let access = match &child.field.attr {
+            AttrAccess::Indexed(idx) => {
+                let idx = syn::Index::from(*idx);
+                quote!(#result.#idx)
+            }
+            AttrAccess::Named(name) => quote!(#result.#name),
+        };
+        quote! {
+            let #merged = ::std::mem::take(&mut #access);
+            for #item in &#merged {
+                #path(&mut #result, #item);
+            }
+            #access = #merged;
+        }
+    });
+    quote! {
+        let mut #result = #struct_val;
+        #(#apply)*
+        Ok(#result)
+    }
+}
+
 pub fn emit_new_type(s: &NewType) -> syn::Result<TokenStream> {
     let s_name = &s.ident;
     let node = syn::Ident::new("node", Span::mixed_site());
@@ -166,7 +238,7 @@ pub fn emit_new_type(s: &NewType) -> syn::Result<TokenStream> {
                 -> Result<Self, ::knuffel::errors::DecodeError<S>>
             {
                 if #node.arguments.len() > 0 ||
-                    #node.properties.len() > 0 ||
+                    #node.properties.keys().any(|k| !#ctx.is_consumed(k)) ||
                     #node.children.is_some()
                 {
                     ::knuffel::Decode::decode_node(#node, #ctx)
@@ -189,7 +261,7 @@ pub(crate) fn decode_enum_item(s: &Common,
     let decode_props = decode_props(s, node)?;
     let decode_children = decode_children(s, &children,
                                           Some(quote!(#node.span())))?;
-    let assign_extra = assign_extra(s)?;
+    let assign_extra = assign_extra(s, Some(node))?;
     let all_fields = s.object.all_fields();
     let struct_val = if named {
         let assignments = all_fields.iter()
@@ -217,6 +289,66 @@ pub(crate) fn decode_enum_item(s: &Common,
     })
 }
 
+/// Path of the runtime function decoding `bytes` values
+fn bytes_fn(url_safe: bool) -> TokenStream {
+    if url_safe {
+        quote!(::knuffel::decode::bytes_url_safe)
+    } else {
+        quote!(::knuffel::decode::bytes)
+    }
+}
+
+/// Decodes string scalar using `decode` expression
+///
+/// The `decode` is evaluated with `value: &str` in scope and returns
+/// `Result<T, E>` where the error is converted into a conversion error.
+fn decode_string(val: &syn::Ident, ctx: &syn::Ident, optional: bool,
+                 rust_type: &str, value: &syn::Ident, decode: TokenStream)
+    -> TokenStream
+{
+    let (ok, null, bad_kind) = if optional {
+        (quote!(.map(Some)), quote! {
+            ::knuffel::ast::Literal::Null => Ok(None),
+        }, quote! {
+            #ctx.emit_error(
+                ::knuffel::errors::DecodeError::scalar_kind(
+                    ::knuffel::decode::Kind::String,
+                    &#val.literal,
+                )
+            );
+            Ok(None)
+        })
+    } else {
+        (quote!(), quote!(), quote! {
+            Err(::knuffel::errors::DecodeError::scalar_kind(
+                ::knuffel::decode::Kind::String,
+                &#val.literal,
+            ))
+        })
+    };
+    quote![{
+        if let Some(typ) = &#val.type_name {
+            #ctx.emit_error(::knuffel::errors::DecodeError::TypeName {
+                span: typ.span().clone(),
+                found: Some((**typ).clone()),
+                expected: ::knuffel::errors::ExpectedType::no_type(),
+                rust_type: #rust_type,
+            });
+        }
+        match *#val.literal {
+            ::knuffel::ast::Literal::String(ref s) => {
+                let #value: &str = s;
+                (#decode).map_err(|e| {
+                    ::knuffel::errors::DecodeError::conversion(
+                        &#val.literal, e)
+                })#ok
+            }
+            #null
+            _ => { #bad_kind }
+        }
+    }]
+}
+
 fn decode_value(val: &syn::Ident, ctx: &syn::Ident, mode: &DecodeMode,
                 optional: bool)
     -> syn::Result<TokenStream>
@@ -282,12 +414,13 @@ fn decode_value(val: &syn::Ident, ctx: &syn::Ident, mode: &DecodeMode,
                 }
             }])
         }
-        DecodeMode::Bytes if optional => {
+        DecodeMode::Bytes { url_safe } if optional => {
+            let bytes = bytes_fn(*url_safe);
             Ok(quote! {
                 if matches!(&*#val.literal, ::knuffel::ast::Literal::Null) {
                     Ok(None)
                 } else {
-                    match ::knuffel::decode::bytes(#val, #ctx).try_into() {
+                    match #bytes(#val, #ctx).try_into() {
                         Ok(v) => Ok(Some(v)),
                         Err(e) => {
                             #ctx.emit_error(
@@ -299,13 +432,270 @@ fn decode_value(val: &syn::Ident, ctx: &syn::Ident, mode: &DecodeMode,
                 }
             })
         }
-        DecodeMode::Bytes => {
+        DecodeMode::Bytes { url_safe } => {
+            let bytes = bytes_fn(*url_safe);
             Ok(quote! {
-                ::knuffel::decode::bytes(#val, #ctx).try_into()
+                #bytes(#val, #ctx).try_into()
                 .map_err(|e| ::knuffel::errors::DecodeError::conversion(
                         &#val.literal, e))
             })
         }
+        DecodeMode::Try(fns) => {
+            let value = syn::Ident::new("value", Span::mixed_site());
+            let chain = DecodeMode::try_chain(fns, &value);
+            Ok(decode_string(val, ctx, optional, "str", &value, chain))
+        }
+        DecodeMode::Units(table) => {
+            let value = syn::Ident::new("value", Span::mixed_site());
+            Ok(decode_string(val, ctx, optional, "f64", &value, quote! {
+                ::knuffel::decode::parse_units(#value, #table)
+            }))
+        }
+        DecodeMode::Percent(range) => {
+            let range = match range.as_deref() {
+                Some(Clamp { min, max }) => {
+                    quote!(Some((#min) as f64..=(#max) as f64))
+                }
+                None => quote!(None),
+            };
+            if optional {
+                Ok(quote! {
+                    if matches!(&*#val.literal, ::knuffel::ast::Literal::Null) {
+                        Ok(None)
+                    } else {
+                        ::knuffel::decode::percent(#val, #ctx, #range).map(Some)
+                    }
+                })
+            } else {
+                Ok(quote! {
+                    ::knuffel::decode::percent(#val, #ctx, #range)
+                })
+            }
+        }
+        DecodeMode::Duration(unit) => {
+            if optional {
+                Ok(quote! {
+                    if matches!(&*#val.literal, ::knuffel::ast::Literal::Null) {
+                        Ok(None)
+                    } else {
+                        ::knuffel::__decode_duration!(#val, #ctx, #unit)
+                            .map(Some)
+                    }
+                })
+            } else {
+                Ok(quote! {
+                    ::knuffel::__decode_duration!(#val, #ctx, #unit)
+                })
+            }
+        }
+        DecodeMode::Path { expand_tilde } => {
+            if optional {
+                Ok(quote! {
+                    if matches!(&*#val.literal, ::knuffel::ast::Literal::Null) {
+                        Ok(None)
+                    } else {
+                        ::knuffel::decode::path(#val, #ctx, #expand_tilde)
+                            .map(Some)
+                    }
+                })
+            } else {
+                Ok(quote! {
+                    ::knuffel::decode::path(#val, #ctx, #expand_tilde)
+                })
+            }
+        }
+        DecodeMode::BoolWords if optional => {
+            Ok(quote! {
+                if matches!(&*#val.literal, ::knuffel::ast::Literal::Null) {
+                    Ok(None)
+                } else {
+                    ::knuffel::decode::bool_words(#val, #ctx).map(Some)
+                }
+            })
+        }
+        DecodeMode::BoolWords => {
+            Ok(quote!(::knuffel::decode::bool_words(#val, #ctx)))
+        }
+        DecodeMode::SplitFlags if optional => {
+            Ok(quote! {
+                if matches!(&*#val.literal, ::knuffel::ast::Literal::Null) {
+                    Ok(None)
+                } else {
+                    Ok(Some(::knuffel::decode::split_flags(#val, #ctx)))
+                }
+            })
+        }
+        DecodeMode::SplitFlags => {
+            Ok(quote! {
+                Ok(::knuffel::decode::split_flags(#val, #ctx))
+            })
+        }
+        DecodeMode::FromU32 if optional => {
+            Ok(quote! {
+                <Option<u32> as ::knuffel::traits::DecodeScalar<_>>
+                    ::decode(#val, #ctx)
+                    .map(|v| v.map(::std::convert::From::from))
+            })
+        }
+        DecodeMode::FromU32 => {
+            Ok(quote! {
+                <u32 as ::knuffel::traits::DecodeScalar<_>>
+                    ::decode(#val, #ctx)
+                    .map(::std::convert::From::from)
+            })
+        }
+    }
+}
+
+fn clamp_value(decode_value: TokenStream, field: &Field,
+               clamp: &Option<Clamp>, optional: bool)
+    -> TokenStream
+{
+    if let Some(clamp) = clamp {
+        let value = syn::Ident::new("value", Span::mixed_site());
+        let ty = &field.ty;
+        let clamped = clamp.apply(&value, optional);
+        quote!((#decode_value).map(|#value: #ty| #clamped))
+    } else {
+        decode_value
+    }
+}
+
+/// Checks literal kind before decoding, so that the error is about the kind
+/// rather than the field type
+fn check_kind(decode_value: TokenStream, val: &syn::Ident,
+              kind: &Option<LiteralKind>, optional: bool)
+    -> TokenStream
+{
+    if let Some(kind) = kind {
+        let pattern = kind.pattern();
+        let null = if optional {
+            Some(quote!(| ::knuffel::ast::Literal::Null))
+        } else {
+            None
+        };
+        let expected = kind.kind();
+        quote!({
+            if matches!(*#val.literal, #pattern #null) {
+                #decode_value
+            } else {
+                Err(::knuffel::errors::DecodeError::scalar_kind(
+                    #expected, &#val.literal))
+            }
+        })
+    } else {
+        decode_value
+    }
+}
+
+/// Decodes the `none_if` string as `None` without decoding it as a value
+fn check_none_if(decode_value: TokenStream, val: &syn::Ident,
+                 none_if: &Option<String>)
+    -> TokenStream
+{
+    if let Some(sentinel) = none_if {
+        let s = syn::Ident::new("s", Span::mixed_site());
+        quote!({
+            if matches!(&*#val.literal,
+                ::knuffel::ast::Literal::String(#s) if &#s[..] == #sentinel)
+            {
+                Ok(None)
+            } else {
+                #decode_value
+            }
+        })
+    } else {
+        decode_value
+    }
+}
+
+/// Requires the `type_name="..."` annotation on the value, then decodes it
+/// without the annotation
+fn check_type_name(decode_value: TokenStream, val: &syn::Ident,
+                   ctx: &syn::Ident, field: &Field, optional: bool,
+                   type_name: &Option<String>)
+    -> TokenStream
+{
+    if let Some(name) = type_name {
+        let ty = if optional {
+            type_params(&field.ty).first().copied().unwrap_or(&field.ty)
+        } else {
+            &field.ty
+        };
+        let typ = syn::Ident::new("typ", Span::mixed_site());
+        let decode_value = strip_type_name(val, decode_value);
+        let null = if optional {
+            Some(quote! {
+                None if matches!(*#val.literal, ::knuffel::ast::Literal::Null)
+                => {}
+            })
+        } else {
+            None
+        };
+        quote!({
+            match &#val.type_name {
+                Some(#typ) if #typ.as_str() == #name => {}
+                #null
+                #typ => {
+                    #ctx.emit_error(::knuffel::errors::DecodeError::TypeName {
+                        span: #typ.as_ref().map(|t| t.span())
+                            .unwrap_or(#val.literal.span()).clone(),
+                        found: #typ.as_ref().map(|t| (**t).clone()),
+                        expected: ::knuffel::errors::ExpectedType::required(
+                            <::knuffel::ast::TypeName as ::std::str::FromStr>
+                            ::from_str(#name).unwrap_or_else(|e| match e {})),
+                        rust_type: stringify!(#ty),
+                    });
+                }
+            }
+            #decode_value
+        })
+    } else {
+        decode_value
+    }
+}
+
+/// Decodes the value as if it had no type annotation, as the annotation
+/// belongs to the `type_of` field
+fn strip_type_name(val: &syn::Ident, decode_value: TokenStream)
+    -> TokenStream
+{
+    quote!({
+        let #val = &::knuffel::ast::Value {
+            type_name: None,
+            literal: #val.literal.clone(),
+        };
+        #decode_value
+    })
+}
+
+fn decode_type_of(type_of: &TypeOfField, ctx: &syn::Ident,
+                  node: &syn::Ident, value: TokenStream)
+    -> TokenStream
+{
+    let fld = &type_of.field.tmp_name;
+    let typ = syn::Ident::new("typ", Span::mixed_site());
+    let decode = quote! {
+        match #value.and_then(|v| v.type_name.as_ref()) {
+            Some(#typ) => Some(::knuffel::traits::DecodeScalar::raw_decode(
+                &#typ.clone().map(|t| {
+                    ::knuffel::ast::Literal::String(t.as_str().into())
+                }),
+                #ctx,
+            )?),
+            None => None,
+        }
+    };
+    if type_of.option {
+        quote!(let #fld = #decode;)
+    } else {
+        let error = format!("type annotation of `{}` is required",
+                            type_of.target.unraw());
+        quote! {
+            let #fld = #decode.ok_or_else(|| {
+                ::knuffel::errors::DecodeError::missing(#node, #error)
+            })?;
+        }
     }
 }
 
@@ -315,11 +705,17 @@ fn decode_specials(s: &Common, node: &syn::Ident)
     let ctx = s.ctx;
     let spans = s.object.spans.iter().flat_map(|span| {
         let fld = &span.field.tmp_name;
-        quote! {
-            let #fld = ::knuffel::traits::DecodeSpan::decode_span(
-                #node.span(),
-                #ctx,
-            );
+        if span.bytes {
+            quote! {
+                let #fld = ::knuffel::decode::span_bytes(#node.span());
+            }
+        } else {
+            quote! {
+                let #fld = ::knuffel::traits::DecodeSpan::decode_span(
+                    #node.span(),
+                    #ctx,
+                );
+            }
         }
     });
     let node_names = s.object.node_names.iter().flat_map(|node_name| {
@@ -332,11 +728,26 @@ fn decode_specials(s: &Common, node: &syn::Ident)
                 })?;
         }
     });
+    let doc_comments = s.object.doc_comments.iter().map(|doc_comment| {
+        let fld = &doc_comment.field.tmp_name;
+        if doc_comment.option {
+            quote! {
+                let #fld = ::knuffel::decode::leading_comments(#node);
+            }
+        } else {
+            quote! {
+                let #fld = ::knuffel::decode::leading_comments(#node)
+                    .unwrap_or_default();
+            }
+        }
+    });
     let type_names = s.object.type_names.iter().flat_map(|type_name| {
         let fld = &type_name.field.tmp_name;
         if type_name.option {
             quote! {
-                let #fld = #node.type_name.as_ref().map(|tn| {
+                let #fld = #node.type_name.as_ref()
+                    .filter(|tn| !#ctx.is_consumed(tn))
+                    .map(|tn| {
                     tn.as_str()
                         .parse()
                         .map_err(|e| {
@@ -346,7 +757,9 @@ fn decode_specials(s: &Common, node: &syn::Ident)
             }
         } else {
             quote! {
-                let #fld = if let Some(tn) = #node.type_name.as_ref() {
+                let #fld = if let Some(tn) = #node.type_name.as_ref()
+                    .filter(|tn| !#ctx.is_consumed(tn))
+                {
                     tn.as_str()
                         .parse()
                         .map_err(|e| {
@@ -361,7 +774,9 @@ fn decode_specials(s: &Common, node: &syn::Ident)
     });
     let validate_type = if s.object.type_names.is_empty() {
         Some(quote! {
-            if let Some(type_name) = &#node.type_name {
+            if let Some(type_name) = #node.type_name.as_ref()
+                .filter(|t| !#ctx.is_consumed(t))
+            {
                 #ctx.emit_error(::knuffel::errors::DecodeError::unexpected(
                             type_name, "type name",
                             "no type name expected for this node"));
@@ -373,6 +788,7 @@ fn decode_specials(s: &Common, node: &syn::Ident)
     Ok(quote! {
         #(#spans)*
         #(#node_names)*
+        #(#doc_comments)*
         #(#type_names)*
         #validate_type
     })
@@ -380,16 +796,41 @@ fn decode_specials(s: &Common, node: &syn::Ident)
 
 fn decode_args(s: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
     let ctx = s.ctx;
+    if !s.object.has_arguments {
+        // every stray argument is reported, decoding of children goes on
+        let val = syn::Ident::new("val", Span::mixed_site());
+        return Ok(quote! {
+            for #val in &#node.arguments {
+                #ctx.emit_error(
+                    ::knuffel::decode::stray_argument(#node, #val));
+            }
+        });
+    }
     let mut decoder = Vec::new();
     let iter_args = syn::Ident::new("iter_args", Span::mixed_site());
     decoder.push(quote! {
         let mut #iter_args = #node.arguments.iter();
     });
-    for arg in &s.object.arguments {
+    let mut type_ofs = Vec::new();
+    for (idx, arg) in s.object.arguments.iter().enumerate() {
         let fld = &arg.field.tmp_name;
         let val = syn::Ident::new("val", Span::mixed_site());
         let decode_value = decode_value(&val, ctx, &arg.decode,
                                         arg.option)?;
+        let decode_value = clamp_value(decode_value, &arg.field,
+                                       &arg.clamp, arg.option);
+        let decode_value = check_kind(decode_value, &val,
+                                      &arg.literal_kind, arg.option);
+        let decode_value = check_none_if(decode_value, &val, &arg.none_if);
+        let decode_value = check_type_name(decode_value, &val, ctx,
+            &arg.field, arg.option, &arg.type_name);
+        let decode_value = if let Some(type_of) = s.object.type_of(&arg.field) {
+            type_ofs.push(decode_type_of(type_of, ctx, node,
+                                         quote!(#node.arguments.get(#idx))));
+            strip_type_name(&val, decode_value)
+        } else {
+            decode_value
+        };
         match (&arg.default, &arg.kind) {
             (None, ArgKind::Value { option: true }) => {
                 decoder.push(quote! {
@@ -399,11 +840,22 @@ fn decode_args(s: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
                 });
             }
             (None, ArgKind::Value { option: false }) => {
-                let error = if arg.field.is_indexed() {
-                    "additional argument is required".into()
+                let name = if arg.field.is_indexed() {
+                    quote!(None)
                 } else {
-                    format!("additional argument `{}` is required", fld.unraw())
+                    let name = fld.unraw().to_string();
+                    quote!(Some(#name))
                 };
+                decoder.push(quote! {
+                    let #val =
+                        #iter_args.next().ok_or_else(|| {
+                            ::knuffel::decode::missing_argument(#node, #name)
+                        })?;
+                    let #fld = #decode_value?;
+                });
+            }
+            (_, ArgKind::Key) => {
+                let error = "node key is required";
                 decoder.push(quote! {
                     let #val =
                         #iter_args.next().ok_or_else(|| {
@@ -429,15 +881,73 @@ fn decode_args(s: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
             }
         }
     }
+    decoder.extend(type_ofs);
     if let Some(var_args) = &s.object.var_args {
         let fld = &var_args.field.tmp_name;
         let val = syn::Ident::new("val", Span::mixed_site());
         let decode_value = decode_value(&val, ctx, &var_args.decode, false)?;
-        decoder.push(quote! {
-            let #fld = #iter_args.map(|#val| {
-                #decode_value
-            }).collect::<Result<_, _>>()?;
-        });
+        let item = var_args.item.as_ref().map(|ty| quote!(::<#ty>));
+        if let Some(len) = &var_args.array_len {
+            let rest = syn::Ident::new("rest", Span::mixed_site());
+            let err = syn::Ident::new("err", Span::mixed_site());
+            decoder.push(quote! {
+                let #rest = #iter_args.as_slice();
+                if #rest.len() < #len {
+                    return Err(::knuffel::errors::DecodeError::missing(
+                        #node, format!("{} arguments are required, found {}",
+                                       #len, #rest.len())));
+                }
+                if let Some(#val) = #rest.get(#len) {
+                    return Err(::knuffel::errors::DecodeError::unexpected(
+                            &#val.literal, "argument",
+                            "unexpected argument"));
+                }
+                // errors are reported for every bad argument, the last one
+                // is returned as there is no value to put in the array
+                let mut #err = None;
+                let #fld = #iter_args.filter_map(|#val| {
+                    match #decode_value {
+                        Ok(v) => Some #item (v),
+                        Err(e) => {
+                            if let Some(prev) = #err.replace(e) {
+                                #ctx.emit_error(prev);
+                            }
+                            None
+                        }
+                    }
+                }).collect::<Vec<_>>();
+                if let Some(e) = #err {
+                    return Err(e);
+                }
+                let #fld = match ::std::convert::TryFrom::try_from(#fld) {
+                    Ok(array) => array,
+                    Err(_) => unreachable!("number of arguments is checked"),
+                };
+            });
+            return Ok(quote! { #(#decoder)* });
+        }
+        // errors are reported for every bad argument, not just the first
+        let values = quote! {
+            #iter_args.filter_map(|#val| {
+                match #decode_value {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        #ctx.emit_error(e);
+                        None
+                    }
+                }
+            })
+        };
+        if let Some(flag) = &var_args.item {
+            decoder.push(quote! {
+                let #fld = ::knuffel::decode::collect_flags::<#flag, _>(
+                    #values);
+            });
+        } else {
+            decoder.push(quote! {
+                let #fld = #values.collect();
+            });
+        }
     } else {
         decoder.push(quote! {
             if let Some(val) = #iter_args.next() {
@@ -450,18 +960,72 @@ fn decode_args(s: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
     Ok(quote! { #(#decoder)* })
 }
 
+/// Names of the properties suggested for the unexpected ones
+///
+/// Includes `property_or_child` fields, which are matched as properties too.
+fn known_properties<'a>(s: &'a Common) -> Vec<&'a str> {
+    s.object.properties.iter()
+        .filter(|p| !p.flatten)
+        .map(|p| &p.name[..])
+        .chain(s.object.children.iter()
+               .filter(|c| c.or_property)
+               .map(|c| &c.name[..]))
+        .collect()
+}
+
+/// Names of the child nodes suggested for the unexpected ones
+///
+/// Includes `children(name=..)` and `property_or_child` fields.
+fn known_children<'a>(s: &'a Common) -> Vec<&'a str> {
+    s.object.children.iter()
+        .filter(|c| !matches!(c.mode, ChildMode::Flatten))
+        .map(|c| &c.name[..])
+        .collect()
+}
+
 fn decode_props(s: &Common, node: &syn::Ident)
     -> syn::Result<TokenStream>
 {
     let mut declare_empty = Vec::new();
     let mut match_branches = Vec::new();
     let mut postprocess = Vec::new();
+    let mut check_unknown = None;
 
     let ctx = s.ctx;
     let val = syn::Ident::new("val", Span::mixed_site());
     let name = syn::Ident::new("name", Span::mixed_site());
     let name_str = syn::Ident::new("name_str", Span::mixed_site());
 
+    let unknown = syn::Ident::new("unknown", Span::mixed_site());
+    let fail_fast = s.object.trait_props.fail_fast;
+
+    if !s.object.has_properties {
+        if fail_fast {
+            return Ok(quote! {
+                let mut #unknown = None;
+                for #name in #node.properties.keys() {
+                    if !#ctx.is_consumed(#name) {
+                        #unknown = Some(::knuffel::decode::first_in_source(
+                            #unknown, #name));
+                    }
+                }
+                if let Some(#name) = #unknown {
+                    return Err(
+                        ::knuffel::decode::stray_property(#node, #name));
+                }
+            });
+        }
+        return Ok(quote! {
+            for #name in #node.properties.keys() {
+                if !#ctx.is_consumed(#name) {
+                    #ctx.emit_error(
+                        ::knuffel::decode::stray_property(#node, #name));
+                }
+            }
+        });
+    }
+
+    let mut flat = Vec::new();
     for prop in &s.object.properties {
         let fld = &prop.field.tmp_name;
         let prop_name = &prop.name;
@@ -470,28 +1034,86 @@ fn decode_props(s: &Common, node: &syn::Ident)
             declare_empty.push(quote! {
                 let mut #fld = ::std::default::Default::default();
             });
-            match_branches.push(quote! {
-                _ if ::knuffel::traits::DecodePartial::
-                    insert_property(&mut #fld, #name, #val, #ctx)?
-                => {}
+            flat.push(quote! {
+                ::knuffel::traits::DecodePartial::
+                    insert_property(&mut #fld, #name, #val, #ctx)
             });
         } else {
             let decode_value = decode_value(&val, ctx, &prop.decode,
                                             prop.option)?;
+            let decode_value = clamp_value(decode_value, &prop.field,
+                                           &prop.clamp, prop.option);
+            let decode_value = check_kind(decode_value, &val,
+                                          &prop.literal_kind, prop.option);
+            let decode_value = check_none_if(decode_value, &val,
+                                             &prop.none_if);
+            let decode_value = check_type_name(decode_value, &val, ctx,
+                &prop.field, prop.option, &prop.type_name);
             declare_empty.push(quote! {
                 let mut #fld = None;
                 let mut #seen_name = false;
             });
+            let (decode_value, remember) = if let Some(type_of) =
+                s.object.type_of(&prop.field)
+            {
+                let prop_val = format_ident!("value_{}", fld,
+                                             span = Span::mixed_site());
+                declare_empty.push(quote! {
+                    let mut #prop_val = None;
+                });
+                postprocess.push(decode_type_of(type_of, ctx, node,
+                                                quote!(#prop_val)));
+                (strip_type_name(&val, decode_value),
+                 quote!(#prop_val = Some(#val);))
+            } else {
+                (decode_value, quote!())
+            };
+            let pattern = prop.name_pattern(&name_str);
+            let unique = quote! {
+                if let Err(e) = ::knuffel::decode::unique_property(
+                    #node, #name, #val)
+                {
+                    #ctx.emit_error(e);
+                }
+            };
+            // names differing in case are distinct properties in KDL, so
+            // they aren't deduplicated by the parser
+            let unique = if prop.case_insensitive {
+                let first = format_ident!("first_{}", fld,
+                                          span = Span::mixed_site());
+                declare_empty.push(quote! {
+                    let mut #first = None;
+                });
+                let dup_err = format!(
+                    "duplicate property `{}`, names differ only in case",
+                    prop_name.escape_default());
+                quote! {
+                    #unique
+                    if let Some(prev) = #first {
+                        #ctx.emit_error(
+                            ::knuffel::errors::DecodeError::duplicate(
+                                #name, prev, "property", #dup_err));
+                    } else {
+                        #first = Some(#name);
+                    }
+                }
+            } else {
+                unique
+            };
             if prop.option {
                 match_branches.push(quote! {
-                    #prop_name => {
+                    #pattern => {
+                        #unique
                         #seen_name = true;
+                        #remember
                         #fld = #decode_value?;
                     }
                 });
             } else {
                 match_branches.push(quote! {
-                    #prop_name => {
+                    #pattern => {
+                        #unique
+                        #remember
                         #fld = Some(#decode_value?);
                     }
                 });
@@ -524,6 +1146,25 @@ fn decode_props(s: &Common, node: &syn::Ident)
             }
         }
     }
+    for child in s.object.children.iter().filter(|c| c.or_property) {
+        // decoded along with the children, to report conflicts there
+        let prop_var = or_property_var(child);
+        let prop_name = &child.name;
+        declare_empty.push(quote! {
+            let mut #prop_var = None;
+        });
+        match_branches.push(quote! {
+            #prop_name => {
+                #prop_var = Some(#val);
+            }
+        });
+    }
+    if !flat.is_empty() {
+        let claim = flatten_claim(flat, quote!(#name), "property");
+        match_branches.push(quote! {
+            _ if #claim? => {}
+        });
+    }
     if let Some(var_props) = &s.object.var_props {
         let fld = &var_props.field.tmp_name;
         let decode_value = decode_value(&val, ctx, &var_props.decode, false)?;
@@ -545,29 +1186,52 @@ fn decode_props(s: &Common, node: &syn::Ident)
         postprocess.push(quote! {
             let #fld = #fld.into_iter().collect();
         });
+    } else if fail_fast {
+        // the first one in the source is reported, properties are sorted
+        // by name in the map
+        let known = known_properties(s);
+        declare_empty.push(quote! {
+            let mut #unknown = None;
+        });
+        match_branches.push(quote! {
+            _ => {
+                #unknown = Some(::knuffel::decode::first_in_source(
+                    #unknown, #name));
+            }
+        });
+        check_unknown = Some(quote! {
+            if let Some(#name) = #unknown {
+                return Err(::knuffel::decode::unexpected_property(
+                    #name, &[#(#known),*]));
+            }
+        });
     } else {
+        let known = known_properties(s);
         match_branches.push(quote! {
-            #name_str => {
-                return Err(::knuffel::errors::DecodeError::unexpected(
-                    #name, "property",
-                    format!("unexpected property `{}`",
-                            #name_str.escape_default())));
+            _ => {
+                #ctx.emit_error(::knuffel::decode::unexpected_property(
+                    #name, &[#(#known),*]));
             }
         });
     };
     Ok(quote! {
         #(#declare_empty)*
         for (#name, #val) in #node.properties.iter() {
+            if #ctx.is_consumed(#name) {
+                continue;
+            }
             match &***#name {
                 #(#match_branches)*
             }
         }
+        #check_unknown
         #(#postprocess)*
     })
 }
 
 fn unwrap_fn(parent: &Common,
-             func: &syn::Ident, name: &syn::Ident, attrs: &FieldAttrs)
+             func: &syn::Ident, name: &syn::Ident, ty: Option<&syn::Type>,
+             attrs: &FieldAttrs)
     -> syn::Result<TokenStream>
 {
     let ctx = parent.ctx;
@@ -577,7 +1241,11 @@ fn unwrap_fn(parent: &Common,
         parent.object.trait_props.clone(),
         parent.object.generics.clone(),
     );
-    bld.add_field(Field::new_named(name), false, false, attrs)?;
+    let mut field = Field::new_named(name);
+    if let Some(ty) = ty {
+        field.ty = ty.clone();
+    }
+    bld.add_field(field, false, false, attrs)?;
     let object = bld.build();
     let common = Common {
         object: &object,
@@ -619,14 +1287,43 @@ fn decode_node(common: &Common, child_def: &Child, in_partial: bool,
     };
     let (init, func) = if let Some(unwrap) = &child_def.unwrap {
         let func = format_ident!("unwrap_{}", fld, span = Span::mixed_site());
-        let unwrap_fn = unwrap_fn(common, &func, fld, unwrap)?;
+        let ty = if matches!(child_def.mode, ChildMode::Multi) {
+            item_type(&child_def.field.ty, child_def.option)
+        } else if child_def.option {
+            type_params(&child_def.field.ty).first().copied()
+        } else {
+            Some(&child_def.field.ty)
+        };
+        let unwrap_fn = unwrap_fn(common, &func, fld, ty, unwrap)?;
         (unwrap_fn, quote!(#func))
     } else {
         (quote!(), quote!(::knuffel::Decode::decode_node))
     };
     let value = syn::Ident::new("value", Span::mixed_site());
     let assign = if matches!(child_def.mode, ChildMode::Multi) {
-        quote!(#dest.push(#value))
+        let check = match &child_def.ordered_by {
+            Some(key) => {
+                let prev = format_ident!("prev_{}", fld,
+                                         span = Span::mixed_site());
+                let prev = if in_partial {
+                    Previous::Last(&dest)
+                } else {
+                    Previous::Var(&prev)
+                };
+                Some(check_order(ctx, child, &value, &child_def.field,
+                                 child_def.option, key, prev)?)
+            }
+            None => None,
+        };
+        if in_partial && child_def.option {
+            quote! {
+                #check
+                #dest.get_or_insert_with(::std::default::Default::default)
+                    .push(#value)
+            }
+        } else {
+            quote!(#check #dest.push(#value))
+        }
     } else {
         quote!(#dest = Some(#value))
     };
@@ -655,18 +1352,78 @@ fn decode_node(common: &Common, child_def: &Child, in_partial: bool,
     }
 }
 
+/// Returns initialization code and a function decoding a single child
+fn var_children_decoder(s: &Common, var_children: &VarChildren)
+    -> syn::Result<(TokenStream, TokenStream)>
+{
+    let fld = &var_children.field.tmp_name;
+    if let Some(unwrap) = &var_children.unwrap {
+        let func = format_ident!("unwrap_{}", fld, span = Span::mixed_site());
+        let ty = item_type(&var_children.field.ty, false);
+        let unwrap_fn = unwrap_fn(s, &func, fld, ty, unwrap)?;
+        Ok((unwrap_fn, quote!(#func)))
+    } else {
+        Ok((quote!(), quote!(::knuffel::Decode::decode_node)))
+    }
+}
+
+/// Where the key of the previous child for `ordered_by` comes from
+enum Previous<'a> {
+    /// Variable keeping the key
+    Var(&'a syn::Ident),
+    /// The last element of the collection, used when decoding a flattened
+    /// structure (`DecodePartial`) as it has no local variables
+    Last(&'a TokenStream),
+}
+
+/// Checks that `value` is not less than the previous child
+fn check_order(ctx: &syn::Ident, child: &syn::Ident, value: &syn::Ident,
+               field: &Field, option: bool,
+               key: &syn::Ident, prev: Previous)
+    -> syn::Result<TokenStream>
+{
+    let item = item_type(&field.ty, option).ok_or_else(|| {
+        syn::Error::new(field.span,
+            "cannot determine element type for `ordered_by`, \
+             use a collection like `Vec<T>`")
+    })?;
+    let msg = format!("node is out of order, children must be sorted by `{}`",
+                      key.unraw());
+    let (prev_key, remember) = match prev {
+        Previous::Var(prev) => (quote!(#prev.as_ref()), quote! {
+            #prev = Some(::std::clone::Clone::clone(&#value.#key));
+        }),
+        Previous::Last(dest) if option => (quote! {
+            #dest.as_ref().and_then(|v| v.last()).map(|prev| &prev.#key)
+        }, quote!()),
+        Previous::Last(dest) => {
+            (quote!(#dest.last().map(|prev| &prev.#key)), quote!())
+        }
+    };
+    Ok(quote! {
+        let #value: #item = #value;
+        if let Some(prev) = #prev_key {
+            if #value.#key < *prev {
+                #ctx.emit_error(::knuffel::errors::DecodeError::unexpected(
+                    &#child.node_name, "node", #msg));
+            }
+        }
+        #remember
+    })
+}
+
 fn insert_child(s: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
     let ctx = s.ctx;
     let mut match_branches = Vec::with_capacity(s.object.children.len());
+    let mut flat = Vec::new();
     for child_def in &s.object.children {
         let dest = &child_def.field.from_self();
         let child_name = &child_def.name;
         if matches!(child_def.mode, ChildMode::Flatten) {
-            match_branches.push(quote! {
-                _ if ::knuffel::traits::DecodePartial
-                    ::insert_child(&mut #dest, #node, #ctx)?
-                => Ok(true),
-            })
+            flat.push(quote! {
+                ::knuffel::traits::DecodePartial
+                    ::insert_child(&mut #dest, #node, #ctx)
+            });
         } else if matches!(child_def.mode, ChildMode::Bool) {
             let dup_err = format!("duplicate node `{}`, single node expected",
                                   child_name.escape_default());
@@ -683,6 +1440,11 @@ fn insert_child(s: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
                     Ok(true)
                 }
             });
+        } else if matches!(child_def.mode, ChildMode::Multi) {
+            let decode = decode_node(s, &child_def, true, node)?;
+            match_branches.push(quote! {
+                #child_name => #decode
+            });
         } else {
             let dup_err = format!("duplicate node `{}`, single node expected",
                                   child_name.escape_default());
@@ -699,6 +1461,12 @@ fn insert_child(s: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
             });
         }
     }
+    if !flat.is_empty() {
+        let claim = flatten_claim(flat, quote!(&#node.node_name), "node");
+        match_branches.push(quote! {
+            _ if #claim? => Ok(true),
+        });
+    }
     Ok(quote! {
         match &**#node.node_name {
             #(#match_branches)*
@@ -711,29 +1479,38 @@ fn insert_property(s: &Common, name: &syn::Ident, value: &syn::Ident)
     -> syn::Result<TokenStream>
 {
     let ctx = s.ctx;
+    let name_str = syn::Ident::new("name_str", Span::mixed_site());
     let mut match_branches = Vec::with_capacity(s.object.children.len());
+    let mut flat = Vec::new();
     for prop in &s.object.properties {
         let dest = &prop.field.from_self();
-        let prop_name = &prop.name;
+        let pattern = prop.name_pattern(&name_str);
         if prop.flatten {
-            match_branches.push(quote! {
-                _ if ::knuffel::traits::DecodePartial
-                    ::insert_property(&mut #dest, #name, #value, #ctx)?
-                => Ok(true),
+            flat.push(quote! {
+                ::knuffel::traits::DecodePartial
+                    ::insert_property(&mut #dest, #name, #value, #ctx)
             });
         } else {
             let decode_value = decode_value(&value, ctx, &prop.decode,
                                             prop.option)?;
+            let decode_value = clamp_value(decode_value, &prop.field,
+                                           &prop.clamp, prop.option);
+            let decode_value = check_kind(decode_value, value,
+                                          &prop.literal_kind, prop.option);
+            let decode_value = check_none_if(decode_value, value,
+                                             &prop.none_if);
+            let decode_value = check_type_name(decode_value, value, ctx,
+                &prop.field, prop.option, &prop.type_name);
             if prop.option {
                 match_branches.push(quote! {
-                    #prop_name => {
+                    #pattern => {
                         #dest = #decode_value?;
                         Ok(true)
                     }
                 });
             } else {
                 match_branches.push(quote! {
-                    #prop_name => {
+                    #pattern => {
                         #dest = Some(#decode_value?);
                         Ok(true)
                     }
@@ -741,6 +1518,12 @@ fn insert_property(s: &Common, name: &syn::Ident, value: &syn::Ident)
             }
         }
     }
+    if !flat.is_empty() {
+        let claim = flatten_claim(flat, quote!(#name), "property");
+        match_branches.push(quote! {
+            _ if #claim? => Ok(true),
+        });
+    }
     Ok(quote! {
         match &***#name {
             #(#match_branches)*
@@ -749,6 +1532,22 @@ fn insert_property(s: &Common, name: &syn::Ident, value: &syn::Ident)
     })
 }
 
+/// Expression offering a property or a child to the flattened fields
+///
+/// When there are several flattened fields, all of them are asked, so that
+/// a name accepted by more than one of them is reported as ambiguous.
+fn flatten_claim(insert: Vec<TokenStream>, name: TokenStream, kind: &str)
+    -> TokenStream
+{
+    if insert.len() == 1 {
+        insert.into_iter().next().unwrap()
+    } else {
+        quote! {
+            ::knuffel::decode::flattened(#name, #kind, [#(#insert),*])
+        }
+    }
+}
+
 fn decode_children(s: &Common, children: &syn::Ident,
                    err_span: Option<TokenStream>)
     -> syn::Result<TokenStream>
@@ -760,30 +1559,33 @@ fn decode_children(s: &Common, children: &syn::Ident,
     let ctx = s.ctx;
     let child = syn::Ident::new("child", Span::mixed_site());
     let name_str = syn::Ident::new("name_str", Span::mixed_site());
+    let mut flat = Vec::new();
     for child_def in &s.object.children {
         let fld = &child_def.field.tmp_name;
         let child_name = &child_def.name;
         match child_def.mode {
             ChildMode::Flatten => {
-                declare_empty.push(quote! {
-                    let mut #fld = ::std::default::Default::default();
+                if !s.object.is_flat_property(&child_def.field) {
+                    declare_empty.push(quote! {
+                        let mut #fld = ::std::default::Default::default();
+                    });
+                }
+                flat.push(quote! {
+                    ::knuffel::traits::DecodePartial
+                        ::insert_child(&mut #fld, #child, #ctx)
                 });
-                match_branches.push(quote! {
-                    _ if (
-                        match ::knuffel::traits::DecodePartial
-                            ::insert_child(&mut #fld, #child, #ctx)
-                        {
-                            Ok(true) => return None,
-                            Ok(false) => false,
-                            Err(e) => return Some(Err(e)),
-                        }
-                    ) => None,
-                })
             }
             ChildMode::Multi => {
                 declare_empty.push(quote! {
                     let mut #fld = Vec::new();
                 });
+                if child_def.ordered_by.is_some() {
+                    let prev = format_ident!("prev_{}", fld,
+                                             span = Span::mixed_site());
+                    declare_empty.push(quote! {
+                        let mut #prev = None;
+                    });
+                }
                 let decode = decode_node(s, &child_def, false, &child)?;
                 match_branches.push(quote! {
                     #child_name => #decode,
@@ -826,26 +1628,63 @@ fn decode_children(s: &Common, children: &syn::Ident,
                 }
             }
             ChildMode::Normal => {
-                declare_empty.push(quote! {
-                    let mut #fld = None;
-                });
                 let dup_err = format!(
                     "duplicate node `{}`, single node expected",
                     child_name.escape_default());
+                let conflict = if child_def.or_property {
+                    let prop_var = or_property_var(child_def);
+                    let val = syn::Ident::new("val", Span::mixed_site());
+                    let mode = child_def.unwrap.as_ref()
+                        .and_then(|u| u.decode.clone())
+                        .unwrap_or(DecodeMode::Normal);
+                    let decode_value = decode_value(&val, ctx, &mode, false)?;
+                    declare_empty.push(quote! {
+                        let mut #fld = match #prop_var {
+                            Some(#val) => Some(#decode_value?),
+                            None => None,
+                        };
+                    });
+                    let conflict_err = format!(
+                        "`{}` is specified both as a property and as a child",
+                        child_name.escape_default());
+                    Some(quote! {
+                        if #prop_var.is_some() {
+                            Some(Err(
+                                ::knuffel::errors::DecodeError::unexpected(
+                                &#child.node_name, "node", #conflict_err)))
+                        } else
+                    })
+                } else {
+                    declare_empty.push(quote! {
+                        let mut #fld = None;
+                    });
+                    None
+                };
                 let decode = decode_node(s, &child_def, false, &child)?;
+                let first = format_ident!("first_{}", fld,
+                                          span = Span::mixed_site());
+                declare_empty.push(quote! {
+                    let mut #first = None;
+                });
                 match_branches.push(quote! {
                     #child_name => {
-                        if #fld.is_some() {
+                        #conflict
+                        if let Some(prev) = #first {
                             Some(Err(
-                                ::knuffel::errors::DecodeError::unexpected(
-                                &#child.node_name, "node", #dup_err)))
+                                ::knuffel::errors::DecodeError::duplicate(
+                                &#child.node_name, prev, "node", #dup_err)))
                         } else {
+                            #first = Some(&#child.node_name);
                             #decode
                         }
                     }
                 });
-                let req_msg = format!("child node `{}` is required",
-                                      child_name);
+                let req_msg = if child_def.or_property {
+                    format!("property or child node `{}` is required",
+                            child_name)
+                } else {
+                    format!("child node `{}` is required", child_name)
+                };
                 if let Some(default_value) = &child_def.default {
                     let default = if let Some(expr) = default_value {
                         quote!(#expr)
@@ -880,17 +1719,22 @@ fn decode_children(s: &Common, children: &syn::Ident,
                 let dup_err = format!(
                     "duplicate node `{}`, single node expected",
                     child_name.escape_default());
+                let first = format_ident!("first_{}", fld,
+                                          span = Span::mixed_site());
                 declare_empty.push(quote! {
                     let mut #fld = false;
+                    let mut #first = None;
                 });
                 match_branches.push(quote! {
                     #child_name => {
                         ::knuffel::decode::check_flag_node(#child, #ctx);
-                        if #fld {
+                        if let Some(prev) = #first {
                             #ctx.emit_error(
-                                ::knuffel::errors::DecodeError::unexpected(
-                                    &#child.node_name, "node", #dup_err));
+                                ::knuffel::errors::DecodeError::duplicate(
+                                    &#child.node_name, prev, "node",
+                                    #dup_err));
                         } else {
+                            #first = Some(&#child.node_name);
                             #fld = true;
                         }
                         None
@@ -899,26 +1743,170 @@ fn decode_children(s: &Common, children: &syn::Ident,
             }
         }
     }
-    if let Some(var_children) = &s.object.var_children {
+    if !flat.is_empty() {
+        let claim = flatten_claim(flat, quote!(&#child.node_name), "node");
+        match_branches.push(quote! {
+            _ if (
+                match #claim {
+                    Ok(true) => return None,
+                    Ok(false) => false,
+                    Err(e) => return Some(Err(e)),
+                }
+            ) => None,
+        });
+    }
+    for var_children in &s.object.typed_children {
         let fld = &var_children.field.tmp_name;
-
-        let (init, func) = if let Some(unwrap) = &var_children.unwrap {
-            let func = format_ident!("unwrap_{}", fld, span = Span::mixed_site());
-            let unwrap_fn = unwrap_fn(s, &func, fld, unwrap)?;
-            (unwrap_fn, quote!(#func))
+        let type_filter = &var_children.type_filter;
+        let (init, func) = var_children_decoder(s, var_children)?;
+        let value = syn::Ident::new("value", Span::mixed_site());
+        let result = syn::Ident::new("result", Span::mixed_site());
+        declare_empty.push(quote! {
+            let mut #fld = Vec::new();
+        });
+        let check = if let Some(key) = &var_children.ordered_by {
+            let prev = format_ident!("prev_{}", fld,
+                                     span = Span::mixed_site());
+            declare_empty.push(quote! {
+                let mut #prev = None;
+            });
+            Some(check_order(ctx, &child, &value, &var_children.field, false,
+                             key, Previous::Var(&prev))?)
         } else {
-            (quote!(), quote!(::knuffel::Decode::decode_node))
+            None
         };
-
         match_branches.push(quote! {
-            _ => {
+            _ if #child.type_name.as_ref()
+                .map(|t| t.as_str()) == Some(#type_filter) =>
+            {
                 #init
-                match #func(#child, #ctx) {
-                    Ok(#child) => Some(Ok(#child)),
+                // the annotation is consumed here, so the node doesn't see it
+                let #result = match &#child.type_name {
+                    Some(type_name) => #ctx.with_consumed(type_name,
+                        |#ctx| #func(#child, #ctx)),
+                    None => #func(#child, #ctx),
+                };
+                match #result {
+                    Ok(#value) => {
+                        #check
+                        #fld.push(#value);
+                        None
+                    }
                     Err(e) => Some(Err(e)),
                 }
             }
         });
+        postprocess.push(quote! {
+            let #fld = #fld.into_iter().collect();
+        });
+    }
+    if let Some(var_children) = &s.object.var_children {
+        let fld = &var_children.field.tmp_name;
+        let (init, func) = var_children_decoder(s, var_children)?;
+        let value = syn::Ident::new("value", Span::mixed_site());
+        let check = if let Some(key) = &var_children.ordered_by {
+            let prev = format_ident!("prev_{}", fld,
+                                     span = Span::mixed_site());
+            declare_empty.push(quote! {
+                let mut #prev = None;
+            });
+            Some(check_order(ctx, &child, &value, &var_children.field, false,
+                             key, Previous::Var(&prev))?)
+        } else {
+            None
+        };
+        // duplicates are skipped before decoding, so they are never checked
+        let dedup = match var_children.dedup_by_name {
+            Some(Dedup::First) => {
+                let first = format_ident!("first_{}", fld,
+                                          span = Span::mixed_site());
+                declare_empty.push(quote! {
+                    let mut #first = ::std::collections::HashSet::new();
+                });
+                Some(quote! {
+                    if !#first.insert(#name_str) {
+                        return None;
+                    }
+                })
+            }
+            Some(Dedup::Last) => {
+                let last = format_ident!("last_{}", fld,
+                                         span = Span::mixed_site());
+                let typed = s.object.typed_children.iter()
+                    .map(|c| &c.type_filter).collect::<Vec<_>>();
+                // children having other fields' types never reach here
+                let filter = (!typed.is_empty()).then(|| quote! {
+                    .filter(|#child| !matches!(
+                        #child.type_name.as_ref().map(|t| t.as_str()),
+                        #(Some(#typed))|*))
+                });
+                declare_empty.push(quote! {
+                    let #last = #children.iter()
+                        #filter
+                        .map(|#child| (&**#child.node_name, #child))
+                        .collect::<::std::collections::HashMap<_, _>>();
+                });
+                Some(quote! {
+                    if !::std::ptr::eq(#last[#name_str], #child) {
+                        return None;
+                    }
+                })
+            }
+            None => None,
+        };
+        if var_children.map {
+            let seen = format_ident!("seen_{}", fld, span = Span::mixed_site());
+            let key = syn::Ident::new("key", Span::mixed_site());
+            declare_empty.push(quote! {
+                let mut #seen = ::std::collections::HashMap::new();
+            });
+            match_branches.push(quote! {
+                #name_str => {
+                    #dedup
+                    if let Some(prev) = #seen.insert(#name_str,
+                                                     &#child.node_name)
+                    {
+                        return Some(Err(
+                            ::knuffel::errors::DecodeError::duplicate(
+                                &#child.node_name, prev, "node",
+                                format!("duplicate node `{}`, \
+                                         single node expected",
+                                        #name_str.escape_default()))));
+                    }
+                    let #key = match ::knuffel::decode::node_name(#child, #ctx) {
+                        Ok(#key) => #key,
+                        Err(e) => return Some(Err(e)),
+                    };
+                    #init
+                    match #func(#child, #ctx) {
+                        Ok(#value) => {
+                            #check
+                            Some(Ok((#key, #value)))
+                        }
+                        Err(e) => Some(Err(e)),
+                    }
+                }
+            });
+        } else {
+            let pattern = if dedup.is_some() {
+                quote!(#name_str)
+            } else {
+                quote!(_)
+            };
+            match_branches.push(quote! {
+                #pattern => {
+                    #dedup
+                    #init
+                    match #func(#child, #ctx) {
+                        Ok(#value) => {
+                            #check
+                            Some(Ok(#value))
+                        }
+                        Err(e) => Some(Err(e)),
+                    }
+                }
+            });
+        }
         Ok(quote! {
             #(#declare_empty)*
             let #fld = #children.iter().flat_map(|#child| {
@@ -929,15 +1917,26 @@ fn decode_children(s: &Common, children: &syn::Ident,
             #(#postprocess)*
         })
     } else {
-        match_branches.push(quote! {
-            #name_str => {
-                #ctx.emit_error(::knuffel::errors::DecodeError::unexpected(
-                    #child, "node",
-                    format!("unexpected node `{}`",
-                            #name_str.escape_default())));
-                None
-            }
-        });
+        if err_span.is_none() && s.object.trait_props.strict_document {
+            // document-level decoding, list known names to help fixing typos
+            let known = known_children(s);
+            match_branches.push(quote! {
+                _ => {
+                    #ctx.emit_error(::knuffel::decode::unexpected_node(
+                        #child, &[#(#known),*]));
+                    None
+                }
+            });
+        } else {
+            let known = known_children(s);
+            match_branches.push(quote! {
+                _ => {
+                    #ctx.emit_error(::knuffel::decode::unexpected_child(
+                        #child, &[#(#known),*]));
+                    None
+                }
+            });
+        }
 
         Ok(quote! {
             #(#declare_empty)*
@@ -951,13 +1950,203 @@ fn decode_children(s: &Common, children: &syn::Ident,
     }
 }
 
-fn assign_extra(s: &Common) -> syn::Result<TokenStream> {
+/// Checks `one_of` and `at_most_one_of` groups of already decoded fields
+///
+/// Positions of the conflicting fields are looked up in the node again, as
+/// decoded values don't keep spans.
+fn check_exclusive(s: &Common, node: Option<&syn::Ident>,
+                   children: &syn::Ident)
+    -> syn::Result<TokenStream>
+{
+    let mut checks = Vec::new();
+    let present = syn::Ident::new("present", Span::mixed_site());
+    let set = syn::Ident::new("set", Span::mixed_site());
+    let first = syn::Ident::new("first", Span::mixed_site());
+    let second = syn::Ident::new("second", Span::mixed_site());
+    let names = syn::Ident::new("names", Span::mixed_site());
+    let spans = syn::Ident::new("spans", Span::mixed_site());
+    let message = syn::Ident::new("message", Span::mixed_site());
+    for group in &s.object.trait_props.exclusive {
+        let mut member_present = Vec::new();
+        let mut member_names = Vec::new();
+        let mut member_spans = Vec::new();
+        for ident in &group.fields {
+            let (fld, name, span) = exclusive_member(s, node, children, ident)?;
+            member_present.push(quote!(#fld.is_some()));
+            member_names.push(name);
+            member_spans.push(span);
+        }
+        let list = member_names.iter()
+            .map(|n| format!("`{}`", n.escape_default()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let missing = if !group.exactly_one {
+            None
+        } else if let Some(node) = node {
+            let msg = format!("exactly one of {} is required", list);
+            Some(quote! {
+                if #first.is_none() {
+                    return Err(::knuffel::errors::DecodeError::missing(
+                        #node, #msg));
+                }
+            })
+        } else {
+            let msg = format!("exactly one of the nodes {} is required", list);
+            Some(quote! {
+                if #first.is_none() {
+                    return Err(::knuffel::errors::DecodeError::MissingNode {
+                        message: #msg.into(),
+                    });
+                }
+            })
+        };
+        checks.push(quote! {
+            {
+                let #present = [#(#member_present),*];
+                let mut #set = (0..#present.len()).filter(|&i| #present[i]);
+                let #first = #set.next();
+                #missing
+                if let (Some(#first), Some(#second)) = (#first, #set.next()) {
+                    let #names = [#(#member_names),*];
+                    let #spans = [#(#member_spans),*];
+                    let #message = format!(
+                        "`{}` and `{}` are mutually exclusive",
+                        #names[#first], #names[#second]);
+                    match (#spans[#second].clone(), #spans[#first].clone()) {
+                        (Some(span), Some(previous)) => {
+                            return Err(
+                                ::knuffel::errors::DecodeError::Duplicate {
+                                    span,
+                                    previous,
+                                    kind: "choice",
+                                    message: #message,
+                                });
+                        }
+                        _ => {
+                            return Err(::knuffel::errors::DecodeError::Custom(
+                                #message.into()));
+                        }
+                    }
+                }
+            }
+        });
+    }
+    Ok(quote!(#(#checks)*))
+}
+
+/// Returns variable, name and expression finding position of the group member
+fn exclusive_member(s: &Common, node: Option<&syn::Ident>,
+                    children: &syn::Ident, ident: &syn::Ident)
+    -> syn::Result<(syn::Ident, String, TokenStream)>
+{
+    let is_field = |field: &Field| {
+        matches!(&field.attr, AttrAccess::Named(name) if name == ident)
+    };
+    let err = |msg: &str| Err(syn::Error::new(ident.span(), msg));
+    let prop_span = |name: &str, case_insensitive: bool| {
+        let key = syn::Ident::new("key", Span::mixed_site());
+        let cmp = if case_insensitive {
+            let lower = name.to_lowercase();
+            quote!(#key.to_lowercase() == #lower)
+        } else {
+            quote!(&***#key == #name)
+        };
+        match node {
+            Some(node) => quote! {
+                #node.properties.keys().find(|&#key| #cmp)
+                    .map(|#key| #key.span().clone())
+            },
+            None => quote!(None),
+        }
+    };
+    let child_span = |name: &str| {
+        let child = syn::Ident::new("child", Span::mixed_site());
+        quote! {
+            #children.iter().find(|#child| &**#child.node_name == #name)
+                .map(|#child| #child.node_name.span().clone())
+        }
+    };
+    let o = s.object;
+    if let Some(idx) = o.arguments.iter().position(|a| is_field(&a.field)) {
+        let arg = &o.arguments[idx];
+        if !arg.option || arg.default.is_some() ||
+            !matches!(arg.kind, ArgKind::Value { .. })
+        {
+            return err("field in the group must be an `Option` \
+                        with no default");
+        }
+        let span = match node {
+            Some(node) => quote! {
+                #node.arguments.get(#idx).map(|a| a.literal.span().clone())
+            },
+            None => quote!(None),
+        };
+        Ok((arg.field.tmp_name.clone(), ident.unraw().to_string(), span))
+    } else if let Some(prop) = o.properties.iter().find(|p| is_field(&p.field))
+    {
+        if !prop.option || prop.flatten || prop.default.is_some() {
+            return err("field in the group must be an `Option` \
+                        with no default");
+        }
+        let span = prop_span(&prop.name, prop.case_insensitive);
+        Ok((prop.field.tmp_name.clone(), prop.name.clone(), span))
+    } else if let Some(child) = o.children.iter().find(|c| is_field(&c.field))
+    {
+        if !child.option || child.default.is_some() ||
+            !matches!(child.mode, ChildMode::Normal)
+        {
+            return err("field in the group must be an `Option` \
+                        with no default");
+        }
+        let span = child_span(&child.name);
+        let span = if child.or_property {
+            let prop = prop_span(&child.name, false);
+            quote!(#prop.or_else(|| #span))
+        } else {
+            span
+        };
+        Ok((child.field.tmp_name.clone(), child.name.clone(), span))
+    } else if o.all_fields().into_iter().any(is_field) {
+        err("only arguments, properties and children \
+             can be mutually exclusive")
+    } else {
+        err("no such field")
+    }
+}
+
+fn assign_extra(s: &Common, node: Option<&syn::Ident>)
+    -> syn::Result<TokenStream>
+{
+    let ctx = s.ctx;
     let items = s.object.extra_fields.iter().map(|fld| {
+        let name = &fld.field.tmp_name;
         match fld.kind {
-            ExtraKind::Auto => {
-                let name = &fld.field.tmp_name;
+            ExtraKind::Auto | ExtraKind::Skip => {
                 quote!(let #name = ::std::default::Default::default();)
             }
+            ExtraKind::Context if fld.option => {
+                quote!(let #name = #ctx.get().cloned();)
+            }
+            ExtraKind::Context => {
+                let ty = &fld.field.ty;
+                let msg = format!("context value of type `{}` is required",
+                                  quote!(#ty).to_string().replace(' ', ""));
+                let err = if let Some(node) = node {
+                    quote!(::knuffel::errors::DecodeError::missing(#node, #msg))
+                } else {
+                    quote! {
+                        ::knuffel::errors::DecodeError::MissingNode {
+                            message: #msg.into(),
+                        }
+                    }
+                };
+                quote! {
+                    let #name = match #ctx.get::<#ty>() {
+                        Some(value) => ::std::clone::Clone::clone(value),
+                        None => return Err(#err),
+                    };
+                }
+            }
         }
     });
     Ok(quote!(#(#items)*))
@@ -1,9 +1,12 @@
-use proc_macro2::TokenStream;
+use proc_macro2::{TokenStream, Span};
 use quote::quote;
-use syn::ext::IdentExt;
 use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 
+use crate::definition::RenameAll;
+use crate::kw;
+
 
 pub enum Scalar {
     Enum(Enum),
@@ -12,27 +15,177 @@ pub enum Scalar {
 pub struct Enum {
     pub ident: syn::Ident,
     pub variants: Vec<Variant>,
+    pub fallback: Option<syn::Ident>,
+    pub normalize: Option<syn::Path>,
+    pub case_insensitive: bool,
+    /// Variants are matched only by `value`, strings aren't accepted
+    pub int_repr: bool,
+}
+
+enum Attr {
+    RawFallback,
+    IntRepr,
+    Normalize(syn::Path),
+    CaseInsensitive,
+    RenameAll(RenameAll),
+    Rename(syn::LitStr),
+    Alias(syn::LitStr),
+    Value(i64),
+    Bool(bool),
 }
 
 pub struct Variant {
     pub ident: syn::Ident,
     pub name: String,
+    /// Additional names matching the variant
+    pub aliases: Vec<String>,
+    /// Integer matching the variant in addition to the name
+    pub value: Option<i64>,
+    /// Boolean matching the variant in addition to the name
+    pub bool_value: Option<bool>,
 }
 
 impl Enum {
-    fn new(ident: syn::Ident, _attrs: Vec<syn::Attribute>,
+    fn new(ident: syn::Ident, attrs: Vec<syn::Attribute>,
            src_variants: impl Iterator<Item=syn::Variant>)
         -> syn::Result<Self>
     {
+        let mut normalize = None;
+        let mut rename_all = RenameAll::Kebab;
+        let mut case_insensitive = false;
+        let mut int_repr = false;
+        let mut string_attr = None;
+        for (attr, span) in parse_attr_list(&attrs)? {
+            match attr {
+                Attr::IntRepr => int_repr = true,
+                Attr::Normalize(path) => {
+                    normalize = Some(path);
+                    string_attr = Some(span);
+                }
+                Attr::CaseInsensitive => {
+                    case_insensitive = true;
+                    string_attr = Some(span);
+                }
+                Attr::RenameAll(case) => {
+                    rename_all = case;
+                    string_attr = Some(span);
+                }
+                _ => return Err(syn::Error::new(span,
+                    "unexpected container attribute")),
+            }
+        }
+        if let Some(span) = string_attr.filter(|_| int_repr) {
+            return Err(syn::Error::new(span,
+                "string matching options can't be used with `kind=\"int\"`"));
+        }
         let mut variants = Vec::new();
+        let mut fallback = None;
+        let mut used_names = Vec::<(String, Span)>::new();
         for var in src_variants {
+            let mut raw_fallback = false;
+            let mut value = None;
+            let mut bool_value = None;
+            let mut rename = None;
+            let mut aliases = Vec::new();
+            for (attr, span) in parse_attr_list(&var.attrs)? {
+                match attr {
+                    Attr::RawFallback => raw_fallback = true,
+                    Attr::Rename(name) => rename = Some(name),
+                    Attr::Alias(name) => aliases.push(name),
+                    Attr::Value(val) => {
+                        if let Some(prev) = variants.iter()
+                            .find(|v: &&Variant| v.value == Some(val))
+                        {
+                            return Err(syn::Error::new(span, format!(
+                                "value {} is already used by `{}`",
+                                val, prev.ident)));
+                        }
+                        value = Some(val);
+                    }
+                    Attr::Bool(val) => {
+                        if let Some(prev) = variants.iter()
+                            .find(|v: &&Variant| v.bool_value == Some(val))
+                        {
+                            return Err(syn::Error::new(span, format!(
+                                "`bool({})` is already used by `{}`",
+                                val, prev.ident)));
+                        }
+                        bool_value = Some(val);
+                    }
+                    _ => return Err(syn::Error::new(span,
+                        "not supported on enum variants")),
+                }
+            }
+            if int_repr && !raw_fallback {
+                if bool_value.is_some() || rename.is_some() ||
+                    !aliases.is_empty()
+                {
+                    return Err(syn::Error::new(var.span(),
+                        "only `value` is allowed on variants of \
+                         `kind=\"int\"` enum"));
+                }
+                if value.is_none() {
+                    return Err(syn::Error::new(var.span(),
+                        "variants of `kind=\"int\"` enum must have \
+                         `value=N`"));
+                }
+            }
+            if raw_fallback &&
+                (value.is_some() || bool_value.is_some() ||
+                 rename.is_some() || !aliases.is_empty())
+            {
+                return Err(syn::Error::new(var.span(),
+                    "`raw_fallback` variant can't have `value`, `bool`, \
+                     `rename` or `alias`"));
+            }
+            if raw_fallback {
+                if fallback.is_some() {
+                    return Err(syn::Error::new(var.span(),
+                        "only single `raw_fallback` variant is allowed"));
+                }
+                match &var.fields {
+                    syn::Fields::Unnamed(u) if u.unnamed.len() == 1 => {}
+                    _ => {
+                        return Err(syn::Error::new(var.span(),
+                            "`raw_fallback` variant must have a single \
+                             `knuffel::ast::Literal` field"));
+                    }
+                }
+                fallback = Some(var.ident);
+                continue;
+            }
             match var.fields {
                 syn::Fields::Unit => {
-                    let name = heck::ToKebabCase
-                        ::to_kebab_case(&var.ident.unraw().to_string()[..]);
+                    let name = rename.as_ref().map(|r| r.value())
+                        .unwrap_or_else(|| rename_all.apply(&var.ident));
+                    let name_span = rename.as_ref().map(|r| r.span())
+                        .unwrap_or_else(|| var.ident.span());
+                    let names = Some((name.clone(), name_span)).into_iter()
+                        .chain(aliases.iter().map(|a| (a.value(), a.span())));
+                    for (name, span) in names {
+                        let name = if case_insensitive {
+                            name.to_ascii_lowercase()
+                        } else {
+                            name
+                        };
+                        if let Some((_, prev)) = used_names.iter()
+                            .find(|(n, _)| *n == name)
+                        {
+                            let mut err = syn::Error::new(span, format!(
+                                "name `{}` is used more than once",
+                                name.escape_default()));
+                            err.combine(syn::Error::new(*prev,
+                                "previously used here"));
+                            return Err(err);
+                        }
+                        used_names.push((name, span));
+                    }
                     variants.push(Variant {
                         ident: var.ident,
                         name,
+                        aliases: aliases.iter().map(|a| a.value()).collect(),
+                        value,
+                        bool_value,
                     });
                 }
                 _ => {
@@ -44,10 +197,94 @@ impl Enum {
         Ok(Enum {
             ident,
             variants,
+            fallback,
+            normalize,
+            case_insensitive,
+            int_repr,
         })
     }
 }
 
+fn parse_attr_list(attrs: &[syn::Attribute])
+    -> syn::Result<Vec<(Attr, Span)>>
+{
+    let mut all = Vec::new();
+    for attr in attrs {
+        if matches!(attr.style, syn::AttrStyle::Outer) &&
+            attr.path.is_ident("knuffel")
+        {
+            all.extend(attr.parse_args_with(|input: ParseStream| {
+                Punctuated::<_, syn::Token![,]>::parse_terminated_with(
+                    input, Attr::parse)
+            })?);
+        }
+    }
+    Ok(all)
+}
+
+impl Attr {
+    fn parse(input: ParseStream) -> syn::Result<(Self, Span)> {
+        let span = input.span();
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::raw_fallback) {
+            let _kw: kw::raw_fallback = input.parse()?;
+            Ok((Attr::RawFallback, span))
+        } else if lookahead.peek(kw::normalize) {
+            let _kw: kw::normalize = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let path: syn::Path = input.parse()?;
+            Ok((Attr::Normalize(path), span))
+        } else if lookahead.peek(kw::rename_all) {
+            let _kw: kw::rename_all = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let case: syn::LitStr = input.parse()?;
+            Ok((Attr::RenameAll(RenameAll::from_lit(&case)?), span))
+        } else if lookahead.peek(kw::kind) {
+            let _kw: kw::kind = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let kind: syn::LitStr = input.parse()?;
+            match &kind.value()[..] {
+                "int" => Ok((Attr::IntRepr, span)),
+                _ => Err(syn::Error::new(kind.span(),
+                    "only `kind=\"int\"` is supported for enums")),
+            }
+        } else if lookahead.peek(kw::case_insensitive) {
+            let _kw: kw::case_insensitive = input.parse()?;
+            Ok((Attr::CaseInsensitive, span))
+        } else if lookahead.peek(kw::alias) {
+            let _kw: kw::alias = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let name: syn::LitStr = input.parse()?;
+            Ok((Attr::Alias(name), span))
+        } else if lookahead.peek(kw::rename) {
+            let _kw: kw::rename = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let name: syn::LitStr = input.parse()?;
+            Ok((Attr::Rename(name), span))
+        } else if lookahead.peek(kw::value) {
+            let _kw: kw::value = input.parse()?;
+            let _eq: syn::Token![=] = input.parse()?;
+            let neg: Option<syn::Token![-]> = input.parse()?;
+            let lit: syn::LitInt = input.parse()?;
+            let value: i64 = if neg.is_some() {
+                format!("-{}", lit.base10_digits()).parse()
+                    .map_err(|e| syn::Error::new(lit.span(), e))?
+            } else {
+                lit.base10_parse()?
+            };
+            Ok((Attr::Value(value), span))
+        } else if lookahead.peek(kw::bool) {
+            let _kw: kw::bool = input.parse()?;
+            let parens;
+            syn::parenthesized!(parens in input);
+            let value: syn::LitBool = parens.parse()?;
+            Ok((Attr::Bool(value.value), span))
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
 
 impl Parse for Scalar {
     fn parse(input: ParseStream) -> syn::Result<Self> {
@@ -77,9 +314,21 @@ pub fn emit_scalar(s: &Scalar) -> syn::Result<TokenStream> {
 }
 
 
+pub fn emit_encode_scalar(s: &Scalar) -> syn::Result<TokenStream> {
+    match s {
+        Scalar::Enum(e) => {
+            emit_encode_enum(e)
+        }
+    }
+}
+
 pub fn emit_enum(e: &Enum) -> syn::Result<TokenStream> {
     let e_name = &e.ident;
-    let value_err = if e.variants.len() <= 3 {
+    let mut value_err = if e.int_repr {
+        format!("expected one of {}", e.variants.iter()
+            .filter_map(|v| v.value.map(|val| val.to_string()))
+            .collect::<Vec<_>>().join(", "))
+    } else if e.variants.len() <= 3 {
         format!("expected one of {}",
                 e.variants.iter()
                 .map(|v| format!("`{}`", v.name.escape_default()))
@@ -91,12 +340,127 @@ pub fn emit_enum(e: &Enum) -> syn::Result<TokenStream> {
                 e.variants[1].name.escape_default(),
                 e.variants.len() - 2)
     };
-    let match_branches = e.variants.iter()
-        .map(|var| {
-            let name = &var.name;
-            let ident = &var.ident;
-            quote!(#name => Ok(#e_name::#ident))
+    let ints = e.variants.iter()
+        .filter_map(|v| v.value.map(|val| (val, &v.ident)))
+        .collect::<Vec<_>>();
+    let bools = e.variants.iter()
+        .filter_map(|v| v.bool_value.map(|val| (val, &v.ident)))
+        .collect::<Vec<_>>();
+    let mut kinds = Vec::new();
+    if !e.int_repr {
+        kinds.push(quote!(::knuffel::decode::Kind::String));
+    }
+    if !ints.is_empty() {
+        if !e.int_repr {
+            value_err.push_str(&format!(", or integer {}", ints.iter()
+                .map(|(val, _)| val.to_string())
+                .collect::<Vec<_>>().join(", ")));
+        }
+        kinds.push(quote!(::knuffel::decode::Kind::Int));
+    }
+    if !bools.is_empty() {
+        value_err.push_str(&format!(", or boolean {}", bools.iter()
+            .map(|(val, _)| val.to_string())
+            .collect::<Vec<_>>().join(", ")));
+        kinds.push(quote!(::knuffel::decode::Kind::Bool));
+    }
+    let (value_fallback, kind_fallback) = if let Some(fb) = &e.fallback {
+        let raw = quote!(Ok(#e_name::#fb((**val).clone())));
+        (raw.clone(), raw)
+    } else {
+        (quote! {
+            Err(::knuffel::errors::DecodeError::conversion(val, #value_err))
+        }, quote! {
+            Err(::knuffel::errors::DecodeError::ScalarKind {
+                span: val.span().clone(),
+                expected: ::knuffel::errors::ExpectedKind::one_of([
+                    #(#kinds),*
+                ]),
+                found: (&**val).into(),
+            })
+        })
+    };
+    // literals of other kinds are matched only if some variant has them
+    let match_int = (!ints.is_empty()).then(|| {
+        let branches = ints.iter().map(|(val, ident)| {
+            quote!(Ok(#val) => Ok(#e_name::#ident))
+        });
+        quote! {
+            ::knuffel::ast::Literal::Int(ref v) => {
+                match <i64 as ::std::convert::TryFrom<_>>::try_from(v) {
+                    #(#branches,)*
+                    _ => #value_fallback,
+                }
+            }
+        }
+    });
+    let match_bool = (!bools.is_empty()).then(|| {
+        let branches = bools.iter().map(|(val, ident)| {
+            quote!(#val => Ok(#e_name::#ident))
         });
+        quote! {
+            ::knuffel::ast::Literal::Bool(v) => {
+                match v {
+                    #(#branches,)*
+                    #[allow(unreachable_patterns)]
+                    _ => #value_fallback,
+                }
+            }
+        }
+    });
+    let case_insensitive = e.case_insensitive;
+    let names = e.variants.iter()
+        .flat_map(|var| {
+            Some(&var.name).into_iter().chain(&var.aliases)
+                .map(move |name| if case_insensitive {
+                    (name.to_ascii_lowercase(), &var.ident)
+                } else {
+                    (name.clone(), &var.ident)
+                })
+        });
+    let match_string = if let Some(normalize) = &e.normalize {
+        // Normalizer can't be called at compile time, so both input and
+        // variant names are normalized at runtime
+        let branches = names
+            .map(|(name, ident)| {
+                quote! {
+                    if value == ::std::convert::AsRef::<str>::as_ref(
+                        &#normalize(#name))
+                    {
+                        Ok(#e_name::#ident)
+                    }
+                }
+            });
+        quote! {{
+            let value = #normalize(&s[..]);
+            let value = ::std::convert::AsRef::<str>::as_ref(&value);
+            #(#branches else)* {
+                #value_fallback
+            }
+        }}
+    } else {
+        let match_branches = names
+            .map(|(name, ident)| {
+                quote!(#name => Ok(#e_name::#ident))
+            });
+        quote! {
+            match &s[..] {
+                #(#match_branches,)*
+                _ => #value_fallback,
+            }
+        }
+    };
+    let match_string = if e.case_insensitive {
+        quote! {{
+            let s = s.to_ascii_lowercase();
+            #match_string
+        }}
+    } else {
+        match_string
+    };
+    let match_string = (!e.int_repr).then(|| quote! {
+        ::knuffel::ast::Literal::String(ref s) => #match_string
+    });
     Ok(quote! {
         impl<S: ::knuffel::traits::ErrorSpan> ::knuffel::DecodeScalar<S>
                 for #e_name {
@@ -106,21 +470,10 @@ pub fn emit_enum(e: &Enum) -> syn::Result<TokenStream> {
                 -> Result<#e_name, ::knuffel::errors::DecodeError<S>>
             {
                 match &**val {
-                    ::knuffel::ast::Literal::String(ref s) => {
-                        match &s[..] {
-                            #(#match_branches,)*
-                            _ => {
-                                Err(::knuffel::errors::DecodeError::conversion(
-                                        val, #value_err))
-                            }
-                        }
-                    }
-                    _ => {
-                        Err(::knuffel::errors::DecodeError::scalar_kind(
-                            ::knuffel::decode::Kind::String,
-                            &val,
-                        ))
-                    }
+                    #match_string
+                    #match_int
+                    #match_bool
+                    _ => #kind_fallback,
                 }
             }
             fn type_check(type_name: &Option<::knuffel::span::Spanned<
@@ -139,3 +492,37 @@ pub fn emit_enum(e: &Enum) -> syn::Result<TokenStream> {
         }
     })
 }
+
+fn emit_encode_enum(e: &Enum) -> syn::Result<TokenStream> {
+    let e_name = &e.ident;
+    let mut branches = e.variants.iter().map(|var| {
+        let ident = &var.ident;
+        match var.value {
+            Some(value) if e.int_repr => quote! {
+                #e_name::#ident => ::knuffel::traits::EncodeScalar
+                    ::encode_scalar(&(#value as i64)),
+            },
+            _ => {
+                let name = &var.name;
+                quote! {
+                    #e_name::#ident => ::knuffel::ast::Literal::String(
+                        #name.into()),
+                }
+            }
+        }
+    }).collect::<Vec<_>>();
+    if let Some(fb) = &e.fallback {
+        branches.push(quote! {
+            #e_name::#fb(value) => value.clone(),
+        });
+    }
+    Ok(quote! {
+        impl ::knuffel::traits::EncodeScalar for #e_name {
+            fn encode_scalar(&self) -> ::knuffel::ast::Literal {
+                match self {
+                    #(#branches)*
+                }
+            }
+        }
+    })
+}
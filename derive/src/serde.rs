@@ -0,0 +1,685 @@
+use proc_macro2::{TokenStream, Span};
+use quote::quote;
+use syn::ext::IdentExt;
+
+use crate::definition::{Struct, Field, ArgKind, ChildMode, DecodeMode};
+use crate::definition::ExtraKind;
+use crate::definition::type_params;
+
+
+fn unsupported(field: &Field, what: &str) -> syn::Error {
+    syn::Error::new(field.span,
+        format!("{} is not supported with `serde`", what))
+}
+
+fn check(s: &Struct) -> syn::Result<()> {
+    if !s.generics.params.is_empty() {
+        return Err(syn::Error::new(s.ident.span(),
+            "`serde` is not supported for generic structures yet"));
+    }
+    if let Some(group) = s.trait_props.exclusive.first() {
+        return Err(syn::Error::new(group.span,
+            "`one_of` and `at_most_one_of` are not supported with `serde`"));
+    }
+    if let Some(span) = s.spans.first() {
+        let what = if span.bytes { "`span_bytes`" } else { "`span`" };
+        return Err(unsupported(&span.field, what));
+    }
+    if let Some(node_name) = s.node_names.first() {
+        return Err(unsupported(&node_name.field, "`node_name`"));
+    }
+    if let Some(type_name) = s.type_names.first() {
+        return Err(unsupported(&type_name.field, "`type_name`"));
+    }
+    if let Some(type_of) = s.type_ofs.first() {
+        return Err(unsupported(&type_of.field, "`type_of`"));
+    }
+    if let Some(doc_comment) = s.doc_comments.first() {
+        return Err(unsupported(&doc_comment.field, "`doc_comment`"));
+    }
+    if let Some(extra) = s.extra_fields.iter()
+        .find(|f| matches!(f.kind, ExtraKind::Context))
+    {
+        return Err(unsupported(&extra.field, "`from_context`"));
+    }
+    for prop in &s.properties {
+        if prop.flatten {
+            return Err(unsupported(&prop.field, "`flatten`"));
+        }
+        if prop.literal_kind.is_some() {
+            return Err(unsupported(&prop.field, "`kind`"));
+        }
+        if prop.none_if.is_some() {
+            return Err(unsupported(&prop.field, "`none_if`"));
+        }
+        if prop.type_name.is_some() {
+            return Err(unsupported(&prop.field, "`type_name`"));
+        }
+    }
+    for child in &s.children {
+        if matches!(child.mode, ChildMode::Flatten) {
+            return Err(unsupported(&child.field, "`flatten`"));
+        }
+        if child.merge.is_some() {
+            return Err(unsupported(&child.field, "`merge`"));
+        }
+        if child.or_property {
+            return Err(unsupported(&child.field, "`property_or_child`"));
+        }
+    }
+    for arg in &s.arguments {
+        if arg.literal_kind.is_some() {
+            return Err(unsupported(&arg.field, "`kind`"));
+        }
+        if arg.none_if.is_some() {
+            return Err(unsupported(&arg.field, "`none_if`"));
+        }
+        if arg.type_name.is_some() {
+            return Err(unsupported(&arg.field, "`type_name`"));
+        }
+    }
+    if let Some(typed) = s.typed_children.first() {
+        return Err(unsupported(&typed.field, "`children` filtered by `type`"));
+    }
+    if let Some(var_children) = &s.var_children {
+        if var_children.dedup_by_name.is_some() {
+            return Err(unsupported(&var_children.field, "`dedup_by_name`"));
+        }
+    }
+    if let (Some(var_children), Some(_)) = (&s.var_children, s.children.first())
+    {
+        return Err(unsupported(&var_children.field,
+            "catch-all `children` combined with named children"));
+    }
+    Ok(())
+}
+
+/// Returns expression decoding the value
+///
+/// The `access` builds an expression of `Option<T>` for the type `T` passed,
+/// and the result is `Option` of the field value too. Decode modes that can't
+/// be expressed with `serde` are reported as errors.
+fn decode_value(field: &Field, access: impl Fn(TokenStream) -> TokenStream,
+                ty: &syn::Type, mode: &DecodeMode, option: bool)
+    -> syn::Result<TokenStream>
+{
+    let split_flags = |s: TokenStream, ty: TokenStream| quote! {
+        #s.split_whitespace().map(|token| {
+            ::knuffel::serde::Deserialize::deserialize(
+                ::knuffel::serde::de::IntoDeserializer
+                    ::<A::Error>::into_deserializer(token))
+        }).collect::<Result<#ty, A::Error>>()
+    };
+    // `decode` is evaluated with `value: &str` in scope
+    let decode_string = |value: &syn::Ident, decode: TokenStream| {
+        let access = access(if option {
+            quote!(Option<String>)
+        } else {
+            quote!(String)
+        });
+        let decode = quote! {
+            |s: String| {
+                let #value: &str = &s;
+                (#decode).map_err(A::Error::custom)
+            }
+        };
+        if option {
+            quote! {
+                #access.map(|v| v.map(#decode).transpose()).transpose()?
+            }
+        } else {
+            quote! {
+                #access.map(#decode).transpose()?
+            }
+        }
+    };
+    match mode {
+        DecodeMode::Str if option => {
+            let access = access(quote!(Option<String>));
+            Ok(quote! {
+                #access.map(|v| {
+                    v.map(|s| s.parse()).transpose().map_err(A::Error::custom)
+                }).transpose()?
+            })
+        }
+        DecodeMode::Str => {
+            let access = access(quote!(String));
+            Ok(quote! {
+                #access.map(|s| {
+                    s.parse::<#ty>().map_err(A::Error::custom)
+                }).transpose()?
+            })
+        }
+        DecodeMode::FromU32 if option => {
+            let access = access(quote!(Option<u32>));
+            Ok(quote! {
+                #access.map(|v| v.map(::std::convert::From::from))
+            })
+        }
+        DecodeMode::FromU32 => {
+            let access = access(quote!(u32));
+            Ok(quote! {
+                #access.map(::std::convert::From::from)
+            })
+        }
+        DecodeMode::SplitFlags if option => {
+            let access = access(quote!(Option<String>));
+            let split = split_flags(quote!(s), quote!(_));
+            Ok(quote! {
+                #access.map(|v| v.map(|s| #split).transpose()).transpose()?
+            })
+        }
+        DecodeMode::SplitFlags => {
+            let access = access(quote!(String));
+            let split = split_flags(quote!(s), quote!(#ty));
+            Ok(quote! {
+                #access.map(|s| #split).transpose()?
+            })
+        }
+        DecodeMode::Try(fns) => {
+            let value = syn::Ident::new("value", Span::mixed_site());
+            let chain = DecodeMode::try_chain(fns, &value);
+            Ok(decode_string(&value, chain))
+        }
+        DecodeMode::Units(table) => {
+            let value = syn::Ident::new("value", Span::mixed_site());
+            Ok(decode_string(&value, quote! {
+                ::knuffel::decode::parse_units(#value, #table)
+            }))
+        }
+        DecodeMode::Normal | DecodeMode::Bytes { url_safe: false } => {
+            Ok(access(quote!(#ty)))
+        }
+        DecodeMode::Bytes { url_safe: true } => {
+            Err(unsupported(field, "`bytes(base64_url)`"))
+        }
+        DecodeMode::Percent(_) => Err(unsupported(field, "`percent`")),
+        DecodeMode::Duration(_) => Err(unsupported(field, "`duration`")),
+        DecodeMode::Path { .. } => Err(unsupported(field, "`path`")),
+        DecodeMode::BoolWords => Err(unsupported(field, "`bool_words`")),
+    }
+}
+
+fn default_value(value: &Option<syn::Expr>) -> TokenStream {
+    if let Some(expr) = value {
+        quote!(#expr)
+    } else {
+        quote!(::std::default::Default::default())
+    }
+}
+
+fn arguments(s: &Struct, all: &mut Vec<TokenStream>)
+    -> syn::Result<Option<TokenStream>>
+{
+    if !s.has_arguments {
+        return Ok(None);
+    }
+    let seq = syn::Ident::new("seq", Span::mixed_site());
+    let section = syn::Ident::new("arguments", Span::mixed_site());
+    let value = syn::Ident::new("value", Span::mixed_site());
+    let mut slots = Vec::new();
+    let mut names = Vec::new();
+    let mut decoders = Vec::new();
+    for arg in &s.arguments {
+        let fld = &arg.field.tmp_name;
+        let ty = &arg.field.ty;
+        let decode = decode_value(&arg.field,
+                                  |t| quote!(#seq.next_element::<#t>()?),
+                                  ty, &arg.decode, arg.option)?;
+        let decode = if let Some(clamp) = &arg.clamp {
+            let clamped = clamp.apply(&value, arg.option);
+            quote!(#decode.map(|#value| #clamped))
+        } else {
+            decode
+        };
+        slots.push(quote!(#fld: Option<#ty>));
+        names.push(fld.clone());
+        decoders.push(quote! {
+            let #fld = #decode;
+        });
+        let finish = match (&arg.default, arg.option) {
+            (Some(value), _) => {
+                let default = default_value(value);
+                quote!(#fld.unwrap_or_else(|| #default))
+            }
+            (None, true) => quote!(#fld.unwrap_or(None)),
+            (None, false) => {
+                let error = if matches!(arg.kind, ArgKind::Key) {
+                    "node key is required".into()
+                } else if arg.field.is_indexed() {
+                    "additional argument is required".into()
+                } else {
+                    format!("additional argument `{}` is required",
+                            fld.unraw())
+                };
+                quote!(#fld.ok_or_else(|| A::Error::custom(#error))?)
+            }
+        };
+        all.push(quote! {
+            let #fld = #section.#fld;
+            let #fld = #finish;
+        });
+    }
+    if let Some(var_args) = &s.var_args {
+        let fld = &var_args.field.tmp_name;
+        let ty = &var_args.field.ty;
+        let elem = match (&var_args.item, type_params(ty).last()) {
+            (Some(elem), _) => elem,
+            (None, Some(elem)) => *elem,
+            (None, None) => return Err(syn::Error::new(var_args.field.span,
+                "cannot determine element type of `arguments` \
+                 for `serde`, use a collection like `Vec<T>`")),
+        };
+        let decode = decode_value(&var_args.field,
+                                  |t| quote!(#seq.next_element::<#t>()?),
+                                  elem, &var_args.decode, false)?;
+        slots.push(quote!(#fld: #ty));
+        names.push(fld.clone());
+        decoders.push(quote! {
+            let mut #fld = Vec::<#elem>::new();
+            while let Some(value) = #decode {
+                #fld.push(value);
+            }
+        });
+        if let Some(len) = &var_args.array_len {
+            decoders.push(quote! {
+                let #fld = ::std::convert::TryFrom::try_from(#fld)
+                    .map_err(|v: Vec<#elem>| A::Error::invalid_length(
+                        v.len(), &&*format!("{} arguments", #len)))?;
+            });
+        } else {
+            decoders.push(quote! {
+                let #fld = #fld.into_iter().collect();
+            });
+        }
+        all.push(quote! {
+            let #fld = #section.#fld;
+        });
+    } else {
+        decoders.push(quote! {
+            if #seq.next_element::<::knuffel::serde::de::IgnoredAny>()?
+                .is_some()
+            {
+                return Err(A::Error::custom("unexpected argument"));
+            }
+        });
+    }
+    Ok(Some(quote! {
+        struct Arguments { #(#slots,)* }
+        impl<'de> ::knuffel::serde::Deserialize<'de> for Arguments {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where D: ::knuffel::serde::Deserializer<'de>
+            {
+                struct Visitor;
+                impl<'de> ::knuffel::serde::de::Visitor<'de> for Visitor {
+                    type Value = Arguments;
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter)
+                        -> ::std::fmt::Result
+                    {
+                        f.write_str("a sequence of node arguments")
+                    }
+                    fn visit_seq<A>(self, mut #seq: A)
+                        -> Result<Arguments, A::Error>
+                        where A: ::knuffel::serde::de::SeqAccess<'de>
+                    {
+                        use ::knuffel::serde::de::Error;
+                        #(#decoders)*
+                        Ok(Arguments { #(#names,)* })
+                    }
+                }
+                deserializer.deserialize_seq(Visitor)
+            }
+        }
+    }))
+}
+
+fn properties(s: &Struct, all: &mut Vec<TokenStream>)
+    -> syn::Result<Option<TokenStream>>
+{
+    if !s.has_properties {
+        return Ok(None);
+    }
+    let map = syn::Ident::new("map", Span::mixed_site());
+    let section = syn::Ident::new("properties", Span::mixed_site());
+    let name_str = syn::Ident::new("name_str", Span::mixed_site());
+    let value = syn::Ident::new("value", Span::mixed_site());
+    let mut slots = Vec::new();
+    let mut names = Vec::new();
+    let mut declare_empty = Vec::new();
+    let mut match_branches = Vec::new();
+    for prop in &s.properties {
+        let fld = &prop.field.tmp_name;
+        let ty = &prop.field.ty;
+        let prop_name = &prop.name;
+        let decode = decode_value(&prop.field,
+                                  |t| quote!(Some(#map.next_value::<#t>()?)),
+                                  ty, &prop.decode, prop.option)?;
+        let decode = if let Some(clamp) = &prop.clamp {
+            let clamped = clamp.apply(&value, prop.option);
+            quote!(#decode.map(|#value| #clamped))
+        } else {
+            decode
+        };
+        slots.push(quote!(#fld: Option<#ty>));
+        names.push(fld.clone());
+        declare_empty.push(quote! {
+            let mut #fld = None;
+        });
+        let pattern = prop.name_pattern(&name_str);
+        match_branches.push(quote! {
+            #pattern => {
+                #fld = #decode;
+            }
+        });
+        let finish = match (&prop.default, prop.option) {
+            (Some(value), _) => {
+                let default = default_value(value);
+                quote!(#fld.unwrap_or_else(|| #default))
+            }
+            (None, true) => quote!(#fld.unwrap_or(None)),
+            (None, false) => {
+                let req_msg = format!("property `{}` is required", prop_name);
+                quote!(#fld.ok_or_else(|| A::Error::custom(#req_msg))?)
+            }
+        };
+        all.push(quote! {
+            let #fld = #section.#fld;
+            let #fld = #finish;
+        });
+    }
+    let postprocess = if let Some(var_props) = &s.var_props {
+        let fld = &var_props.field.tmp_name;
+        let ty = &var_props.field.ty;
+        let (key, value) = match &type_params(ty)[..] {
+            [key, value] => (*key, *value),
+            _ => return Err(syn::Error::new(var_props.field.span,
+                "cannot determine key and value types of `properties` \
+                 for `serde`, use a map like `HashMap<K, V>`")),
+        };
+        let decode = decode_value(&var_props.field,
+                                  |t| quote!(Some(#map.next_value::<#t>()?)),
+                                  value, &var_props.decode, false)?;
+        slots.push(quote!(#fld: #ty));
+        names.push(fld.clone());
+        declare_empty.push(quote! {
+            let mut #fld = Vec::<(#key, #value)>::new();
+        });
+        match_branches.push(quote! {
+            _ => {
+                let converted_name = name.parse()
+                    .map_err(A::Error::custom)?;
+                if let Some(value) = #decode {
+                    #fld.push((converted_name, value));
+                }
+            }
+        });
+        all.push(quote! {
+            let #fld = #section.#fld;
+        });
+        Some(quote! {
+            let #fld = #fld.into_iter().collect();
+        })
+    } else {
+        match_branches.push(quote! {
+            _ => {
+                return Err(A::Error::custom(format!(
+                    "unexpected property `{}`", name.escape_default())));
+            }
+        });
+        None
+    };
+    Ok(Some(quote! {
+        struct Properties { #(#slots,)* }
+        impl<'de> ::knuffel::serde::Deserialize<'de> for Properties {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where D: ::knuffel::serde::Deserializer<'de>
+            {
+                struct Visitor;
+                impl<'de> ::knuffel::serde::de::Visitor<'de> for Visitor {
+                    type Value = Properties;
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter)
+                        -> ::std::fmt::Result
+                    {
+                        f.write_str("a map of node properties")
+                    }
+                    fn visit_map<A>(self, mut #map: A)
+                        -> Result<Properties, A::Error>
+                        where A: ::knuffel::serde::de::MapAccess<'de>
+                    {
+                        use ::knuffel::serde::de::Error;
+                        #(#declare_empty)*
+                        while let Some(name) = #map.next_key::<String>()? {
+                            match &name[..] {
+                                #(#match_branches)*
+                            }
+                        }
+                        #postprocess
+                        Ok(Properties { #(#names,)* })
+                    }
+                }
+                deserializer.deserialize_map(Visitor)
+            }
+        }
+    }))
+}
+
+fn children(s: &Struct, all: &mut Vec<TokenStream>)
+    -> syn::Result<Option<TokenStream>>
+{
+    let section = syn::Ident::new("children", Span::mixed_site());
+    if let Some(var_children) = &s.var_children {
+        let fld = &var_children.field.tmp_name;
+        let ty = &var_children.field.ty;
+        all.push(quote! {
+            let #fld = #section.unwrap_or_default();
+        });
+        return Ok(Some(quote! {
+            type Children = #ty;
+        }));
+    }
+    if s.children.is_empty() {
+        return Ok(None);
+    }
+    let map = syn::Ident::new("map", Span::mixed_site());
+    let mut slots = Vec::new();
+    let mut names = Vec::new();
+    let mut declare_empty = Vec::new();
+    let mut match_branches = Vec::new();
+    for child in &s.children {
+        let fld = &child.field.tmp_name;
+        let ty = &child.field.ty;
+        let child_name = &child.name;
+        let dup_err = format!("duplicate node `{}`, single node expected",
+                              child_name.escape_default());
+        slots.push(quote!(#fld: Option<#ty>));
+        names.push(fld.clone());
+        declare_empty.push(quote! {
+            let mut #fld = None;
+        });
+        match_branches.push(quote! {
+            #child_name => {
+                if #fld.is_some() {
+                    return Err(A::Error::custom(#dup_err));
+                }
+                #fld = Some(#map.next_value::<#ty>()?);
+            }
+        });
+        let finish = match (&child.default, &child.mode, child.option) {
+            (Some(value), _, _) => {
+                let default = default_value(value);
+                quote!(#fld.unwrap_or_else(|| #default))
+            }
+            (None, _, true) => quote!(#fld.unwrap_or(None)),
+            (None, ChildMode::Bool, false) => quote!(#fld.unwrap_or(false)),
+            (None, ChildMode::Multi, false) => quote!(#fld.unwrap_or_default()),
+            (None, _, false) => {
+                let req_msg = format!("child node `{}` is required",
+                                      child_name);
+                quote!(#fld.ok_or_else(|| A::Error::custom(#req_msg))?)
+            }
+        };
+        all.push(quote! {
+            let #fld = #section.#fld;
+            let #fld = #finish;
+        });
+    }
+    Ok(Some(quote! {
+        struct Children { #(#slots,)* }
+        impl<'de> ::knuffel::serde::Deserialize<'de> for Children {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where D: ::knuffel::serde::Deserializer<'de>
+            {
+                struct Visitor;
+                impl<'de> ::knuffel::serde::de::Visitor<'de> for Visitor {
+                    type Value = Children;
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter)
+                        -> ::std::fmt::Result
+                    {
+                        f.write_str("a map of child nodes")
+                    }
+                    fn visit_map<A>(self, mut #map: A)
+                        -> Result<Children, A::Error>
+                        where A: ::knuffel::serde::de::MapAccess<'de>
+                    {
+                        use ::knuffel::serde::de::Error;
+                        #(#declare_empty)*
+                        while let Some(name) = #map.next_key::<String>()? {
+                            match &name[..] {
+                                #(#match_branches)*
+                                _ => {
+                                    return Err(A::Error::custom(format!(
+                                        "unexpected node `{}`",
+                                        name.escape_default())));
+                                }
+                            }
+                        }
+                        Ok(Children { #(#names,)* })
+                    }
+                }
+                deserializer.deserialize_map(Visitor)
+            }
+        }
+    }))
+}
+
+pub fn emit_serde(s: &Struct, named: bool) -> syn::Result<TokenStream> {
+    check(s)?;
+    let s_name = &s.ident;
+    let map = syn::Ident::new("map", Span::mixed_site());
+    let mut assign = Vec::new();
+    let mut sections = Vec::new();
+    let mut section_names = Vec::new();
+    let mut declare_empty = Vec::new();
+    let mut match_branches = Vec::new();
+
+    let arguments = arguments(s, &mut assign)?;
+    let properties = properties(s, &mut assign)?;
+    let children = children(s, &mut assign)?;
+    for (key, def) in [("arguments", arguments),
+                       ("properties", properties),
+                       ("children", children)]
+    {
+        let ident = syn::Ident::new(key, Span::mixed_site());
+        let ty = syn::Ident::new(
+            &heck::ToUpperCamelCase::to_upper_camel_case(key),
+            Span::mixed_site());
+        if let Some(def) = def {
+            let dup_err = format!("duplicate field `{}`", key);
+            sections.push(def);
+            section_names.push(key);
+            declare_empty.push(quote! {
+                let mut #ident = None::<#ty>;
+            });
+            match_branches.push(quote! {
+                #key => {
+                    if #ident.is_some() {
+                        return Err(A::Error::custom(#dup_err));
+                    }
+                    #ident = Some(#map.next_value()?);
+                }
+            });
+            // missing section is treated as an empty one
+            let empty = if key == "arguments" {
+                quote! {
+                    ::knuffel::serde::de::value::SeqDeserializer
+                        ::<_, A::Error>::new(::std::iter::empty::<()>())
+                }
+            } else {
+                quote! {
+                    ::knuffel::serde::de::value::MapDeserializer
+                        ::<_, A::Error>::new(::std::iter::empty::<((), ())>())
+                }
+            };
+            if key != "children" || s.var_children.is_none() {
+                assign.insert(0, quote! {
+                    let #ident = match #ident {
+                        Some(value) => value,
+                        None => {
+                            ::knuffel::serde::Deserialize::deserialize(
+                                #empty)?
+                        }
+                    };
+                });
+            }
+        }
+    }
+
+    for extra in &s.extra_fields {
+        let fld = &extra.field.tmp_name;
+        assign.push(quote! {
+            let #fld = ::std::default::Default::default();
+        });
+    }
+
+    let all_fields = s.all_fields();
+    let struct_val = if named {
+        let assignments = all_fields.iter()
+            .map(|f| f.as_assign_pair().unwrap());
+        quote!(#s_name { #(#assignments,)* })
+    } else {
+        let mut fields = all_fields.iter()
+            .map(|f| (f.as_index().unwrap(), &f.tmp_name))
+            .collect::<Vec<_>>();
+        fields.sort_by_key(|(idx, _)| *idx);
+        let assignments = fields.iter().map(|(_, v)| v);
+        quote!{ #s_name(#(#assignments),*) }
+    };
+    let expecting = format!("a KDL node decoded into `{}`", s_name.unraw());
+    Ok(quote! {
+        impl<'de> ::knuffel::serde::Deserialize<'de> for #s_name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+                where D: ::knuffel::serde::Deserializer<'de>
+            {
+                #(#sections)*
+                struct Visitor;
+                impl<'de> ::knuffel::serde::de::Visitor<'de> for Visitor {
+                    type Value = #s_name;
+                    fn expecting(&self, f: &mut ::std::fmt::Formatter)
+                        -> ::std::fmt::Result
+                    {
+                        f.write_str(#expecting)
+                    }
+                    fn visit_map<A>(self, mut #map: A)
+                        -> Result<#s_name, A::Error>
+                        where A: ::knuffel::serde::de::MapAccess<'de>
+                    {
+                        use ::knuffel::serde::de::Error;
+                        #(#declare_empty)*
+                        while let Some(key) = #map.next_key::<String>()? {
+                            match &key[..] {
+                                #(#match_branches)*
+                                _ => {
+                                    return Err(A::Error::unknown_field(
+                                        &key, &[#(#section_names),*]));
+                                }
+                            }
+                        }
+                        #(#assign)*
+                        Ok(#struct_val)
+                    }
+                }
+                deserializer.deserialize_map(Visitor)
+            }
+        }
+    })
+}
@@ -1,6 +1,7 @@
 use proc_macro2::{TokenStream, Span};
 use quote::quote;
 
+use crate::bounds;
 use crate::definition::{Enum, VariantKind};
 use crate::node;
 
@@ -22,15 +23,11 @@ pub fn emit_enum(e: &Enum) -> syn::Result<TokenStream> {
     if let Some(ty) = e.trait_props.span_type.as_ref() {
         span_ty = quote!(#ty);
     } else {
-        if common_generics.params.is_empty() {
-            common_generics.lt_token = Some(Default::default());
-            common_generics.gt_token = Some(Default::default());
-        }
-        common_generics.params.push(syn::parse2(quote!(S)).unwrap());
+        bounds::add_span_param(&mut common_generics);
         span_ty = quote!(S);
-        common_generics.make_where_clause().predicates.push(
-            syn::parse2(quote!(S: ::knuffel::traits::ErrorSpan)).unwrap());
     };
+    common_generics.make_where_clause().predicates
+        .extend(bounds::decode_enum(e, &span_ty));
     let trait_gen = quote!(<#span_ty>);
     let (impl_gen, _, bounds) = common_generics.split_for_impl();
 
@@ -73,6 +70,9 @@ fn decode(e: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
                                     "unexpected argument"));
                         }
                         for (name, _) in &#node.properties {
+                            if #ctx.is_consumed(name) {
+                                continue;
+                            }
                             #ctx.emit_error(
                                 ::knuffel::errors::DecodeError::unexpected(
                                     name, "property",
@@ -93,17 +93,18 @@ fn decode(e: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
                     }
                 });
             }
-            VariantKind::Nested { option: false } => {
+            VariantKind::Nested { option: false, .. } => {
                 branches.push(quote! {
                     #name => ::knuffel::Decode::decode_node(#node, #ctx)
                         .map(#enum_name::#variant_name),
                 });
             }
-            VariantKind::Nested { option: true } => {
+            VariantKind::Nested { option: true, .. } => {
                 branches.push(quote! {
                     #name => {
                         if #node.arguments.len() > 0 ||
-                            #node.properties.len() > 0 ||
+                            #node.properties.keys()
+                                .any(|k| !#ctx.is_consumed(k)) ||
                             #node.children.is_some()
                         {
                             ::knuffel::Decode::decode_node(#node, #ctx)
@@ -115,7 +116,7 @@ fn decode(e: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
                     }
                 });
             }
-            VariantKind::Tuple(s) => {
+            VariantKind::Tuple(s) | VariantKind::Named(s) => {
                 let common = node::Common {
                     object: s,
                     ctx,
@@ -125,13 +126,12 @@ fn decode(e: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
                     &common,
                     quote!(#enum_name::#variant_name),
                     node,
-                    false,
+                    matches!(var.kind, VariantKind::Named(_)),
                 )?;
                 branches.push(quote! {
                     #name => { #decode }
                 });
             }
-            VariantKind::Named(_) => todo!(),
         }
     }
     // TODO(tailhook) use strsim to find similar names
@@ -147,6 +147,28 @@ fn decode(e: &Common, node: &syn::Ident) -> syn::Result<TokenStream> {
                 e.object.variants[1].name.escape_default(),
                 e.object.variants.len() - 2)
     };
+    if let Some(tag) = &e.object.tag {
+        let missing = format!("property `{}` is required",
+                              tag.escape_default());
+        return Ok(quote! {
+            let (tag_name, tag_value) = #node.properties.get_key_value(#tag)
+                .ok_or_else(|| ::knuffel::errors::DecodeError::missing(
+                    #node, #missing))?;
+            let tag_str = match &*tag_value.literal {
+                ::knuffel::ast::Literal::String(s) => s,
+                _ => return Err(::knuffel::errors::DecodeError::scalar_kind(
+                    ::knuffel::decode::Kind::String, &tag_value.literal)),
+            };
+            // the tag is consumed here, so variants don't see it
+            #ctx.with_consumed(tag_name, |#ctx| match &tag_str[..] {
+                #(#branches)*
+                _ => {
+                    Err(::knuffel::errors::DecodeError::conversion(
+                            &tag_value.literal, #err))
+                }
+            })
+        });
+    }
     Ok(quote! {
         match &**#node.node_name {
             #(#branches)*
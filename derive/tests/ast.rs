@@ -8,6 +8,21 @@ struct AstChildren {
     children: Vec<knuffel::ast::SpannedNode<Span>>,
 }
 
+#[derive(knuffel_derive::Decode, Debug)]
+struct Header {
+    #[knuffel(argument)]
+    title: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug)]
+#[knuffel(span_type=knuffel::span::Span)]
+struct HeaderAndRest {
+    #[knuffel(child)]
+    header: Header,
+    #[knuffel(children)]
+    rest: Vec<knuffel::ast::Node<Span>>,
+}
+
 fn parse<T: Decode<Span>>(text: &str) -> T {
     let mut nodes: Vec<T> = knuffel::parse("<test>", text).unwrap();
     assert_eq!(nodes.len(), 1);
@@ -19,3 +34,27 @@ fn parse_node_span() {
     let item = parse::<AstChildren>(r#"node {a; b;}"#);
     assert_eq!(item.children.len(), 2);
 }
+
+#[test]
+fn parse_header_and_rest() {
+    let item = parse::<HeaderAndRest>(r#"doc {
+        header "Title"
+        para "one"
+        (note)para "two" {
+            raw 1
+        }
+        footer
+    }"#);
+    assert_eq!(item.header.title, "Title");
+    assert_eq!(item.rest.iter().map(|n| &**n.node_name).collect::<Vec<_>>(),
+               vec!["para", "para", "footer"]);
+    assert_eq!(item.rest[1].type_name.as_ref().map(|t| t.as_str()),
+               Some("note"));
+    assert_eq!(item.rest[1].children.as_ref().unwrap().len(), 1);
+
+    // named children are extracted wherever they are
+    let item = parse::<HeaderAndRest>(r#"doc { para "x"; header "T"; end; }"#);
+    assert_eq!(item.header.title, "T");
+    assert_eq!(item.rest.iter().map(|n| &**n.node_name).collect::<Vec<_>>(),
+               vec!["para", "end"]);
+}
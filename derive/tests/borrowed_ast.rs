@@ -0,0 +1,67 @@
+//! Checks that the borrowed AST doesn't copy strings from the source
+//!
+//! This is a separate test binary because it installs a counting allocator.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use knuffel::span::Span;
+
+
+struct Counting;
+
+// per thread, so allocations of the test harness are not counted
+thread_local! {
+    #[allow(clippy::missing_const_for_thread_local)]  // const needs rust 1.59
+    static LIVE_BYTES: Cell<isize> = Cell::new(0);
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.with(|n| n.set(n.get() + layout.size() as isize));
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.with(|n| n.set(n.get() - layout.size() as isize));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// Bytes of heap held by the value returned from `f`
+fn retained<T>(f: impl FnOnce() -> T) -> isize {
+    let before = LIVE_BYTES.with(Cell::get);
+    let value = f();
+    let after = LIVE_BYTES.with(Cell::get);
+    drop(value);
+    after - before
+}
+
+fn document(name_len: usize) -> String {
+    let name = "x".repeat(name_len);
+    format!(r##"
+        (t{name}){name} "{name}" r#"{name}"# {name}="{name}" {{
+            "{name}" null true (t{name})"{name}"
+        }}
+    "##, name=name)
+}
+
+#[test]
+fn no_string_allocations() {
+    let short = document(1);
+    let long = document(1000);
+    let parse_ref = |text| move || {
+        knuffel::parse_ast_ref::<Span>("<test>", text).unwrap()
+    };
+    let parse = |text| move || {
+        knuffel::parse_ast::<Span>("<test>", text).unwrap()
+    };
+    // warm up any lazily initialized state
+    retained(parse_ref(&short));
+
+    assert_eq!(retained(parse_ref(&short)), retained(parse_ref(&long)));
+    // sanity check that the owned AST is measured as growing
+    assert!(retained(parse(&short)) < retained(parse(&long)));
+}
@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+
+use knuffel::{Decode, Encode};
+use knuffel::encode::PrintOptions;
+use knuffel::span::Span;
+use knuffel::traits::{DecodeChildren, EncodeChildren};
+
+
+#[derive(knuffel_derive::DecodeScalar, knuffel_derive::EncodeScalar)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Debug,
+    Info,
+    WarnOnly,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+#[knuffel(rename_all="camelCase")]
+struct Server {
+    #[knuffel(argument)]
+    name: String,
+    #[knuffel(argument, str)]
+    addr: std::net::SocketAddr,
+    #[knuffel(arguments)]
+    aliases: Vec<String>,
+    #[knuffel(property)]
+    max_connections: u32,
+    #[knuffel(property(name="log"))]
+    log_level: Option<Level>,
+    #[knuffel(property, type_name="ratio")]
+    weight: f64,
+    #[knuffel(properties)]
+    labels: HashMap<String, String>,
+    #[knuffel(child)]
+    tls: Option<Tls>,
+    #[knuffel(child)]
+    enabled: bool,
+    #[knuffel(child, unwrap(argument))]
+    root_dir: Option<String>,
+    #[knuffel(children(name="route"))]
+    routes: Vec<Route>,
+    #[knuffel(children(name="header"), unwrap(arguments))]
+    headers: Vec<Vec<String>>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Tls {
+    #[knuffel(property)]
+    cert: String,
+    #[knuffel(property)]
+    key: Option<String>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Route {
+    #[knuffel(argument)]
+    path: String,
+    #[knuffel(children(name="route"))]
+    subroutes: Vec<Route>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+enum TopLevel {
+    Server(Box<Server>),
+    Include(#[knuffel(argument)] String),
+    Quit,
+    Log {
+        #[knuffel(argument)]
+        level: Level,
+        #[knuffel(property)]
+        to: Option<String>,
+    },
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Config {
+    #[knuffel(child, unwrap(argument))]
+    version: u32,
+    #[knuffel(children)]
+    items: BTreeMap<String, Item>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Item {
+    #[knuffel(argument)]
+    value: Option<i64>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+#[knuffel(tag="type")]
+enum Field {
+    Int {
+        #[knuffel(property)]
+        min: i32,
+    },
+    Text,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Form {
+    #[knuffel(children(name="field"))]
+    fields: Vec<Field>,
+}
+
+#[derive(knuffel_derive::DecodeScalar, knuffel_derive::EncodeScalar)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Percent,
+    Px,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Size {
+    #[knuffel(argument)]
+    value: f64,
+    #[knuffel(type_of=value)]
+    value_unit: Unit,
+    #[knuffel(property)]
+    min: Option<f64>,
+    #[knuffel(type_of=min)]
+    min_unit: Option<Unit>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Subnet {
+    #[knuffel(argument)]
+    net: ipnet::IpNet,
+    #[knuffel(property)]
+    share: num_rational::Rational64,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+enum Action {
+    Run,
+    #[knuffel(skip)]
+    #[allow(dead_code)]
+    Internal(u32),
+}
+
+fn round_trip<T>(value: &T) -> String
+    where T: DecodeChildren<Span> + EncodeChildren + Debug + PartialEq,
+{
+    let text = knuffel::to_string_pretty(value);
+    match knuffel::parse::<T>("<test>", &text) {
+        Ok(decoded) => assert_eq!(&decoded, value, "{}", text),
+        Err(e) => panic!("{:?}\n{}", miette::Report::new(e), text),
+    }
+    let compact = knuffel::to_string(value);
+    match knuffel::parse::<T>("<test>", &compact) {
+        Ok(decoded) => assert_eq!(&decoded, value, "{}", compact),
+        Err(e) => panic!("{:?}\n{}", miette::Report::new(e), compact),
+    }
+    text
+}
+
+fn server() -> Server {
+    Server {
+        name: "main".into(),
+        addr: "127.0.0.1:8080".parse().unwrap(),
+        aliases: vec!["www".into(), "api server".into()],
+        max_connections: 100,
+        log_level: Some(Level::WarnOnly),
+        weight: 0.5,
+        labels: vec![
+            ("zone".into(), "eu".into()),
+            ("app".into(), "web \"x\"".into()),
+        ].into_iter().collect(),
+        tls: Some(Tls { cert: "a.pem".into(), key: None }),
+        enabled: true,
+        root_dir: None,
+        routes: vec![
+            Route {
+                path: "/api".into(),
+                subroutes: vec![
+                    Route { path: "/api/v1".into(), subroutes: vec![] },
+                ],
+            },
+        ],
+        headers: vec![vec!["X-A".into(), "1".into()], vec![]],
+    }
+}
+
+#[test]
+fn encode_struct() {
+    let text = round_trip(&vec![TopLevel::Server(Box::new(server()))]);
+    assert_eq!(text, r#"server "main" "127.0.0.1:8080" "www" "api server" maxConnections=100 log="warn-only" weight=(ratio)0.5 app="web \"x\"" zone="eu" {
+    tls cert="a.pem"
+    enabled
+    route "/api" {
+        route "/api/v1"
+    }
+    header "X-A" "1"
+    header
+}
+"#);
+}
+
+#[test]
+fn encode_enum() {
+    let items = vec![
+        TopLevel::Include("other.kdl".into()),
+        TopLevel::Quit,
+        TopLevel::Log { level: Level::Debug, to: None },
+        TopLevel::Log { level: Level::Info, to: Some("stderr".into()) },
+    ];
+    let text = round_trip(&items);
+    assert_eq!(text, "include \"other.kdl\"\nquit\nlog \"debug\"\n\
+                      log \"info\" to=\"stderr\"\n");
+    assert_eq!(knuffel::to_string(&items),
+               r#"include "other.kdl"; quit; log "debug"; log "info" to="stderr";"#);
+}
+
+#[test]
+fn encode_tagged_enum() {
+    let form = Form {
+        fields: vec![Field::Int { min: -1 }, Field::Text],
+    };
+    let text = round_trip(&form);
+    assert_eq!(text, "field type=\"int\" min=-1\nfield type=\"text\"\n");
+}
+
+#[test]
+fn encode_document() {
+    let config = Config {
+        version: 2,
+        items: vec![
+            ("zero".into(), Item { value: Some(0) }),
+            ("empty".into(), Item { value: None }),
+            ("1st".into(), Item { value: Some(-5) }),
+        ].into_iter().collect(),
+    };
+    let text = round_trip(&config);
+    assert_eq!(text, "version 2\n\"1st\" -5\nempty null\nzero 0\n");
+}
+
+#[test]
+fn indentation() {
+    let routes = vec![Route {
+        path: "/".into(),
+        subroutes: vec![Route { path: "/a".into(), subroutes: vec![] }],
+    }];
+    assert_eq!(
+        knuffel::to_string_with_options(&routes,
+                                         &PrintOptions { indent: 2 }),
+        "route \"/\" {\n  route \"/a\"\n}\n");
+    assert_eq!(knuffel::to_string(&routes),
+               r#"route "/" { route "/a"; };"#);
+}
+
+#[test]
+fn encode_type_of() {
+    let sizes = vec![
+        Size { value: 50.0, value_unit: Unit::Percent,
+               min: Some(10.0), min_unit: Some(Unit::Px) },
+        Size { value: 1.5, value_unit: Unit::Px,
+               min: Some(2.0), min_unit: None },
+    ];
+    let text = round_trip(&sizes);
+    assert_eq!(text, "size (percent)50.0 min=(px)10.0\n\
+                      size (px)1.5 min=2.0\n");
+}
+
+#[test]
+fn encode_skipped_variant() {
+    let actions = vec![Action::Run, Action::Internal(1), Action::Run];
+    assert_eq!(knuffel::to_string(&actions), "run; run;");
+}
+
+#[test]
+fn encode_display_scalars() {
+    let subnets = vec![Subnet {
+        net: "10.0.0.0/8".parse().unwrap(),
+        share: num_rational::Rational64::new(2, 6),
+    }];
+    let text = round_trip(&subnets);
+    assert_eq!(text, "subnet \"10.0.0.0/8\" share=\"1/3\"\n");
+}
@@ -1,4 +1,4 @@
-use knuffel::span::Span;
+use knuffel::span::{Span, Spanned, LineSpan, LineColSpan, LinePos};
 use knuffel::traits::Decode;
 use knuffel::ast::{TypeName, BuiltinType};
 
@@ -16,6 +16,23 @@ struct NodeSpan {
     children: Vec<Child>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct NodeBytes {
+    #[knuffel(span_bytes)]
+    loc: (usize, usize),
+    #[knuffel(children)]
+    children: Vec<NodeBytes>,
+}
+
+#[derive(knuffel_derive::Decode, Debug)]
+#[knuffel(span_type=LineColSpan)]
+struct NodeLineCol {
+    #[knuffel(span)]
+    span: LineColSpan,
+    #[knuffel(argument)]
+    value: Spanned<String, LineColSpan>,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct NodeType {
     #[knuffel(type_name)]
@@ -30,6 +47,30 @@ struct NameAndType {
     type_name: Option<TypeName>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+struct BaseDir(String);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Profile(&'static str);
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Include {
+    #[knuffel(argument)]
+    path: String,
+    #[knuffel(from_context)]
+    base: BaseDir,
+    #[knuffel(from_context)]
+    profile: Option<Profile>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Config {
+    #[knuffel(children(name="include"))]
+    includes: Vec<Include>,
+    #[knuffel(from_context)]
+    base: BaseDir,
+}
+
 fn parse<T: Decode<Span>>(text: &str) -> T {
     let mut nodes: Vec<T> = knuffel::parse("<test>", text).unwrap();
     assert_eq!(nodes.len(), 1);
@@ -64,6 +105,56 @@ fn parse_node_span() {
                });
 }
 
+#[test]
+fn parse_node_span_bytes() {
+    assert_eq!(parse::<NodeBytes>(r#"  node { child; }"#),
+               NodeBytes {
+                   loc: (2, 17),
+                   children: vec![NodeBytes { loc: (9, 15), children: vec![] }],
+               });
+    // offsets are the same for any span type
+    let nodes = knuffel::parse::<Vec<NodeBytes>>("<test>", "a\nbb").unwrap();
+    let lines = knuffel::parse_with_context::<Vec<NodeBytes>, LineSpan, _>(
+        "<test>", "a\nbb", |_| {}).unwrap();
+    assert_eq!(nodes, lines);
+    assert_eq!(lines.iter().map(|n| n.loc).collect::<Vec<_>>(),
+               vec![(0, 2), (2, 4)]);
+}
+
+fn pos(offset: usize, line: usize, column: usize) -> LinePos {
+    LinePos { offset, line, column }
+}
+
+#[test]
+fn parse_line_col_span() {
+    let nodes = knuffel::parse_with_span::<LineColSpan, Vec<NodeLineCol>>(
+        "<test>", "n \"h\u{e9}llo\"\n\tn \"\u{65e5}\u{672c}\"").unwrap();
+    // multi-byte characters and tabs advance column by one
+    assert_eq!(nodes[0].span.0, pos(0, 0, 0));
+    assert_eq!(nodes[0].value.span(),
+               &LineColSpan(pos(2, 0, 2), pos(10, 0, 9)));
+    assert_eq!(nodes[1].span.0, pos(12, 1, 1));
+    assert_eq!(nodes[1].value.span(),
+               &LineColSpan(pos(14, 1, 3), pos(22, 1, 7)));
+    assert_eq!(nodes[1].value.span().to_string(), "2:4..2:8");
+}
+
+#[test]
+fn error_line_col() {
+    let text = "\u{e9} {}\n\t\"\u{fc}\" 1";
+    let err = knuffel::parse::<Vec<NodeBytes>>("<test>", text).unwrap_err();
+    let diagnostics = err.to_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].span_start, Some(12));
+    assert_eq!((diagnostics[0].line, diagnostics[0].column),
+               (Some(2), Some(6)));
+    assert_eq!(diagnostics[0].to_string(),
+               "2:6: unexpected argument for node `\\u{fc}`");
+    let diagnostics = err.to_diagnostics_with_tab_width(4);
+    assert_eq!((diagnostics[0].line, diagnostics[0].column),
+               (Some(2), Some(9)));
+}
+
 #[test]
 fn parse_node_type() {
     assert_eq!(parse::<NodeType>(r#"(unknown)node {}"#),
@@ -84,3 +175,49 @@ fn parse_name_and_type() {
                    type_name: None,
                });
 }
+
+#[test]
+fn parse_from_context() {
+    let cfg = knuffel::parse_with_context::<Config, Span, _>(
+        "<test>", r#"include "a.kdl""#,
+        |ctx| ctx.set(BaseDir("/etc".into()))).unwrap();
+    assert_eq!(cfg, Config {
+        includes: vec![Include {
+            path: "a.kdl".into(),
+            base: BaseDir("/etc".into()),
+            profile: None,
+        }],
+        base: BaseDir("/etc".into()),
+    });
+    let cfg = knuffel::parse_with_context::<Config, Span, _>(
+        "<test>", r#"include "a.kdl""#,
+        |ctx| {
+            ctx.set(BaseDir("/etc".into()));
+            ctx.set(Profile("dev"));
+        }).unwrap();
+    assert_eq!(cfg.includes[0].profile, Some(Profile("dev")));
+
+    let err = knuffel::parse::<Config>("<test>", r#"include "a.kdl""#)
+        .unwrap_err();
+    assert_eq!(err.to_diagnostics().into_iter().map(|d| d.message)
+                  .collect::<Vec<_>>(),
+               vec!["context value of type `BaseDir` is required"]);
+}
+
+#[test]
+fn parse_str_source_name() {
+    use miette::Diagnostic;
+
+    let text = "node 1 {}";
+    let named = knuffel::parse::<Vec<NodeSpan>>("<test>", text).unwrap_err();
+    let unnamed = knuffel::parse_str::<Vec<NodeSpan>>(text).unwrap_err();
+    assert_eq!(unnamed.to_diagnostics(), named.to_diagnostics());
+    let label = unnamed.related().unwrap().next().unwrap()
+        .labels().unwrap().next().unwrap();
+    let source = unnamed.source_code().unwrap()
+        .read_span(label.inner(), 0, 0).unwrap();
+    assert_eq!(source.name(), Some("<string>"));
+
+    let parsed = knuffel::parse_str::<Vec<NodeSpan>>(r#"node "x""#).unwrap();
+    assert_eq!(parsed[0].span, Span(0, 8));
+}
@@ -18,6 +18,18 @@ struct FlatProp {
     props: Prop1,
 }
 
+#[derive(knuffel_derive::Decode, Default, Debug, PartialEq)]
+struct CaseProp {
+    #[knuffel(property, case_insensitive)]
+    label: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct FlatCaseProp {
+    #[knuffel(flatten(property))]
+    props: CaseProp,
+}
+
 #[derive(knuffel_derive::Decode, Default, Debug, PartialEq)]
 struct Unwrap {
     #[knuffel(child, unwrap(argument))]
@@ -30,6 +42,64 @@ struct FlatChild {
     children: Unwrap,
 }
 
+#[derive(knuffel_derive::Decode, Default, Debug, PartialEq)]
+struct Timeouts {
+    #[knuffel(property)]
+    connect: Option<u32>,
+    #[knuffel(child, unwrap(argument))]
+    read: Option<u32>,
+    #[knuffel(property)]
+    label: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode, Default, Debug, PartialEq)]
+struct Auth {
+    #[knuffel(property)]
+    user: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    token: Option<String>,
+    #[knuffel(property)]
+    label: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Server {
+    #[knuffel(flatten)]
+    timeouts: Timeouts,
+    #[knuffel(flatten)]
+    auth: Auth,
+    #[knuffel(property)]
+    host: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct LabeledServer {
+    #[knuffel(flatten)]
+    timeouts: Timeouts,
+    #[knuffel(flatten)]
+    auth: Auth,
+    #[knuffel(property)]
+    label: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Stage {
+    #[knuffel(argument)]
+    order: u32,
+}
+
+#[derive(knuffel_derive::Decode, Default, Debug, PartialEq)]
+struct Stages {
+    #[knuffel(children(name="stage"), ordered_by=order)]
+    stages: Option<Vec<Stage>>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct FlatStages {
+    #[knuffel(flatten(child))]
+    pipeline: Stages,
+}
+
 
 fn parse<T: Decode<Span>>(text: &str) -> T {
     let mut nodes: Vec<T> = knuffel::parse("<test>", text).unwrap();
@@ -63,6 +133,14 @@ fn parse_flat_prop() {
         "unexpected property `something`");
 }
 
+#[test]
+fn parse_flat_case_insensitive_prop() {
+    assert_eq!(parse::<FlatCaseProp>(r#"node LABEL="hello""#),
+        FlatCaseProp { props: CaseProp { label: Some("hello".into()) } } );
+    assert_eq!(parse::<FlatCaseProp>(r#"node label="hello""#),
+        FlatCaseProp { props: CaseProp { label: Some("hello".into()) } } );
+}
+
 #[test]
 fn parse_flat_child() {
     assert_eq!(parse_doc::<FlatChild>(r#"label "hello""#),
@@ -70,3 +148,68 @@ fn parse_flat_child() {
     assert_eq!(parse_doc_err::<FlatChild>(r#"something "world""#),
         "unexpected node `something`");
 }
+
+#[test]
+fn parse_flat_groups() {
+    assert_eq!(parse::<Server>(r#"
+        server host="a" connect=5 user="admin" {
+            read 10
+            token "secret"
+        }
+    "#), Server {
+        timeouts: Timeouts {
+            connect: Some(5),
+            read: Some(10),
+            label: None,
+        },
+        auth: Auth {
+            user: Some("admin".into()),
+            token: Some("secret".into()),
+            label: None,
+        },
+        host: "a".into(),
+    });
+    assert_eq!(parse::<Server>(r#"server host="b""#), Server {
+        timeouts: Timeouts::default(),
+        auth: Auth::default(),
+        host: "b".into(),
+    });
+    assert_eq!(parse_err::<Server>(r#"server host="a" port=1"#),
+        "unexpected property `port`");
+}
+
+#[test]
+fn parse_flat_precedence() {
+    // the field of the node itself takes precedence over flattened ones
+    assert_eq!(parse::<LabeledServer>(r#"server label="x""#),
+        LabeledServer {
+            timeouts: Timeouts::default(),
+            auth: Auth::default(),
+            label: "x".into(),
+        });
+    let err = knuffel::parse::<Vec<Server>>("<test>",
+                                            r#"server host="a" label="x""#)
+        .unwrap_err();
+    let errors = err.related().unwrap()
+        .map(|e| (e.to_string(), e.labels().unwrap()
+            .map(|l| (l.offset(), l.len()))
+            .collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec![
+        ("ambiguous property `label`, accepted by 2 flattened fields".into(),
+         vec![(16, 5)]),
+    ]);
+}
+
+#[test]
+fn parse_flat_ordered_children() {
+    assert_eq!(parse_doc::<FlatStages>(r#"stage 1; stage 2; stage 2"#),
+        FlatStages { pipeline: Stages { stages: Some(vec![
+            Stage { order: 1 },
+            Stage { order: 2 },
+            Stage { order: 2 },
+        ])}});
+    assert_eq!(parse_doc_err::<FlatStages>(r#"stage 2; stage 1; stage 0"#),
+        "node is out of order, children must be sorted by `order`\n\
+         node is out of order, children must be sorted by `order`");
+}
@@ -0,0 +1,49 @@
+#![forbid(unsafe_code)]
+//! Generated code must compile in crates that forbid unsafe code
+
+use knuffel::span::Span;
+
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+enum Mode {
+    Fast,
+    Safe,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Node {
+    #[knuffel(argument)]
+    name: String,
+    #[knuffel(property)]
+    mode: Option<Mode>,
+    #[knuffel(children)]
+    children: Vec<Node>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+enum Item {
+    Node(Node),
+    Unit,
+}
+
+#[test]
+fn parse() {
+    let items = knuffel::parse::<Vec<Item>>("<test>", r#"
+        node "a" mode="safe" { node "b"; }
+        unit
+    "#).unwrap();
+    assert_eq!(items, vec![
+        Item::Node(Node {
+            name: "a".into(),
+            mode: Some(Mode::Safe),
+            children: vec![Node {
+                name: "b".into(),
+                mode: None,
+                children: Vec::new(),
+            }],
+        }),
+        Item::Unit,
+    ]);
+    let _: knuffel::ast::Document<Span> =
+        knuffel::parse_ast("<test>", "node").unwrap();
+}
@@ -0,0 +1,125 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use knuffel::{Decode, Encode};
+use knuffel::span::Span;
+use miette::Diagnostic;
+
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Wrapper<T> {
+    #[knuffel(argument)]
+    value: T,
+    #[knuffel(property)]
+    fallback: Option<T>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Group<N: Debug> {
+    #[knuffel(property)]
+    name: String,
+    #[knuffel(children)]
+    items: Vec<N>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+struct Item {
+    #[knuffel(argument)]
+    id: u32,
+}
+
+#[derive(Decode, Debug, PartialEq)]
+struct Labeled<'a, K, const N: usize> {
+    #[knuffel(child, unwrap(argument))]
+    key: K,
+    #[knuffel(child, unwrap(arguments))]
+    values: Vec<K>,
+    #[knuffel(child)]
+    item: Option<Item>,
+    #[knuffel(default)]
+    marker: PhantomData<&'a [u8; N]>,
+}
+
+#[derive(Decode, Encode, Debug, PartialEq)]
+enum Either<L, R> {
+    Left(#[knuffel(argument)] L),
+    Right(R),
+}
+
+#[derive(Decode, Debug, PartialEq)]
+#[knuffel(span_type=Span)]
+struct Concrete<T> {
+    #[knuffel(argument)]
+    value: T,
+    #[knuffel(span)]
+    span: Span,
+}
+
+#[test]
+fn parse_generic_scalar() {
+    assert_eq!(knuffel::parse::<Vec<Wrapper<u16>>>("<test>",
+                                                  "w 1 fallback=2").unwrap(),
+               vec![Wrapper { value: 1, fallback: Some(2) }]);
+    assert_eq!(knuffel::parse::<Vec<Wrapper<String>>>("<test>",
+                                                     r#"w "x""#).unwrap(),
+               vec![Wrapper { value: "x".into(), fallback: None }]);
+    let err = knuffel::parse::<Vec<Wrapper<u16>>>("<test>", r#"w "x""#)
+        .unwrap_err();
+    assert_eq!(err.related().unwrap().map(|e| e.to_string())
+               .collect::<Vec<_>>(),
+               ["expected integer scalar, found string"]);
+}
+
+#[test]
+fn parse_generic_child() {
+    assert_eq!(knuffel::parse::<Vec<Group<Item>>>("<test>",
+                   r#"group name="a" { item 1; item 2; }"#).unwrap(),
+               vec![Group {
+                   name: "a".into(),
+                   items: vec![Item { id: 1 }, Item { id: 2 }],
+               }]);
+    let nested = knuffel::parse::<Vec<Group<Wrapper<bool>>>>("<test>",
+        r#"group name="b" { w true; w false fallback=true; }"#).unwrap();
+    assert_eq!(nested[0].items, [
+        Wrapper { value: true, fallback: None },
+        Wrapper { value: false, fallback: Some(true) },
+    ]);
+}
+
+#[test]
+fn parse_lifetime_and_const() {
+    let parsed = knuffel::parse::<Vec<Labeled<'static, i8, 4>>>("<test>",
+        r#"labeled { key -1; values 2 3; }"#).unwrap();
+    assert_eq!(parsed, [Labeled {
+        key: -1,
+        values: vec![2, 3],
+        item: None,
+        marker: PhantomData,
+    }]);
+}
+
+#[test]
+fn parse_generic_enum() {
+    assert_eq!(knuffel::parse::<Vec<Either<u8, Item>>>("<test>",
+                   r#"left 1; right 2"#).unwrap(),
+               [Either::Left(1), Either::Right(Item { id: 2 })]);
+}
+
+#[test]
+fn parse_concrete_span() {
+    let parsed = knuffel::parse::<Vec<Concrete<f32>>>("<test>", "node 1.5")
+        .unwrap();
+    assert_eq!(parsed[0].value, 1.5);
+    assert_eq!(parsed[0].span, Span(0, 8));
+}
+
+#[test]
+fn encode_generic() {
+    let group = vec![Group {
+        name: "g".into(),
+        items: vec![Either::<u8, Item>::Left(1),
+                    Either::Right(Item { id: 2 })],
+    }];
+    assert_eq!(knuffel::to_string(&group),
+               r#"group name="g" { left 1; right 2; };"#);
+}
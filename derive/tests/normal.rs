@@ -1,6 +1,8 @@
 use std::fmt;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::default::Default;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use miette::Diagnostic;
 
@@ -33,9 +35,11 @@ struct ArgDefValue {
 }
 
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
-struct ArgDefOptValue {
-    #[knuffel(argument, default=Some("unnamed".into()))]
-    name: Option<String>,
+struct Address {
+    #[knuffel(argument)]
+    name: String,
+    #[knuffel(arguments)]
+    octets: [u8; 4],
 }
 
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
@@ -44,6 +48,16 @@ struct OptArg {
     name: Option<String>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Pair {
+    #[knuffel(argument)]
+    key: String,
+    #[knuffel(argument)]
+    value: u32,
+    #[knuffel(argument)]
+    comment: Option<String>,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct Extra {
     field: String,
@@ -55,12 +69,48 @@ struct VarArg {
     params: Vec<u64>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct VarArgDeque {
+    #[knuffel(arguments)]
+    queue: VecDeque<String>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Skipped {
+    #[knuffel(property)]
+    label: String,
+    #[knuffel(skip)]
+    cache: Vec<u32>,
+    #[knuffel(skip)]
+    hits: Option<u64>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+enum SkippedVariant {
+    // not a nested node, so no other node is decoded here
+    Cached(#[knuffel(skip)] Vec<u32>),
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq, Default)]
 struct Prop1 {
     #[knuffel(property)]
     label: String,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(fail_fast)]
+struct FailFast {
+    #[knuffel(property)]
+    label: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(fail_fast)]
+struct FailFastEmpty {
+    #[knuffel(child)]
+    child: Option<Prop1>,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq, Default)]
 struct Prop1RawIdent {
     #[knuffel(property)]
@@ -85,12 +135,71 @@ struct PropDefOptValue {
     label: Option<String>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Defaults {
+    #[knuffel(argument, default)]
+    name: String,
+    #[knuffel(argument, default=8080)]
+    port: u16,
+    #[knuffel(property, default)]
+    verbose: bool,
+    #[knuffel(property, default=3)]
+    retries: u8,
+    #[knuffel(property, default)]
+    timeout: Option<u64>,
+}
+
+static FALLBACKS: AtomicUsize = AtomicUsize::new(0);
+
+fn fallback_timeout() -> u64 {
+    FALLBACKS.fetch_add(1, Ordering::SeqCst);
+    30
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct DefaultExpr {
+    #[knuffel(argument, default=u64::pow(2, 10))]
+    buffer: u64,
+    #[knuffel(property, default=fallback_timeout())]
+    timeout: u64,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct PropNamed {
     #[knuffel(property(name="x"))]
     label: String,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Headers {
+    #[knuffel(property(name="Content-Type"))]
+    content_type: String,
+    #[knuffel(property(name="x.request.id"))]
+    request_id: Option<String>,
+    #[knuffel(property)]
+    max_size: Option<u64>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(rename_all="camelCase")]
+struct CamelCase {
+    #[knuffel(property)]
+    plugin_name: String,
+    #[knuffel(property(name="plugin-version"))]
+    plugin_version: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    max_size: Option<u64>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(rename_all="PascalCase")]
+struct PascalCase {
+    #[knuffel(property)]
+    r#type: Option<String>,
+    #[knuffel(child)]
+    read_only: bool,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct OptProp {
     #[knuffel(property)]
@@ -103,6 +212,36 @@ struct VarProp {
     scores: BTreeMap<String, u64>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct CaseProp {
+    #[knuffel(property, case_insensitive)]
+    api_key: String,
+    #[knuffel(property(name="X-Token"), case_insensitive)]
+    token: Option<String>,
+    #[knuffel(property)]
+    region: String,
+}
+
+const MAX_VOLUME: i8 = 10;
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Clamped {
+    #[knuffel(argument, clamp=0..=100)]
+    pct: u8,
+    #[knuffel(property, clamp=-MAX_VOLUME..=MAX_VOLUME)]
+    volume: Option<i8>,
+    #[knuffel(property, clamp=0.0..=1.0, default)]
+    ratio: f64,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct ClampedChild {
+    #[knuffel(child, unwrap(argument, clamp=1..=10))]
+    level: Option<u32>,
+    #[knuffel(children(name="gain"), unwrap(argument, clamp=0.0..=2.0))]
+    gains: Vec<f32>,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct Children {
     #[knuffel(children)]
@@ -117,6 +256,32 @@ struct FilteredChildren {
     right: Vec<OptArg>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct FilteredWithChild {
+    #[knuffel(child)]
+    fallback: Option<Arg1>,
+    #[knuffel(children(name="route"))]
+    routes: Vec<Arg1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Stage {
+    #[knuffel(argument)]
+    order: u32,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct OrderedChildren {
+    #[knuffel(children, ordered_by=order)]
+    stages: Vec<Stage>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct OrderedFilteredChildren {
+    #[knuffel(children(name="stage"), ordered_by=order)]
+    stages: Vec<Stage>,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 enum Variant {
     Arg1(Arg1),
@@ -126,6 +291,141 @@ enum Variant {
     Var3(u32),
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+enum Event {
+    Start(#[knuffel(argument)] String),
+    Message(Prop1),
+    Stop,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct UnixSocket {
+    #[knuffel(argument)]
+    path: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+enum Connection {
+    Tcp {
+        #[knuffel(property)]
+        host: String,
+        #[knuffel(property)]
+        port: u16,
+    },
+    Unix(UnixSocket),
+    Inherit,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct StringField {
+    #[knuffel(property)]
+    name: String,
+    #[knuffel(property)]
+    max_length: Option<u32>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct IntField {
+    #[knuffel(property)]
+    name: String,
+    #[knuffel(argument)]
+    default: Option<i64>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct BoolField {
+    #[knuffel(property)]
+    name: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(tag="type")]
+enum Field {
+    String(StringField),
+    Int(IntField),
+    Bool(BoolField),
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(tag="kind")]
+enum Widget {
+    Panel {
+        #[knuffel(properties)]
+        attrs: BTreeMap<String, String>,
+        #[knuffel(children)]
+        items: Vec<Widget>,
+    },
+    Spacer,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Attrs {
+    #[knuffel(properties)]
+    attrs: BTreeMap<String, String>,
+}
+
+/// Decodes the same node twice, the tag must be visible the second time
+#[derive(Debug, PartialEq)]
+struct WidgetAttrs(Widget, Attrs);
+
+impl Decode<Span> for WidgetAttrs {
+    fn decode_node(node: &knuffel::ast::SpannedNode<Span>,
+                   ctx: &mut knuffel::decode::Context<Span>)
+        -> Result<Self, knuffel::errors::DecodeError<Span>>
+    {
+        let widget = Decode::decode_node(node, ctx)?;
+        let attrs = Decode::decode_node(node, ctx)?;
+        Ok(WidgetAttrs(widget, attrs))
+    }
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct TaggedChildren {
+    #[knuffel(children(name="field"))]
+    fields: Vec<Field>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Define {
+    #[knuffel(argument)]
+    first: u32,
+    #[knuffel(node_key)]
+    name: String,
+    #[knuffel(arguments)]
+    rest: Vec<u32>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct KeyTuple(#[knuffel(argument)] Option<u32>,
+                #[knuffel(node_key, str)] std::net::Ipv4Addr);
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Override {
+    #[knuffel(property)]
+    port: Option<u16>,
+    #[knuffel(property)]
+    host: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Server {
+    #[knuffel(property)]
+    port: u16,
+    #[knuffel(property, default="localhost".into())]
+    host: String,
+    #[knuffel(children(name="override"), merge=apply_override)]
+    overrides: Vec<Override>,
+}
+
+fn apply_override(server: &mut Server, item: &Override) {
+    if let Some(port) = item.port {
+        server.port = port;
+    }
+    if let Some(host) = &item.host {
+        server.host = host.clone();
+    }
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct Child {
     #[knuffel(child)]
@@ -154,12 +454,30 @@ struct Unwrap {
     label: String,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct UnwrapProp {
+    #[knuffel(child, unwrap(property(name="max")))]
+    limit: u32,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct UnwrapRawIdent {
     #[knuffel(child, unwrap(argument))]
     r#type: String,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct UnwrapArgs {
+    #[knuffel(child, unwrap(arguments))]
+    coords: Vec<f64>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct UnwrapChildBlock {
+    #[knuffel(child, unwrap(children))]
+    routes: Vec<Arg1>,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct UnwrapFiltChildren {
     #[knuffel(children(name="labels"), unwrap(arguments))]
@@ -190,12 +508,226 @@ struct Bytes {
     data: Vec<u8>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct UrlBytes {
+    #[knuffel(argument, bytes(base64_url))]
+    key: Vec<u8>,
+}
+
 #[derive(knuffel_derive::Decode, Debug, PartialEq)]
 struct OptBytes {
     #[knuffel(property, bytes)]
     data: Option<Vec<u8>>,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct IntAddr {
+    #[knuffel(argument, from_u32)]
+    addr: std::net::Ipv4Addr,
+    #[knuffel(property, from_u32)]
+    mask: Option<std::net::Ipv4Addr>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct StrAddr {
+    #[knuffel(argument, str)]
+    addr: std::net::Ipv4Addr,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(expose_name)]
+struct ListenAddr {
+    #[knuffel(argument)]
+    addr: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(expose_name, node_name="log")]
+struct LogLevel(#[knuffel(argument)] String);
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(expose_name)]
+enum ServerItem {
+    ListenAddr(ListenAddr),
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(non_empty_document)]
+struct RequiredConfig {
+    #[knuffel(child, unwrap(argument))]
+    listen: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Ports {
+    #[knuffel(children, type="input")]
+    inputs: Vec<Arg1>,
+    #[knuffel(children, type="output")]
+    outputs: Vec<Arg1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct MaybeTyped {
+    #[knuffel(type_name)]
+    type_name: Option<String>,
+    #[knuffel(argument)]
+    name: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct TypedItems {
+    #[knuffel(children, type="item")]
+    items: Vec<MaybeTyped>,
+    #[knuffel(children)]
+    rest: Vec<MaybeTyped>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct PortsRest {
+    #[knuffel(children, type="input")]
+    inputs: Vec<Arg1>,
+    #[knuffel(children)]
+    rest: Vec<Arg1>,
+    #[knuffel(children, type="output", unwrap(argument))]
+    outputs: Vec<String>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Documented {
+    #[knuffel(doc_comment)]
+    docs: Option<String>,
+    #[knuffel(argument)]
+    name: String,
+    #[knuffel(children)]
+    children: Vec<Documented>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(strict_document)]
+struct StrictConfig {
+    #[knuffel(child, unwrap(argument))]
+    listen: Option<String>,
+    #[knuffel(child)]
+    verbose: bool,
+    #[knuffel(children(name="plugin"))]
+    plugins: Vec<Arg1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(strict_document)]
+struct StrictConfigRest {
+    #[knuffel(child, unwrap(argument))]
+    listen: Option<String>,
+    #[knuffel(children)]
+    rest: Vec<Arg1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Color {
+    #[knuffel(argument, try=[parse_hex, u32::from_str])]
+    rgb: u32,
+    #[knuffel(property, try=[parse_hex])]
+    alpha: Option<u32>,
+}
+
+fn parse_hex(value: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let hex = value.strip_prefix('#').ok_or("expected `#` prefix")?;
+    Ok(u32::from_str_radix(hex, 16)?)
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Sections {
+    #[knuffel(child, unwrap(argument))]
+    version: Option<u32>,
+    #[knuffel(children)]
+    sections: HashMap<String, Prop1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct DedupFirst {
+    #[knuffel(child, unwrap(argument))]
+    version: Option<u32>,
+    #[knuffel(children, dedup_by_name="first")]
+    items: Vec<Prop1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct DedupLast {
+    #[knuffel(children, type="extra")]
+    extra: Vec<Prop1>,
+    #[knuffel(children, dedup_by_name="last")]
+    items: Vec<Prop1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct DedupMap {
+    #[knuffel(children, dedup_by_name="last")]
+    items: BTreeMap<String, Prop1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Connect {
+    #[knuffel(property_or_child)]
+    timeout: u32,
+    #[knuffel(property_or_child, str)]
+    bind_address: Option<std::net::IpAddr>,
+    #[knuffel(property_or_child, default=3)]
+    attempts: u8,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Validated {
+    #[knuffel(argument)]
+    id: u8,
+    #[knuffel(property)]
+    label: String,
+    #[knuffel(child)]
+    main: Prop1,
+    #[knuffel(children(name="item"))]
+    items: Vec<Arg1>,
+    #[knuffel(child)]
+    extra: Prop1,
+}
+
+#[derive(knuffel_derive::DecodeScalar, Debug, PartialEq, Eq, Hash)]
+enum Method {
+    Get,
+    Post,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Routes {
+    #[knuffel(children)]
+    handlers: HashMap<Method, Arg1>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(one_of(url, host_port))]
+struct Endpoint {
+    #[knuffel(property)]
+    url: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    host_port: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(at_most_one_of(name, id))]
+struct Lookup {
+    #[knuffel(argument)]
+    name: Option<String>,
+    #[knuffel(property)]
+    id: Option<u64>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(one_of(listen, socket))]
+struct Listener {
+    #[knuffel(child, unwrap(argument))]
+    listen: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    socket: Option<String>,
+}
+
 fn parse<T: Decode<Span>>(text: &str) -> T {
     let mut nodes: Vec<T> = knuffel::parse("<test>", text).unwrap();
     assert_eq!(nodes.len(), 1);
@@ -229,7 +761,7 @@ fn parse_arg1() {
     assert_eq!(parse_err::<Arg1>(r#"(some)node "hello""#),
         "no type name expected for this node");
     assert_eq!(parse_err::<Arg1>(r#"node"#),
-        "additional argument `name` is required");
+        "missing required argument `name` for node `node`");
 }
 
 #[test]
@@ -241,7 +773,7 @@ fn parse_arg1_raw_ident() {
     assert_eq!(parse_err::<Arg1RawIdent>(r#"(some)node "hello""#),
                "no type name expected for this node");
     assert_eq!(parse_err::<Arg1RawIdent>(r#"node"#),
-               "additional argument `type` is required");
+               "missing required argument `type` for node `node`");
 }
 
 
@@ -263,15 +795,33 @@ fn parse_arg_def_value() {
         "unexpected argument");
     assert_eq!(parse::<ArgDefValue>(r#"node"#),
                ArgDefValue { name: "unnamed".into() } );
+}
 
-    assert_eq!(parse::<ArgDefOptValue>(r#"node "hello""#),
-               ArgDefOptValue { name: Some("hello".into()) } );
-    assert_eq!(parse_err::<ArgDefValue>(r#"node "hello" "world""#),
+#[test]
+fn parse_slashdash_arguments() {
+    assert_eq!(parse::<Address>(r#"addr /-"old" "gw" 192 /-10 168 0 1"#),
+               Address { name: "gw".into(), octets: [192, 168, 0, 1] });
+    assert_eq!(parse::<Address>(r#"addr "gw" 1 2 3 4 /-{ unknown; }"#),
+               Address { name: "gw".into(), octets: [1, 2, 3, 4] });
+    assert_eq!(parse_err::<Address>(r#"addr "gw" /-1 2 3 4"#),
+        "4 arguments are required, found 3");
+}
+
+#[test]
+fn parse_arg_array() {
+    assert_eq!(parse::<Address>(r#"addr "gw" 192 168 0 255"#),
+               Address { name: "gw".into(), octets: [192, 168, 0, 255] });
+    assert_eq!(parse_err::<Address>(r#"addr "gw" 192 168 0 256"#),
+        "value 256 out of range for u8 (0..=255)");
+    assert_eq!(parse_err::<Address>(r#"addr "gw" 192 -1 0 256"#),
+        "value -1 out of range for u8 (0..=255)\n\
+         value 256 out of range for u8 (0..=255)");
+    assert_eq!(parse_err::<Address>(r#"addr "gw" 192 168 0"#),
+        "4 arguments are required, found 3");
+    assert_eq!(parse_err::<Address>(r#"addr "gw" 192 168 0 1 2"#),
         "unexpected argument");
-    assert_eq!(parse::<ArgDefOptValue>(r#"node"#),
-               ArgDefOptValue { name: Some("unnamed".into()) } );
-    assert_eq!(parse::<ArgDefOptValue>(r#"node null"#),
-               ArgDefOptValue { name: None } );
+    assert_eq!(parse_err::<Address>(r#"addr "gw""#),
+        "4 arguments are required, found 0");
 }
 
 #[test]
@@ -290,10 +840,35 @@ fn parse_prop() {
                Prop1 { label: "hello".into() } );
     assert_eq!(parse_err::<Prop1>(r#"node label="hello" y="world""#),
         "unexpected property `y`");
+    // every unknown property is reported, in the order of their names
+    assert_eq!(parse_err::<Prop1>(r#"node z=1 label="hello" b=2 a=3"#),
+        "unexpected property `a`\n\
+         unexpected property `b`\n\
+         unexpected property `z`");
+    assert_eq!(parse_err::<FailFast>(r#"node z=1 label="hello" b=2 a=3"#),
+        "unexpected property `z`");
+    assert_eq!(parse_err::<FailFast>(r#"node label="hello" b=2 z=1 a=3"#),
+        "unexpected property `b`");
+    assert_eq!(parse_err::<FailFastEmpty>(r#"node z=1 b=2 a=3"#),
+        "unexpected property `z` for node `node`");
     assert_eq!(parse_err::<Prop1>(r#"node"#),
         "property `label` is required");
 }
 
+#[test]
+fn parse_skip() {
+    assert_eq!(parse::<Skipped>(r#"node label="x""#),
+               Skipped { label: "x".into(), cache: vec![], hits: None });
+    assert_eq!(parse_err::<Skipped>(r#"node label="x" cache=1"#),
+               "unexpected property `cache`");
+    assert_eq!(parse_err::<Skipped>(r#"node label="x" { cache; }"#),
+               "unexpected node `cache`");
+    assert_eq!(parse::<SkippedVariant>(r#"cached"#),
+               SkippedVariant::Cached(vec![]));
+    assert_eq!(parse_err::<SkippedVariant>(r#"cached 1"#),
+               "unexpected argument for node `cached`");
+}
+
 #[test]
 fn parse_prop_raw_ident() {
     assert_eq!(parse::<Prop1RawIdent>(r#"node type="hello""#),
@@ -312,6 +887,49 @@ fn parse_prop_default() {
                PropDef { label: "".into() });
 }
 
+#[test]
+fn parse_mixed_defaults() {
+    assert_eq!(parse::<Defaults>(r#"server"#),
+               Defaults {
+                   name: "".into(),
+                   port: 8080,
+                   verbose: false,
+                   retries: 3,
+                   timeout: None,
+               });
+    assert_eq!(parse::<Defaults>(r#"server "main" retries=5"#),
+               Defaults {
+                   name: "main".into(),
+                   port: 8080,
+                   verbose: false,
+                   retries: 5,
+                   timeout: None,
+               });
+    assert_eq!(parse::<Defaults>(r#"server "main" 80 verbose=true timeout=10"#),
+               Defaults {
+                   name: "main".into(),
+                   port: 80,
+                   verbose: true,
+                   retries: 3,
+                   timeout: Some(10),
+               });
+    // defaults don't hide errors in values that are present
+    assert_eq!(parse_err::<Defaults>(r#"server "main" retries=-1"#),
+        "value -1 out of range for u8 (0..=255)");
+}
+
+#[test]
+fn parse_default_expr() {
+    let before = FALLBACKS.load(Ordering::SeqCst);
+    assert_eq!(parse::<DefaultExpr>(r#"node"#),
+               DefaultExpr { buffer: 1024, timeout: 30 });
+    assert_eq!(FALLBACKS.load(Ordering::SeqCst), before + 1);
+    // the expression is not evaluated when the value is present
+    assert_eq!(parse::<DefaultExpr>(r#"node 16 timeout=1"#),
+               DefaultExpr { buffer: 16, timeout: 1 });
+    assert_eq!(FALLBACKS.load(Ordering::SeqCst), before + 1);
+}
+
 #[test]
 fn parse_prop_def_value() {
     assert_eq!(parse::<PropDefValue>(r#"node label="hello""#),
@@ -332,35 +950,128 @@ fn parse_prop_named() {
     assert_eq!(parse::<PropNamed>(r#"node x="hello""#),
                PropNamed { label: "hello".into() } );
     assert_eq!(parse_err::<PropNamed>(r#"node label="hello" y="world""#),
-        "unexpected property `label`");
+        "unexpected property `label`\n\
+         unexpected property `y`, did you mean `x`?\n\
+         property `x` is required");
     assert_eq!(parse_err::<PropNamed>(r#"node"#),
         "property `x` is required");
 }
 
+#[test]
+fn parse_prop_renamed_keys() {
+    assert_eq!(parse::<Headers>(
+                   r#"headers Content-Type="text/plain" "x.request.id"="1" \
+                      max-size=10"#),
+               Headers {
+                   content_type: "text/plain".into(),
+                   request_id: Some("1".into()),
+                   max_size: Some(10),
+               });
+    assert_eq!(parse_err::<Headers>(r#"headers content-type="text/plain""#),
+        "unexpected property `content-type`, \
+         did you mean `Content-Type`?\n\
+         property `Content-Type` is required");
+    assert_eq!(parse_err::<Headers>(r#"headers Content-Type="" max_size=1"#),
+        "unexpected property `max_size`, did you mean `max-size`?");
+}
+
+#[test]
+fn parse_rename_all() {
+    assert_eq!(parse::<CamelCase>(
+                   r#"node pluginName="x" plugin-version="1" { maxSize 10; }"#),
+               CamelCase {
+                   plugin_name: "x".into(),
+                   plugin_version: Some("1".into()),
+                   max_size: Some(10),
+               });
+    assert_eq!(parse_err::<CamelCase>(r#"node plugin-name="x""#),
+        "unexpected property `plugin-name`, \
+         did you mean `pluginName`?\n\
+         property `pluginName` is required");
+    assert_eq!(parse::<PascalCase>(r#"node Type="disk" { ReadOnly; }"#),
+               PascalCase { r#type: Some("disk".into()), read_only: true });
+}
+
 #[test]
 fn parse_unwrap() {
     assert_eq!(parse::<Unwrap>(r#"node { label "hello"; }"#),
                Unwrap { label: "hello".into() } );
     assert_eq!(parse_err::<Unwrap>(r#"node label="hello""#),
-        "unexpected property `label`");
+        "unexpected property `label` for node `node`\n\
+         child node `label` is required");
     assert_eq!(parse_err::<Unwrap>(r#"node"#),
         "child node `label` is required");
+    assert_eq!(parse_err::<Unwrap>(r#"node { label "hello" "world"; }"#),
+        "unexpected argument");
+    assert_eq!(parse_err::<Unwrap>(r#"node { label "hello" x=1; }"#),
+        "unexpected property `x` for node `label`");
     assert_eq!(parse_doc::<Unwrap>(r#"label "hello""#),
                Unwrap { label: "hello".into() } );
 }
 
+#[test]
+fn parse_unwrap_property() {
+    assert_eq!(parse::<UnwrapProp>(r#"node { limit max=10; }"#),
+               UnwrapProp { limit: 10 } );
+    assert_eq!(parse_err::<UnwrapProp>(r#"node { limit 10; }"#),
+        "unexpected argument for node `limit`\n\
+         property `max` is required");
+    assert_eq!(parse_err::<UnwrapProp>(r#"node { limit; }"#),
+        "property `max` is required");
+}
+
 #[test]
 fn parse_unwrap_raw_ident() {
     assert_eq!(parse::<UnwrapRawIdent>(r#"node { type "hello"; }"#),
                UnwrapRawIdent { r#type: "hello".into() } );
     assert_eq!(parse_err::<UnwrapRawIdent>(r#"node type="hello""#),
-               "unexpected property `type`");
+               "unexpected property `type` for node `node`\n\
+                child node `type` is required");
     assert_eq!(parse_err::<UnwrapRawIdent>(r#"node"#),
                "child node `type` is required");
     assert_eq!(parse_doc::<UnwrapRawIdent>(r#"type "hello""#),
                UnwrapRawIdent { r#type: "hello".into() } );
 }
 
+#[test]
+fn parse_unwrap_child_block() {
+    assert_eq!(parse::<UnwrapChildBlock>(
+                   r#"server { routes { route "a"; route "b"; }; }"#),
+               UnwrapChildBlock { routes: vec![
+                   Arg1 { name: "a".into() },
+                   Arg1 { name: "b".into() },
+               ] });
+    assert_eq!(parse::<UnwrapChildBlock>(r#"server { routes; }"#),
+               UnwrapChildBlock { routes: vec![] });
+    assert_eq!(parse_err::<UnwrapChildBlock>(r#"server"#),
+        "child node `routes` is required");
+    assert_eq!(parse_err::<UnwrapChildBlock>(
+                   r#"server { routes "x" y=1 { route "a"; }; }"#),
+        "unexpected argument for node `routes`\n\
+         unexpected property `y` for node `routes`");
+    assert_eq!(parse_err::<UnwrapChildBlock>(
+                   r#"server { routes { route 1; }; }"#),
+        "expected string scalar, found integer");
+}
+
+#[test]
+fn parse_unwrap_arguments() {
+    assert_eq!(parse::<UnwrapArgs>(r#"node { coords 1 2 3; }"#),
+               UnwrapArgs { coords: vec![1.0, 2.0, 3.0] } );
+    assert_eq!(parse::<UnwrapArgs>(r#"node { coords 1.5 -2 0x10; }"#),
+               UnwrapArgs { coords: vec![1.5, -2.0, 16.0] } );
+    assert_eq!(parse::<UnwrapArgs>(r#"node { coords; }"#),
+               UnwrapArgs { coords: vec![] } );
+    assert_eq!(parse_err::<UnwrapArgs>(r#"node { coords 1.0 x=2.0; }"#),
+        "unexpected property `x` for node `coords`");
+    assert_eq!(parse_err::<UnwrapArgs>(r#"node { coords 1.0 { z 2.0; }; }"#),
+        "unexpected node `z`");
+    assert_eq!(parse_err::<UnwrapArgs>(r#"node { coords "x"; }"#),
+        "expected decimal scalar, found string");
+    assert_eq!(parse_err::<UnwrapArgs>(r#"node"#),
+        "child node `coords` is required");
+}
+
 #[test]
 fn parse_unwrap_filtered_children() {
     assert_eq!(parse::<UnwrapFiltChildren>(
@@ -401,6 +1112,62 @@ fn parse_var_arg() {
                VarArg { params: vec![] } );
 }
 
+#[test]
+fn parse_var_arg_deque() {
+    let mut val = parse::<VarArgDeque>(r#"queue "first" "second" "third""#);
+    assert_eq!(val.queue.pop_front().as_deref(), Some("first"));
+    assert_eq!(val.queue.pop_front().as_deref(), Some("second"));
+    assert_eq!(val.queue.pop_front().as_deref(), Some("third"));
+    assert_eq!(val.queue.pop_front(), None);
+    assert_eq!(parse::<VarArgDeque>(r#"queue"#),
+               VarArgDeque { queue: VecDeque::new() });
+}
+
+#[test]
+fn parse_case_insensitive_prop() {
+    let expected = CaseProp {
+        api_key: "k".into(),
+        token: None,
+        region: "eu".into(),
+    };
+    assert_eq!(parse::<CaseProp>(r#"node api-key="k" region="eu""#),
+               expected);
+    assert_eq!(parse::<CaseProp>(r#"node API-KEY="k" region="eu""#),
+               expected);
+    assert_eq!(parse::<CaseProp>(r#"node Api-Key="k" region="eu""#),
+               expected);
+    assert_eq!(parse::<CaseProp>(r#"node api-key="k" x-token="t" region="eu""#),
+               CaseProp { token: Some("t".into()), ..expected });
+    assert_eq!(parse_err::<CaseProp>(r#"node API-KEY="k" REGION="eu""#),
+        "unexpected property `REGION`\n\
+         property `region` is required");
+    assert_eq!(parse_err::<CaseProp>(r#"node api_key="k" region="eu""#),
+        "unexpected property `api_key`, did you mean `api-key`?\n\
+         property `api-key` is required");
+    assert_eq!(parse_err::<CaseProp>(
+        r#"node api-key="a" API-KEY="b" region="eu""#),
+        "duplicate property `api-key`, names differ only in case");
+}
+
+#[test]
+fn parse_clamp() {
+    assert_eq!(parse::<Clamped>(r#"node 50 volume=-3 ratio=0.5"#),
+               Clamped { pct: 50, volume: Some(-3), ratio: 0.5 });
+    assert_eq!(parse::<Clamped>(r#"node 200 volume=100 ratio=1.5"#),
+               Clamped { pct: 100, volume: Some(10), ratio: 1.0 });
+    assert_eq!(parse::<Clamped>(r#"node 0 volume=-100 ratio=-2.0"#),
+               Clamped { pct: 0, volume: Some(-10), ratio: 0.0 });
+    assert_eq!(parse::<Clamped>(r#"node 100 volume=null"#),
+               Clamped { pct: 100, volume: None, ratio: 0.0 });
+    assert_eq!(parse_err::<Clamped>(r#"node 300"#),
+        "value 300 out of range for u8 (0..=255)");
+    assert_eq!(parse_err::<Clamped>(r#"node 1 volume=1000"#),
+        "value 1000 out of range for i8 (-128..=127)");
+
+    assert_eq!(parse_doc::<ClampedChild>(r#"level 0; gain 1.5; gain 3.0"#),
+               ClampedChild { level: Some(1), gains: vec![1.5, 2.0] });
+}
+
 #[test]
 fn parse_var_prop() {
     let mut scores = BTreeMap::new();
@@ -431,6 +1198,31 @@ fn parse_children() {
                Children { children: Vec::new() } );
 }
 
+#[test]
+fn parse_ordered_children() {
+    assert_eq!(parse_doc::<OrderedChildren>(r#"a 1; b 2; c 2; d 5"#),
+               OrderedChildren { stages: vec![
+                   Stage { order: 1 },
+                   Stage { order: 2 },
+                   Stage { order: 2 },
+                   Stage { order: 5 },
+               ]});
+    assert_eq!(parse_doc_err::<OrderedChildren>(r#"a 1; b 3; c 2"#),
+               "node is out of order, children must be sorted by `order`");
+
+    assert_eq!(parse_doc::<OrderedFilteredChildren>(
+                   r#"stage 1; stage 7; stage 10"#),
+               OrderedFilteredChildren { stages: vec![
+                   Stage { order: 1 },
+                   Stage { order: 7 },
+                   Stage { order: 10 },
+               ]});
+    assert_eq!(parse_doc_err::<OrderedFilteredChildren>(
+                   r#"stage 2; stage 1; stage 0"#),
+               "node is out of order, children must be sorted by `order`\n\
+                node is out of order, children must be sorted by `order`");
+}
+
 #[test]
 fn parse_filtered_children() {
     assert_eq!(parse_doc::<FilteredChildren>(
@@ -457,6 +1249,48 @@ fn parse_filtered_children() {
                "unexpected node `some`");
 }
 
+#[test]
+fn parse_filtered_children_with_child() {
+    assert_eq!(parse_doc::<FilteredWithChild>(
+                   r#"route "/a"; fallback "/404"; route "/b""#),
+               FilteredWithChild {
+                   fallback: Some(Arg1 { name: "/404".into() }),
+                   routes: vec![
+                       Arg1 { name: "/a".into() },
+                       Arg1 { name: "/b".into() },
+                   ],
+               });
+    assert_eq!(parse_doc::<FilteredWithChild>(r#""#),
+               FilteredWithChild { fallback: None, routes: vec![] });
+    assert_eq!(parse_doc_err::<FilteredWithChild>(
+                   r#"route "/a"; redirect "/b""#),
+               "unexpected node `redirect`");
+}
+
+#[test]
+fn parse_did_you_mean() {
+    assert_eq!(parse_err::<Server>(r#"server porrt=8080"#),
+               "unexpected property `porrt`, did you mean `port`?\n\
+                property `port` is required");
+    assert_eq!(parse_err::<Server>(r#"server port=80 hosts="a""#),
+               "unexpected property `hosts`, did you mean `host`?");
+    assert_eq!(parse_err::<Server>(r#"server port=80 address="a""#),
+               "unexpected property `address`");
+    assert_eq!(parse_err::<Server>(r#"server port=80 { overide; }"#),
+               "unexpected node `overide`, did you mean `override`?");
+    assert_eq!(parse_err::<Child>(r#"parent { main label="x"; extar; }"#),
+               "unexpected node `extar`");
+    assert_eq!(parse_err::<Child>(r#"parent { main label="x"; flg; }"#),
+               "unexpected node `flg`, did you mean `flag`?");
+    assert_eq!(parse_err::<Server>(r#"server port=80 { overides; }"#),
+               "unexpected node `overides`, did you mean `override`?");
+    assert_eq!(parse_err::<Connect>(r#"connect timeot=30"#),
+               "unexpected property `timeot`, did you mean `timeout`?\n\
+                property or child node `timeout` is required");
+    assert_eq!(parse_err::<Connect>(r#"connect timeout=3 { atempts 5; }"#),
+               "unexpected node `atempts`, did you mean `attempts`?");
+}
+
 #[test]
 fn parse_child() {
     assert_eq!(parse::<Child>(r#"parent { main label="val1"; }"#),
@@ -479,6 +1313,17 @@ fn parse_child() {
                 child node `main` is required");
     assert_eq!(parse_err::<Child>(r#"parent"#),
                "child node `main` is required");
+    assert_eq!(parse_err::<Child>(r#"parent {
+                    main label="primary";
+                    main label="secondary";
+                 }"#),
+               "duplicate node `main`, single node expected");
+    assert_eq!(parse_err::<Child>(r#"parent {
+                    main label="primary";
+                    extra label="replica";
+                    extra label="backup";
+                 }"#),
+               "duplicate node `extra`, single node expected");
 
     assert_eq!(parse_doc::<Child>(r#"main label="val1""#),
                Child {
@@ -537,19 +1382,165 @@ fn parse_enum() {
         "expected one of `arg1`, `prop1`");
 }
 
+#[test]
+fn parse_enum_variant_kinds() {
+    assert_eq!(parse::<Connection>(r#"tcp host="localhost" port=8080"#),
+               Connection::Tcp { host: "localhost".into(), port: 8080 });
+    assert_eq!(parse::<Connection>(r#"unix "/run/app.sock""#),
+               Connection::Unix(UnixSocket { path: "/run/app.sock".into() }));
+    assert_eq!(parse::<Connection>(r#"inherit"#), Connection::Inherit);
+    assert_eq!(parse_err::<Connection>(r#"tcp host="localhost""#),
+        "property `port` is required");
+    assert_eq!(parse_err::<Connection>(r#"inherit "x""#),
+        "unexpected argument");
+    assert_eq!(parse_err::<Connection>(r#"udp"#),
+        "expected one of `tcp`, `unix`, `inherit`");
+}
+
+#[test]
+fn parse_enum_document() {
+    let events = knuffel::parse::<Vec<Event>>("<test>", r#"
+        start "job-1"
+        message label="hello"
+        stop
+        start "job-2"
+    "#).unwrap();
+    assert_eq!(events, vec![
+        Event::Start("job-1".into()),
+        Event::Message(Prop1 { label: "hello".into() }),
+        Event::Stop,
+        Event::Start("job-2".into()),
+    ]);
+    assert_eq!(parse_doc::<Vec<Event>>(""), vec![]);
+    assert_eq!(parse_doc_err::<Vec<Event>>(r#"start "x"; pause; resume"#),
+        "expected one of `start`, `message`, `stop`\n\
+         expected one of `start`, `message`, `stop`");
+}
+
+#[test]
+fn parse_node_key() {
+    assert_eq!(parse::<Define>(r#"define "x" 1 2 3"#),
+               Define { name: "x".into(), first: 1, rest: vec![2, 3] });
+    assert_eq!(parse::<Define>(r#"define "x" 1"#),
+               Define { name: "x".into(), first: 1, rest: vec![] });
+    assert_eq!(parse_err::<Define>(r#"define "x""#),
+        "missing required argument `first` for node `define`");
+    assert_eq!(parse_err::<Define>(r#"define"#),
+        "node key is required");
+    assert_eq!(parse_err::<Define>(r#"define 1 2"#),
+        "expected string scalar, found integer");
+
+    assert_eq!(parse::<KeyTuple>(r#"host "10.0.0.1" 5"#),
+               KeyTuple(Some(5), [10, 0, 0, 1].into()));
+    assert_eq!(parse::<KeyTuple>(r#"host "10.0.0.1""#),
+               KeyTuple(None, [10, 0, 0, 1].into()));
+}
+
+#[test]
+fn parse_merge() {
+    assert_eq!(parse::<Server>(r#"server port=80"#),
+               Server {
+                   port: 80,
+                   host: "localhost".into(),
+                   overrides: vec![],
+               });
+    assert_eq!(parse::<Server>(r#"
+        server port=80 {
+            override port=8080 host="example.com"
+            override port=9090
+        }
+    "#), Server {
+        port: 9090,
+        host: "example.com".into(),
+        overrides: vec![
+            Override { port: Some(8080), host: Some("example.com".into()) },
+            Override { port: Some(9090), host: None },
+        ],
+    });
+}
+
+#[test]
+fn parse_tagged_enum() {
+    assert_eq!(parse::<TaggedChildren>(r#"
+        fields {
+            field name="x" type="string" max-length=10
+            field type="int" name="y" 7
+            field name="z" type="bool"
+        }
+    "#), TaggedChildren { fields: vec![
+        Field::String(StringField {
+            name: "x".into(),
+            max_length: Some(10),
+        }),
+        Field::Int(IntField { name: "y".into(), default: Some(7) }),
+        Field::Bool(BoolField { name: "z".into() }),
+    ]});
+    assert_eq!(parse_err::<Field>(r#"field name="x""#),
+        "property `type` is required");
+    assert_eq!(parse_err::<Field>(r#"field name="x" type="float""#),
+        "expected one of `string`, `int`, `bool`");
+    assert_eq!(parse_err::<Field>(r#"field name="x" type=1"#),
+        "expected string scalar, found integer");
+    assert_eq!(parse_err::<Field>(r#"field name="x" type="bool" 1"#),
+        "unexpected argument for node `field`");
+}
+
+#[test]
+fn parse_tagged_enum_nested() {
+    assert_eq!(parse::<Widget>(r#"
+        widget kind="panel" title="a" {
+            widget kind="spacer"
+            item title="b" kind="panel"
+        }
+    "#), Widget::Panel {
+        attrs: vec![("title".into(), "a".into())].into_iter().collect(),
+        items: vec![
+            Widget::Spacer,
+            Widget::Panel {
+                attrs: vec![("title".into(), "b".into())]
+                    .into_iter().collect(),
+                items: vec![],
+            },
+        ],
+    });
+    assert_eq!(parse_err::<Widget>(r#"widget kind="spacer" size=1"#),
+        "unexpected property `size`");
+    assert_eq!(parse::<WidgetAttrs>(r#"widget kind="spacer""#),
+        WidgetAttrs(Widget::Spacer, Attrs {
+            attrs: vec![("kind".into(), "spacer".into())].into_iter().collect(),
+        }));
+}
+
 #[test]
 fn parse_str() {
+    // the message differs between Rust versions
+    let addr_err = "2/3".parse::<std::net::SocketAddr>()
+        .unwrap_err().to_string();
     assert_eq!(parse_doc::<Parse>(r#"listen "127.0.0.1:8080""#),
                Parse { listen: "127.0.0.1:8080".parse().unwrap() });
-    assert_eq!(parse_doc_err::<Parse>(r#"listen "2/3""#),
-        "invalid IP address syntax");
+    assert_eq!(parse_doc_err::<Parse>(r#"listen "2/3""#), addr_err);
 
     assert_eq!(parse::<ParseOpt>(r#"server listen="127.0.0.1:8080""#),
                ParseOpt { listen: Some("127.0.0.1:8080".parse().unwrap()) });
-    assert_eq!(parse_err::<ParseOpt>(r#"server listen="2/3""#),
-        "invalid IP address syntax");
+    assert_eq!(parse_err::<ParseOpt>(r#"server listen="2/3""#), addr_err);
     assert_eq!(parse::<ParseOpt>(r#"server listen=null"#),
                ParseOpt { listen: None });
+    assert_eq!(parse_err::<ParseOpt>(r#"server listen=8080"#),
+        "expected string scalar, found integer");
+    assert_eq!(parse_err::<StrAddr>(r#"host 167772161"#),
+        "expected string scalar, found integer");
+}
+
+#[test]
+fn parse_bytes_url_safe() {
+    assert_eq!(parse::<UrlBytes>(r#"key (base64)"-_8=""#),
+               UrlBytes { key: vec![0xfb, 0xff] });
+    assert_eq!(parse::<UrlBytes>(r#"key "plain""#),
+               UrlBytes { key: b"plain".to_vec() });
+    assert_eq!(parse_err::<UrlBytes>(r#"key (base64)"+/8=""#),
+        "Invalid byte 43, offset 0.");
+    assert_eq!(parse_err::<Bytes>(r#"node { data (base64)"-_8="; }"#),
+        "Invalid byte 45, offset 0.");
 }
 
 #[test]
@@ -569,10 +1560,526 @@ fn parse_bytes() {
                OptBytes { data: None });
 }
 
+#[test]
+fn parse_from_u32() {
+    assert_eq!(parse::<IntAddr>(r#"node 3232235521"#),
+               IntAddr { addr: [192, 168, 0, 1].into(), mask: None });
+    assert_eq!(parse::<IntAddr>(r#"node 0 mask=4294967040"#),
+               IntAddr {
+                   addr: [0, 0, 0, 0].into(),
+                   mask: Some([255, 255, 255, 0].into()),
+               });
+    assert_eq!(parse::<IntAddr>(r#"node 1 mask=null"#),
+               IntAddr { addr: [0, 0, 0, 1].into(), mask: None });
+    assert_eq!(parse::<StrAddr>(r#"node "192.168.0.1""#),
+               StrAddr { addr: [192, 168, 0, 1].into() });
+    assert_eq!(parse_err::<IntAddr>(r#"node 4294967296"#),
+        "value 4294967296 out of range for u32 (0..=4294967295)");
+    assert_eq!(parse_err::<IntAddr>(r#"node -1"#),
+        "value -1 out of range for u32 (0..=4294967295)");
+    assert_eq!(parse_err::<IntAddr>(r#"node "192.168.0.1""#),
+        "expected integer scalar, found string");
+}
+
 #[test]
 fn parse_extra() {
     assert_eq!(parse::<Extra>(r#"data"#),
                Extra { field: "".into() });
     assert_eq!(parse_err::<Extra>(r#"data x=1"#),
-        "unexpected property `x`");
+        "unexpected property `x` for node `data`");
+}
+
+#[test]
+fn expose_name() {
+    assert_eq!(ListenAddr::NODE_NAME, "listen-addr");
+    assert_eq!(LogLevel::NODE_NAME, "log");
+    assert_eq!(ServerItem::NODE_NAME, "server-item");
+    let doc = knuffel::parse_ast::<Span>("<test>", r#"
+        listen-addr "127.0.0.1"
+        log "debug"
+    "#).unwrap();
+    let names = doc.nodes.iter()
+        .map(|n| &n.node_name[..])
+        .collect::<Vec<_>>();
+    assert_eq!(names, [ListenAddr::NODE_NAME, LogLevel::NODE_NAME]);
+    assert_eq!(parse::<ServerItem>(r#"listen-addr "0.0.0.0""#),
+               ServerItem::ListenAddr(ListenAddr { addr: "0.0.0.0".into() }));
+}
+
+#[test]
+fn parse_non_empty_document() {
+    assert_eq!(parse_doc_err::<RequiredConfig>(""),
+        "document is empty; expected at least one node");
+    assert_eq!(parse_doc_err::<RequiredConfig>(r#"
+        // only a comment
+        /* and another one */
+        /-listen "127.0.0.1"
+    "#),
+        "document is empty; expected at least one node");
+    assert_eq!(parse_doc::<RequiredConfig>(r#"listen "127.0.0.1""#),
+               RequiredConfig { listen: Some("127.0.0.1".into()) });
+}
+
+#[test]
+fn parse_children_by_type() {
+    assert_eq!(parse::<Ports>(r#"ports {
+            (input)port "a"
+            (output)port "x"
+            (input)other "b"
+        }"#),
+        Ports {
+            inputs: vec![Arg1 { name: "a".into() }, Arg1 { name: "b".into() }],
+            outputs: vec![Arg1 { name: "x".into() }],
+        });
+    assert_eq!(parse::<Ports>(r#"ports"#),
+               Ports { inputs: vec![], outputs: vec![] });
+    assert_eq!(parse_err::<Ports>(r#"ports {
+            (input)port "a"
+            port "b"
+            (inout)port "c"
+        }"#),
+        "unexpected node `port`\nunexpected node `port`");
+    assert_eq!(parse::<PortsRest>(r#"ports {
+            (output)port "x"
+            port "a"
+            (input)port "b"
+            other "c"
+        }"#),
+        PortsRest {
+            inputs: vec![Arg1 { name: "b".into() }],
+            rest: vec![Arg1 { name: "a".into() }, Arg1 { name: "c".into() }],
+            outputs: vec!["x".into()],
+        });
+    // the type name is consumed by the field, the node doesn't see it
+    assert_eq!(parse::<TypedItems>(r#"items {
+            (item)a "x"
+            (other)b "y"
+        }"#),
+        TypedItems {
+            items: vec![MaybeTyped { type_name: None, name: "x".into() }],
+            rest: vec![MaybeTyped {
+                type_name: Some("other".into()),
+                name: "y".into(),
+            }],
+        });
+}
+
+#[test]
+fn parse_doc_comment() {
+    let doc = |docs: Option<&str>, name: &str| Documented {
+        docs: docs.map(Into::into),
+        name: name.into(),
+        children: Vec::new(),
+    };
+    assert_eq!(parse_doc::<Vec<Documented>>(r#"
+        // The first node
+        //   is documented
+        node "first" {
+            // nested too
+            child "x"
+        }
+
+        // detached comment
+
+        node "second"; node "third"
+        /* block */
+        node "fourth"
+    "#), vec![
+        Documented {
+            docs: Some("The first node\n  is documented".into()),
+            name: "first".into(),
+            children: vec![doc(Some("nested too"), "x")],
+        },
+        doc(None, "second"),
+        doc(None, "third"),
+        doc(None, "fourth"),
+    ]);
+    // comment-like lines of a multi-line string are not comments
+    assert_eq!(parse_doc::<Vec<Documented>>("node \"a\n// x\"\nnode \"b\""),
+               vec![doc(None, "a\n// x"), doc(None, "b")]);
+    assert_eq!(parse_doc::<Vec<Documented>>(
+                   "// one\n/-node \"a\"\n// two\nnode \"b\" // three\n\
+                    node \"c\"; // four\nnode \"d\""),
+               vec![doc(Some("two"), "b"), doc(None, "c"), doc(None, "d")]);
+    // comments are part of the AST, so decoding it directly keeps them
+    let ast = knuffel::parse_ast::<Span>("<test>", "// docs\nnode \"x\"")
+        .unwrap();
+    assert_eq!(knuffel::decode::node::<Documented, _>(&ast.nodes[0]).unwrap(),
+               doc(Some("docs"), "x"));
+}
+
+#[test]
+fn parse_strict_document() {
+    assert_eq!(parse_doc::<StrictConfig>(r#"listen "a"; plugin "x""#),
+               StrictConfig {
+                   listen: Some("a".into()),
+                   verbose: false,
+                   plugins: vec![Arg1 { name: "x".into() }],
+               });
+    assert_eq!(parse_doc_err::<StrictConfig>(r#"lisen "a""#),
+        "unexpected node `lisen`, expected one of `listen`, `verbose`, \
+         `plugin`, did you mean `listen`?");
+    assert_eq!(parse_doc_err::<StrictConfig>(r#"plugins "x"; timeout 1"#),
+        "unexpected node `plugins`, expected one of `listen`, `verbose`, \
+         `plugin`, did you mean `plugin`?\n\
+         unexpected node `timeout`, expected one of `listen`, `verbose`, \
+         `plugin`");
+    // only applies at the document level, not when decoded as a node
+    assert_eq!(parse_err::<StrictConfig>(r#"config { lisen "a"; }"#),
+        "unexpected node `lisen`, did you mean `listen`?");
+    assert_eq!(parse_doc::<StrictConfigRest>(r#"listen "a"; lisen "b""#),
+               StrictConfigRest {
+                   listen: Some("a".into()),
+                   rest: vec![Arg1 { name: "b".into() }],
+               });
+}
+
+#[test]
+fn parse_try() {
+    assert_eq!(parse::<Color>(r##"color "#ff0000""##),
+               Color { rgb: 0xff0000, alpha: None });
+    assert_eq!(parse::<Color>(r##"color "255" alpha="#80""##),
+               Color { rgb: 255, alpha: Some(0x80) });
+    assert_eq!(parse::<Color>(r#"color "255" alpha=null"#),
+               Color { rgb: 255, alpha: None });
+    assert_eq!(parse_err::<Color>(r#"color "red""#),
+        "none of the decoders succeeded: \
+         `parse_hex`: expected `#` prefix; \
+         `u32::from_str`: invalid digit found in string");
+    assert_eq!(parse_err::<Color>(r##"color "#zz""##),
+        "none of the decoders succeeded: \
+         `parse_hex`: invalid digit found in string; \
+         `u32::from_str`: invalid digit found in string");
+    assert_eq!(parse_err::<Color>(r#"color 255"#),
+        "expected string scalar, found integer");
+}
+
+#[test]
+fn parse_children_map() {
+    let res = parse::<Sections>(r#"config {
+        version 2
+        server label="srv"
+        client label="cli"
+        "with space" label="x"
+    }"#);
+    assert_eq!(res.version, Some(2));
+    assert_eq!(res.sections.len(), 3);
+    assert_eq!(res.sections["server"], Prop1 { label: "srv".into() });
+    assert_eq!(res.sections["client"], Prop1 { label: "cli".into() });
+    assert_eq!(res.sections["with space"], Prop1 { label: "x".into() });
+    assert_eq!(parse::<Sections>(r#"config"#).sections, HashMap::new());
+    assert_eq!(parse_err::<Sections>(r#"config {
+        server label="a"
+        client label="b"
+        server label="c"
+    }"#), "duplicate node `server`, single node expected");
+    assert_eq!(parse_err::<Sections>(r#"config { server; }"#),
+        "property `label` is required");
+}
+
+#[test]
+fn parse_children_enum_map() {
+    let res = parse::<Routes>(r#"routes {
+        get "index"
+        post "submit"
+    }"#);
+    assert_eq!(res.handlers.len(), 2);
+    assert_eq!(res.handlers[&Method::Get], Arg1 { name: "index".into() });
+    assert_eq!(res.handlers[&Method::Post], Arg1 { name: "submit".into() });
+    assert_eq!(parse_err::<Routes>(r#"routes { get "a"; put "b"; }"#),
+        "expected one of `get`, `post`");
+
+    let text = r#"routes { get "a"; post "b"; get "c"; }"#;
+    let err = knuffel::parse::<Vec<Routes>>("<test>", text).unwrap_err();
+    let errors = err.related().unwrap().collect::<Vec<_>>();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(),
+               "duplicate node `get`, single node expected");
+    let labels = errors[0].labels().unwrap()
+        .map(|l| (l.label().unwrap().to_string(), l.offset(), l.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec![
+        ("duplicate node".into(), 28, 3),
+        ("first node is here".into(), 9, 3),
+    ]);
+}
+
+#[test]
+fn parse_duplicate_child_labels() {
+    let text = r#"parent { main label="a"; flag; flag; main label="b"; }"#;
+    let err = knuffel::parse::<Vec<Child>>("<test>", text).unwrap_err();
+    let labels = err.related().unwrap()
+        .map(|e| (e.to_string(), e.labels().unwrap()
+            .map(|l| (l.label().unwrap().to_string(), l.offset()))
+            .collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec![
+        ("duplicate node `flag`, single node expected".into(), vec![
+            ("duplicate node".into(), 31),
+            ("first node is here".into(), 25),
+        ]),
+        ("duplicate node `main`, single node expected".into(), vec![
+            ("duplicate node".into(), 37),
+            ("first node is here".into(), 9),
+        ]),
+    ]);
+}
+
+#[test]
+fn decode_synthetic_property_spans() {
+    // spans of a hand-built AST need not follow the source text layout
+    let mut doc = knuffel::parse_ast::<Span>("<test>", r#"node label="a""#)
+        .unwrap();
+    let node = &mut doc.nodes[0];
+    node.properties = std::mem::take(&mut node.properties).into_iter()
+        .map(|(name, mut value)| {
+            value.literal = value.literal.map_span(|_| Span(10, 11));
+            (name.map_span(|_| Span(0, 1)), value)
+        })
+        .collect();
+    assert_eq!(knuffel::decode::node::<Prop1, _>(&doc.nodes[0]).unwrap(),
+               Prop1 { label: "a".into() });
+}
+
+#[test]
+fn parse_duplicate_property_span() {
+    let text = r#"node label="a" label="b""#;
+    let err = knuffel::parse::<Vec<Prop1>>("<test>", text).unwrap_err();
+    let errors = err.related().unwrap()
+        .map(|e| (e.to_string(), e.labels().unwrap()
+            .map(|l| (l.label().unwrap().to_string(), l.offset(), l.len()))
+            .collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec![
+        ("duplicate property `label`".into(),
+         vec![("duplicate property".into(), 21, 3),
+              ("first property is here".into(), 5, 5)]),
+    ]);
+    let unique = |text| {
+        let doc = knuffel::parse_ast::<Span>("<test>", text).unwrap();
+        let node = &doc.nodes[0];
+        let (name, value) = node.properties.iter().next().unwrap();
+        knuffel::decode::unique_property(node, name, value).is_ok()
+    };
+    assert!(unique(r#"node label=(t)"a""#));
+    assert!(!unique(r#"node label=(t)"a" label=(t)"b""#));
+    // AST keeps the rightmost value as required by the spec
+    let doc = knuffel::parse_ast::<Span>("<test>", text).unwrap();
+    assert_eq!(doc.nodes[0].properties.len(), 1);
+    assert_eq!(doc.nodes[0].duplicate_properties.iter()
+               .map(|n| (&n[..], n.span().0)).collect::<Vec<_>>(),
+               vec![("label", 15)]);
+    assert_eq!(parse::<VarProp>(r#"node a=1 b=2 a=3"#),
+        VarProp { scores: vec![
+            ("a".into(), 3),
+            ("b".into(), 2),
+        ].into_iter().collect() });
+}
+
+#[test]
+fn parse_surplus_argument_span() {
+    let text = r#"node "a" "b" "c""#;
+    let err = knuffel::parse::<Vec<Arg1>>("<test>", text).unwrap_err();
+    let errors = err.related().unwrap()
+        .map(|e| (e.to_string(), e.labels().unwrap()
+            .map(|l| (l.offset(), l.len()))
+            .collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec![
+        ("unexpected argument".into(), vec![(9, 3)]),
+    ]);
+}
+
+#[test]
+fn parse_children_only_stray_values() {
+    let text = r#"parent "x" foo=1 { main label="a"; }"#;
+    let err = knuffel::parse::<Vec<Child>>("<test>", text).unwrap_err();
+    let errors = err.related().unwrap()
+        .map(|e| (e.to_string(), e.labels().unwrap()
+            .map(|l| (l.offset(), l.len()))
+            .collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec![
+        ("unexpected argument for node `parent`".into(), vec![(7, 3)]),
+        ("unexpected property `foo` for node `parent`".into(), vec![(11, 3)]),
+    ]);
+}
+
+#[test]
+fn parse_missing_arguments() {
+    assert_eq!(parse::<Pair>(r#"pair "a" 1"#),
+               Pair { key: "a".into(), value: 1, comment: None });
+    assert_eq!(parse_err::<Pair>(r#"pair "a""#),
+        "missing required argument `value` for node `pair`");
+    let err = knuffel::parse::<Vec<Pair>>("<test>", "x\n  pair {}")
+        .unwrap_err();
+    let errors = err.related().unwrap()
+        .map(|e| (e.to_string(), e.labels().unwrap()
+            .map(|l| (l.offset(), l.len()))
+            .collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec![
+        ("missing required argument `key` for node `x`".into(), vec![(0, 1)]),
+        ("missing required argument `key` for node `pair`".into(),
+         vec![(4, 4)]),
+    ]);
+}
+
+#[test]
+fn parse_all_errors() {
+    // every independent problem is reported in a single pass, including
+    // ones in other nodes of the document
+    assert_eq!(parse_err::<Validated>(r#"
+        node 1000 label=1 { main label=2; item 3; item "ok"; item 4; bad; }
+        node "x" label="ok" { main label="ok"; extra label="ok"; }
+    "#),
+        "value 1000 out of range for u8 (0..=255)\n\
+         expected string scalar, found integer\n\
+         expected string scalar, found integer\n\
+         expected string scalar, found integer\n\
+         expected string scalar, found integer\n\
+         unexpected node `bad`\n\
+         child node `extra` is required\n\
+         expected integer scalar, found string");
+}
+
+#[test]
+fn parse_tolerant() {
+    let tolerant = knuffel::ParseOptions { tolerant: true };
+    let expected = vec![Prop1 { label: "hello".into() }];
+    assert_eq!(knuffel::parse_with_options::<Vec<Prop1>>("<test>",
+        "\u{FEFF}node label=\"hello\"\n", &tolerant).unwrap(), expected);
+    assert_eq!(knuffel::parse_with_options::<Vec<Prop1>>("<test>",
+        "node label=\"hello\"\n\0", &tolerant).unwrap(), expected);
+    assert_eq!(knuffel::parse_with_options::<Vec<Prop1>>("<test>",
+        "\u{FEFF}node label=\"hello\"\0", &tolerant).unwrap(), expected);
+    assert!(knuffel::parse_with_options::<Vec<Prop1>>("<test>",
+        "node label=\"hello\"\0", &Default::default()).is_err());
+    assert!(knuffel::parse_with_options::<Vec<Prop1>>("<test>",
+        "node label=\"hello\"\0\0", &tolerant).is_err());
+
+    // offsets are relative to the original text, BOM included
+    let err = knuffel::parse_with_options::<Vec<Prop1>>("<test>",
+        "\u{FEFF}node label=1\0", &tolerant).unwrap_err();
+    let labels = err.related().unwrap()
+        .flat_map(|e| e.labels().into_iter().flatten()
+                  .map(|l| (l.offset(), l.len())))
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec![(14, 1)]);
+}
+
+#[test]
+fn parse_dedup_by_name() {
+    fn labels(items: &[Prop1]) -> Vec<&str> {
+        items.iter().map(|p| &p.label[..]).collect()
+    }
+    let res = parse::<DedupFirst>(r#"parent {
+        a label="a1"
+        b label="b1"
+        version 1
+        a label="a2"
+        c label="c1"
+    }"#);
+    assert_eq!(labels(&res.items), vec!["a1", "b1", "c1"]);
+    assert_eq!(res.version, Some(1));
+    let res = parse::<DedupLast>(r#"parent {
+        a label="a1"
+        b label="b1"
+        a label="a2"
+        (extra)a label="x"
+        c label="c1"
+    }"#);
+    assert_eq!(labels(&res.items), vec!["b1", "a2", "c1"]);
+    assert_eq!(labels(&res.extra), vec!["x"]);
+
+    // dropped duplicates are not decoded at all
+    assert_eq!(labels(&parse::<DedupFirst>(r#"parent { a label="a1"; a; }"#)
+                      .items),
+               vec!["a1"]);
+    assert_eq!(labels(&parse::<DedupLast>(r#"parent { a; a label="a2"; }"#)
+                      .items),
+               vec!["a2"]);
+    let res = parse::<DedupMap>(r#"parent { b label="b1"; a label="a1";
+                                            b label="b2"; }"#);
+    assert_eq!(res.items.iter().map(|(k, v)| (&k[..], &v.label[..]))
+               .collect::<Vec<_>>(),
+               vec![("a", "a1"), ("b", "b2")]);
+    // other fields are not affected
+    assert_eq!(parse_err::<DedupFirst>(r#"parent { version 1; version 2; }"#),
+        "duplicate node `version`, single node expected");
+}
+
+#[test]
+fn parse_property_or_child() {
+    assert_eq!(parse::<Connect>(r#"connect timeout=30"#),
+               Connect { timeout: 30, bind_address: None, attempts: 3 });
+    assert_eq!(parse::<Connect>(r#"connect { timeout 30; }"#),
+               Connect { timeout: 30, bind_address: None, attempts: 3 });
+    assert_eq!(parse::<Connect>(r#"connect timeout=30 attempts=5 {
+                                       bind-address "127.0.0.1"
+                                   }"#),
+               Connect {
+                   timeout: 30,
+                   bind_address: Some([127, 0, 0, 1].into()),
+                   attempts: 5,
+               });
+    assert_eq!(parse_err::<Connect>(r#"connect timeout=30 { timeout 40; }"#),
+        "`timeout` is specified both as a property and as a child");
+    assert_eq!(parse_err::<Connect>(r#"connect { timeout 30; timeout 40; }"#),
+        "duplicate node `timeout`, single node expected");
+    assert_eq!(parse_err::<Connect>(r#"connect"#),
+        "property or child node `timeout` is required");
+    assert_eq!(parse_err::<Connect>(r#"connect timeout="x""#),
+        "expected integer scalar, found string");
+}
+
+#[test]
+fn parse_one_of() {
+    assert_eq!(parse::<Endpoint>(r#"endpoint url="http://localhost""#),
+               Endpoint { url: Some("http://localhost".into()),
+                          host_port: None });
+    assert_eq!(parse::<Endpoint>(r#"endpoint { host-port "localhost:80"; }"#),
+               Endpoint { url: None, host_port: Some("localhost:80".into()) });
+    assert_eq!(parse_err::<Endpoint>(r#"endpoint"#),
+        "exactly one of `url`, `host-port` is required");
+
+    let text = r#"endpoint url="a" { host-port "b"; }"#;
+    let err = knuffel::parse::<Vec<Endpoint>>("<test>", text).unwrap_err();
+    let errors = err.related().unwrap().collect::<Vec<_>>();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(),
+               "`url` and `host-port` are mutually exclusive");
+    let labels = errors[0].labels().unwrap()
+        .map(|l| (l.label().unwrap().to_string(), l.offset(), l.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(labels, vec![
+        ("duplicate choice".into(), 19, 9),
+        ("first choice is here".into(), 9, 3),
+    ]);
+}
+
+#[test]
+fn parse_at_most_one_of() {
+    assert_eq!(parse::<Lookup>(r#"lookup"#),
+               Lookup { name: None, id: None });
+    assert_eq!(parse::<Lookup>(r#"lookup "root""#),
+               Lookup { name: Some("root".into()), id: None });
+    assert_eq!(parse::<Lookup>(r#"lookup id=0"#),
+               Lookup { name: None, id: Some(0) });
+    assert_eq!(parse::<Lookup>(r#"lookup null id=0"#),
+               Lookup { name: None, id: Some(0) });
+    assert_eq!(parse_err::<Lookup>(r#"lookup "root" id=0"#),
+        "`name` and `id` are mutually exclusive");
+}
+
+#[test]
+fn parse_one_of_document() {
+    assert_eq!(parse_doc::<Listener>(r#"listen "127.0.0.1:80""#),
+               Listener { listen: Some("127.0.0.1:80".into()), socket: None });
+    assert_eq!(parse_doc::<Listener>(r#"socket "/run/server.sock""#),
+               Listener { listen: None, socket: Some("/run/server.sock".into()) });
+    assert_eq!(parse_doc_err::<Listener>(r#""#),
+        "exactly one of the nodes `listen`, `socket` is required");
+    assert_eq!(parse_doc_err::<Listener>(r#"listen "a"; socket "b""#),
+        "`listen` and `socket` are mutually exclusive");
 }
@@ -1,7 +1,15 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::time::Duration;
 
-use knuffel::{Decode};
-use knuffel::span::Span;
+use enumflags2::BitFlags;
+use knuffel::{Decode, DecodeScalar};
+use knuffel::ast::{Literal, TypeName, Value};
+use knuffel::decode::Context;
+use knuffel::errors::{DecodeError, ExpectedType};
+use knuffel::span::{Span, Spanned};
+use knuffel::traits::ErrorSpan;
 use miette::Diagnostic;
 
 
@@ -11,12 +19,373 @@ enum SomeScalar {
     AnotherOption,
 }
 
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+enum Color {
+    Red,
+    Green,
+    #[knuffel(raw_fallback)]
+    Raw(Literal),
+}
+
+fn squash(value: &str) -> Cow<'_, str> {
+    value.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .map(|c| c.to_ascii_lowercase())
+        .collect::<String>()
+        .into()
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+#[knuffel(normalize=squash)]
+enum Normalized {
+    First,
+    AnotherOption,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+#[knuffel(rename_all="snake_case")]
+enum Snake {
+    First,
+    AnotherOption,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+#[knuffel(rename_all="SCREAMING_SNAKE_CASE")]
+enum Screaming {
+    First,
+    AnotherOption,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+enum Encoding {
+    #[knuffel(rename="UTF-8")]
+    Utf8,
+    #[knuffel(rename="latin1", alias="iso-8859-1", alias="ISO-8859-1")]
+    Latin1,
+    #[knuffel(alias="us-ascii")]
+    Ascii,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+#[knuffel(case_insensitive)]
+enum Level {
+    Debug,
+    #[knuffel(alias="Warn")]
+    Warning,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+#[knuffel(kind="int")]
+enum Version {
+    #[knuffel(value=1)]
+    V1,
+    #[knuffel(value=2)]
+    V2,
+    #[knuffel(value=3)]
+    V3,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+enum Switch {
+    #[knuffel(value=0, bool(false))]
+    Off,
+    #[knuffel(value=1, bool(true))]
+    On,
+    #[knuffel(value=-1)]
+    Auto,
+}
+
 #[derive(knuffel::Decode, Debug, PartialEq)]
 struct Item {
     #[knuffel(argument)]
     value: SomeScalar,
 }
 
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct NormalizedItem {
+    #[knuffel(argument)]
+    value: Normalized,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct RenamedItem {
+    #[knuffel(argument)]
+    snake: Snake,
+    #[knuffel(argument)]
+    screaming: Screaming,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct EncodingItem {
+    #[knuffel(argument)]
+    value: Encoding,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct LevelItem {
+    #[knuffel(argument)]
+    value: Level,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct VersionItem {
+    #[knuffel(argument)]
+    value: Version,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Ports {
+    #[knuffel(arguments)]
+    ports: Vec<u16>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Limits {
+    #[knuffel(properties)]
+    limits: HashMap<String, u32>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Primitives {
+    #[knuffel(argument)]
+    byte: u8,
+    #[knuffel(argument)]
+    ratio: f64,
+    #[knuffel(argument)]
+    flag: bool,
+    #[knuffel(argument)]
+    label: String,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Bounded {
+    #[knuffel(argument)]
+    unsigned: u8,
+    #[knuffel(argument)]
+    signed: i8,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Nullable {
+    #[knuffel(argument)]
+    first: Option<u32>,
+    #[knuffel(arguments)]
+    rest: Vec<Option<u32>>,
+    #[knuffel(property)]
+    label: Option<String>,
+    #[knuffel(property, default)]
+    unset: (),
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct NotNull {
+    #[knuffel(argument)]
+    value: u32,
+    #[knuffel(property)]
+    label: String,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Annotated {
+    #[knuffel(argument, type_name="date")]
+    date: String,
+    #[knuffel(argument, type_name="u8")]
+    level: u8,
+    #[knuffel(property, type_name="ms")]
+    timeout: Option<u64>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct SwitchItem {
+    #[knuffel(argument)]
+    value: Switch,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct ColorItem {
+    #[knuffel(argument)]
+    value: Color,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq)]
+enum NumberType {
+    F64,
+    Percent,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Number {
+    #[knuffel(argument)]
+    value: f64,
+    #[knuffel(type_of=value)]
+    value_type: Option<NumberType>,
+    #[knuffel(property)]
+    label: Option<String>,
+    #[knuffel(type_of=label)]
+    label_type: String,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, PartialEq, Eq, Hash)]
+enum Mode {
+    Read,
+    Write,
+    Exec,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Access {
+    #[knuffel(argument, split_flags)]
+    modes: HashSet<Mode>,
+    #[knuffel(property, split_flags)]
+    default: Option<Vec<Mode>>,
+}
+
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(knuffel::DecodeScalar, Debug, Clone, Copy, PartialEq)]
+enum Permission {
+    Read = 0b001,
+    Write = 0b010,
+    Exec = 0b100,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Perms {
+    #[knuffel(arguments)]
+    perms: BitFlags<Permission>,
+}
+
+const FREQUENCY: &[(&str, f64)] = &[("Hz", 1.0), ("kHz", 1e3), ("MHz", 1e6)];
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Tone {
+    #[knuffel(argument, units=FREQUENCY)]
+    freq: f64,
+    #[knuffel(property, units=FREQUENCY)]
+    max: Option<f64>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct ItemList {
+    #[knuffel(arguments)]
+    values: Vec<SomeScalar>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Length {
+    Px(u32),
+    Em(u32),
+}
+
+impl<S: ErrorSpan> DecodeScalar<S> for Length {
+    fn type_check(_: &Option<Spanned<TypeName, S>>, _: &mut Context<S>) {}
+    fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
+        -> Result<Self, DecodeError<S>>
+    {
+        DecodeScalar::raw_decode(val, ctx).map(Length::Px)
+    }
+    fn decode(value: &Value<S>, ctx: &mut Context<S>)
+        -> Result<Self, DecodeError<S>>
+    {
+        let num = DecodeScalar::raw_decode(&value.literal, ctx)?;
+        match value.type_name.as_ref() {
+            None => Ok(Length::Px(num)),
+            Some(typ) => match typ.as_str() {
+                "px" => Ok(Length::Px(num)),
+                "em" => Ok(Length::Em(num)),
+                _ => Err(DecodeError::TypeName {
+                    span: typ.span().clone(),
+                    found: Some((**typ).clone()),
+                    expected: ExpectedType::optional(
+                        "px".parse::<TypeName>().unwrap()),
+                    rust_type: "Length",
+                }),
+            },
+        }
+    }
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct LengthList {
+    #[knuffel(arguments)]
+    values: Vec<Length>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct IntList {
+    #[knuffel(arguments)]
+    values: Vec<u32>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Opacity {
+    #[knuffel(argument, percent)]
+    value: f64,
+    #[knuffel(property, percent=0..=100)]
+    fade: Option<f64>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Toggle {
+    #[knuffel(argument, bool_words)]
+    enabled: bool,
+    #[knuffel(property, bool_words)]
+    verbose: Option<bool>,
+    #[knuffel(property)]
+    strict: Option<bool>,
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Retry {
+    #[knuffel(argument)]
+    timeout: Duration,
+    #[knuffel(property, duration(unit="ms"))]
+    delay: Option<Duration>,
+}
+
+/// Accepts a literal of any kind
+#[derive(Debug, PartialEq)]
+struct Loose(String);
+
+impl<S: ErrorSpan> DecodeScalar<S> for Loose {
+    fn type_check(_: &Option<Spanned<TypeName, S>>, _: &mut Context<S>) {}
+    fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
+        -> Result<Self, DecodeError<S>>
+    {
+        match &**val {
+            Literal::String(s) => Ok(Loose(s.to_string())),
+            Literal::Int(_) => DecodeScalar::raw_decode(val, ctx)
+                .map(|v: i64| Loose(v.to_string())),
+            Literal::Decimal(_) => DecodeScalar::raw_decode(val, ctx)
+                .map(|v: f64| Loose(v.to_string())),
+            Literal::Bool(b) => Ok(Loose(b.to_string())),
+            Literal::Null => Ok(Loose("null".into())),
+        }
+    }
+}
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Forced {
+    #[knuffel(argument, kind="string")]
+    version: Loose,
+    #[knuffel(argument)]
+    any: Option<Loose>,
+    #[knuffel(property, kind="int")]
+    port: Option<u32>,
+    #[knuffel(property, kind="bool")]
+    flag: Option<Loose>,
+}
+
+
+#[derive(knuffel::Decode, Debug, PartialEq)]
+struct Proxy {
+    #[knuffel(argument, none_if="none")]
+    host: Option<String>,
+    #[knuffel(property, none_if="")]
+    user: Option<String>,
+    #[knuffel(property, none_if="auto")]
+    port: Option<u16>,
+}
 
 fn parse<T: Decode<Span>>(text: &str) -> T {
     let mut nodes: Vec<T> = knuffel::parse("<test>", text).unwrap();
@@ -31,6 +400,19 @@ fn parse_err<T: Decode<Span>+fmt::Debug>(text: &str) -> String {
         .join("\n")
 }
 
+fn parse_err_spans<T: Decode<Span>+fmt::Debug>(text: &str)
+    -> Vec<(String, usize, usize)>
+{
+    let err = knuffel::parse::<Vec<T>>("<test>", text).unwrap_err();
+    err.related().unwrap()
+        .flat_map(|e| {
+            let msg = e.to_string();
+            e.labels().into_iter().flatten()
+                .map(move |l| (msg.clone(), l.offset(), l.len()))
+        })
+        .collect()
+}
+
 #[test]
 fn parse_some_scalar() {
     assert_eq!(parse::<Item>(r#"node "first""#),
@@ -40,3 +422,472 @@ fn parse_some_scalar() {
     assert_eq!(parse_err::<Item>(r#"node "test""#),
         "expected one of `first`, `another-option`");
 }
+
+#[test]
+fn parse_type_of() {
+    assert_eq!(parse::<Number>(r#"node (f64)1.5 label=(text)"x""#),
+               Number {
+                   value: 1.5,
+                   value_type: Some(NumberType::F64),
+                   label: Some("x".into()),
+                   label_type: "text".into(),
+               });
+    assert_eq!(parse::<Number>(r#"node (percent)50 label=(x)null"#),
+               Number {
+                   value: 50.0,
+                   value_type: Some(NumberType::Percent),
+                   label: None,
+                   label_type: "x".into(),
+               });
+    assert_eq!(parse::<Number>(r#"node 1.5 label=(text)"x""#).value_type,
+               None);
+    assert_eq!(parse_err::<Number>(r#"node (u8)1.5 label=(text)"x""#),
+               "expected one of `f64`, `percent`");
+    assert_eq!(parse_err::<Number>(r#"node 1.5 label="x""#),
+               "type annotation of `label` is required");
+}
+
+#[test]
+fn parse_scalar_list() {
+    assert_eq!(parse::<ItemList>(r#"node "first" "another-option""#),
+               ItemList { values: vec![
+                   SomeScalar::First,
+                   SomeScalar::AnotherOption,
+               ]});
+    assert_eq!(parse_err_spans::<ItemList>(
+                   r#"node "first" "bad" "another-option""#),
+               vec![("expected one of `first`, `another-option`".into(),
+                     13, 5)]);
+    assert_eq!(parse_err_spans::<ItemList>(r#"node "x" "first" "yy""#),
+               vec![("expected one of `first`, `another-option`".into(),
+                     5, 3),
+                    ("expected one of `first`, `another-option`".into(),
+                     17, 4)]);
+}
+
+#[test]
+fn parse_annotated_list() {
+    assert_eq!(parse::<LengthList>(r#"node (em)1 2 (px)3 (em)4"#),
+               LengthList { values: vec![
+                   Length::Em(1),
+                   Length::Px(2),
+                   Length::Px(3),
+                   Length::Em(4),
+               ]});
+    assert_eq!(parse_err_spans::<LengthList>(r#"node (em)1 (pt)2 (cm)3"#),
+               vec![("px or no type for Length, found pt".into(), 11, 4),
+                    ("px or no type for Length, found cm".into(), 17, 4)]);
+    assert_eq!(parse::<IntList>(r#"node (u32)1 2 (u32)3"#),
+               IntList { values: vec![1, 2, 3] });
+    assert_eq!(parse_err_spans::<IntList>(r#"node (u32)1 (u8)2 3"#),
+               vec![("u32 or no type for u32, found u8".into(), 12, 4)]);
+}
+
+#[test]
+fn parse_split_flags() {
+    assert_eq!(parse::<Access>(r#"access "read write""#),
+               Access {
+                   modes: [Mode::Read, Mode::Write].into_iter().collect(),
+                   default: None,
+               });
+    assert_eq!(parse::<Access>(r#"access "  exec\t read  exec " default="""#),
+               Access {
+                   modes: [Mode::Read, Mode::Exec].into_iter().collect(),
+                   default: Some(vec![]),
+               });
+    assert_eq!(parse::<Access>(r#"access "" default="read read""#),
+               Access {
+                   modes: HashSet::new(),
+                   default: Some(vec![Mode::Read, Mode::Read]),
+               });
+    assert_eq!(parse::<Access>(r#"access "read" default=null"#).default,
+               None);
+    assert_eq!(parse_err_spans::<Access>(r#"access "read wirte exe""#),
+               vec![("expected one of `read`, `write`, `exec`".into(), 7, 16),
+                    ("expected one of `read`, `write`, `exec`".into(), 7, 16)]);
+    assert_eq!(parse_err::<Access>(r#"access 1"#),
+        "expected string scalar, found integer");
+    assert_eq!(parse_err::<Access>(r#"access (u8)"read write""#),
+        "no type for Mode, found u8");
+}
+
+#[test]
+fn parse_bitflags() {
+    use Permission::*;
+    assert_eq!(parse::<Perms>(r#"perms "read" "write" "exec""#).perms,
+               Read | Write | Exec);
+    assert_eq!(parse::<Perms>(r#"perms "exec" "read" "exec""#).perms,
+               Exec | Read);
+    assert_eq!(parse::<Perms>(r#"perms"#).perms, BitFlags::empty());
+    assert_eq!(parse_err::<Perms>(r#"perms "read" "delete""#),
+        "expected one of `read`, `write`, `exec`");
+}
+
+#[test]
+fn parse_units() {
+    assert_eq!(parse::<Tone>(r#"tone "3 kHz""#),
+               Tone { freq: 3000.0, max: None });
+    assert_eq!(parse::<Tone>(r#"tone " 1.5\t  MHz " max="440""#),
+               Tone { freq: 1_500_000.0, max: Some(440.0) });
+    assert_eq!(parse::<Tone>(r#"tone "-2e-3 Hz" max=null"#),
+               Tone { freq: -0.002, max: None });
+    assert_eq!(parse_err_spans::<Tone>(r#"tone "3 khz""#),
+        vec![("unknown unit `khz`, expected one of `Hz`, `kHz`, `MHz`".into(),
+              5, 7)]);
+    assert_eq!(parse_err::<Tone>(r#"tone "3kHz""#),
+        "invalid float literal");
+    assert_eq!(parse_err::<Tone>(r#"tone 3"#),
+        "expected string scalar, found integer");
+}
+
+#[test]
+fn parse_literal_kind() {
+    assert_eq!(parse::<Forced>(r#"node "1.0" 2 port=80 flag=true"#),
+               Forced {
+                   version: Loose("1.0".into()),
+                   any: Some(Loose("2".into())),
+                   port: Some(80),
+                   flag: Some(Loose("true".into())),
+               });
+    assert_eq!(parse::<Forced>(r#"node "1.0" port=null flag=null"#),
+               Forced {
+                   version: Loose("1.0".into()),
+                   any: None,
+                   port: None,
+                   flag: None,
+               });
+    assert_eq!(parse_err_spans::<Forced>(r#"node 1.0"#),
+               vec![("expected string scalar, found decimal".into(), 5, 3)]);
+    assert_eq!(parse_err::<Forced>(r#"node "1.0" flag="yes""#),
+        "expected boolean scalar, found string");
+    assert_eq!(parse_err::<Forced>(r#"node "1.0" port=8.0"#),
+        "expected integer scalar, found decimal");
+    // the kind is checked first, the type name is checked by the field type
+    assert_eq!(parse_err::<Forced>(r#"node "1.0" port=(u8)80"#),
+        "u32 or no type for u32, found u8");
+}
+
+#[test]
+fn parse_none_if() {
+    assert_eq!(parse::<Proxy>(r#"proxy "none" user="" port="auto""#),
+               Proxy { host: None, user: None, port: None });
+    assert_eq!(parse::<Proxy>(r#"proxy "localhost" user="admin" port=3128"#),
+               Proxy {
+                   host: Some("localhost".into()),
+                   user: Some("admin".into()),
+                   port: Some(3128),
+               });
+    assert_eq!(parse::<Proxy>(r#"proxy"#),
+               Proxy { host: None, user: None, port: None });
+    // only exact string matches, other kinds are decoded as usual
+    assert_eq!(parse::<Proxy>(r#"proxy "None" user=" ""#),
+               Proxy {
+                   host: Some("None".into()),
+                   user: Some(" ".into()),
+                   port: None,
+               });
+    assert_eq!(parse_err::<Proxy>(r#"proxy port="manual""#),
+        "expected integer scalar, found string");
+}
+
+#[test]
+fn parse_percent() {
+    assert_eq!(parse::<Opacity>(r#"opacity "50%""#),
+               Opacity { value: 0.5, fade: None });
+    assert_eq!(parse::<Opacity>(r#"opacity 0.5 fade="100 %""#),
+               Opacity { value: 0.5, fade: Some(1.0) });
+    assert_eq!(parse::<Opacity>(r#"opacity 2 fade=0"#),
+               Opacity { value: 2.0, fade: Some(0.0) });
+    assert_eq!(parse::<Opacity>(r#"opacity "150%" fade=null"#),
+               Opacity { value: 1.5, fade: None });
+    // bare fractions are kept exactly as written
+    assert_eq!(parse::<Opacity>(r#"opacity 0.014 fade=0.07"#),
+               Opacity { value: 0.014, fade: Some(0.07) });
+    assert_eq!(parse_err_spans::<Opacity>(r#"opacity "50%" fade="150%""#),
+               vec![("150% is out of range 0%..=100%".into(), 19, 6)]);
+    assert_eq!(parse_err::<Opacity>(r#"opacity 0 fade=-0.25"#),
+        "-25% is out of range 0%..=100%");
+    assert_eq!(parse_err::<Opacity>(r#"opacity "50""#),
+        "percentage must end with `%`");
+    assert_eq!(parse_err::<Opacity>(r#"opacity "half%""#),
+        "invalid float literal");
+    assert_eq!(parse_err::<Opacity>(r#"opacity true"#),
+        "expected string scalar, found boolean");
+}
+
+#[test]
+fn parse_bool_words() {
+    let toggle = |enabled, verbose| Toggle { enabled, verbose, strict: None };
+    assert_eq!(parse::<Toggle>(r#"toggle "yes" verbose="off""#),
+               toggle(true, Some(false)));
+    assert_eq!(parse::<Toggle>(r#"toggle "No" verbose="ON""#),
+               toggle(false, Some(true)));
+    assert_eq!(parse::<Toggle>(r#"toggle "TRUE" verbose="False""#),
+               toggle(true, Some(false)));
+    assert_eq!(parse::<Toggle>(r#"toggle "oFf" verbose=null"#),
+               toggle(false, None));
+    assert_eq!(parse::<Toggle>(r#"toggle true verbose=false strict=true"#),
+               Toggle { enabled: true, verbose: Some(false),
+                        strict: Some(true) });
+    assert_eq!(parse_err_spans::<Toggle>(r#"toggle "maybe""#),
+        vec![("expected a boolean, one of `yes`, `no`, `on`, `off`, \
+               `true`, `false`, found `maybe`".into(), 7, 7)]);
+    assert_eq!(parse_err::<Toggle>(r#"toggle 1"#),
+        "expected boolean scalar, found integer");
+    assert_eq!(parse_err::<Toggle>(r#"toggle true strict="yes""#),
+        "expected boolean scalar, found string");
+}
+
+#[test]
+fn parse_duration() {
+    assert_eq!(parse::<Retry>(r#"retry "30s" delay="2m""#),
+               Retry { timeout: Duration::from_secs(30),
+                       delay: Some(Duration::from_secs(120)) });
+    assert_eq!(parse::<Retry>(r#"retry 30 delay=1500"#),
+               Retry { timeout: Duration::from_secs(30),
+                       delay: Some(Duration::from_millis(1500)) });
+    assert_eq!(parse::<Retry>(r#"retry "1.5 h" delay="250""#),
+               Retry { timeout: Duration::from_secs(5400),
+                       delay: Some(Duration::from_millis(250)) });
+    assert_eq!(parse::<Retry>(r#"retry "10us" delay=null"#),
+               Retry { timeout: Duration::from_micros(10), delay: None });
+    assert_eq!(parse::<Retry>(r#"retry "1d" delay="3ns""#),
+               Retry { timeout: Duration::from_secs(86400),
+                       delay: Some(Duration::from_nanos(3)) });
+    assert_eq!(parse_err_spans::<Retry>(r#"retry "30 sec""#),
+        vec![("invalid duration `30 sec`, expected a number followed by \
+               one of `ns`, `us`, `ms`, `s`, `m`, `h`, `d`".into(), 6, 8)]);
+    assert_eq!(parse_err::<Retry>(r#"retry "s""#),
+        "invalid duration `s`, expected a number followed by \
+         one of `ns`, `us`, `ms`, `s`, `m`, `h`, `d`");
+    assert_eq!(parse_err::<Retry>(r#"retry "99999999999999999999d""#),
+        "duration `99999999999999999999d` is too large");
+    assert_eq!(parse_err::<Retry>(r#"retry -1"#),
+        "value -1 out of range for u64 (0..=18446744073709551615)");
+    assert_eq!(parse_err::<Retry>(r#"retry 1.5"#),
+        "expected string scalar, found decimal");
+}
+
+#[test]
+fn parse_mixed_kinds() {
+    assert_eq!(parse::<SwitchItem>(r#"node "auto""#),
+               SwitchItem { value: Switch::Auto });
+    assert_eq!(parse::<SwitchItem>(r#"node 1"#),
+               SwitchItem { value: Switch::On });
+    assert_eq!(parse::<SwitchItem>(r#"node -1"#),
+               SwitchItem { value: Switch::Auto });
+    assert_eq!(parse::<SwitchItem>(r#"node false"#),
+               SwitchItem { value: Switch::Off });
+    assert_eq!(parse_err::<SwitchItem>(r#"node 2"#),
+        "expected one of `off`, `on`, `auto`, or integer 0, 1, -1, \
+         or boolean false, true");
+    assert_eq!(parse_err::<SwitchItem>(r#"node "yes""#),
+        "expected one of `off`, `on`, `auto`, or integer 0, 1, -1, \
+         or boolean false, true");
+    assert_eq!(parse_err::<SwitchItem>(r#"node 1.0"#),
+        "expected string, integer or boolean scalar, found decimal");
+    // string-only enums still report only strings
+    assert_eq!(parse_err::<Item>(r#"node true"#),
+        "expected string scalar, found boolean");
+}
+
+#[test]
+fn parse_raw_fallback() {
+    assert_eq!(parse::<ColorItem>(r#"node "red""#),
+               ColorItem { value: Color::Red } );
+    assert_eq!(parse::<ColorItem>(r#"node "green""#),
+               ColorItem { value: Color::Green } );
+    assert_eq!(parse::<ColorItem>(r##"node "#ff0000""##),
+               ColorItem {
+                   value: Color::Raw(Literal::String("#ff0000".into())),
+               });
+    match parse::<ColorItem>(r#"node 0xff0000"#).value {
+        Color::Raw(Literal::Int(value)) => {
+            assert_eq!(u32::try_from(&value).unwrap(), 0xff0000);
+        }
+        value => panic!("unexpected value {:?}", value),
+    }
+    assert_eq!(parse::<ColorItem>(r#"node null"#),
+               ColorItem { value: Color::Raw(Literal::Null) });
+}
+
+#[test]
+fn parse_normalized() {
+    assert_eq!(parse::<NormalizedItem>(r#"node "first""#),
+               NormalizedItem { value: Normalized::First } );
+    assert_eq!(parse::<NormalizedItem>(r#"node "another-option""#),
+               NormalizedItem { value: Normalized::AnotherOption } );
+    assert_eq!(parse::<NormalizedItem>(r#"node "Another Option""#),
+               NormalizedItem { value: Normalized::AnotherOption } );
+    assert_eq!(parse::<NormalizedItem>(r#"node "another_option""#),
+               NormalizedItem { value: Normalized::AnotherOption } );
+    assert_eq!(parse_err::<NormalizedItem>(r#"node "other""#),
+        "expected one of `first`, `another-option`");
+}
+
+#[test]
+fn parse_rename_all() {
+    assert_eq!(parse::<RenamedItem>(r#"node "first" "FIRST""#),
+               RenamedItem { snake: Snake::First,
+                             screaming: Screaming::First });
+    assert_eq!(
+        parse::<RenamedItem>(r#"node "another_option" "ANOTHER_OPTION""#),
+        RenamedItem { snake: Snake::AnotherOption,
+                      screaming: Screaming::AnotherOption });
+    assert_eq!(parse_err::<RenamedItem>(r#"node "another-option" "FIRST""#),
+        "expected one of `first`, `another_option`");
+    assert_eq!(parse_err::<RenamedItem>(r#"node "first" "first""#),
+        "expected one of `FIRST`, `ANOTHER_OPTION`");
+}
+
+#[test]
+fn parse_variant_rename() {
+    assert_eq!(parse::<EncodingItem>(r#"node "UTF-8""#),
+               EncodingItem { value: Encoding::Utf8 });
+    assert_eq!(parse::<EncodingItem>(r#"node "latin1""#),
+               EncodingItem { value: Encoding::Latin1 });
+    assert_eq!(parse::<EncodingItem>(r#"node "ascii""#),
+               EncodingItem { value: Encoding::Ascii });
+}
+
+#[test]
+fn parse_variant_alias() {
+    assert_eq!(parse::<EncodingItem>(r#"node "iso-8859-1""#),
+               EncodingItem { value: Encoding::Latin1 });
+    assert_eq!(parse::<EncodingItem>(r#"node "ISO-8859-1""#),
+               EncodingItem { value: Encoding::Latin1 });
+    assert_eq!(parse::<EncodingItem>(r#"node "us-ascii""#),
+               EncodingItem { value: Encoding::Ascii });
+    assert_eq!(parse_err::<EncodingItem>(r#"node "utf8""#),
+        "expected one of `UTF-8`, `latin1`, `ascii`");
+}
+
+#[test]
+fn parse_case_insensitive_scalar() {
+    assert_eq!(parse::<LevelItem>(r#"node "debug""#),
+               LevelItem { value: Level::Debug });
+    assert_eq!(parse::<LevelItem>(r#"node "DEBUG""#),
+               LevelItem { value: Level::Debug });
+    assert_eq!(parse::<LevelItem>(r#"node "Warning""#),
+               LevelItem { value: Level::Warning });
+    assert_eq!(parse::<LevelItem>(r#"node "warn""#),
+               LevelItem { value: Level::Warning });
+    assert_eq!(parse_err::<LevelItem>(r#"node "Trace""#),
+        "expected one of `debug`, `warning`");
+    assert_eq!(parse_err::<LevelItem>(r#"node (lvl)"debug""#),
+        "no type for Level, found lvl");
+}
+
+#[test]
+fn parse_int_repr() {
+    assert_eq!(parse::<VersionItem>(r#"node 1"#),
+               VersionItem { value: Version::V1 });
+    assert_eq!(parse::<VersionItem>(r#"node 3"#),
+               VersionItem { value: Version::V3 });
+    assert_eq!(parse_err::<VersionItem>(r#"node 4"#),
+        "expected one of 1, 2, 3");
+    assert_eq!(parse_err::<VersionItem>(r#"node "v1""#),
+        "expected integer scalar, found string");
+}
+
+#[test]
+fn parse_arguments_per_element() {
+    assert_eq!(parse::<Ports>(r#"node 80 443 8080"#),
+               Ports { ports: vec![80, 443, 8080] });
+    assert_eq!(parse_err_spans::<Ports>(
+        r#"node 80 443 "http" 70000 (port)22"#), vec![
+        ("expected integer scalar, found string".into(), 12, 6),
+        ("value 70000 out of range for u16 (0..=65535)".into(), 19, 5),
+        ("u16 or no type for u16, found port".into(), 25, 6),
+    ]);
+}
+
+#[test]
+fn parse_properties_map() {
+    let limits = parse::<Limits>(r#"node files=1024 procs=64"#).limits;
+    assert_eq!(limits.len(), 2);
+    assert_eq!(limits["files"], 1024);
+    assert_eq!(limits["procs"], 64);
+    assert_eq!(parse_err_spans::<Limits>(
+        r#"node files=1024 procs="many" mem=-1"#), vec![
+        // node properties are sorted by name
+        ("value -1 out of range for u32 (0..=4294967295)".into(), 33, 2),
+        ("expected integer scalar, found string".into(), 22, 6),
+    ]);
+}
+
+#[test]
+fn parse_primitive_type_name_spans() {
+    assert_eq!(parse::<Primitives>(r#"node (u8)1 (f64)0.5 true "x""#),
+               Primitives { byte: 1, ratio: 0.5, flag: true,
+                            label: "x".into() });
+    let text = r#"node (u16)1 (f32)0.5 (u8)true (u8)"x""#;
+    // spans cover `(type)` only, not the value after it
+    assert_eq!(parse_err_spans::<Primitives>(text), vec![
+        ("u8 or no type for u8, found u16".into(), 5, 5),
+        ("f64 or no type for f64, found f32".into(), 12, 5),
+        ("no type for bool, found u8".into(), 21, 4),
+        ("no type for String, found u8".into(), 30, 4),
+    ]);
+}
+
+#[test]
+fn parse_int_range() {
+    assert_eq!(parse::<Bounded>(r#"node 255 -128"#),
+               Bounded { unsigned: 255, signed: -128 });
+    assert_eq!(parse::<Bounded>(r#"node 0 127"#),
+               Bounded { unsigned: 0, signed: 127 });
+    assert_eq!(parse::<Bounded>(r#"node 0xff -0b1000_0000"#),
+               parse::<Bounded>(r#"node 255 -128"#));
+    assert_eq!(parse::<Bounded>(r#"node 0o17 0x7F"#),
+               Bounded { unsigned: 15, signed: 127 });
+    assert_eq!(parse_err::<Bounded>(r#"node 256 0"#),
+        "value 256 out of range for u8 (0..=255)");
+    assert_eq!(parse_err::<Bounded>(r#"node -1 0"#),
+        "value -1 out of range for u8 (0..=255)");
+    assert_eq!(parse_err::<Bounded>(r#"node 0 -129"#),
+        "value -129 out of range for i8 (-128..=127)");
+    assert_eq!(parse_err::<Bounded>(r#"node 0x100 -0x81"#),
+        "value 0x100 out of range for u8 (0..=255)\n\
+         value -0x81 out of range for i8 (-128..=127)");
+}
+
+#[test]
+fn parse_required_type_name() {
+    assert_eq!(parse::<Annotated>(r#"node (date)"2020-01-01" (u8)3"#),
+               Annotated { date: "2020-01-01".into(), level: 3,
+                           timeout: None });
+    assert_eq!(parse::<Annotated>(
+                   r#"node (date)"2020-01-01" (u8)3 timeout=(ms)250"#),
+               Annotated { date: "2020-01-01".into(), level: 3,
+                           timeout: Some(250) });
+    assert_eq!(parse::<Annotated>(
+                   r#"node (date)"2020-01-01" (u8)3 timeout=null"#),
+               Annotated { date: "2020-01-01".into(), level: 3,
+                           timeout: None });
+    // the span points at the annotation, or at the value if there is none
+    assert_eq!(parse_err_spans::<Annotated>(
+                   r#"node "2020-01-01" (i8)3 timeout=(s)1"#), vec![
+        ("date for String, found no type name".into(), 5, 12),
+        ("u8 for u8, found i8".into(), 18, 4),
+        ("ms for u64, found s".into(), 32, 3),
+    ]);
+}
+
+#[test]
+fn parse_null() {
+    assert_eq!(parse::<Nullable>(r#"node null 1 null 2 label=null"#),
+               Nullable { first: None, rest: vec![Some(1), None, Some(2)],
+                          label: None, unset: () });
+    assert_eq!(parse::<Nullable>(r#"node 1 label="x" unset=null"#),
+               Nullable { first: Some(1), rest: vec![],
+                          label: Some("x".into()), unset: () });
+    assert_eq!(parse_err_spans::<Nullable>(r#"node unset=0 label=1"#), vec![
+        ("expected string scalar, found integer".into(), 19, 1),
+        ("expected null scalar, found integer".into(), 11, 1),
+    ]);
+    assert_eq!(parse_err_spans::<NotNull>(r#"node null label=null"#), vec![
+        ("expected integer scalar, found null".into(), 5, 4),
+        ("expected string scalar, found null".into(), 16, 4),
+    ]);
+}
@@ -0,0 +1,352 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use knuffel::{Decode, span::Span};
+
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Plugin {
+    #[knuffel(argument)]
+    name: String,
+    #[knuffel(argument)]
+    version: Option<String>,
+    #[knuffel(arguments)]
+    flags: Vec<u32>,
+    #[knuffel(property)]
+    priority: u8,
+    #[knuffel(property, default="main".into())]
+    group: String,
+    #[knuffel(properties)]
+    extra: BTreeMap<String, i64>,
+    #[knuffel(child)]
+    listen: Listen,
+    #[knuffel(child, unwrap(argument))]
+    description: Option<String>,
+    #[knuffel(child)]
+    enabled: bool,
+    #[knuffel(children(name="dep"), unwrap(argument))]
+    deps: Vec<String>,
+    cache: Vec<u8>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Listen {
+    #[knuffel(argument, str)]
+    addr: SocketAddr,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Config {
+    #[knuffel(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Pair(#[knuffel(argument)] u32, #[knuffel(argument)] u32);
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Octets {
+    #[knuffel(arguments)]
+    octets: [u8; 4],
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Header {
+    #[knuffel(property, case_insensitive)]
+    content_type: String,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Tags {
+    #[knuffel(argument, split_flags)]
+    tags: Vec<String>,
+    #[knuffel(property, split_flags)]
+    extra: Option<Vec<String>>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Port {
+    #[knuffel(argument, try=[parse_named, u16::from_str])]
+    number: u16,
+    #[knuffel(property, try=[parse_named])]
+    fallback: Option<u16>,
+}
+
+fn parse_named(value: &str) -> Result<u16, &'static str> {
+    match value {
+        "http" => Ok(80),
+        "https" => Ok(443),
+        _ => Err("unknown port name"),
+    }
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Route {
+    #[knuffel(property, str)]
+    via: SocketAddr,
+    #[knuffel(property, str)]
+    backup: Option<SocketAddr>,
+    #[knuffel(property, from_u32)]
+    gateway: std::net::Ipv4Addr,
+    #[knuffel(property, from_u32)]
+    mask: Option<std::net::Ipv4Addr>,
+}
+
+const FREQUENCY: &[(&str, f64)] = &[("Hz", 1.0), ("kHz", 1e3), ("MHz", 1e6)];
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+#[knuffel(serde)]
+struct Tone {
+    #[knuffel(argument, units=FREQUENCY)]
+    freq: f64,
+    #[knuffel(property, units=FREQUENCY)]
+    max: Option<f64>,
+}
+
+
+fn parse<T: Decode<Span>>(text: &str) -> T {
+    let mut nodes: Vec<T> = knuffel::parse("<test>", text).unwrap();
+    assert_eq!(nodes.len(), 1);
+    nodes.remove(0)
+}
+
+fn parse_json<'a, T: serde::Deserialize<'a>>(text: &'a str) -> T {
+    serde_json::from_str(text).unwrap()
+}
+
+fn parse_json_err<'a, T>(text: &'a str) -> String
+    where T: serde::Deserialize<'a> + fmt::Debug,
+{
+    let err = serde_json::from_str::<T>(text).unwrap_err().to_string();
+    // strip position, it's checked by serde_json itself
+    err.split(" at line ").next().unwrap().into()
+}
+
+#[test]
+fn same_data() {
+    let kdl = parse::<Plugin>(r#"
+        plugin "auth" "1.2" 1 2 3 priority=10 retries=5 {
+            listen "127.0.0.1:8080"
+            description "Authentication"
+            enabled
+            dep "db"
+            dep "cache"
+        }
+    "#);
+    let json = parse_json::<Plugin>(r#"{
+        "arguments": ["auth", "1.2", 1, 2, 3],
+        "properties": {"priority": 10, "retries": 5},
+        "children": {
+            "listen": {"arguments": ["127.0.0.1:8080"]},
+            "description": "Authentication",
+            "enabled": true,
+            "dep": ["db", "cache"]
+        }
+    }"#);
+    assert_eq!(kdl, json);
+    assert_eq!(json, Plugin {
+        name: "auth".into(),
+        version: Some("1.2".into()),
+        flags: vec![1, 2, 3],
+        priority: 10,
+        group: "main".into(),
+        extra: vec![("retries".into(), 5)].into_iter().collect(),
+        listen: Listen { addr: "127.0.0.1:8080".parse().unwrap() },
+        description: Some("Authentication".into()),
+        enabled: true,
+        deps: vec!["db".into(), "cache".into()],
+        cache: Vec::new(),
+    });
+}
+
+#[test]
+fn same_defaults() {
+    let kdl = parse::<Plugin>(r#"
+        plugin "auth" priority=1 {
+            listen "[::1]:80"
+        }
+    "#);
+    let json = parse_json::<Plugin>(r#"{
+        "arguments": ["auth"],
+        "properties": {"priority": 1},
+        "children": {"listen": {"arguments": ["[::1]:80"]}}
+    }"#);
+    assert_eq!(kdl, json);
+    assert_eq!(json.version, None);
+    assert!(!json.enabled);
+    assert_eq!(json.deps, Vec::<String>::new());
+}
+
+#[test]
+fn same_children() {
+    let kdl = parse::<Config>(r#"
+        config {
+            plugin "a" priority=1 { listen "127.0.0.1:1"; }
+            plugin "b" priority=2 { listen "127.0.0.1:2"; }
+        }
+    "#);
+    let json = parse_json::<Config>(r#"{
+        "children": [
+            {"arguments": ["a"], "properties": {"priority": 1},
+             "children": {"listen": {"arguments": ["127.0.0.1:1"]}}},
+            {"arguments": ["b"], "properties": {"priority": 2},
+             "children": {"listen": {"arguments": ["127.0.0.1:2"]}}}
+        ]
+    }"#);
+    assert_eq!(kdl, json);
+    assert_eq!(parse_json::<Config>("{}"), Config { plugins: Vec::new() });
+}
+
+#[test]
+fn same_tuple() {
+    assert_eq!(parse::<Pair>("pair 1 2"), parse_json::<Pair>(r#"{
+        "arguments": [1, 2]
+    }"#));
+}
+
+#[test]
+fn same_array() {
+    assert_eq!(parse::<Octets>("octets 10 0 0 1"), parse_json::<Octets>(r#"{
+        "arguments": [10, 0, 0, 1]
+    }"#));
+    assert_eq!(parse_json_err::<Octets>(r#"{"arguments": [10, 0, 0]}"#),
+        "invalid length 3, expected 4 arguments");
+}
+
+#[test]
+fn same_case_insensitive() {
+    assert_eq!(parse::<Header>(r#"header Content-Type="text/plain""#),
+               parse_json::<Header>(r#"{
+                   "properties": {"CONTENT-TYPE": "text/plain"}
+               }"#));
+}
+
+#[test]
+fn same_split_flags() {
+    assert_eq!(parse::<Tags>(r#"tags "a  b" extra="c""#),
+               parse_json::<Tags>(r#"{
+                   "arguments": ["a  b"],
+                   "properties": {"extra": "c"}
+               }"#));
+    assert_eq!(parse_json::<Tags>(r#"{"arguments": [" x y "]}"#),
+               Tags { tags: vec!["x".into(), "y".into()], extra: None });
+}
+
+#[test]
+fn same_try() {
+    assert_eq!(parse::<Port>(r#"port "http" fallback="https""#),
+               parse_json::<Port>(r#"{
+                   "arguments": ["http"],
+                   "properties": {"fallback": "https"}
+               }"#));
+    assert_eq!(parse_json::<Port>(r#"{"arguments": ["8080"]}"#),
+               Port { number: 8080, fallback: None });
+    assert_eq!(parse_json_err::<Port>(r#"{"arguments": ["x"]}"#),
+        "none of the decoders succeeded: \
+         `parse_named`: unknown port name; \
+         `u16::from_str`: invalid digit found in string");
+}
+
+#[test]
+fn same_str_properties() {
+    assert_eq!(parse::<Route>(r#"
+            route via="10.0.0.1:53" backup=null gateway=3232235521 mask=null
+        "#),
+               parse_json::<Route>(r#"{
+                   "properties": {
+                       "via": "10.0.0.1:53",
+                       "backup": null,
+                       "gateway": 3232235521,
+                       "mask": null
+                   }
+               }"#));
+    assert_eq!(parse_json::<Route>(r#"{
+            "properties": {
+                "via": "10.0.0.1:53",
+                "backup": "10.0.0.2:53",
+                "gateway": 1,
+                "mask": 4294967040
+            }
+        }"#),
+        Route {
+            via: "10.0.0.1:53".parse().unwrap(),
+            backup: Some("10.0.0.2:53".parse().unwrap()),
+            gateway: "0.0.0.1".parse().unwrap(),
+            mask: Some("255.255.255.0".parse().unwrap()),
+        });
+}
+
+#[test]
+fn same_units() {
+    assert_eq!(parse::<Tone>(r#"tone "3 kHz" max="1 MHz""#),
+               parse_json::<Tone>(r#"{
+                   "arguments": ["3 kHz"],
+                   "properties": {"max": "1 MHz"}
+               }"#));
+    assert_eq!(parse_json_err::<Tone>(r#"{"arguments": ["3 Khz"]}"#),
+        "unknown unit `Khz`, expected one of `Hz`, `kHz`, `MHz`");
+}
+
+#[test]
+fn json_errors() {
+    assert_eq!(parse_json_err::<Pair>(r#"{"arguments": [1]}"#),
+        "additional argument is required");
+    assert_eq!(parse_json_err::<Pair>(r#"{"arguments": [1, 2, 3]}"#),
+        "unexpected argument");
+    assert_eq!(parse_json_err::<Pair>(r#"{"properties": {}}"#),
+        "unknown field `properties`, expected `arguments`");
+    assert_eq!(parse_json_err::<Listen>(r#"{"arguments": ["x"]}"#),
+        "invalid socket address syntax");
+    assert_eq!(parse_json_err::<Plugin>(r#"{
+        "arguments": ["x"],
+        "children": {"listen": {"arguments": ["127.0.0.1:1"]}}
+    }"#), "property `priority` is required");
+    assert_eq!(parse_json_err::<Plugin>(r#"{
+        "arguments": ["x"],
+        "properties": {"priority": 1}
+    }"#), "child node `listen` is required");
+    assert_eq!(parse_json_err::<Plugin>(r#"{
+        "arguments": ["x"],
+        "properties": {"priority": 1},
+        "children": {"listen": {"arguments": ["127.0.0.1:1"]}, "x": 1}
+    }"#), "unexpected node `x`");
+}
+
+#[test]
+fn error_diagnostics() {
+    let text = "listen \"127.0.0.1:80\"\nlisten 80";
+    let err = knuffel::parse::<Vec<Listen>>("<test>", text).unwrap_err();
+    let diagnostics = err.to_diagnostics();
+    assert_eq!(diagnostics, vec![knuffel::errors::Diagnostic {
+        message: "expected string scalar, found integer".into(),
+        span_start: Some(29),
+        span_end: Some(31),
+        line: Some(2),
+        column: Some(8),
+    }]);
+    assert_eq!(serde_json::to_value(&diagnostics).unwrap(), serde_json::json!([
+        {
+            "message": "expected string scalar, found integer",
+            "span_start": 29,
+            "span_end": 31,
+            "line": 2,
+            "column": 8,
+        },
+    ]));
+
+    let err = knuffel::parse::<Vec<Listen>>("<test>", "listen {").unwrap_err();
+    let diagnostics = err.to_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].span_start, Some(7));
+}
@@ -17,6 +17,9 @@ struct Opt(Option<Arg>);
 #[derive(Debug, Decode, PartialEq)]
 struct Extra(#[knuffel(argument)] Option<String>, u32);
 
+#[derive(Debug, Decode, PartialEq)]
+struct Point(#[knuffel(argument)] i32, #[knuffel(argument)] i32);
+
 #[derive(Debug, Decode, PartialEq)]
 enum Enum {
     Unit,
@@ -43,14 +46,14 @@ fn parse_err<T: Decode<Span>+fmt::Debug>(text: &str) -> String {
 fn parse_unit() {
     assert_eq!(parse::<Unit>(r#"node"#), Unit);
     assert_eq!(parse_err::<Unit>(r#"node something="world""#),
-        "unexpected property `something`");
+        "unexpected property `something` for node `node`");
 }
 
 #[test]
 fn parse_arg() {
     assert_eq!(parse::<Arg>(r#"node 123"#), Arg(123));
     assert_eq!(parse_err::<Arg>(r#"node something="world""#),
-        "additional argument is required");
+        "missing required argument for node `node`");
 }
 
 #[test]
@@ -61,12 +64,26 @@ fn parse_extra() {
         "unexpected argument");
 }
 
+#[test]
+fn parse_point() {
+    assert_eq!(parse::<Point>(r#"point 1 -2"#), Point(1, -2));
+    assert_eq!(parse_err::<Point>(r#"point 1"#),
+        "missing required argument for node `point`");
+
+    let err = knuffel::parse::<Vec<Point>>("<test>", "point 1 2 3").unwrap_err();
+    let errors = err.related().unwrap().collect::<Vec<_>>();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "unexpected argument");
+    let label = errors[0].labels().unwrap().next().unwrap();
+    assert_eq!((label.offset(), label.len()), (10, 1));
+}
+
 #[test]
 fn parse_opt() {
     assert_eq!(parse::<Opt>(r#"node 123"#), Opt(Some(Arg(123))));
     assert_eq!(parse::<Opt>(r#"node"#), Opt(None));
     assert_eq!(parse_err::<Opt>(r#"node something="world""#),
-        "additional argument is required");
+        "missing required argument for node `node`");
 }
 
 #[test]
@@ -1,6 +1,20 @@
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
 
-use knuffel::{span::Span};
+use camino::Utf8PathBuf;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+use chrono::{TimeZone, Utc};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use num_rational::Rational64;
+use uuid::Uuid;
+
+use miette::Diagnostic;
+
+use knuffel::ast::Decimal;
+use knuffel::decode::BaseDir;
+use knuffel::span::Span;
 use knuffel::traits::DecodeChildren;
 
 
@@ -18,10 +32,103 @@ struct Scalars {
     boolean: bool,
 }
 
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Utf8PathNode {
+    #[knuffel(argument)]
+    path: Utf8PathBuf,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Include {
+    #[knuffel(argument, path)]
+    file: PathBuf,
+    #[knuffel(property, expand_tilde)]
+    cache: Option<PathBuf>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Command {
+    #[knuffel(arguments)]
+    args: Vec<OsString>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Acl {
+    #[knuffel(child, unwrap(argument))]
+    any: IpNet,
+    #[knuffel(child, unwrap(argument))]
+    v4: Option<Ipv4Net>,
+    #[knuffel(child, unwrap(argument))]
+    v6: Option<Ipv6Net>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Listen {
+    #[knuffel(argument)]
+    addr: SocketAddr,
+    #[knuffel(property)]
+    bind: Option<IpAddr>,
+    #[knuffel(property)]
+    v4: Option<Ipv4Addr>,
+    #[knuffel(property)]
+    v6: Option<Ipv6Addr>,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Floats {
+    #[knuffel(argument)]
+    single: f32,
+    #[knuffel(argument)]
+    double: f64,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Measure {
+    #[knuffel(argument)]
+    float: f64,
+    #[knuffel(argument)]
+    exact: Decimal,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Ratio {
+    #[knuffel(argument)]
+    value: Rational64,
+}
+
+#[derive(knuffel_derive::Decode, Debug, PartialEq)]
+struct Event {
+    #[knuffel(child, unwrap(argument))]
+    at: Option<DateTime<FixedOffset>>,
+    #[knuffel(child, unwrap(argument))]
+    utc: Option<DateTime<Utc>>,
+    #[knuffel(child, unwrap(argument))]
+    local: Option<NaiveDateTime>,
+    #[knuffel(child, unwrap(argument))]
+    day: Option<NaiveDate>,
+    #[knuffel(child, unwrap(argument))]
+    time: Option<NaiveTime>,
+}
+
+#[derive(knuffel_derive::Decode, knuffel_derive::Encode, Debug, PartialEq)]
+struct Resource {
+    #[knuffel(argument)]
+    id: Uuid,
+    #[knuffel(property)]
+    parent: Option<Uuid>,
+}
+
 fn parse<T: DecodeChildren<Span>>(text: &str) -> T {
     knuffel::parse("<test>", text).unwrap()
 }
 
+fn parse_err<T: DecodeChildren<Span>+fmt::Debug>(text: &str) -> String {
+    let err = knuffel::parse::<T>("<test>", text).unwrap_err();
+    err.related().unwrap()
+        .map(|e| e.to_string()).collect::<Vec<_>>()
+        .join("\n")
+}
+
 
 #[test]
 fn parse_enum() {
@@ -41,3 +148,267 @@ fn parse_enum() {
             boolean: true,
         });
 }
+
+#[test]
+fn parse_utf8_path() {
+    assert_eq!(
+        parse::<Vec<Utf8PathNode>>(r#"path "/hello/world""#),
+        vec![Utf8PathNode { path: Utf8PathBuf::from("/hello/world") }]);
+    assert_eq!(parse_err::<Vec<Utf8PathNode>>(r#"path 1"#),
+        "expected string scalar, found integer");
+    assert_eq!(parse_err::<Vec<Utf8PathNode>>(r#"path (u8)"x""#),
+        "no type for Utf8PathBuf, found u8");
+}
+
+#[test]
+fn parse_os_string() {
+    let cmd = parse::<Vec<Command>>(r#"cmd "ls" "-la" "/tmp/ünïcödé""#);
+    assert_eq!(cmd[0].args, ["ls", "-la", "/tmp/ünïcödé"]);
+    assert_eq!(cmd[0].args[2].to_str(), Some("/tmp/ünïcödé"));
+    assert_eq!(parse_err::<Vec<Command>>(r#"cmd "ls" 1"#),
+        "expected string scalar, found integer");
+    assert_eq!(parse_err::<Vec<Command>>(r#"cmd (u8)"ls""#),
+        "no type for OsString, found u8");
+}
+
+#[test]
+fn parse_ipnet() {
+    assert_eq!(
+        parse::<Acl>(r#"
+            any "10.0.0.0/8"
+            v4 "192.168.0.0/16"
+            v6 "fd00::/8"
+        "#),
+        Acl {
+            any: "10.0.0.0/8".parse().unwrap(),
+            v4: Some("192.168.0.0/16".parse().unwrap()),
+            v6: Some("fd00::/8".parse().unwrap()),
+        });
+    assert_eq!(parse::<Acl>(r#"any "2001:db8::/32""#).any,
+               "2001:db8::/32".parse::<IpNet>().unwrap());
+    assert_eq!(parse_err::<Acl>(r#"any "10.0.0.0/33""#),
+        "invalid IP address syntax");
+    assert_eq!(parse_err::<Acl>(r#"any "10.0.0.0/8"; v6 "fd00::/129""#),
+        "invalid IP address syntax");
+    assert_eq!(parse_err::<Acl>(r#"any 10"#),
+        "expected string scalar, found integer");
+}
+
+#[test]
+fn parse_rational() {
+    assert_eq!(parse::<Vec<Ratio>>(r#"aspect "16/9""#),
+               vec![Ratio { value: Rational64::new(16, 9) }]);
+    assert_eq!(parse::<Vec<Ratio>>(r#"scale "4""#),
+               vec![Ratio { value: Rational64::from_integer(4) }]);
+    assert_eq!(parse::<Vec<Ratio>>(r#"half "-2/4""#),
+               vec![Ratio { value: Rational64::new(-1, 2) }]);
+    assert_eq!(parse_err::<Vec<Ratio>>(r#"aspect "1/0""#),
+        "zero value denominator");
+    assert_eq!(parse_err::<Vec<Ratio>>(r#"aspect "16:9""#),
+        "failed to parse integer");
+    assert_eq!(parse_err::<Vec<Ratio>>(r#"aspect 16"#),
+        "expected string scalar, found integer");
+}
+
+#[test]
+fn parse_decimal_exponent() {
+    let m = parse::<Vec<Measure>>(r#"m 1.5e-9 6.022e23"#).remove(0);
+    assert_eq!(m.float, 1.5e-9);
+    assert_eq!(m.exact.as_str(), "6.022e23");
+    let m = parse::<Vec<Measure>>(r#"m 00.5E-3 -0_001.2_5e+2"#).remove(0);
+    assert_eq!(m.float, 0.5e-3);
+    assert_eq!(m.exact.as_str(), "-0001.25e+2");
+    let m = parse::<Vec<Measure>>(r#"m 2 10"#).remove(0);
+    assert_eq!(m.float, 2.0);
+    assert_eq!(m.exact.as_str(), "10");
+    assert_eq!(parse_err::<Vec<Measure>>(r#"m 1.0 0x10"#),
+        "expected decimal scalar, found integer");
+    assert_eq!(parse_err::<Vec<Measure>>(r#"m 1.0 "1.0""#),
+        "expected decimal scalar, found string");
+}
+
+#[test]
+fn parse_float_from_integer() {
+    assert_eq!(parse::<Vec<Floats>>(r#"f 16777216 0x20000000000000"#),
+        vec![Floats { single: 16777216.0, double: 9007199254740992.0 }]);
+    assert_eq!(parse::<Vec<Floats>>(r#"f -0b101 0o17"#),
+        vec![Floats { single: -5.0, double: 15.0 }]);
+    assert_eq!(parse_err::<Vec<Floats>>(r#"f 16777217 0x20000000000001"#),
+        "integer 16777217 can't be represented exactly as f32\n\
+        integer 9007199254740993 can't be represented exactly as f64");
+}
+
+#[test]
+fn parse_net_addr() {
+    assert_eq!(
+        parse::<Vec<Listen>>(r#"
+            listen "[::1]:8080" bind="fe80::1" v4="10.0.0.1" v6="::"
+        "#),
+        vec![Listen {
+            addr: "[::1]:8080".parse().unwrap(),
+            bind: Some(IpAddr::V6("fe80::1".parse().unwrap())),
+            v4: Some(Ipv4Addr::new(10, 0, 0, 1)),
+            v6: Some(Ipv6Addr::UNSPECIFIED),
+        }]);
+    assert_eq!(parse::<Vec<Listen>>(r#"listen (host)"1.2.3.4:80""#)[0].addr,
+               "1.2.3.4:80".parse::<SocketAddr>().unwrap());
+    assert_eq!(parse_err::<Vec<Listen>>(r#"listen "999.0.0.1:80""#),
+        "invalid socket address syntax");
+    assert_eq!(parse_err::<Vec<Listen>>(r#"listen "::1:80""#),
+        "invalid socket address syntax");
+    assert_eq!(parse_err::<Vec<Listen>>(r#"listen "1.2.3.4:80" v4="::1""#),
+        "invalid IPv4 address syntax");
+    assert_eq!(parse_err::<Vec<Listen>>(r#"listen "1.2.3.4:80" bind="999.0.0.1""#),
+        "invalid IP address syntax");
+    assert_eq!(parse_err::<Vec<Listen>>(r#"listen (ip)"1.2.3.4:80""#),
+        "host or no type for SocketAddr, found ip");
+    assert_eq!(parse_err::<Vec<Listen>>(r#"listen 80"#),
+        "expected string scalar, found integer");
+
+    let err = knuffel::parse::<Vec<Listen>>("<test>",
+        r#"listen "1.2.3.4:80" v6="999.0.0.1""#).unwrap_err();
+    let spans = err.related().unwrap()
+        .flat_map(|e| e.labels().into_iter().flatten())
+        .map(|l| (l.offset(), l.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(spans, vec![(23, 11)]);
+}
+
+fn include(text: &str, base: Option<&str>) -> Include {
+    let mut items = knuffel::parse_with_context::<Vec<Include>, Span, _>(
+        "<test>", text,
+        |ctx| if let Some(base) = base { ctx.set(BaseDir(base.into())) },
+    ).unwrap();
+    items.remove(0)
+}
+
+fn home() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var).filter(|h| !h.is_empty()).map(PathBuf::from)
+}
+
+#[test]
+fn parse_path() {
+    assert_eq!(include(r#"include "a/b.kdl""#, None).file,
+               Path::new("a/b.kdl"));
+    assert_eq!(include(r#"include "a/b.kdl""#, Some("cfg")).file,
+               Path::new("cfg").join("a/b.kdl"));
+    assert_eq!(include(r#"include "~/b.kdl""#, Some("cfg")).file,
+               Path::new("cfg").join("~/b.kdl"));
+    assert_eq!(include(r#"include "x" cache=null"#, Some("cfg")).cache,
+               None);
+    assert_eq!(parse_err::<Vec<Include>>(r#"include 1"#),
+        "expected string scalar, found integer");
+    assert_eq!(parse_err::<Vec<Include>>(r#"include (u8)"x""#),
+        "no type for PathBuf, found u8");
+}
+
+#[test]
+#[cfg(unix)]
+fn parse_path_unix() {
+    assert_eq!(include(r#"include "/etc/b.kdl""#, Some("/cfg")).file,
+               Path::new("/etc/b.kdl"));
+    assert_eq!(include(r#"include "b.kdl""#, Some("/cfg")).file,
+               Path::new("/cfg/b.kdl"));
+    if let Some(home) = home() {
+        assert_eq!(include(r#"include "x" cache="~/.cache""#, Some("/cfg"))
+                   .cache, Some(home.join(".cache")));
+        assert_eq!(include(r#"include "x" cache="~""#, None).cache,
+                   Some(home));
+    }
+    // not a separator on unix, so no expansion
+    assert_eq!(include(r#"include "x" cache="~\\a""#, Some("/cfg")).cache,
+               Some(PathBuf::from("/cfg/~\\a")));
+    assert_eq!(include(r#"include "x" cache="~user/a""#, Some("/cfg")).cache,
+               Some(PathBuf::from("/cfg/~user/a")));
+}
+
+#[test]
+#[cfg(windows)]
+fn parse_path_windows() {
+    assert_eq!(include(r#"include "C:\\etc\\b.kdl""#, Some(r"D:\cfg")).file,
+               Path::new(r"C:\etc\b.kdl"));
+    assert_eq!(include(r#"include "sub\\b.kdl""#, Some(r"D:\cfg")).file,
+               Path::new(r"D:\cfg\sub\b.kdl"));
+    assert_eq!(include(r#"include "sub/b.kdl""#, Some(r"D:\cfg")).file,
+               Path::new(r"D:\cfg\sub/b.kdl"));
+    if let Some(home) = home() {
+        assert_eq!(include(r#"include "x" cache="~\\.cache""#, None).cache,
+                   Some(home.join(".cache")));
+        assert_eq!(include(r#"include "x" cache="~/.cache""#, None).cache,
+                   Some(home.join(".cache")));
+    }
+}
+
+#[test]
+fn parse_chrono() {
+    let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+    let time = NaiveTime::from_hms_opt(15, 4, 5).unwrap();
+    let local = date.and_time(time);
+    let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+    assert_eq!(parse::<Event>(r#"
+        at "2024-01-02T15:04:05+02:00"
+        utc "2024-01-02T17:04:05+02:00"
+        local (date-time)"2024-01-02T15:04:05"
+        day (date)"2024-01-02"
+        time (time)"15:04:05"
+    "#), Event {
+        at: Some(offset.from_local_datetime(&local).unwrap()),
+        utc: Some(Utc.from_utc_datetime(&local)),
+        local: Some(local),
+        day: Some(date),
+        time: Some(time),
+    });
+    assert_eq!(parse::<Event>(r#"at (date-time)"2024-01-02T15:04:05Z""#)
+               .at.unwrap().offset().local_minus_utc(), 0);
+    assert_eq!(parse_err::<Event>(r#"day "2024-02-30""#),
+        "input is out of range");
+    assert_eq!(parse_err::<Event>(r#"day "02/01/2024""#),
+        "input contains invalid characters");
+    assert_eq!(parse_err::<Event>(r#"at "2024-01-02 15:04""#),
+        "premature end of input");
+    assert_eq!(parse_err::<Event>(r#"day (time)"2024-01-02""#),
+        "date or no type for NaiveDate, found time");
+    assert_eq!(parse_err::<Event>(r#"time 15"#),
+        "expected string scalar, found integer");
+
+    let err = knuffel::parse::<Event>("<test>", r#"day "2024-13-01""#)
+        .unwrap_err();
+    let spans = err.related().unwrap()
+        .flat_map(|e| e.labels().into_iter().flatten())
+        .map(|l| (l.offset(), l.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(spans, vec![(4, 12)]);
+}
+
+#[test]
+fn parse_uuid() {
+    let id = Uuid::from_u128(0x67e55044_10b1_426f_9247_bb680e5fe0c8);
+    let text = r#"resource "67e55044-10b1-426f-9247-bb680e5fe0c8";"#;
+    let parsed = parse::<Vec<Resource>>(text);
+    assert_eq!(parsed, vec![Resource { id, parent: None }]);
+    assert_eq!(knuffel::to_string(&parsed), text);
+    assert_eq!(parse::<Vec<Resource>>(r#"
+        resource (uuid)"67e5504410b1426f9247bb680e5fe0c8" parent=null
+    "#), vec![Resource { id, parent: None }]);
+    assert_eq!(parse::<Vec<Resource>>(r#"
+        resource "00000000-0000-0000-0000-000000000000" \
+            parent=(uuid)"67e55044-10b1-426f-9247-bb680e5fe0c8"
+    "#)[0].parent, Some(id));
+    assert_eq!(parse_err::<Vec<Resource>>(
+                   r#"resource (id)"67e5504410b1426f9247bb680e5fe0c8""#),
+        "uuid or no type for Uuid, found id");
+    assert_eq!(parse_err::<Vec<Resource>>(r#"resource 1"#),
+        "expected string scalar, found integer");
+
+    let err = knuffel::parse::<Vec<Resource>>("<test>",
+        r#"resource "67e55044-10b1""#).unwrap_err();
+    let errors = err.related().unwrap()
+        .map(|e| (e.to_string(), e.labels().unwrap()
+            .map(|l| (l.offset(), l.len()))
+            .collect::<Vec<_>>()))
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec![
+        ("invalid group count: expected 5, found 2".into(), vec![(9, 15)]),
+    ]);
+}
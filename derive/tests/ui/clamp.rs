@@ -0,0 +1,25 @@
+#[derive(knuffel_derive::Decode)]
+struct OpenRange {
+    #[knuffel(argument, clamp=0..100)]
+    pct: u8,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct OnArguments {
+    #[knuffel(arguments, clamp=0..=100)]
+    pct: Vec<u8>,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct Reversed {
+    #[knuffel(argument, clamp=100..=-1)]
+    pct: i8,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct NotANumber {
+    #[knuffel(property, clamp=0.0..=f64::NAN)]
+    ratio: f64,
+}
+
+fn main() {}
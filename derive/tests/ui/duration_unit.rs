@@ -0,0 +1,7 @@
+#[derive(knuffel_derive::Decode)]
+struct Timeout {
+    #[knuffel(argument, duration(unit="sec"))]
+    value: std::time::Duration,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[derive(knuffel_derive::Encode)]
+struct Data {
+    #[knuffel(argument, bytes)]
+    data: Vec<u8>,
+}
+
+fn main() {}
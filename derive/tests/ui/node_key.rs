@@ -0,0 +1,15 @@
+#[derive(knuffel_derive::Decode)]
+struct OptKey {
+    #[knuffel(node_key)]
+    name: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct TwoKeys {
+    #[knuffel(node_key)]
+    name: String,
+    #[knuffel(node_key)]
+    other: String,
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use std::collections::HashMap;
+
+#[derive(knuffel_derive::Decode)]
+struct OptArguments {
+    #[knuffel(arguments)]
+    items: Option<Vec<String>>,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct OptProperties {
+    #[knuffel(properties)]
+    items: Option<HashMap<String, String>>,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct OptChildren {
+    #[knuffel(children)]
+    items: Option<Vec<OptArguments>>,
+}
+
+fn main() {}
@@ -0,0 +1,13 @@
+#[derive(knuffel_derive::Decode)]
+struct OptionalDefault {
+    #[knuffel(argument, default)]
+    name: Option<String>,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct OptionalDefaultValue {
+    #[knuffel(argument, default=Some("unnamed".into()))]
+    name: Option<String>,
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+#[derive(knuffel_derive::DecodeScalar)]
+#[knuffel(kind="int")]
+enum Version {
+    #[knuffel(value=1)]
+    V1,
+    V2,
+}
+
+#[derive(knuffel_derive::DecodeScalar)]
+#[knuffel(kind="int")]
+enum Protocol {
+    #[knuffel(value=1, rename="http")]
+    Http,
+}
+
+#[derive(knuffel_derive::DecodeScalar)]
+#[knuffel(kind="int", case_insensitive)]
+enum Level {
+    #[knuffel(value=0)]
+    Debug,
+}
+
+fn main() {}
@@ -0,0 +1,23 @@
+#[derive(knuffel_derive::DecodeScalar)]
+enum Encoding {
+    #[knuffel(rename="ascii")]
+    Utf8,
+    Ascii,
+}
+
+#[derive(knuffel_derive::DecodeScalar)]
+enum Format {
+    Json,
+    #[knuffel(rename="json")]
+    JsonLines,
+}
+
+#[derive(knuffel_derive::DecodeScalar)]
+enum Color {
+    #[knuffel(alias="grey")]
+    Gray,
+    #[knuffel(alias="grey")]
+    Silver,
+}
+
+fn main() {}
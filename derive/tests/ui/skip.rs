@@ -0,0 +1,13 @@
+#[derive(knuffel_derive::Decode)]
+struct SkipProperty {
+    #[knuffel(skip, property)]
+    cache: Vec<u8>,
+}
+
+#[derive(knuffel_derive::Decode)]
+struct SkipDefault {
+    #[knuffel(default, skip)]
+    cache: Vec<u8>,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+#[derive(knuffel_derive::Decode)]
+#[knuffel(argument)]
+struct Marker;
+
+#[derive(knuffel_derive::Decode)]
+#[knuffel(expose_name, child, default)]
+#[derive(Debug)]
+struct Flag;
+
+fn main() {}
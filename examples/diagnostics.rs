@@ -0,0 +1,36 @@
+//! Shows how decode errors look when printed by miette
+//!
+//! Run with `cargo run --example diagnostics`.
+
+#[derive(knuffel::Decode, Debug)]
+#[allow(dead_code)]
+struct Listen {
+    #[knuffel(argument)]
+    address: String,
+    #[knuffel(property)]
+    port: u16,
+}
+
+#[derive(knuffel::Decode, Debug)]
+#[allow(dead_code)]
+struct Server {
+    #[knuffel(child)]
+    listen: Listen,
+    #[knuffel(child, unwrap(argument))]
+    workers: u32,
+}
+
+const TEXT: &str = r#"
+listen "0.0.0.0" port=65536
+workers 4
+workers 8
+"#;
+
+fn main() {
+    // Every error is a separate diagnostic with its own labels, e.g. the
+    // duplicate node points both at the second node and at the first one
+    match knuffel::parse::<Server>("server.kdl", TEXT) {
+        Ok(server) => println!("{:#?}", server),
+        Err(e) => println!("{:?}", miette::Report::new(e)),
+    }
+}
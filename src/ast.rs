@@ -19,6 +19,9 @@ use std::str::FromStr;
 
 use crate::span::Spanned;
 
+pub use crate::ast_ref::{DocumentRef, NodeRef, ValueRef, LiteralRef};
+pub use crate::ast_ref::{SpannedNodeRef, SpannedStr};
+
 /// A shortcut for nodes children that includes span of enclosing braces `{..}`
 pub type SpannedChildren<S> = Spanned<Vec<SpannedNode<S>>, S>;
 /// KDL names with span information are represented using this type
@@ -45,6 +48,14 @@ pub struct Node<S> {
     /// Node's children. This field is not none if there are braces `{..}`
     #[cfg_attr(feature="minicbor", n(4))]
     pub children: Option<SpannedChildren<S>>,
+    /// Properties that are repeated in the node, with spans of the repeated
+    /// occurrences (only the rightmost value is kept in `properties`)
+    #[cfg_attr(feature="minicbor", n(5))]
+    pub duplicate_properties: Vec<SpannedName<S>>,
+    /// Line comments directly above the node, each on its own line, without
+    /// the `//` marker
+    #[cfg_attr(feature="minicbor", n(6))]
+    pub leading_comments: Vec<Box<str>>,
 }
 
 /// KDL document root
@@ -186,6 +197,16 @@ impl<S> Node<S> {
     }
 }
 
+impl Decimal {
+    /// Returns the decimal as written in the source, without underscores
+    ///
+    /// This allows exact decimal types to be parsed without going through
+    /// `f64`, e.g. `1.5e-9` is returned as is.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
 impl BuiltinType {
     /// Returns string representation of the builtin type as defined by KDL
     /// specification
@@ -288,6 +309,43 @@ impl fmt::Display for TypeName {
     }
 }
 
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (sign, digits) = match self.1.strip_prefix(&['-', '+'][..]) {
+            Some(digits) => (&self.1[..1], digits),
+            None => ("", &self.1[..]),
+        };
+        let prefix = match self.0 {
+            Radix::Bin => "0b",
+            Radix::Oct => "0o",
+            Radix::Dec => "",
+            Radix::Hex => "0x",
+        };
+        write!(f, "{}{}{}", sign, prefix, digits)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Formats the literal as KDL source, i.e. strings are quoted and escaped
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Literal::Null => f.write_str("null"),
+            Literal::Bool(val) => val.fmt(f),
+            Literal::Int(val) => val.fmt(f),
+            Literal::Decimal(val) => val.fmt(f),
+            Literal::String(val) => {
+                write!(f, "\"{}\"", crate::string::escape(val))
+            }
+        }
+    }
+}
+
 impl Into<TypeName> for BuiltinType {
     fn into(self) -> TypeName {
         self.as_type()
@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use crate::ast::{Literal, Integer, Decimal};
+use crate::span::Spanned;
+
+/// A borrowed node with span of the whole node (including children)
+pub type SpannedNodeRef<'a, S> = Spanned<NodeRef<'a, S>, S>;
+/// A borrowed name (or string) with span information
+pub type SpannedStr<'a, S> = Spanned<Cow<'a, str>, S>;
+
+/// KDL document root that borrows strings from the source text
+///
+/// Names, type names and string values are [`Cow::Borrowed`] slices of the
+/// source text whenever the value is written in the source as is, i.e. for
+/// identifiers, raw strings and strings without escape sequences. Strings
+/// containing escapes are [`Cow::Owned`] with escapes already decoded.
+///
+/// Use [`parse_ast_ref`](crate::parse_ast_ref) to create one.
+#[derive(Debug, Clone)]
+pub struct DocumentRef<'a, S> {
+    /// Nodes of the document
+    pub nodes: Vec<SpannedNodeRef<'a, S>>,
+}
+
+/// Single node of the borrowed KDL document
+#[derive(Debug, Clone)]
+pub struct NodeRef<'a, S> {
+    /// A type name if specified in parenthesis
+    pub type_name: Option<SpannedStr<'a, S>>,
+    /// A node name
+    pub node_name: SpannedStr<'a, S>,
+    /// Positional arguments
+    pub arguments: Vec<ValueRef<'a, S>>,
+    /// Named properties
+    pub properties: BTreeMap<SpannedStr<'a, S>, ValueRef<'a, S>>,
+    /// Node's children. This field is not none if there are braces `{..}`
+    pub children: Option<Spanned<Vec<SpannedNodeRef<'a, S>>, S>>,
+    /// Properties that are repeated in the node, with spans of the repeated
+    /// occurrences (only the rightmost value is kept in `properties`)
+    pub duplicate_properties: Vec<SpannedStr<'a, S>>,
+    /// Line comments directly above the node, each on its own line, without
+    /// the `//` marker
+    pub leading_comments: Vec<&'a str>,
+}
+
+/// Possibly typed KDL scalar value that borrows from the source text
+#[derive(Debug, Clone)]
+pub struct ValueRef<'a, S> {
+    /// A type name if specified in parenthesis
+    pub type_name: Option<SpannedStr<'a, S>>,
+    /// The actual value literal
+    pub literal: Spanned<LiteralRef<'a>, S>,
+}
+
+/// Scalar KDL value that borrows strings from the source text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LiteralRef<'a> {
+    /// Null value
+    Null,
+    /// Boolean value
+    Bool(bool),
+    /// Integer value
+    Int(Integer),
+    /// Decimal (or floating point) value
+    Decimal(Decimal),
+    /// String value
+    String(Cow<'a, str>),
+}
+
+impl From<Literal> for LiteralRef<'_> {
+    fn from(literal: Literal) -> Self {
+        match literal {
+            Literal::Null => LiteralRef::Null,
+            Literal::Bool(v) => LiteralRef::Bool(v),
+            Literal::Int(v) => LiteralRef::Int(v),
+            Literal::Decimal(v) => LiteralRef::Decimal(v),
+            Literal::String(s) => LiteralRef::String(Cow::Owned(s.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+    use std::ops::Range;
+
+    use crate::span::Span;
+    use super::{DocumentRef, NodeRef, SpannedStr, LiteralRef};
+
+    fn parse(text: &str) -> DocumentRef<'_, Span> {
+        crate::parse_ast_ref("<test>", text).unwrap()
+    }
+
+    #[allow(clippy::ptr_arg)]  // variant of the `Cow` is checked
+    fn assert_borrowed(value: &Cow<'_, str>, text: &Range<usize>) {
+        match value {
+            Cow::Borrowed(s) => {
+                assert!(text.contains(&(s.as_ptr() as usize)),
+                        "{:?} is not a slice of the source", s);
+            }
+            Cow::Owned(s) => panic!("{:?} is owned", s),
+        }
+    }
+
+    fn check_node(node: &NodeRef<'_, Span>, text: &Range<usize>) {
+        let check_str = |s: &SpannedStr<'_, Span>| assert_borrowed(s, text);
+        node.type_name.iter().for_each(check_str);
+        check_str(&node.node_name);
+        for value in node.arguments.iter().chain(node.properties.values()) {
+            value.type_name.iter().for_each(check_str);
+            if let LiteralRef::String(s) = &*value.literal {
+                assert_borrowed(s, text);
+            }
+        }
+        node.properties.keys().for_each(check_str);
+        for child in node.children.iter().flat_map(|c| c.iter()) {
+            check_node(child, text);
+        }
+    }
+
+    #[test]
+    fn unescaped_borrowed() {
+        let text = r##"
+            (my-type)node "arg" r#"raw "quoted" arg"# (tp)"x" 1 null {
+                "quoted name" key="value" other=(custom)r"raw"
+                child; "" empty=""
+            }
+        "##;
+        let range = text.as_ptr() as usize..text.as_ptr() as usize + text.len();
+        let doc = parse(text);
+        assert_eq!(doc.nodes.len(), 1);
+        let node = &doc.nodes[0];
+        check_node(node, &range);
+        assert_eq!(node.type_name.as_deref().map(|t| &t[..]),
+                   Some("my-type"));
+        assert_eq!(&node.node_name[..], "node");
+        assert_eq!(*node.arguments[1].literal,
+                   LiteralRef::String(r#"raw "quoted" arg"#.into()));
+        let children = node.children.as_ref().unwrap();
+        assert_eq!(&children[0].node_name[..], "quoted name");
+        assert_eq!(children.len(), 3);
+    }
+
+    #[test]
+    fn escaped_owned() {
+        let text = r#"node "a\nb" "plain" "tab\there"=1"#;
+        let doc = parse(text);
+        let node = &doc.nodes[0];
+        assert!(matches!(&*node.arguments[0].literal,
+                         LiteralRef::String(Cow::Owned(s)) if s == "a\nb"));
+        assert!(matches!(&*node.arguments[1].literal,
+                         LiteralRef::String(Cow::Borrowed("plain"))));
+        let (name, _) = node.properties.iter().next().unwrap();
+        assert!(matches!(&**name, Cow::Owned(s) if s == "tab\there"));
+    }
+
+    #[test]
+    fn same_errors() {
+        let errors = |text| {
+            let owned = crate::parse_ast::<Span>("<test>", text)
+                .map(|_| ()).unwrap_err();
+            let borrowed = crate::parse_ast_ref::<Span>("<test>", text)
+                .map(|_| ()).unwrap_err();
+            (format!("{:?}", owned.errors), format!("{:?}", borrowed.errors))
+        };
+        for text in [
+            r#"node "unclosed"#, r##"node r#"unclosed"##, "node { child",
+            "node true=1", "node 1=2", "node arg", "(type)", r#"node "\x""#,
+        ] {
+            let (owned, borrowed) = errors(text);
+            assert_eq!(owned, borrowed, "for {:?}", text);
+        }
+    }
+
+    #[test]
+    fn leading_comments() {
+        let text = "// first\n//second\nnode\n\n// detached\n\nother";
+        let range = text.as_ptr() as usize..text.as_ptr() as usize + text.len();
+        let doc = parse(text);
+        let comments = &doc.nodes[0].leading_comments;
+        assert_eq!(comments, &[" first", "second"]);
+        for comment in comments {
+            assert!(range.contains(&(comment.as_ptr() as usize)));
+        }
+        assert!(doc.nodes[1].leading_comments.is_empty());
+    }
+
+    #[test]
+    fn builtin_type() {
+        let doc = parse(r#"node (u8)1"#);
+        assert_eq!(doc.nodes[0].arguments[0].type_name.as_deref()
+                   .map(|t| &t[..]), Some("u8"));
+    }
+}
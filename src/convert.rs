@@ -1,3 +1,7 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::num::IntErrorKind;
 use std::str::FromStr;
 use std::path::PathBuf;
 use std::default::Default;
@@ -24,7 +28,7 @@ macro_rules! impl_number {
         // is in parentheses followed by a comma as described above.
         $(
             impl_number!(@try_from, $type_name, $number_type, $marker);
-            impl_number!(@decode_scalar, $type_name, $number_type, $marker, $default);
+            impl_number!(@decode_scalar_for, $type_name, $number_type, $marker, $default);
         )*
         // The asterisk will repeat the contents inside parentheses zero-or-more
         // times, as many times as the left-hand side of the pattern branch has matched
@@ -67,11 +71,66 @@ macro_rules! impl_number {
                 <$number_type>::from_str(&val.0)
             }
         }
+        // Integer literals are valid values for floating point numbers too,
+        // as long as the number represents them exactly.
+        impl TryFrom<&Integer> for $number_type {
+            type Error = Box<dyn std::error::Error + Send + Sync>;
+            fn try_from(val: &Integer) -> Result<$number_type, Self::Error>
+            {
+                let radix = match val.0 {
+                    Radix::Bin => 2,
+                    Radix::Oct => 8,
+                    Radix::Dec => 10,
+                    Radix::Hex => 16,
+                };
+                let int = i128::from_str_radix(&val.1, radix)?;
+                let float = int as $number_type;
+                // the cast back saturates, so `i128::MAX` is checked apart
+                if float >= i128::MAX as $number_type || float as i128 != int {
+                    return Err(format!(
+                        "integer {} can't be represented exactly as {}",
+                        int, stringify!($number_type)).into());
+                }
+                Ok(float)
+            }
+        }
+    };
+    // Builds the error for a literal of the `<type_name>` that failed to
+    // convert. Integers that don't fit are reported with the range of the
+    // `<number_type>`.
+    (@error, Int, $number_type: ident, $val: ident, $value: ident, $e: ident) => {
+        match $e.kind() {
+            IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                DecodeError::conversion($val, out_of_range($value,
+                    stringify!($number_type),
+                    <$number_type>::MIN, <$number_type>::MAX))
+            }
+            // unsigned types fail on the minus sign
+            IntErrorKind::InvalidDigit
+                if <$number_type>::MIN == 0 && $value.1.starts_with('-')
+            => {
+                DecodeError::conversion($val, out_of_range($value,
+                    stringify!($number_type),
+                    <$number_type>::MIN, <$number_type>::MAX))
+            }
+            _ => DecodeError::conversion($val, $e),
+        }
+    };
+    (@error, Decimal, $number_type: ident, $val: ident, $value: ident, $e: ident) => {
+        DecodeError::conversion($val, $e)
+    };
+    // Dispatches to `@decode_scalar` with the list of additional literal
+    // kinds accepted for the `<type_name>`.
+    (@decode_scalar_for, Int, $number_type: ident, $marker: ident, $default: expr) => {
+        impl_number!(@decode_scalar, Int, $number_type, $marker, $default, []);
+    };
+    (@decode_scalar_for, Decimal, $number_type: ident, $marker: ident, $default: expr) => {
+        impl_number!(@decode_scalar, Decimal, $number_type, $marker, $default, [Int]);
     };
     // This is a "private" pattern that matches
     // one pattern of `(@scalar_decode, <type_name>, <number_type>, <marker>, <default>)`
     // Handles the implementation of `DecodeScalar` for the `<number_type>`.
-    (@decode_scalar, $type_name: ident, $number_type: ident, $marker: ident, $default: expr) => {
+    (@decode_scalar, $type_name: ident, $number_type: ident, $marker: ident, $default: expr, [$($extra: ident),*]) => {
         impl<S: ErrorSpan> DecodeScalar<S> for $number_type {
             fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
                 -> Result<$number_type, DecodeError<S>>
@@ -81,14 +140,27 @@ macro_rules! impl_number {
                         match value.try_into() {
                             Ok(val) => Ok(val),
                             Err(e) => {
-                                ctx.emit_error(DecodeError::conversion(val, e));
+                                ctx.emit_error(impl_number!(@error,
+                                    $type_name, $number_type, val, value, e));
                                 Ok($default)
                             }
                         }
                     }
+                    $(
+                        Literal::$extra(ref value) => {
+                            match value.try_into() {
+                                Ok(val) => Ok(val),
+                                Err(e) => {
+                                    ctx.emit_error(
+                                        DecodeError::conversion(val, e));
+                                    Ok($default)
+                                }
+                            }
+                        }
+                    )*
                     _ => {
                         ctx.emit_error(DecodeError::scalar_kind(
-                                Kind::String, val));
+                                Kind::$type_name, val));
                         Ok($default)
                     }
                 }
@@ -103,7 +175,7 @@ macro_rules! impl_number {
                             found: Some(typ.value.clone()),
                             expected: ExpectedType::optional(
                                 BuiltinType::$marker),
-                            rust_type: stringify!($typ),
+                            rust_type: stringify!($number_type),
                         });
                     }
                 }
@@ -112,6 +184,14 @@ macro_rules! impl_number {
     };
 }
 
+fn out_of_range(value: &Integer, type_name: &str,
+                min: impl fmt::Display, max: impl fmt::Display)
+    -> String
+{
+    format!("value {} out of range for {} ({}..={})",
+            value, type_name, min, max)
+}
+
 impl_number!(
     (Int, i8, I8, 0),
     (Int, u8, U8, 0),
@@ -180,6 +260,273 @@ impl<S: ErrorSpan> DecodeScalar<S> for PathBuf {
     }
 }
 
+impl<S: ErrorSpan> DecodeScalar<S> for OsString {
+    fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
+        -> Result<OsString, DecodeError<S>>
+    {
+        match &**val {
+            Literal::String(ref s) => Ok(String::from(s.clone()).into()),
+            _ => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::String, val));
+                Ok(Default::default())
+            }
+        }
+    }
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                  ctx: &mut Context<S>)
+    {
+        if let Some(typ) = type_name {
+            ctx.emit_error(DecodeError::TypeName {
+                span: typ.span().clone(),
+                found: Some(typ.value.clone()),
+                expected: ExpectedType::no_type(),
+                rust_type: "OsString",
+            });
+        }
+    }
+}
+
+#[cfg(feature="duration")]
+impl<S: ErrorSpan> DecodeScalar<S> for std::time::Duration {
+    fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
+        -> Result<std::time::Duration, DecodeError<S>>
+    {
+        crate::decode::duration_literal(val, ctx, "s")
+    }
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                  ctx: &mut Context<S>)
+    {
+        if let Some(typ) = type_name {
+            ctx.emit_error(DecodeError::TypeName {
+                span: typ.span().clone(),
+                found: Some(typ.value.clone()),
+                expected: ExpectedType::no_type(),
+                rust_type: "Duration",
+            });
+        }
+    }
+}
+
+#[cfg(feature="camino")]
+impl<S: ErrorSpan> DecodeScalar<S> for camino::Utf8PathBuf {
+    fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
+        -> Result<camino::Utf8PathBuf, DecodeError<S>>
+    {
+        match &**val {
+            Literal::String(ref s) => Ok(String::from(s.clone()).into()),
+            _ => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::String, val));
+                Ok(Default::default())
+            }
+        }
+    }
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                  ctx: &mut Context<S>)
+    {
+        if let Some(typ) = type_name {
+            ctx.emit_error(DecodeError::TypeName {
+                span: typ.span().clone(),
+                found: Some(typ.value.clone()),
+                expected: ExpectedType::no_type(),
+                rust_type: "Utf8PathBuf",
+            });
+        }
+    }
+}
+
+// Implements `DecodeScalar` for types parsed from string literals using the
+// `FromStr` trait.
+macro_rules! impl_from_str {
+    ($(#[$meta: meta] $typ: ty, $name: expr;)+) => {
+        $(
+            #[$meta]
+            impl<S: ErrorSpan> DecodeScalar<S> for $typ {
+                fn raw_decode(val: &Spanned<Literal, S>, _: &mut Context<S>)
+                    -> Result<$typ, DecodeError<S>>
+                {
+                    match &**val {
+                        Literal::String(ref s) => {
+                            s.parse()
+                                .map_err(|e| DecodeError::conversion(val, e))
+                        }
+                        _ => Err(DecodeError::scalar_kind(Kind::String, val)),
+                    }
+                }
+                fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                              ctx: &mut Context<S>)
+                {
+                    if let Some(typ) = type_name {
+                        ctx.emit_error(DecodeError::TypeName {
+                            span: typ.span().clone(),
+                            found: Some(typ.value.clone()),
+                            expected: ExpectedType::no_type(),
+                            rust_type: $name,
+                        });
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_from_str!(
+    #[doc="Parsed with `FromStr`, like `\"127.0.0.1\"` or `\"::1\"`"]
+    IpAddr, "IpAddr";
+    #[doc="Parsed with `FromStr`, like `\"127.0.0.1\"`"]
+    Ipv4Addr, "Ipv4Addr";
+    #[doc="Parsed with `FromStr`, like `\"::1\"`"]
+    Ipv6Addr, "Ipv6Addr";
+    #[cfg(feature="ipnet")] ipnet::IpNet, "IpNet";
+    #[cfg(feature="ipnet")] ipnet::Ipv4Net, "Ipv4Net";
+    #[cfg(feature="ipnet")] ipnet::Ipv6Net, "Ipv6Net";
+    #[cfg(feature="num_rational")] num_rational::Rational64, "Rational64";
+);
+
+// Checks the type name of the values which may be annotated with the type
+// name reserved by the KDL spec, or not annotated at all.
+fn optional_annotation<S: ErrorSpan>(type_name: &Option<Spanned<TypeName, S>>,
+                                     ctx: &mut Context<S>,
+                                     expected: &str, rust_type: &'static str)
+{
+    if let Some(typ) = type_name {
+        if typ.as_str() != expected {
+            ctx.emit_error(DecodeError::TypeName {
+                span: typ.span().clone(),
+                found: Some(typ.value.clone()),
+                expected: ExpectedType::optional(
+                    expected.parse::<TypeName>().unwrap()),
+                rust_type,
+            });
+        }
+    }
+}
+
+// Implements `DecodeScalar` for `chrono` types parsed from string literals,
+// which may be annotated with the type name reserved by the KDL spec.
+#[cfg(feature="chrono")]
+macro_rules! impl_chrono {
+    ($(#[$meta: meta] $typ: ty, $name: expr, $type_name: expr,
+       $parse: expr;)+) => {
+        $(
+            #[$meta]
+            impl<S: ErrorSpan> DecodeScalar<S> for $typ {
+                fn raw_decode(val: &Spanned<Literal, S>, _: &mut Context<S>)
+                    -> Result<$typ, DecodeError<S>>
+                {
+                    match &**val {
+                        Literal::String(ref s) => {
+                            $parse(s)
+                                .map_err(|e| DecodeError::conversion(val, e))
+                        }
+                        _ => Err(DecodeError::scalar_kind(Kind::String, val)),
+                    }
+                }
+                fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                              ctx: &mut Context<S>)
+                {
+                    optional_annotation(type_name, ctx, $type_name, $name);
+                }
+            }
+        )+
+    };
+}
+
+#[cfg(feature="chrono")]
+impl_chrono!(
+    #[doc="RFC 3339 timestamp, like `\"2024-01-02T15:04:05+02:00\"`, \
+           may be annotated as `(date-time)`"]
+    chrono::DateTime<chrono::FixedOffset>, "DateTime<FixedOffset>",
+    "date-time", |s: &str| chrono::DateTime::parse_from_rfc3339(s);
+    #[doc="RFC 3339 timestamp, like `\"2024-01-02T15:04:05Z\"`, \
+           converted to UTC, may be annotated as `(date-time)`"]
+    chrono::DateTime<chrono::Utc>, "DateTime<Utc>", "date-time",
+    |s: &str| chrono::DateTime::parse_from_rfc3339(s)
+        .map(|d| d.with_timezone(&chrono::Utc));
+    #[doc="ISO 8601 date-time without offset, like \
+           `\"2024-01-02T15:04:05\"`, may be annotated as `(date-time)`"]
+    chrono::NaiveDateTime, "NaiveDateTime", "date-time",
+    |s: &str| s.parse::<chrono::NaiveDateTime>();
+    #[doc="ISO 8601 date, like `\"2024-01-02\"`, may be annotated as \
+           `(date)`"]
+    chrono::NaiveDate, "NaiveDate", "date",
+    |s: &str| s.parse::<chrono::NaiveDate>();
+    #[doc="ISO 8601 time, like `\"15:04:05\"`, may be annotated as `(time)`"]
+    chrono::NaiveTime, "NaiveTime", "time",
+    |s: &str| s.parse::<chrono::NaiveTime>();
+);
+
+/// Parsed with `FromStr`, like `"1.2.3.4:80"` or `"[::1]:80"`
+///
+/// The value may be annotated as `(host)"1.2.3.4:80"`.
+impl<S: ErrorSpan> DecodeScalar<S> for SocketAddr {
+    fn raw_decode(val: &Spanned<Literal, S>, _: &mut Context<S>)
+        -> Result<SocketAddr, DecodeError<S>>
+    {
+        match &**val {
+            Literal::String(ref s) => {
+                s.parse().map_err(|e| DecodeError::conversion(val, e))
+            }
+            _ => Err(DecodeError::scalar_kind(Kind::String, val)),
+        }
+    }
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                  ctx: &mut Context<S>)
+    {
+        optional_annotation(type_name, ctx, "host", "SocketAddr");
+    }
+}
+
+/// Parsed in either hyphenated or simple form, like
+/// `"67e55044-10b1-426f-9247-bb680e5fe0c8"`
+///
+/// The value may be annotated as `(uuid)"..."`.
+#[cfg(feature="uuid")]
+impl<S: ErrorSpan> DecodeScalar<S> for uuid::Uuid {
+    fn raw_decode(val: &Spanned<Literal, S>, _: &mut Context<S>)
+        -> Result<uuid::Uuid, DecodeError<S>>
+    {
+        match &**val {
+            Literal::String(ref s) => {
+                uuid::Uuid::parse_str(s)
+                    .map_err(|e| DecodeError::conversion(val, e))
+            }
+            _ => Err(DecodeError::scalar_kind(Kind::String, val)),
+        }
+    }
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                  ctx: &mut Context<S>)
+    {
+        optional_annotation(type_name, ctx, "uuid", "Uuid");
+    }
+}
+
+impl<S: ErrorSpan> DecodeScalar<S> for Decimal {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                  ctx: &mut Context<S>)
+    {
+        if let Some(typ) = type_name {
+            ctx.emit_error(DecodeError::TypeName {
+                span: typ.span().clone(),
+                found: Some(typ.value.clone()),
+                expected: ExpectedType::no_type(),
+                rust_type: "Decimal",
+            });
+        }
+    }
+    fn raw_decode(value: &Spanned<Literal, S>, _ctx: &mut Context<S>)
+        -> Result<Self, DecodeError<S>>
+    {
+        match &**value {
+            Literal::Decimal(d) => Ok(d.clone()),
+            // decimal integers are valid decimals as written
+            Literal::Int(i) if matches!(i.0, Radix::Dec) => {
+                Ok(Decimal(i.1.clone()))
+            }
+            _ => Err(DecodeError::scalar_kind(Kind::Decimal, value)),
+        }
+    }
+}
+
 impl<S: ErrorSpan> DecodeScalar<S> for bool {
     fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
         -> Result<bool, DecodeError<S>>
@@ -205,3 +552,29 @@ impl<S: ErrorSpan> DecodeScalar<S> for bool {
         }
     }
 }
+
+impl<S: ErrorSpan> DecodeScalar<S> for () {
+    fn raw_decode(val: &Spanned<Literal, S>, ctx: &mut Context<S>)
+        -> Result<(), DecodeError<S>>
+    {
+        match &**val {
+            Literal::Null => Ok(()),
+            _ => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::Null, val));
+                Ok(())
+            }
+        }
+    }
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>,
+                  ctx: &mut Context<S>)
+    {
+        if let Some(typ) = type_name {
+            ctx.emit_error(DecodeError::TypeName {
+                span: typ.span().clone(),
+                found: Some(typ.value.clone()),
+                expected: ExpectedType::no_type(),
+                rust_type: "()",
+            });
+        }
+    }
+}
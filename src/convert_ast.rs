@@ -33,6 +33,10 @@ impl<S, T> Decode<S> for Node<T>
                         .collect::<Result<_, _>>()?,
                 })
             }).transpose()?,
+            duplicate_properties: node.duplicate_properties.iter()
+                .map(|n| n.clone_as(ctx))
+                .collect(),
+            leading_comments: node.leading_comments.clone(),
         })
     }
 }
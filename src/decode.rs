@@ -2,13 +2,18 @@
 //!
 //! Mostly useful for manual implementation of various `Decode*` traits.
 use std::any::{Any, TypeId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::default::Default;
-use std::fmt;
+use std::fmt::{self, Write};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+#[cfg(feature="duration")]
+use std::time::Duration;
 
-use crate::ast::{Literal, BuiltinType, Value, SpannedNode};
+use crate::ast::{Literal, BuiltinType, Node, Value, SpannedNode, SpannedName};
 use crate::errors::{DecodeError, ExpectedType};
-use crate::traits::{ErrorSpan, Decode};
+use crate::span::Spanned;
+use crate::traits::{ErrorSpan, Decode, DecodeScalar};
 
 
 /// Context is passed through all the decode operations and can be used for:
@@ -19,6 +24,9 @@ use crate::traits::{ErrorSpan, Decode};
 pub struct Context<S: ErrorSpan> {
     errors: Vec<DecodeError<S>>,
     extensions: HashMap<TypeId, Box<dyn Any>>,
+    /// Addresses of elements consumed by the parents being decoded, only
+    /// compared and never dereferenced
+    consumed: HashSet<usize>,
 }
 
 /// Scalar value kind
@@ -47,13 +55,35 @@ pub enum Kind {
 /// Used internally by `#[knuffel(..., bytes)]` attribute. But can be used
 /// manually for implementing [`DecodeScalar`](crate::traits::DecodeScalar).
 pub fn bytes<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>) -> Vec<u8> {
+    decode_bytes(value, ctx, false)
+}
+
+/// Decodes KDL value as bytes, using URL-safe alphabet for `(base64)` values
+///
+/// Used internally by `#[knuffel(..., bytes(base64_url))]` attribute. Plain
+/// strings are decoded the same way as in [`bytes`].
+pub fn bytes_url_safe<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>)
+    -> Vec<u8>
+{
+    decode_bytes(value, ctx, true)
+}
+
+fn decode_bytes<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>,
+                              url_safe: bool)
+    -> Vec<u8>
+{
     if let Some(typ) = &value.type_name {
         match typ.as_builtin() {
             Some(&BuiltinType::Base64) => {
                 #[cfg(feature="base64")] {
                     match &*value.literal {
                         Literal::String(s) => {
-                            match base64::decode(s.as_bytes()) {
+                            let config = if url_safe {
+                                base64::URL_SAFE
+                            } else {
+                                base64::STANDARD
+                            };
+                            match base64::decode_config(s.as_bytes(), config) {
                                 Ok(vec) => vec,
                                 Err(e) => {
                                     ctx.emit_error(DecodeError::conversion(
@@ -70,6 +100,7 @@ pub fn bytes<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>) -> Vec<u8> {
                     }
                 }
                 #[cfg(not(feature="base64"))] {
+                    let _ = url_safe;
                     ctx.emit_error(DecodeError::unsupported(
                             &value.literal,
                             "base64 support is not compiled in"));
@@ -98,6 +129,364 @@ pub fn bytes<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>) -> Vec<u8> {
     }
 }
 
+/// Returns start and end byte offsets of the span
+///
+/// Used internally by `#[knuffel(span_bytes)]` attribute. Any span type can
+/// be converted to [`miette::SourceSpan`], so offsets are always available.
+pub fn span_bytes<S: ErrorSpan>(span: &S) -> (usize, usize) {
+    let span: miette::SourceSpan = span.clone().into();
+    (span.offset(), span.offset() + span.len())
+}
+
+/// Combines flags decoded from the arguments into a flag set
+///
+/// Used internally by `#[knuffel(arguments)]` attribute for `BitFlags<T>`
+/// fields.
+#[cfg(feature="enumflags2")]
+pub fn collect_flags<T, I>(flags: I) -> enumflags2::BitFlags<T>
+    where T: enumflags2::BitFlag,
+          I: IntoIterator<Item=T>,
+{
+    flags.into_iter().collect()
+}
+
+/// Decodes whitespace-separated tokens of the string value into a collection
+///
+/// Each token is decoded as a separate string scalar of type `T`, the type
+/// name of the value is checked once for the whole string. Any amount of
+/// whitespace separates tokens, so there are no empty tokens, and an empty
+/// string results in an empty collection. Errors are emitted into the
+/// context and bad tokens are skipped, so all of them are reported. Tokens
+/// have no spans on their own, so errors point to the whole string.
+///
+/// Used internally by `#[knuffel(..., split_flags)]` attribute.
+pub fn split_flags<T, C, S>(value: &Value<S>, ctx: &mut Context<S>) -> C
+    where T: DecodeScalar<S>,
+          C: FromIterator<T>,
+          S: ErrorSpan,
+{
+    T::type_check(&value.type_name, ctx);
+    match &*value.literal {
+        Literal::String(s) => {
+            s.split_whitespace().filter_map(|token| {
+                let token = Spanned {
+                    span: value.literal.span().clone(),
+                    value: Literal::String(token.into()),
+                };
+                match T::raw_decode(&token, ctx) {
+                    Ok(v) => Some(v),
+                    Err(e) => {
+                        ctx.emit_error(e);
+                        None
+                    }
+                }
+            }).collect()
+        }
+        _ => {
+            ctx.emit_error(DecodeError::scalar_kind(
+                Kind::String, &value.literal));
+            std::iter::empty().collect()
+        }
+    }
+}
+
+/// Parses a magnitude followed by an optional unit from the `table`
+///
+/// Magnitude and unit are separated by whitespace, so `3 kHz` is valid and
+/// `3kHz` is not. The magnitude is parsed as `f64` and multiplied by the
+/// unit's multiplier. Units are case sensitive. A string without a unit is
+/// returned as is.
+///
+/// Used internally by `#[knuffel(..., units=TABLE)]` attribute.
+pub fn parse_units(text: &str, table: &[(&str, f64)])
+    -> Result<f64, Box<dyn std::error::Error + Send + Sync>>
+{
+    let text = text.trim();
+    let (magnitude, unit) = match text.split_once(char::is_whitespace) {
+        Some((magnitude, unit)) => (magnitude, Some(unit.trim_start())),
+        None => (text, None),
+    };
+    let magnitude: f64 = magnitude.parse()?;
+    let unit = match unit {
+        Some(unit) => unit,
+        None => return Ok(magnitude),
+    };
+    match table.iter().find(|(name, _)| *name == unit) {
+        Some((_, multiplier)) => Ok(magnitude * multiplier),
+        None => {
+            let mut msg = format!("unknown unit `{}`, expected one of ",
+                                  unit.escape_default());
+            for (i, (name, _)) in table.iter().enumerate() {
+                if i > 0 {
+                    msg.push_str(", ");
+                }
+                write!(&mut msg, "`{}`", name.escape_default()).unwrap();
+            }
+            Err(msg.into())
+        }
+    }
+}
+
+/// Decodes a percentage like `"50%"` into a fraction like `0.5`
+///
+/// Numbers are fractions already, so both `0.5` and `"50%"` decode to `0.5`.
+/// If the `range` is specified, it is in percents (i.e. `0.0..=100.0`) and
+/// values outside of it are errors. Values above 100% are allowed otherwise.
+///
+/// Used internally by `#[knuffel(..., percent)]` attribute.
+pub fn percent<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>,
+                             range: Option<RangeInclusive<f64>>)
+    -> Result<f64, DecodeError<S>>
+{
+    <f64 as DecodeScalar<S>>::type_check(&value.type_name, ctx);
+    // bare fractions are returned unchanged and only scaled for the check
+    let (fraction, percents) = match &*value.literal {
+        Literal::String(s) => {
+            let number = s.strip_suffix('%').ok_or_else(|| {
+                DecodeError::conversion(&value.literal,
+                    "percentage must end with `%`")
+            })?;
+            let percents = number.trim_end().parse::<f64>()
+                .map_err(|e| DecodeError::conversion(&value.literal, e))?;
+            (percents / 100.0, percents)
+        }
+        Literal::Int(_) | Literal::Decimal(_) => {
+            let fraction: f64 = DecodeScalar::raw_decode(&value.literal, ctx)?;
+            (fraction, fraction * 100.0)
+        }
+        _ => return Err(DecodeError::scalar_kind(Kind::String, &value.literal)),
+    };
+    if let Some(range) = range {
+        if !range.contains(&percents) {
+            return Err(DecodeError::conversion(&value.literal,
+                format!("{}% is out of range {}%..={}%",
+                        percents, range.start(), range.end())));
+        }
+    }
+    Ok(fraction)
+}
+
+/// Returns the number of nanoseconds in a duration unit
+#[cfg(feature="duration")]
+fn duration_unit(unit: &str) -> Option<u64> {
+    match unit {
+        "ns" => Some(1),
+        "us" => Some(1_000),
+        "ms" => Some(1_000_000),
+        "s" => Some(1_000_000_000),
+        "m" => Some(60_000_000_000),
+        "h" => Some(3_600_000_000_000),
+        "d" => Some(86_400_000_000_000),
+        _ => None,
+    }
+}
+
+/// Parses a duration like `30s`, `1500ms` or `1.5 h`
+///
+/// Supported units are `ns`, `us`, `ms`, `s`, `m`, `h` and `d`, optionally
+/// separated from the number by whitespace. A number without a unit is in
+/// `default_unit`. Integers are converted exactly, fractions go through
+/// `f64`.
+///
+/// Used internally by `#[knuffel(..., duration)]` attribute.
+#[cfg(feature="duration")]
+pub fn parse_duration(text: &str, default_unit: &str)
+    -> Result<Duration, Box<dyn std::error::Error + Send + Sync>>
+{
+    const NANOS_PER_SEC: u128 = 1_000_000_000;
+    let invalid = || format!("invalid duration `{}`, expected a number \
+        followed by one of `ns`, `us`, `ms`, `s`, `m`, `h`, `d`",
+        text.escape_default());
+    let trimmed = text.trim();
+    let end = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(end);
+    let unit = match unit.trim_start() {
+        "" => default_unit,
+        unit => unit,
+    };
+    let nanos = duration_unit(unit).ok_or_else(invalid)?;
+    if let Ok(value) = number.parse::<u64>() {
+        let total = u128::from(value) * u128::from(nanos);
+        let secs = u64::try_from(total / NANOS_PER_SEC)
+            .map_err(|_| format!("duration `{}` is too large",
+                                 text.escape_default()))?;
+        return Ok(Duration::new(secs, (total % NANOS_PER_SEC) as u32));
+    }
+    let value: f64 = number.parse().map_err(|_| invalid())?;
+    let secs = value * nanos as f64 / NANOS_PER_SEC as f64;
+    if !secs.is_finite() || secs >= u64::MAX as f64 {
+        return Err(format!("duration `{}` is too large",
+                           text.escape_default()).into());
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Decodes a duration from a string like `"30s"` or a bare integer
+///
+/// Integers and strings without a unit are in `default_unit`. See
+/// [`parse_duration`] for the accepted syntax.
+///
+/// Used internally by `#[knuffel(..., duration)]` attribute.
+#[cfg(feature="duration")]
+pub fn duration<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>,
+                              default_unit: &str)
+    -> Result<Duration, DecodeError<S>>
+{
+    <Duration as DecodeScalar<S>>::type_check(&value.type_name, ctx);
+    duration_literal(&value.literal, ctx, default_unit)
+}
+
+/// Calls [`duration`] or reports that the `duration` feature is disabled
+///
+/// Used internally by `#[knuffel(..., duration)]` attribute.
+#[cfg(feature="duration")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __decode_duration {
+    ($value: expr, $ctx: expr, $unit: expr) => {
+        $crate::decode::duration($value, $ctx, $unit)
+    };
+}
+
+/// Reports that the `duration` feature is disabled
+///
+/// Used internally by `#[knuffel(..., duration)]` attribute.
+#[cfg(not(feature="duration"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __decode_duration {
+    ($value: expr, $ctx: expr, $unit: expr) => {
+        compile_error!("`#[knuffel(duration)]` requires the `duration` \
+                        feature of `knuffel`")
+    };
+}
+
+#[cfg(feature="duration")]
+pub(crate) fn duration_literal<S: ErrorSpan>(literal: &Spanned<Literal, S>,
+                                             ctx: &mut Context<S>,
+                                             default_unit: &str)
+    -> Result<Duration, DecodeError<S>>
+{
+    match &**literal {
+        Literal::String(s) => parse_duration(s, default_unit)
+            .map_err(|e| DecodeError::conversion(literal, e)),
+        Literal::Int(_) => {
+            let value: u64 = DecodeScalar::raw_decode(literal, ctx)?;
+            parse_duration(&value.to_string(), default_unit)
+                .map_err(|e| DecodeError::conversion(literal, e))
+        }
+        _ => Err(DecodeError::scalar_kind(Kind::String, literal)),
+    }
+}
+
+/// Decodes a boolean, also accepting words like `"yes"` or `"off"`
+///
+/// Strings `yes`, `on`, `true` and `no`, `off`, `false` are accepted in any
+/// case, in addition to the native boolean literals.
+///
+/// Used internally by `#[knuffel(..., bool_words)]` attribute.
+pub fn bool_words<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>)
+    -> Result<bool, DecodeError<S>>
+{
+    <bool as DecodeScalar<S>>::type_check(&value.type_name, ctx);
+    match &*value.literal {
+        Literal::String(s) => {
+            const TRUE: &[&str] = &["yes", "on", "true"];
+            const FALSE: &[&str] = &["no", "off", "false"];
+            if TRUE.iter().any(|w| w.eq_ignore_ascii_case(s)) {
+                Ok(true)
+            } else if FALSE.iter().any(|w| w.eq_ignore_ascii_case(s)) {
+                Ok(false)
+            } else {
+                Err(DecodeError::conversion(&value.literal, format!(
+                    "expected a boolean, one of `yes`, `no`, `on`, `off`, \
+                     `true`, `false`, found `{}`", s.escape_default())))
+            }
+        }
+        _ => DecodeScalar::raw_decode(&value.literal, ctx),
+    }
+}
+
+/// Base directory for relative paths
+///
+/// When put into the context using
+/// [`parse_with_context`](crate::parse_with_context), relative paths decoded
+/// with `#[knuffel(..., path)]` or `#[knuffel(..., expand_tilde)]` are joined
+/// to it. Usually this is the directory of the file being parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseDir(pub PathBuf);
+
+/// Decodes a path, resolving it against [`BaseDir`] if there is one
+///
+/// If `expand_tilde` is set, a leading `~` is replaced with the home
+/// directory (taken from `HOME`, or `USERPROFILE` on Windows). Absolute paths
+/// are returned unchanged.
+///
+/// Used internally by `#[knuffel(..., path)]` and
+/// `#[knuffel(..., expand_tilde)]` attributes.
+pub fn path<S: ErrorSpan>(value: &Value<S>, ctx: &mut Context<S>,
+                          expand_tilde: bool)
+    -> Result<PathBuf, DecodeError<S>>
+{
+    <PathBuf as DecodeScalar<S>>::type_check(&value.type_name, ctx);
+    let text = match &*value.literal {
+        Literal::String(s) => s,
+        _ => return Err(DecodeError::scalar_kind(Kind::String, &value.literal)),
+    };
+    let path = match strip_tilde(text).filter(|_| expand_tilde) {
+        Some(rest) => {
+            let home = home_dir().ok_or_else(|| {
+                DecodeError::conversion(&value.literal,
+                    "cannot expand `~`, home directory is unknown")
+            })?;
+            if rest.is_empty() { home } else { home.join(rest) }
+        }
+        None => PathBuf::from(&text[..]),
+    };
+    match ctx.get::<BaseDir>() {
+        Some(BaseDir(base)) => Ok(base.join(path)),
+        None => Ok(path),
+    }
+}
+
+fn strip_tilde(text: &str) -> Option<&str> {
+    if text == "~" {
+        return Some("");
+    }
+    let rest = text.strip_prefix('~')?;
+    if rest.starts_with('/') || (cfg!(windows) && rest.starts_with('\\')) {
+        Some(&rest[1..])
+    } else {
+        None
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+    std::env::var_os(var).filter(|h| !h.is_empty()).map(PathBuf::from)
+}
+
+/// Decodes the name of the node as a string scalar
+///
+/// The type name of the node is not passed to the scalar, as it belongs to
+/// the node itself.
+///
+/// Used internally by `#[knuffel(children)]` collected into a map.
+pub fn node_name<T, S>(node: &SpannedNode<S>, ctx: &mut Context<S>)
+    -> Result<T, DecodeError<S>>
+    where T: DecodeScalar<S>,
+          S: ErrorSpan,
+{
+    T::decode(&Value {
+        type_name: None,
+        literal: Spanned {
+            span: node.node_name.span().clone(),
+            value: Literal::String(node.node_name.value.clone()),
+        },
+    }, ctx)
+}
+
 /// Emits error(s) if node is not a flag node
 ///
 /// Flag node is a node that has no arguments, properties or children.
@@ -130,6 +519,225 @@ pub fn check_flag_node<S: ErrorSpan>(
     }
 }
 
+/// Returns error for the node whose name matches none of the `known` names
+///
+/// The error lists the known names and suggests the closest one if any of
+/// them is similar enough to the node name.
+///
+/// Used internally by `#[knuffel(strict_document)]`.
+pub fn unexpected_node<S: ErrorSpan>(node: &SpannedNode<S>, known: &[&str])
+    -> DecodeError<S>
+{
+    let name = &**node.node_name;
+    let mut message = format!("unexpected node `{}`", name.escape_default());
+    match known {
+        [] => message.push_str(", no nodes are expected"),
+        [single] => write!(&mut message, ", expected `{}`", single).unwrap(),
+        _ => {
+            message.push_str(", expected one of ");
+            for (idx, item) in known.iter().enumerate() {
+                if idx > 0 {
+                    message.push_str(", ");
+                }
+                write!(&mut message, "`{}`", item).unwrap();
+            }
+        }
+    }
+    did_you_mean(&mut message, name, known);
+    DecodeError::unexpected(node, "node", message)
+}
+
+/// Returns error for the child node not matching any field
+///
+/// Suggests the closest of the `known` names if there is one similar enough.
+///
+/// Used internally by `#[derive(Decode)]`.
+pub fn unexpected_child<S: ErrorSpan>(node: &SpannedNode<S>, known: &[&str])
+    -> DecodeError<S>
+{
+    let name = &**node.node_name;
+    let mut message = format!("unexpected node `{}`", name.escape_default());
+    did_you_mean(&mut message, name, known);
+    DecodeError::unexpected(node, "node", message)
+}
+
+/// Returns error for the property not matching any field
+///
+/// Suggests the closest of the `known` names if there is one similar enough.
+///
+/// Used internally by `#[derive(Decode)]`.
+pub fn unexpected_property<S: ErrorSpan>(name: &SpannedName<S>,
+                                         known: &[&str])
+    -> DecodeError<S>
+{
+    let mut message = format!("unexpected property `{}`",
+                              name.escape_default());
+    did_you_mean(&mut message, name, known);
+    DecodeError::unexpected(name, "property", message)
+}
+
+/// Returns error for the required argument that is missing in the node
+///
+/// The `name` is the name of the field, if it has one.
+///
+/// Used internally by `#[derive(Decode)]`.
+pub fn missing_argument<S: ErrorSpan>(node: &SpannedNode<S>,
+                                      name: Option<&str>)
+    -> DecodeError<S>
+{
+    let node_name = node.node_name.escape_default();
+    let message = match name {
+        Some(name) => format!("missing required argument `{}` for node `{}`",
+                              name, node_name),
+        None => format!("missing required argument for node `{}`",
+                        node_name),
+    };
+    DecodeError::missing(node, message)
+}
+
+/// Returns error for the argument of a node that accepts no arguments
+///
+/// Used internally by `#[derive(Decode)]`.
+pub fn stray_argument<S: ErrorSpan>(node: &SpannedNode<S>, value: &Value<S>)
+    -> DecodeError<S>
+{
+    DecodeError::unexpected(&value.literal, "argument",
+        format!("unexpected argument for node `{}`",
+                node.node_name.escape_default()))
+}
+
+/// Returns error for the property of a node that accepts no properties
+///
+/// Used internally by `#[derive(Decode)]`.
+pub fn stray_property<S: ErrorSpan>(node: &SpannedNode<S>,
+                                    name: &SpannedName<S>)
+    -> DecodeError<S>
+{
+    DecodeError::unexpected(name, "property",
+        format!("unexpected property `{}` for node `{}`",
+                name.escape_default(), node.node_name.escape_default()))
+}
+
+/// Returns whichever of the elements comes first in the source text
+///
+/// Used internally by `#[knuffel(fail_fast)]` attribute.
+pub fn first_in_source<'a, T, S: ErrorSpan>(first: Option<&'a Spanned<T, S>>,
+                                            elem: &'a Spanned<T, S>)
+    -> &'a Spanned<T, S>
+{
+    match first {
+        Some(first)
+        if span_bytes(first.span()).0 <= span_bytes(elem.span()).0 => first,
+        _ => elem,
+    }
+}
+
+/// Checks that the property is not repeated in the node
+///
+/// The AST keeps the rightmost value of a repeated property, as the KDL spec
+/// requires, and the parser records the repetition in
+/// [`Node::duplicate_properties`].
+///
+/// Used internally by `#[derive(Decode)]`.
+pub fn unique_property<S: ErrorSpan>(node: &Node<S>,
+                                     name: &SpannedName<S>, value: &Value<S>)
+    -> Result<(), DecodeError<S>>
+{
+    if node.duplicate_properties.iter().any(|dup| dup == name) {
+        return Err(DecodeError::Duplicate {
+            span: value.literal.span().clone(),
+            previous: name.span().clone(),
+            kind: "property",
+            message: format!("duplicate property `{}`",
+                             name.escape_default()),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the property or node is accepted by at most one of the
+/// flattened fields
+///
+/// Returns whether any of them accepted it.
+///
+/// Used internally by `#[knuffel(flatten)]` attribute.
+pub fn flattened<S: ErrorSpan>(name: &SpannedName<S>, kind: &'static str,
+    accepted: impl IntoIterator<Item=Result<bool, DecodeError<S>>>)
+    -> Result<bool, DecodeError<S>>
+{
+    let mut count = 0;
+    for item in accepted {
+        if item? {
+            count += 1;
+        }
+    }
+    if count > 1 {
+        return Err(DecodeError::unexpected(name, kind,
+            format!("ambiguous {} `{}`, accepted by {} flattened fields",
+                    kind, name.escape_default(), count)));
+    }
+    Ok(count > 0)
+}
+
+/// Returns the name from `known` closest to `name` by edit distance
+///
+/// Only names within a third of the `name` length (but at least one edit)
+/// are considered, so unrelated names are never returned.
+pub fn closest_name<'a>(name: &str, known: &[&'a str]) -> Option<&'a str> {
+    let threshold = (name.chars().count() / 3).max(1);
+    known.iter()
+        .map(|item| (edit_distance(name, item), *item))
+        .filter(|(dist, _)| *dist <= threshold)
+        .min_by_key(|(dist, _)| *dist)
+        .map(|(_, item)| item)
+}
+
+fn did_you_mean(message: &mut String, name: &str, known: &[&str]) {
+    if let Some(item) = closest_name(name, known) {
+        write!(message, ", did you mean `{}`?", item).unwrap();
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Returns line comments immediately preceding the node
+///
+/// Only `//` comments each on its own line directly above the node are
+/// collected (see [`Node::leading_comments`]). A single space following the
+/// comment marker is stripped, and lines are joined with `\n`.
+///
+/// Returns `None` if there are no such comments.
+///
+/// Used internally by `#[knuffel(doc_comment)]`.
+pub fn leading_comments<S: ErrorSpan>(node: &SpannedNode<S>)
+    -> Option<String>
+{
+    if node.leading_comments.is_empty() {
+        return None;
+    }
+    let comments = node.leading_comments.iter()
+        .map(|text| text.strip_prefix(' ').unwrap_or(text))
+        .collect::<Vec<_>>();
+    Some(comments.join("\n"))
+}
+
 /// Parse single KDL node from AST
 pub fn node<T, S>(ast: &SpannedNode<S>) -> Result<T, Vec<DecodeError<S>>>
     where T: Decode<S>,
@@ -153,7 +761,36 @@ impl<S: ErrorSpan> Context<S> {
         Context {
             errors: Vec::new(),
             extensions: HashMap::new(),
+            consumed: HashSet::new(),
+        }
+    }
+    /// Runs `f` with the element of the node marked as decoded by the parent
+    ///
+    /// Derived decoders skip marked properties and type names, this hides
+    /// the tag of an enum having `tag` and the type name matched by
+    /// `children(type=..)` from the child without copying the node. The mark
+    /// is removed when `f` returns, so it never outlives the borrow of the
+    /// element.
+    ///
+    /// Used internally by `#[derive(Decode)]`.
+    pub fn with_consumed<T, R>(&mut self, elem: &Spanned<T, S>,
+                               f: impl FnOnce(&mut Self) -> R)
+        -> R
+    {
+        let addr = elem as *const Spanned<T, S> as usize;
+        let inserted = self.consumed.insert(addr);
+        let result = f(self);
+        if inserted {
+            self.consumed.remove(&addr);
         }
+        return result;
+    }
+    /// Returns `true` if the element is marked by
+    /// [`with_consumed`](Context::with_consumed)
+    ///
+    /// Used internally by `#[derive(Decode)]`.
+    pub fn is_consumed<T>(&self, elem: &Spanned<T, S>) -> bool {
+        self.consumed.contains(&(elem as *const Spanned<T, S> as usize))
     }
     /// Add error
     ///
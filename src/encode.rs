@@ -0,0 +1,596 @@
+//! Encoding of Rust values back into KDL text
+//!
+//! Most users need only [`to_string`] or [`to_string_pretty`] along with
+//! [`Encode`](derive@crate::Encode) derive. The [`Writer`] is used by the
+//! derived code and by manual implementations of
+//! [`Encode`](crate::traits::Encode).
+use std::ffi::OsString;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use chumsky::Parser;
+use chumsky::prelude::end;
+
+use crate::ast::{Literal, Integer, Decimal, Radix};
+use crate::grammar;
+use crate::span::{Span, Spanned};
+use crate::traits::{Encode, EncodeChildren, EncodeScalar};
+use crate::traits::sealed::Sealed;
+
+
+/// Options for [`to_string_with_options`]
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    /// Number of spaces used for each level of nesting
+    pub indent: usize,
+}
+
+/// Writes KDL nodes into a string
+///
+/// Every node is written by calling [`start_node`](Writer::start_node),
+/// then adding arguments, properties and children, then calling
+/// [`end_node`](Writer::end_node). Children are written between
+/// [`start_children`](Writer::start_children) and
+/// [`end_children`](Writer::end_children). Braces are omitted if no
+/// children were written.
+#[derive(Debug)]
+pub struct Writer {
+    out: String,
+    /// Indentation width, `None` means everything is written in one line
+    indent: Option<usize>,
+    depth: usize,
+    /// Positions of the opening braces of the children blocks
+    blocks: Vec<usize>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> PrintOptions {
+        PrintOptions {
+            indent: 4,
+        }
+    }
+}
+
+fn is_bare_ident(name: &str) -> bool {
+    grammar::bare_ident::<Span>().then_ignore(end())
+        .parse(Span::stream(name))
+        .is_ok()
+}
+
+impl Writer {
+    /// Creates a writer which puts every node on its own line
+    pub fn new(options: &PrintOptions) -> Writer {
+        Writer {
+            out: String::new(),
+            indent: Some(options.indent),
+            depth: 0,
+            blocks: Vec::new(),
+        }
+    }
+    /// Creates a writer which puts the whole document in a single line
+    pub fn compact() -> Writer {
+        Writer {
+            out: String::new(),
+            indent: None,
+            depth: 0,
+            blocks: Vec::new(),
+        }
+    }
+    fn identifier(&mut self, name: &str) {
+        if is_bare_ident(name) {
+            self.out.push_str(name);
+        } else {
+            self.out.push('"');
+            self.out.push_str(&crate::string::escape(name));
+            self.out.push('"');
+        }
+    }
+    fn type_name(&mut self, type_name: &str) {
+        self.out.push('(');
+        self.identifier(type_name);
+        self.out.push(')');
+    }
+    fn indent(&mut self) {
+        match self.indent {
+            Some(width) => {
+                self.out.extend((0..width*self.depth).map(|_| ' '));
+            }
+            None if !self.out.is_empty() => self.out.push(' '),
+            None => {}
+        }
+    }
+    /// Starts a node, the name is quoted if needed
+    pub fn start_node(&mut self, name: &str) {
+        self.indent();
+        self.identifier(name);
+    }
+    /// Starts a node annotated with the type name
+    pub fn start_typed_node(&mut self, type_name: &str, name: &str) {
+        self.indent();
+        self.type_name(type_name);
+        self.identifier(name);
+    }
+    /// Adds an argument to the current node
+    pub fn argument(&mut self, value: &Literal) {
+        use std::fmt::Write;
+
+        write!(&mut self.out, " {}", value).unwrap();
+    }
+    /// Adds an argument annotated with the type name
+    pub fn typed_argument(&mut self, type_name: &str, value: &Literal) {
+        use std::fmt::Write;
+
+        self.out.push(' ');
+        self.type_name(type_name);
+        write!(&mut self.out, "{}", value).unwrap();
+    }
+    /// Adds a property to the current node
+    pub fn property(&mut self, name: &str, value: &Literal) {
+        use std::fmt::Write;
+
+        self.out.push(' ');
+        self.identifier(name);
+        write!(&mut self.out, "={}", value).unwrap();
+    }
+    /// Adds a property annotated with the type name
+    pub fn typed_property(&mut self, name: &str, type_name: &str,
+                          value: &Literal)
+    {
+        use std::fmt::Write;
+
+        self.out.push(' ');
+        self.identifier(name);
+        self.out.push('=');
+        self.type_name(type_name);
+        write!(&mut self.out, "{}", value).unwrap();
+    }
+    /// Opens a block of children of the current node
+    pub fn start_children(&mut self) {
+        self.blocks.push(self.out.len());
+        self.out.push_str(" {");
+        if self.indent.is_some() {
+            self.out.push('\n');
+        }
+        self.depth += 1;
+    }
+    /// Closes a block of children opened by
+    /// [`start_children`](Writer::start_children)
+    pub fn end_children(&mut self) {
+        self.depth -= 1;
+        let start = self.blocks.pop()
+            .expect("end_children() without start_children()");
+        match self.indent {
+            Some(_) if self.out.len() == start + " {\n".len() => {
+                self.out.truncate(start);
+            }
+            Some(width) => {
+                self.out.extend((0..width*self.depth).map(|_| ' '));
+                self.out.push('}');
+            }
+            None if self.out.len() == start + " {".len() => {
+                self.out.truncate(start);
+            }
+            None => self.out.push_str(" }"),
+        }
+    }
+    /// Finishes the current node
+    pub fn end_node(&mut self) {
+        match self.indent {
+            Some(_) => self.out.push('\n'),
+            None => self.out.push(';'),
+        }
+    }
+    /// Returns the text written so far
+    pub fn into_string(self) -> String {
+        self.out
+    }
+}
+
+/// Encode the value as a KDL document written in a single line
+pub fn to_string<T: EncodeChildren + ?Sized>(value: &T) -> String {
+    let mut writer = Writer::compact();
+    value.encode_children(&mut writer);
+    writer.into_string()
+}
+
+/// Encode the value as a KDL document using the default [`PrintOptions`]
+///
+/// Every node is put on its own line and children are indented by four
+/// spaces.
+pub fn to_string_pretty<T: EncodeChildren + ?Sized>(value: &T) -> String {
+    to_string_with_options(value, &PrintOptions::default())
+}
+
+/// Encode the value as a KDL document using the specified options
+pub fn to_string_with_options<T>(value: &T, options: &PrintOptions)
+    -> String
+    where T: EncodeChildren + ?Sized,
+{
+    let mut writer = Writer::new(options);
+    value.encode_children(&mut writer);
+    writer.into_string()
+}
+
+/// Returns the type name stored in the `type_of` field
+///
+/// String values are used as is, like they are decoded from the type name.
+///
+/// Used internally by `#[derive(Encode)]`.
+pub fn type_name<T: EncodeScalar + ?Sized>(value: &T) -> String {
+    match value.encode_scalar() {
+        Literal::String(s) => s.into(),
+        literal => literal.to_string(),
+    }
+}
+
+/// Encodes the value as a string using its `Display` implementation
+///
+/// This is the reverse of `#[knuffel(str)]`.
+pub fn display<T: fmt::Display + ?Sized>(value: &T) -> Literal {
+    Literal::String(value.to_string().into())
+}
+
+macro_rules! impl_integer {
+    ($($typ: ident),*) => {
+        $(
+            impl EncodeScalar for $typ {
+                fn encode_scalar(&self) -> Literal {
+                    Literal::Int(Integer(Radix::Dec, self.to_string().into()))
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize);
+
+macro_rules! impl_float {
+    ($($typ: ident),*) => {
+        $(
+            /// Infinity and NaN can't be represented in KDL and are encoded
+            /// as `null`
+            impl EncodeScalar for $typ {
+                fn encode_scalar(&self) -> Literal {
+                    if self.is_finite() {
+                        // Debug output always has a decimal point or
+                        // an exponent, unlike Display
+                        Literal::Decimal(Decimal(format!("{:?}", self).into()))
+                    } else {
+                        Literal::Null
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_float!(f32, f64);
+
+impl EncodeScalar for bool {
+    fn encode_scalar(&self) -> Literal {
+        Literal::Bool(*self)
+    }
+}
+
+impl EncodeScalar for str {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self.into())
+    }
+}
+
+impl EncodeScalar for String {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self[..].into())
+    }
+}
+
+/// Non UTF-8 paths are encoded lossily
+impl EncodeScalar for PathBuf {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self.to_string_lossy().into())
+    }
+}
+
+/// Non UTF-8 strings are encoded lossily
+impl EncodeScalar for OsString {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self.to_string_lossy().into())
+    }
+}
+
+#[cfg(feature="camino")]
+impl EncodeScalar for camino::Utf8PathBuf {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self.as_str().into())
+    }
+}
+
+// Implements `EncodeScalar` for types written as strings using `Display`,
+// which is the reverse of parsing them with `FromStr`.
+macro_rules! impl_display {
+    ($(#[$meta: meta] $typ: ty;)+) => {
+        $(
+            #[$meta]
+            impl EncodeScalar for $typ {
+                fn encode_scalar(&self) -> Literal {
+                    display(self)
+                }
+            }
+        )+
+    };
+}
+
+impl_display!(
+    #[doc="Written like `\"127.0.0.1\"` or `\"::1\"`"] IpAddr;
+    #[doc="Written like `\"127.0.0.1\"`"] Ipv4Addr;
+    #[doc="Written like `\"::1\"`"] Ipv6Addr;
+    #[doc="Written like `\"127.0.0.1:80\"` or `\"[::1]:80\"`"] SocketAddr;
+    #[cfg(feature="ipnet")] ipnet::IpNet;
+    #[cfg(feature="ipnet")] ipnet::Ipv4Net;
+    #[cfg(feature="ipnet")] ipnet::Ipv6Net;
+    #[cfg(feature="num_rational")] num_rational::Rational64;
+);
+
+/// Written as RFC 3339 timestamp, like `"2024-01-02T15:04:05+02:00"`
+#[cfg(feature="chrono")]
+impl EncodeScalar for chrono::DateTime<chrono::FixedOffset> {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self.to_rfc3339().into())
+    }
+}
+
+/// Written as RFC 3339 timestamp, like `"2024-01-02T15:04:05Z"`
+#[cfg(feature="chrono")]
+impl EncodeScalar for chrono::DateTime<chrono::Utc> {
+    fn encode_scalar(&self) -> Literal {
+        let text = self.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
+        Literal::String(text.into())
+    }
+}
+
+/// Written as ISO 8601 date-time, like `"2024-01-02T15:04:05"`
+#[cfg(feature="chrono")]
+impl EncodeScalar for chrono::NaiveDateTime {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self.format("%Y-%m-%dT%H:%M:%S%.f").to_string().into())
+    }
+}
+
+impl_display!(
+    #[cfg(feature="chrono")] chrono::NaiveDate;
+    #[cfg(feature="chrono")] chrono::NaiveTime;
+);
+
+/// Written in the hyphenated form
+#[cfg(feature="uuid")]
+impl EncodeScalar for uuid::Uuid {
+    fn encode_scalar(&self) -> Literal {
+        Literal::String(self.hyphenated().to_string().into())
+    }
+}
+
+/// Written as a string in the largest unit that represents it exactly
+#[cfg(feature="duration")]
+impl EncodeScalar for std::time::Duration {
+    fn encode_scalar(&self) -> Literal {
+        const UNITS: &[(&str, u128)] = &[
+            ("d", 86_400_000_000_000),
+            ("h", 3_600_000_000_000),
+            ("m", 60_000_000_000),
+            ("s", 1_000_000_000),
+            ("ms", 1_000_000),
+            ("us", 1_000),
+        ];
+        let nanos = self.as_nanos();
+        let (unit, size) = UNITS.iter()
+            .find(|(_, size)| nanos % size == 0)
+            .copied()
+            .unwrap_or(("ns", 1));
+        let text = if nanos == 0 {
+            String::from("0s")
+        } else {
+            format!("{}{}", nanos / size, unit)
+        };
+        Literal::String(text.into())
+    }
+}
+
+impl EncodeScalar for Decimal {
+    fn encode_scalar(&self) -> Literal {
+        Literal::Decimal(self.clone())
+    }
+}
+
+impl EncodeScalar for Literal {
+    fn encode_scalar(&self) -> Literal {
+        self.clone()
+    }
+}
+
+impl EncodeScalar for () {
+    fn encode_scalar(&self) -> Literal {
+        Literal::Null
+    }
+}
+
+impl<T: EncodeScalar> EncodeScalar for Option<T> {
+    fn encode_scalar(&self) -> Literal {
+        match self {
+            Some(value) => value.encode_scalar(),
+            None => Literal::Null,
+        }
+    }
+}
+
+impl<T: EncodeScalar + ?Sized> EncodeScalar for &T {
+    fn encode_scalar(&self) -> Literal {
+        (**self).encode_scalar()
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode_node(&self, name: Option<&str>, writer: &mut Writer) {
+        (**self).encode_node(name, writer)
+    }
+}
+
+impl<T: EncodeScalar, S> EncodeScalar for Spanned<T, S> {
+    fn encode_scalar(&self) -> Literal {
+        self.value.encode_scalar()
+    }
+}
+
+impl<T: Encode, S> Encode for Spanned<T, S> {
+    fn encode_node(&self, name: Option<&str>, writer: &mut Writer) {
+        self.value.encode_node(name, writer)
+    }
+}
+
+macro_rules! impl_pointer {
+    ($($ptr: ident),*) => {
+        $(
+            impl<T: EncodeScalar + ?Sized> EncodeScalar for $ptr<T> {
+                fn encode_scalar(&self) -> Literal {
+                    (**self).encode_scalar()
+                }
+            }
+
+            impl<T: Encode + ?Sized> Encode for $ptr<T> {
+                fn encode_node(&self, name: Option<&str>,
+                               writer: &mut Writer)
+                {
+                    (**self).encode_node(name, writer)
+                }
+            }
+
+            impl<T: EncodeChildren + ?Sized> EncodeChildren for $ptr<T> {
+                fn encode_children(&self, writer: &mut Writer) {
+                    (**self).encode_children(writer)
+                }
+            }
+        )*
+    };
+}
+
+impl_pointer!(Box, Rc, Arc);
+
+impl<T: Encode> EncodeChildren for [T] {
+    fn encode_children(&self, writer: &mut Writer) {
+        for node in self {
+            node.encode_node(None, writer);
+        }
+    }
+}
+
+impl<T: Encode> EncodeChildren for Vec<T> {
+    fn encode_children(&self, writer: &mut Writer) {
+        self[..].encode_children(writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{Literal, Decimal, Integer, Radix};
+    use super::{Writer, PrintOptions, is_bare_ident};
+    use crate::traits::EncodeScalar;
+
+    fn sample(writer: &mut Writer) {
+        writer.start_node("a");
+        writer.argument(&Literal::Int(Integer(Radix::Hex, "-1f".into())));
+        writer.property("key word", &Literal::String("x\"y".into()));
+        writer.start_children();
+        writer.start_node("b");
+        writer.start_children();
+        writer.end_children();
+        writer.end_node();
+        writer.start_typed_node("t", "c");
+        writer.argument(&Literal::Null);
+        writer.typed_property("p", "u8", &Literal::Bool(true));
+        writer.end_node();
+        writer.end_children();
+        writer.end_node();
+        writer.start_node("true");
+        writer.end_node();
+    }
+
+    #[test]
+    fn pretty() {
+        let mut writer = Writer::new(&PrintOptions { indent: 2 });
+        sample(&mut writer);
+        assert_eq!(writer.into_string(),
+            "a -0x1f \"key word\"=\"x\\\"y\" {\n  b\n  (t)c null p=(u8)true\n}\n\"true\"\n");
+    }
+
+    #[test]
+    fn compact() {
+        let mut writer = Writer::compact();
+        sample(&mut writer);
+        assert_eq!(writer.into_string(),
+            "a -0x1f \"key word\"=\"x\\\"y\" { b; (t)c null p=(u8)true; }; \"true\";");
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_bare_ident("node-name"));
+        assert!(is_bare_ident("-"));
+        assert!(!is_bare_ident(""));
+        assert!(!is_bare_ident("1st"));
+        assert!(!is_bare_ident("-1"));
+        assert!(!is_bare_ident("null"));
+        assert!(!is_bare_ident("a=b"));
+    }
+
+    #[test]
+    fn scalars() {
+        assert_eq!(1.0f64.encode_scalar(),
+                   Literal::Decimal(Decimal("1.0".into())));
+        assert_eq!(1e100f64.encode_scalar(),
+                   Literal::Decimal(Decimal("1e100".into())));
+        assert_eq!(f32::NAN.encode_scalar(), Literal::Null);
+        assert_eq!((-5i8).encode_scalar().to_string(), "-5");
+        assert_eq!(Some("a\nb").encode_scalar().to_string(), "\"a\\nb\"");
+        assert_eq!(None::<u32>.encode_scalar(), Literal::Null);
+    }
+
+    #[test]
+    #[cfg(feature="duration")]
+    fn durations() {
+        use std::time::Duration;
+        let text = |d: Duration| d.encode_scalar().to_string();
+        assert_eq!(text(Duration::from_secs(0)), "\"0s\"");
+        assert_eq!(text(Duration::from_secs(7200)), "\"2h\"");
+        assert_eq!(text(Duration::from_secs(90)), "\"90s\"");
+        assert_eq!(text(Duration::from_millis(1500)), "\"1500ms\"");
+        assert_eq!(text(Duration::new(1, 1)), "\"1000000001ns\"");
+    }
+
+    #[test]
+    fn addresses() {
+        use std::net::{IpAddr, SocketAddr};
+        let ip: IpAddr = "::1".parse().unwrap();
+        assert_eq!(ip.encode_scalar().to_string(), "\"::1\"");
+        let addr: SocketAddr = "127.0.0.1:80".parse().unwrap();
+        assert_eq!(addr.encode_scalar().to_string(), "\"127.0.0.1:80\"");
+    }
+
+    #[test]
+    #[cfg(feature="chrono")]
+    fn chrono() {
+        use chrono::{DateTime, FixedOffset, Utc, NaiveDateTime, NaiveDate};
+        let text = "2024-01-02T15:04:05+02:00";
+        let dt: DateTime<FixedOffset> = DateTime::parse_from_rfc3339(text)
+            .unwrap();
+        assert_eq!(dt.encode_scalar().to_string(), format!("{:?}", text));
+        let utc: DateTime<Utc> = dt.with_timezone(&Utc);
+        assert_eq!(utc.encode_scalar().to_string(),
+                   "\"2024-01-02T13:04:05Z\"");
+        let naive: NaiveDateTime = "2024-01-02T15:04:05.5".parse().unwrap();
+        assert_eq!(naive.encode_scalar().to_string(),
+                   "\"2024-01-02T15:04:05.500\"");
+        let date: NaiveDate = "2024-01-02".parse().unwrap();
+        assert_eq!(date.encode_scalar().to_string(), "\"2024-01-02\"");
+    }
+}
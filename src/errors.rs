@@ -7,7 +7,7 @@ use std::collections::BTreeSet;
 use std::fmt::{self, Write};
 
 use thiserror::Error;
-use miette::{Diagnostic, NamedSource};
+use miette::{NamedSource, SourceCode};
 
 use crate::ast::{TypeName, Literal, SpannedNode};
 use crate::span::{Spanned};
@@ -22,7 +22,7 @@ use crate::traits::{ErrorSpan, Span};
 ///
 /// See [crate documentation](crate#Errors) and [miette} documentation to
 /// find out how deal with them.
-#[derive(Debug, Diagnostic, Error)]
+#[derive(Debug, miette::Diagnostic, Error)]
 #[error("error parsing KDL")]
 pub struct Error {
     #[source_code]
@@ -31,10 +31,106 @@ pub struct Error {
     pub(crate) errors: Vec<miette::Report>,
 }
 
+/// Machine-readable description of a single error
+///
+/// Returned by [`Error::to_diagnostics`], for example to show errors in an
+/// editor. With `serde` feature enabled it can be serialized, e.g. to JSON.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Error message, same as its `Display` output
+    pub message: String,
+    /// Byte offset of the start of the primary span, if there is one
+    pub span_start: Option<usize>,
+    /// Byte offset of the end of the primary span, if there is one
+    pub span_end: Option<usize>,
+    /// One-based line number of the start of the primary span
+    pub line: Option<usize>,
+    /// One-based column of the start of the primary span
+    ///
+    /// Columns are counted in characters rather than bytes, so it can be
+    /// shown to the user as is. Tab counts as a single column unless
+    /// [`Error::to_diagnostics_with_tab_width`] is used.
+    pub column: Option<usize>,
+}
+
+impl Error {
+    /// Returns a description of each error without formatting code snippets
+    ///
+    /// The primary span is the first label of the error. Errors about
+    /// missing top-level nodes have no labels, so they have no span.
+    pub fn to_diagnostics(&self) -> Vec<Diagnostic> {
+        self.to_diagnostics_with_tab_width(1)
+    }
+    /// Same as [`to_diagnostics`](Error::to_diagnostics) but tab characters
+    /// move the column to the next multiple of `tab_width`
+    pub fn to_diagnostics_with_tab_width(&self, tab_width: usize)
+        -> Vec<Diagnostic>
+    {
+        self.errors.iter().map(|err| {
+            let span = err.labels().and_then(|mut labels| labels.next());
+            let pos = span.as_ref().and_then(|s| {
+                line_col(&self.source_code, s.offset(), tab_width)
+            });
+            Diagnostic {
+                message: err.to_string(),
+                span_start: span.as_ref().map(|s| s.offset()),
+                span_end: span.as_ref().map(|s| s.offset() + s.len()),
+                line: pos.map(|(line, _)| line),
+                column: pos.map(|(_, column)| column),
+            }
+        }).collect()
+    }
+}
+
+/// Returns one-based line and column of the byte `offset`
+fn line_col(source: &NamedSource, offset: usize, tab_width: usize)
+    -> Option<(usize, usize)>
+{
+    // column reported by miette is in bytes, so characters of the line
+    // before the offset are counted instead
+    let contents = source.read_span(&(offset, 0).into(), 0, 0).ok()?;
+    let bytes = contents.column();
+    let start = offset.checked_sub(bytes)?;
+    let line = source.read_span(&(start, bytes).into(), 0, 0).ok()?;
+    let prefix = std::str::from_utf8(line.data()).ok()?.get(..bytes)?;
+    let column = prefix.chars().fold(0, |col, c| match c {
+        '\t' if tab_width > 1 => (col / tab_width + 1) * tab_width,
+        _ => col + 1,
+    });
+    Some((contents.line() + 1, column + 1))
+}
+
+/// Formats the diagnostic as `line:column: message`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let (Some(line), Some(column)) = (self.line, self.column) {
+            write!(f, "{}:{}: ", line, column)?;
+        }
+        self.message.fmt(f)
+    }
+}
+
+#[cfg(feature="serde")]
+impl serde::Serialize for Diagnostic {
+    fn serialize<S: serde::Serializer>(&self, serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        use serde::ser::SerializeStruct;
+
+        let mut st = serializer.serialize_struct("Diagnostic", 5)?;
+        st.serialize_field("message", &self.message)?;
+        st.serialize_field("span_start", &self.span_start)?;
+        st.serialize_field("span_end", &self.span_end)?;
+        st.serialize_field("line", &self.line)?;
+        st.serialize_field("column", &self.column)?;
+        st.end()
+    }
+}
+
 /// An error type that is returned by decoder traits and emitted to the context
 ///
 /// These are elements of the
-#[derive(Debug, Diagnostic, Error)]
+#[derive(Debug, miette::Diagnostic, Error)]
 #[non_exhaustive]
 pub enum DecodeError<S: ErrorSpan> {
     /// Unexpected type name encountered
@@ -110,6 +206,25 @@ pub enum DecodeError<S: ErrorSpan> {
         /// Description of the error
         message: String,
     },
+    /// Entity encountered twice
+    ///
+    /// Similar to `Unexpected`, but also points to the first occurrence.
+    /// Also emitted for the second field specified in the `one_of` and
+    /// `at_most_one_of` groups, with `kind` of `"choice"`.
+    #[diagnostic()]
+    #[error("{}", message)]
+    Duplicate {
+        /// Position of the duplicate element
+        #[label("duplicate {}", kind)]
+        span: S,
+        /// Position of the first occurrence
+        #[label("first {} is here", kind)]
+        previous: S,
+        /// Kind of element that was found
+        kind: &'static str,
+        /// Description of the error
+        message: String,
+    },
     /// Bad scalar conversion
     ///
     /// This error is emitted when some scalar value of right kind cannot be
@@ -160,7 +275,7 @@ pub(crate) enum TokenFormat {
 
 struct FormatUnexpected<'x>(&'x TokenFormat, &'x BTreeSet<TokenFormat>);
 
-#[derive(Debug, Diagnostic, Error)]
+#[derive(Debug, miette::Diagnostic, Error)]
 pub(crate) enum ParseError<S: ErrorSpan> {
     #[error("{}", FormatUnexpected(found, expected))]
     #[diagnostic()]
@@ -312,6 +427,14 @@ impl<S: ErrorSpan> ParseError<S> {
         }
         self
     }
+    /// Returns true if the error is caused by the input ending too early,
+    /// i.e. it could go away if more text is appended
+    pub(crate) fn is_at_end(&self) -> bool {
+        use ParseError::*;
+        matches!(self,
+            Unexpected { found: TokenFormat::Eoi, .. } |
+            Unclosed { found: TokenFormat::Eoi, .. })
+    }
     #[allow(dead_code)]
     pub(crate) fn map_span<T>(self, f: impl Fn(S) -> T) -> ParseError<T>
         where T: ErrorSpan,
@@ -424,6 +547,18 @@ impl<S: ErrorSpan> DecodeError<S> {
             message: message.into(),
         }
     }
+    /// Construct [`DecodeError::Duplicate`] error
+    pub fn duplicate<T>(elem: &Spanned<T, S>, previous: &Spanned<T, S>,
+                        kind: &'static str, message: impl Into<String>)
+        -> Self
+    {
+        DecodeError::Duplicate {
+            span: elem.span().clone(),
+            previous: previous.span().clone(),
+            kind,
+            message: message.into(),
+        }
+    }
     /// Construct [`DecodeError::Unsupported`] error
     pub fn unsupported<T, M>(span: &Spanned<T, S>, message: M)-> Self
         where M: Into<Cow<'static, str>>,
@@ -450,6 +585,8 @@ impl<S: ErrorSpan> DecodeError<S> {
             => MissingNode { message },
             Unexpected { span, kind, message }
             => Unexpected { span: f(span), kind, message},
+            Duplicate { span, previous, kind, message }
+            => Duplicate { span: f(span), previous: f(previous), kind, message },
             Conversion { span, source }
             => Conversion { span: f(span), source },
             Unsupported { span, message }
@@ -523,18 +660,43 @@ impl fmt::Display for ExpectedType {
 
 /// Declares kind of value expected for the scalar value
 ///
-/// Use [`Kind`](crate::decode::Kind) and `.into()` to create this value.
+/// Use [`Kind`](crate::decode::Kind) and `.into()` to create this value, or
+/// [`ExpectedKind::one_of`] if several kinds are accepted.
 #[derive(Debug)]
-pub struct ExpectedKind(Kind);
+pub struct ExpectedKind {
+    kinds: Vec<Kind>,
+}
+
+impl ExpectedKind {
+    /// Declare that any of the specified kinds is accepted
+    pub fn one_of(kinds: impl IntoIterator<Item=Kind>) -> Self {
+        ExpectedKind {
+            kinds: kinds.into_iter().collect(),
+        }
+    }
+}
 
 impl From<Kind> for ExpectedKind {
     fn from(kind: Kind) -> ExpectedKind {
-        ExpectedKind(kind)
+        ExpectedKind {
+            kinds: vec![kind],
+        }
     }
 }
 
 impl fmt::Display for ExpectedKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0.as_str())
+        let mut iter = self.kinds.iter();
+        if let Some(first) = iter.next() {
+            write!(f, "{}", first.as_str())?;
+        }
+        let last = iter.next_back();
+        for item in iter {
+            write!(f, ", {}", item.as_str())?;
+        }
+        if let Some(last) = last {
+            write!(f, " or {}", last.as_str())?;
+        }
+        Ok(())
     }
 }
@@ -1,9 +1,12 @@
+use std::borrow::Cow;
 use std::collections::{BTreeSet, BTreeMap};
 
 use chumsky::prelude::*;
 
 use crate::ast::{Literal, TypeName, Node, Value, Integer, Decimal, Radix};
 use crate::ast::{SpannedName, SpannedNode, Document};
+use crate::ast::{DocumentRef, NodeRef, ValueRef, LiteralRef};
+use crate::ast::{SpannedNodeRef, SpannedStr};
 use crate::span::{Spanned};
 use crate::traits::{Span};
 use crate::errors::{ParseError as Error, TokenFormat};
@@ -122,7 +125,7 @@ fn ml_comment<S: Span>() -> impl Parser<char, (), Error=Error<S>> {
     })
 }
 
-fn raw_string<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+fn raw_string_chars<S: Span>() -> impl Parser<char, Vec<char>, Error=Error<S>> {
     just('r')
         .ignore_then(just('#').repeated().map(|v| v.len()))
         .then_ignore(just('"'))
@@ -148,9 +151,11 @@ fn raw_string<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
                 }
             })
         )
-    .map(|(text, ())| {
-        text.into_iter().collect::<String>().into()
-    })
+    .map(|(text, ())| text)
+}
+
+fn raw_string<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+    raw_string_chars().map(|text| text.into_iter().collect::<String>().into())
 }
 
 fn string<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
@@ -203,14 +208,35 @@ fn esc_char<S: Span>() -> impl Parser<char, char, Error=Error<S>> {
             .recover_with(skip_until(['}', '"', '\\'], |_| '\0'))))
 }
 
+fn escaped_chars<S: Span>() -> impl Parser<char, Vec<char>, Error=Error<S>> {
+    filter(|&c| c != '"' && c != '\\')
+    .or(just('\\').ignore_then(esc_char()))
+    .repeated()
+}
+
+fn escaped_body<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+    escaped_chars().map(|val| val.into_iter().collect::<String>().into())
+}
+
+/// Parses contents of the escaped string without quotes
+pub(crate) fn unescape<S: Span>()
+    -> impl Parser<char, Box<str>, Error=Error<S>>
+{
+    escaped_body().then_ignore(end())
+}
+
 fn escaped_string<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+    quoted(escaped_body())
+}
+
+fn quoted<S, T, P>(body: P) -> impl Parser<char, T, Error=Error<S>>
+    where P: Parser<char, T, Error=Error<S>>,
+          S: Span,
+{
     just('"')
     .ignore_then(
-        filter(|&c| c != '"' && c != '\\')
-        .or(just('\\').ignore_then(esc_char()))
-        .repeated()
+        body
         .then_ignore(just('"'))
-        .map(|val| val.into_iter().collect::<String>().into())
         .map_err_with_span(|e: Error<S>, span| {
             if matches!(&e, Error::Unexpected { found: TokenFormat::Eoi, .. })
             {
@@ -229,44 +255,56 @@ fn escaped_string<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
     )
 }
 
-fn bare_ident<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+fn bare_ident_chars<S: Span>()
+    -> impl Parser<char, Vec<char>, Error=Error<S>>
+{
     let sign = just('+').or(just('-'));
     choice((
         sign.chain(id_sans_dig().chain(id_char().repeated())),
         sign.repeated().exactly(1),
         id_sans_sign_dig().chain(id_char().repeated())
     ))
+}
+
+/// Rejects keywords where an identifier is expected
+fn not_keyword<S: Span>(s: &str, span: S) -> Result<(), Error<S>> {
+    let keyword = match s {
+        "true" => "true",
+        "false" => "false",
+        "null" => "null",
+        _ => return Ok(()),
+    };
+    Err(Error::Unexpected {
+        label: Some("keyword"),
+        span,
+        found: TokenFormat::Token(keyword),
+        expected: expected_kind("identifier"),
+    })
+}
+
+pub(crate) fn bare_ident<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+    bare_ident_chars()
     .map(|v| v.into_iter().collect()).try_map(|s: String, span| {
-        match &s[..] {
-            "true" => Err(Error::Unexpected {
-                label: Some("keyword"),
-                span,
-                found: TokenFormat::Token("true"),
-                expected: expected_kind("identifier"),
-            }),
-            "false" => Err(Error::Unexpected {
-                label: Some("keyword"),
-                span,
-                found: TokenFormat::Token("false"),
-                expected: expected_kind("identifier"),
-            }),
-            "null" => Err(Error::Unexpected {
-                label: Some("keyword"),
-                span,
-                found: TokenFormat::Token("null"),
-                expected: expected_kind("identifier"),
-            }),
-            _ => Ok(s.into()),
-        }
+        not_keyword(&s, span)?;
+        Ok(s.into())
     })
 }
 
 fn ident<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+    ident_of(bare_ident(), string())
+}
+
+fn ident_of<S, T, B, Q>(bare_ident: B, string: Q)
+    -> impl Parser<char, T, Error=Error<S>>
+    where B: Parser<char, T, Error=Error<S>>,
+          Q: Parser<char, T, Error=Error<S>>,
+          S: Span,
+{
     choice((
         // match -123 so `-` will not be treated as an ident by backtracking
         number().map(Err),
-        bare_ident().map(Ok),
-        string().map(Ok),
+        bare_ident.map(Ok),
+        string.map(Ok),
     ))
     // when backtracking is not already possible,
     // throw error for numbers (mapped to `Result::Err`)
@@ -296,6 +334,13 @@ fn digit<S: Span>(radix: u32) -> impl Parser<char, char, Error=Error<S>> {
     filter(move |c: &char| c.is_digit(radix))
 }
 
+/// First digit after the radix prefix, so `0x` alone is reported clearly
+fn radix_digit<S: Span>(radix: u32, kind: &'static str)
+    -> impl Parser<char, char, Error=Error<S>>
+{
+    digit(radix).map_err(move |e: Error<S>| e.with_expected_kind(kind))
+}
+
 fn digits<S: Span>(radix: u32) -> impl Parser<char, Vec<char>, Error=Error<S>> {
     filter(move |c: &char| c == &'_' || c.is_digit(radix)).repeated()
 }
@@ -323,11 +368,14 @@ fn radix_number<S: Span>() -> impl Parser<char, Literal, Error=Error<S>> {
     .then_ignore(just('0'))
     .then(choice((
         just('b').ignore_then(
-            digit(2).chain(digits(2)).map(|s| (Radix::Bin, s))),
+            radix_digit(2, "binary digit").chain(digits(2))
+            .map(|s| (Radix::Bin, s))),
         just('o').ignore_then(
-            digit(8).chain(digits(8)).map(|s| (Radix::Oct, s))),
+            radix_digit(8, "octal digit").chain(digits(8))
+            .map(|s| (Radix::Oct, s))),
         just('x').ignore_then(
-            digit(16).chain(digits(16)).map(|s| (Radix::Hex, s))),
+            radix_digit(16, "hexadecimal digit").chain(digits(16))
+            .map(|s| (Radix::Hex, s))),
     )))
     .map(|(sign, (radix, value))| {
         let mut s = String::with_capacity(value.len() + sign.map_or(0, |_| 1));
@@ -360,6 +408,15 @@ fn spanned<T, S, P>(p: P) -> impl Parser<char, Spanned<T, S>, Error=Error<S>>
     p.map_with_span(|value, span| Spanned { span, value })
 }
 
+/// Same as [`spanned`] but only the first element of the pair is wrapped
+fn spanned_first<T, U, S, P>(p: P)
+    -> impl Parser<char, (Spanned<T, S>, U), Error=Error<S>>
+    where P: Parser<char, (T, U), Error=Error<S>>,
+          S: Span,
+{
+    p.map_with_span(|(value, other), span| (Spanned { span, value }, other))
+}
+
 fn esc_line<S: Span>() -> impl Parser<char, (), Error=Error<S>> {
     just('\\')
         .ignore_then(ws().repeated())
@@ -370,13 +427,19 @@ fn node_space<S: Span>() -> impl Parser<char, (), Error=Error<S>> {
     ws().or(esc_line())
 }
 
-fn node_terminator<S: Span>() -> impl Parser<char, (), Error=Error<S>> {
-    choice((newline(), comment(), just(';').ignored(), end()))
+/// Returns whether the node is terminated by end of line
+fn node_terminator<S: Span>() -> impl Parser<char, bool, Error=Error<S>> {
+    choice((
+        newline().to(true),
+        comment().to(true),
+        just(';').to(false),
+        end().to(false),
+    ))
 }
 
-enum PropOrArg<S> {
-    Prop(SpannedName<S>, Value<S>),
-    Arg(Value<S>),
+enum PropOrArg<N, V> {
+    Prop(N, V),
+    Arg(V),
     Ignore,
 }
 
@@ -390,8 +453,38 @@ fn value<S: Span>() -> impl Parser<char, Value<S>, Error=Error<S>> {
     .or(spanned(literal()).map(|literal| Value { type_name: None, literal }))
 }
 
+fn keyword_property_name<S: Span>(span: S) -> Error<S> {
+    Error::Unexpected {
+        label: Some("unexpected keyword"),
+        span,
+        found: TokenFormat::Kind("keyword"),
+        expected: [
+            TokenFormat::Kind("identifier"),
+            TokenFormat::Kind("string"),
+        ].into_iter().collect(),
+    }
+}
+
+fn number_property_name<S: Span>(span: S) -> Error<S> {
+    Error::MessageWithHelp {
+        label: Some("unexpected number"),
+        span,
+        message: "numbers cannot be used as property names".into(),
+        help: "consider enclosing in double quotes \"..\"",
+    }
+}
+
+fn identifier_argument<S: Span>(span: S) -> Error<S> {
+    Error::MessageWithHelp {
+        label: Some("unexpected identifier"),
+        span,
+        message: "identifiers cannot be used as arguments".into(),
+        help: "consider enclosing in double quotes \"..\"",
+    }
+}
+
 fn prop_or_arg_inner<S: Span>()
-    -> impl Parser<char, PropOrArg<S>, Error=Error<S>>
+    -> impl Parser<char, PropOrArg<SpannedName<S>, Value<S>>, Error=Error<S>>
 {
     use PropOrArg::*;
     choice((
@@ -407,24 +500,10 @@ fn prop_or_arg_inner<S: Span>()
                         Ok(Prop(name, value))
                     }
                     (Literal::Bool(_) | Literal::Null, Some(_)) => {
-                        Err(Error::Unexpected {
-                            label: Some("unexpected keyword"),
-                            span: name_span,
-                            found: TokenFormat::Kind("keyword"),
-                            expected: [
-                                TokenFormat::Kind("identifier"),
-                                TokenFormat::Kind("string"),
-                            ].into_iter().collect(),
-                        })
+                        Err(keyword_property_name(name_span))
                     }
                     (Literal::Int(_) | Literal::Decimal(_), Some(_)) => {
-                        Err(Error::MessageWithHelp {
-                            label: Some("unexpected number"),
-                            span: name_span,
-                            message: "numbers cannot be used as property names"
-                                .into(),
-                            help: "consider enclosing in double quotes \"..\"",
-                        })
+                        Err(number_property_name(name_span))
                     }
                     (value, None) => Ok(Arg(Value {
                         type_name: None,
@@ -438,13 +517,7 @@ fn prop_or_arg_inner<S: Span>()
         spanned(bare_ident()).then(just('=').ignore_then(value()).or_not())
             .validate(|(name, value), span, emit| {
                 if value.is_none() {
-                    emit(Error::MessageWithHelp {
-                        label: Some("unexpected identifier"),
-                        span,
-                        message: "identifiers cannot be used as arguments"
-                            .into(),
-                        help: "consider enclosing in double quotes \"..\"",
-                    });
+                    emit(identifier_argument(span));
                 }
                 (name, value)
             })
@@ -464,74 +537,224 @@ fn prop_or_arg_inner<S: Span>()
     ))
 }
 
-fn prop_or_arg<S: Span>() -> impl Parser<char, PropOrArg<S>, Error=Error<S>> {
+fn prop_or_arg<S: Span>()
+    -> impl Parser<char, PropOrArg<SpannedName<S>, Value<S>>, Error=Error<S>>
+{
+    prop_or_arg_of(prop_or_arg_inner)
+}
+
+fn prop_or_arg_of<S, N, V, F, P>(inner: F)
+    -> impl Parser<char, PropOrArg<N, V>, Error=Error<S>>
+    where F: Fn() -> P,
+          P: Parser<char, PropOrArg<N, V>, Error=Error<S>>,
+          S: Span,
+{
     begin_comment('-')
         .ignore_then(node_space().repeated())
-        .ignore_then(prop_or_arg_inner())
+        .ignore_then(inner())
         .map(|_| PropOrArg::Ignore)
-    .or(prop_or_arg_inner())
+    .or(inner())
+}
+
+/// Line space between nodes
+///
+/// Text of line comments is kept, as it may document the following node.
+enum Trivia<L> {
+    Newline,
+    Space,
+    BlockComment,
+    LineComment(L),
+}
+
+fn comment_char<S: Span>() -> impl Parser<char, char, Error=Error<S>> {
+    none_of("\r\n\x0C\u{0085}\u{2028}\u{2029}")
+}
+
+fn comment_text<S: Span>() -> impl Parser<char, Box<str>, Error=Error<S>> {
+    comment_char().repeated().collect::<String>().map(String::into_boxed_str)
+}
+
+fn comment_text_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, &'a str, Error=Error<S>> + 'a
+{
+    comment_char().repeated().map_with_span(move |_, span| source(text, span))
+}
+
+fn line_space_of<S, L, P>(comment_text: P)
+    -> impl Parser<char, Trivia<L>, Error=Error<S>>
+    where P: Parser<char, L, Error=Error<S>>,
+          S: Span,
+{
+    newline().map(|()| Trivia::Newline)
+    .or(ws_char().repeated().at_least(1).map(|_| Trivia::Space)
+        .or(ml_comment().map(|()| Trivia::BlockComment))
+        .map_err(|e| e.with_expected_kind("whitespace")))
+    .or(begin_comment('/')
+        .ignore_then(comment_text)
+        .then_ignore(newline().or(end()))
+        .map(Trivia::LineComment))
+}
+
+/// Returns line comments directly above the node
+///
+/// Every comment, as well as the node itself, must be the only thing on its
+/// line. `line_start` tells whether the line space starts on a new line.
+fn comments_above<L>(mut space: Vec<Trivia<L>>, line_start: bool) -> Vec<L> {
+    let mut comments = Vec::new();
+    let mut candidate = None;
+    loop {
+        while let Some(Trivia::Space) = space.last() {
+            space.pop();
+        }
+        let (at_line_start, next) = match space.pop() {
+            None => (line_start, None),
+            Some(Trivia::Newline) => (true, None),
+            // line comment ends with a newline itself
+            Some(Trivia::LineComment(text)) => (true, Some(text)),
+            Some(Trivia::Space) | Some(Trivia::BlockComment) => (false, None),
+        };
+        if !at_line_start {
+            break;
+        }
+        comments.extend(candidate.take());
+        match next {
+            Some(text) => candidate = Some(text),
+            None => break,
+        }
+    }
+    comments.reverse();
+    return comments;
 }
 
-fn line_space<S: Span>() -> impl Parser<char, (), Error=Error<S>> {
-    newline().or(ws()).or(comment())
+fn braced<S, T, P>(nodes: P) -> impl Parser<char, T, Error=Error<S>>
+    where P: Parser<char, T, Error=Error<S>>,
+          S: Span,
+{
+    just('{')
+    .ignore_then(nodes
+        .then_ignore(just('}'))
+        .map_err_with_span(|e, span| {
+            if matches!(&e, Error::Unexpected {
+                found: TokenFormat::Eoi, .. })
+            {
+                e.merge(Error::Unclosed {
+                    label: "curly braces",
+                    // we know it's `{` at the start of the span
+                    opened_at: span.before_start(1),
+                    opened: '{'.into(),
+                    expected_at: span.at_end(),
+                    expected: '}'.into(),
+                    found: None.into(),
+                })
+            } else {
+                e
+            }
+        }))
 }
 
+/// Parses a node, construction of the node itself is left to the caller
+///
+/// Returns type name, node name, line items, children, if the children
+/// are not commented out, and whether the node is terminated by end of line.
+fn node_of<S, T, N, I, C, PT, PN, PI, PC>(type_name: PT, node_name: PN,
+                                          item: PI, children: PC)
+    -> impl Parser<char, (Option<T>, N, Vec<I>, Option<C>, bool),
+                   Error=Error<S>>
+    where PT: Parser<char, T, Error=Error<S>>,
+          PN: Parser<char, N, Error=Error<S>>,
+          PI: Parser<char, I, Error=Error<S>>,
+          PC: Parser<char, C, Error=Error<S>>,
+          S: Span,
+{
+    type_name.or_not()
+        .then(node_name)
+        .then(
+            node_space()
+            .repeated().at_least(1)
+            .ignore_then(item)
+            .repeated()
+        )
+        .then(node_space().repeated()
+              .ignore_then(begin_comment('-')
+                           .then_ignore(node_space().repeated())
+                           .or_not())
+              .then(children)
+              .or_not())
+        .then_ignore(node_space().repeated())
+        .then(node_terminator())
+        .map(|((((type_name, node_name), line_items), opt_children),
+               ends_line)| {
+            let children = match opt_children {
+                Some((Some(_comment), _)) => None,
+                Some((None, children)) => Some(children),
+                None => None,
+            };
+            (type_name, node_name, line_items, children, ends_line)
+        })
+}
+
+/// Parses nodes separated by line space, skipping commented out ones
+///
+/// Node parser returns whether the node is terminated by end of line. Each
+/// node is returned along with line comments directly above it.
+fn node_list<S, N, L, P, F, PL>(node: P, comment_text: F)
+    -> impl Parser<char, Vec<(Vec<L>, N)>, Error=Error<S>>
+    where P: Parser<char, (N, bool), Error=Error<S>>,
+          F: Fn() -> PL,
+          PL: Parser<char, L, Error=Error<S>>,
+          S: Span,
+{
+    let space = || {
+        line_space_of(comment_text()).repeated()
+        .map_with_span(|space, span: S| {
+            let span: miette::SourceSpan = span.into();
+            // nodes of the document start on a new line
+            (space, span.offset() == 0)
+        })
+    };
+    space()
+    .then(begin_comment('-').then_ignore(node_space().repeated()).or_not())
+    .then(node)
+        // `repeated()` would mix errors emitted while parsing the failed node
+        // after the last one into the expected tokens
+        .separated_by(empty())
+        .then_ignore(space())
+        .map(|vec| {
+            let mut line_start = false;
+            vec.into_iter().filter_map(|(((space, at_start), comment),
+                                         (node, ends_line))| {
+                let comments = comments_above(space, line_start || at_start);
+                line_start = ends_line;
+                if comment.is_none() {
+                    Some((comments, node))
+                } else {
+                    None
+                }
+            }).collect()
+        })
+}
 
 fn nodes<S: Span>() -> impl Parser<char, Vec<SpannedNode<S>>, Error=Error<S>> {
     use PropOrArg::*;
     recursive(|nodes: chumsky::recursive::Recursive<char, _, Error<S>>| {
-        let braced_nodes =
-            just('{')
-            .ignore_then(nodes
-                .then_ignore(just('}'))
-                .map_err_with_span(|e, span| {
-                    if matches!(&e, Error::Unexpected {
-                        found: TokenFormat::Eoi, .. })
-                    {
-                        e.merge(Error::Unclosed {
-                            label: "curly braces",
-                            // we know it's `{` at the start of the span
-                            opened_at: span.before_start(1),
-                            opened: '{'.into(),
-                            expected_at: span.at_end(),
-                            expected: '}'.into(),
-                            found: None.into(),
-                        })
-                    } else {
-                        e
-                    }
-                }));
-
-        let node = spanned(type_name()).or_not()
-            .then(spanned(ident()))
-            .then(
-                node_space()
-                .repeated().at_least(1)
-                .ignore_then(prop_or_arg())
-                .repeated()
-            )
-            .then(node_space().repeated()
-                  .ignore_then(begin_comment('-')
-                               .then_ignore(node_space().repeated())
-                               .or_not())
-                  .then(spanned(braced_nodes))
-                  .or_not())
-            .then_ignore(node_space().repeated().then(node_terminator()))
-            .map(|(((type_name, node_name), line_items), opt_children)| {
+        let node = node_of(spanned(type_name()), spanned(ident()),
+                           prop_or_arg(), spanned(braced(nodes)))
+            .map(|(type_name, node_name, line_items, children, ends_line)| {
                 let mut node = Node {
                     type_name,
                     node_name,
                     properties: BTreeMap::new(),
                     arguments: Vec::new(),
-                    children: match opt_children {
-                        Some((Some(_comment), _)) => None,
-                        Some((None, children)) => Some(children),
-                        None => None,
-                    },
+                    children,
+                    duplicate_properties: Vec::new(),
+                    leading_comments: Vec::new(),
                 };
                 for item in line_items {
                     match item {
                         Prop(name, value) => {
+                            if node.properties.contains_key(&name) {
+                                node.duplicate_properties.push(name.clone());
+                            }
                             node.properties.insert(name, value);
                         }
                         Arg(value) => {
@@ -540,19 +763,13 @@ fn nodes<S: Span>() -> impl Parser<char, Vec<SpannedNode<S>>, Error=Error<S>> {
                         Ignore => {}
                     }
                 }
-                node
+                (node, ends_line)
             });
-
-        begin_comment('-').then_ignore(node_space().repeated()).or_not()
-        .then(spanned(node))
-            .separated_by(line_space().repeated())
-            .allow_leading().allow_trailing()
-            .map(|vec| vec.into_iter().filter_map(|(comment, node)| {
-                if comment.is_none() {
-                    Some(node)
-                } else {
-                    None
-                }
+        // boxing keeps the type of the parser, and so compile time, sane
+        node_list(spanned_first(node).boxed(), comment_text)
+            .map(|vec| vec.into_iter().map(|(comments, mut node)| {
+                node.leading_comments = comments;
+                node
             }).collect())
     })
 }
@@ -563,6 +780,196 @@ pub(crate) fn document<S: Span>()
     nodes().then_ignore(end()).map(|nodes| Document { nodes })
 }
 
+/// Slice of the source text covered by the span
+fn source<S: Span>(text: &str, span: S) -> &str {
+    let span: miette::SourceSpan = span.into();
+    return &text[span.offset()..span.offset() + span.len()];
+}
+
+fn raw_string_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, &'a str, Error=Error<S>> + 'a
+{
+    raw_string_chars().map_with_span(move |_, span| {
+        // strip `r`, sharps and quotes
+        let raw = &source(text, span)[1..];
+        let sharps = raw.len() - raw.trim_start_matches('#').len();
+        &raw[sharps+1..raw.len()-sharps-1]
+    })
+}
+
+fn escaped_string_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, Cow<'a, str>, Error=Error<S>> + 'a
+{
+    quoted(escaped_chars().map_with_span(move |chars, span| {
+        let raw = source(text, span);
+        if raw.contains('\\') {
+            Cow::Owned(chars.into_iter().collect())
+        } else {
+            Cow::Borrowed(raw)
+        }
+    }))
+}
+
+fn string_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, Cow<'a, str>, Error=Error<S>> + 'a
+{
+    raw_string_ref(text).map(Cow::Borrowed).or(escaped_string_ref(text))
+}
+
+fn bare_ident_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, &'a str, Error=Error<S>> + 'a
+{
+    bare_ident_chars().try_map(move |_, span: S| {
+        let s = source(text, span.clone());
+        not_keyword(s, span)?;
+        Ok(s)
+    })
+}
+
+fn ident_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, Cow<'a, str>, Error=Error<S>> + 'a
+{
+    ident_of(bare_ident_ref(text).map(Cow::Borrowed), string_ref(text))
+}
+
+fn literal_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, LiteralRef<'a>, Error=Error<S>> + 'a
+{
+    choice((
+        string_ref(text).map(LiteralRef::String),
+        keyword().map(LiteralRef::from),
+        number().map(LiteralRef::from),
+    ))
+}
+
+fn type_name_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, Cow<'a, str>, Error=Error<S>> + 'a
+{
+    ident_ref(text).delimited_by(just('('), just(')'))
+}
+
+fn type_name_value_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, ValueRef<'a, S>, Error=Error<S>> + 'a
+{
+    spanned(type_name_ref(text)).then(spanned(literal_ref(text)))
+    .map(|(type_name, literal)| ValueRef { type_name: Some(type_name), literal })
+}
+
+fn value_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, ValueRef<'a, S>, Error=Error<S>> + 'a
+{
+    type_name_value_ref(text)
+    .or(spanned(literal_ref(text))
+        .map(|literal| ValueRef { type_name: None, literal }))
+}
+
+fn prop_or_arg_inner_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, PropOrArg<SpannedStr<'a, S>, ValueRef<'a, S>>,
+                   Error=Error<S>> + 'a
+{
+    use PropOrArg::*;
+    choice((
+        spanned(literal_ref(text))
+            .then(just('=').ignore_then(value_ref(text)).or_not())
+            .try_map(|(name, value), _| {
+                let name_span = name.span;
+                match (name.value, value) {
+                    (LiteralRef::String(s), Some(value)) => {
+                        let name = Spanned {
+                            span: name_span,
+                            value: s,
+                        };
+                        Ok(Prop(name, value))
+                    }
+                    (LiteralRef::Bool(_) | LiteralRef::Null, Some(_)) => {
+                        Err(keyword_property_name(name_span))
+                    }
+                    (LiteralRef::Int(_) | LiteralRef::Decimal(_), Some(_)) => {
+                        Err(number_property_name(name_span))
+                    }
+                    (value, None) => Ok(Arg(ValueRef {
+                        type_name: None,
+                        literal: Spanned {
+                            span: name_span,
+                            value,
+                        },
+                    })),
+                }
+            }),
+        spanned(bare_ident_ref(text))
+            .then(just('=').ignore_then(value_ref(text)).or_not())
+            .validate(|(name, value), span, emit| {
+                if value.is_none() {
+                    emit(identifier_argument(span));
+                }
+                (name, value)
+            })
+            .map(|(name, value)| {
+                let name = name.map(Cow::Borrowed);
+                if let Some(value) = value {
+                    Prop(name, value)
+                } else {
+                    // error is already emitted in validate() above
+                    Arg(ValueRef {
+                        type_name: None,
+                        literal: name.map(LiteralRef::String),
+                    })
+                }
+            }),
+        type_name_value_ref(text).map(Arg),
+    ))
+}
+
+fn nodes_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, Vec<SpannedNodeRef<'a, S>>, Error=Error<S>> + 'a
+{
+    use PropOrArg::*;
+    recursive(move |nodes: chumsky::recursive::Recursive<char, _, Error<S>>| {
+        let node = node_of(spanned(type_name_ref(text)),
+                           spanned(ident_ref(text)),
+                           prop_or_arg_of(move || prop_or_arg_inner_ref(text)),
+                           spanned(braced(nodes)))
+            .map(|(type_name, node_name, line_items, children, ends_line)| {
+                let mut node = NodeRef {
+                    type_name,
+                    node_name,
+                    properties: BTreeMap::new(),
+                    arguments: Vec::new(),
+                    children,
+                    duplicate_properties: Vec::new(),
+                    leading_comments: Vec::new(),
+                };
+                for item in line_items {
+                    match item {
+                        Prop(name, value) => {
+                            if node.properties.contains_key(&name) {
+                                node.duplicate_properties.push(name.clone());
+                            }
+                            node.properties.insert(name, value);
+                        }
+                        Arg(value) => {
+                            node.arguments.push(value);
+                        }
+                        Ignore => {}
+                    }
+                }
+                (node, ends_line)
+            });
+        // boxing keeps the type of the parser, and so compile time, sane
+        node_list(spanned_first(node).boxed(), move || comment_text_ref(text))
+            .map(|vec| vec.into_iter().map(|(comments, mut node)| {
+                node.leading_comments = comments;
+                node
+            }).collect())
+    })
+}
+
+pub(crate) fn document_ref<'a, S: Span>(text: &'a str)
+    -> impl Parser<char, DocumentRef<'a, S>, Error=Error<S>> + 'a
+{
+    nodes_ref(text).then_ignore(end()).map(|nodes| DocumentRef { nodes })
+}
+
 #[cfg(test)]
 mod test {
     use chumsky::prelude::*;
@@ -740,6 +1147,11 @@ mod test {
         assert_eq!(&*parse(string(), r#""hello\nworld!""#).unwrap(),
                    "hello\nworld!");
         assert_eq!(&*parse(string(), r#""\u{1F680}""#).unwrap(), "🚀");
+        assert_eq!(&*parse(string(), r#""\u{1F600}""#).unwrap(), "😀");
+        assert_eq!(&*parse(string(), r#""\u{9}\u{00e9}\u{10FFFF}""#).unwrap(),
+                   "\t\u{e9}\u{10FFFF}");
+        assert_eq!(&*parse(string(), r#""\b\f\/\r""#).unwrap(),
+                   "\u{0008}\u{000C}/\r");
     }
 
     #[test]
@@ -751,6 +1163,33 @@ mod test {
                    "a\\n\"##b");
     }
 
+    #[test]
+    fn parse_multiline_str() {
+        assert_eq!(&*parse(string(), "\"hello\n  world\"").unwrap(),
+                   "hello\n  world");
+        assert_eq!(&*parse(string(), "r#\"line 1\nline \"2\"\"#").unwrap(),
+                   "line 1\nline \"2\"");
+        // raw strings keep escapes verbatim, escaped strings interpret them
+        assert_eq!(parse(string(), r##"r#"a\"b\n"#"##).unwrap(),
+                   parse(string(), r#""a\\\"b\\n""#).unwrap());
+        assert_eq!(parse(string(), r##"r"tab\t""##).unwrap(),
+                   parse(string(), r#""tab\\t""#).unwrap());
+    }
+
+    #[test]
+    fn parse_str_spans() {
+        let nval = single(parse(nodes(),
+            "n \"a\\nb\" r#\"x\"# r\"y\ny\" k=r##\"z\"##"));
+        let spans = nval.arguments.iter()
+            .map(|a| a.literal.span().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(spans, vec![Span(2, 8), Span(9, 15), Span(16, 22)]);
+        let (name, value) = nval.properties.iter().next().unwrap();
+        assert_eq!(name.span(), &Span(23, 24));
+        assert_eq!(value.literal.span(), &Span(25, 33));
+        assert_eq!(&*value.literal, &Literal::String("z".into()));
+    }
+
     #[test]
     fn parse_str_err() {
         err_eq!(parse(string(), r#""hello"#), r#"{
@@ -858,6 +1297,40 @@ mod test {
             }]
         }"#);
     }
+    #[test]
+    fn parse_str_surrogate_err() {
+        err_eq!(parse(string(), r#""ab\u{D800}cd""#), r#"{
+            "message": "error parsing KDL",
+            "severity": "error",
+            "labels": [],
+            "related": [{
+                "message": "converted integer out of range for `char`",
+                "severity": "error",
+                "filename": "<test>",
+                "labels": [
+                    {"label": "invalid character code",
+                    "span": {"offset": 5, "length": 6}}
+                ],
+                "related": []
+            }]
+        }"#);
+        err_eq!(parse(string(), r#""\u{DFFF}""#), r#"{
+            "message": "error parsing KDL",
+            "severity": "error",
+            "labels": [],
+            "related": [{
+                "message": "converted integer out of range for `char`",
+                "severity": "error",
+                "filename": "<test>",
+                "labels": [
+                    {"label": "invalid character code",
+                    "span": {"offset": 3, "length": 6}}
+                ],
+                "related": []
+            }]
+        }"#);
+    }
+
     #[test]
     fn parse_raw_str_err() {
         err_eq!(parse(string(), r#"r"hello"#),  r#"{
@@ -1202,6 +1675,29 @@ mod test {
         assert_eq!(nval.children().len(), 0);
     }
 
+    #[test]
+    fn parse_slashdash_spans() {
+        let nval = single(parse(nodes(), r#"n /-"a" "b" /-k=1 "c" /-{ x; }"#));
+        let args = nval.arguments.iter()
+            .map(|a| (&*a.literal, a.literal.span().clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(args, vec![
+            (&Literal::String("b".into()), Span(8, 11)),
+            (&Literal::String("c".into()), Span(18, 21)),
+        ]);
+        assert_eq!(nval.properties.len(), 0);
+        assert!(nval.children.is_none());
+
+        let nval = parse(nodes(), "/-a {
+ b { c; }
+}
+d 1").unwrap();
+        assert_eq!(nval.len(), 1);
+        assert_eq!(nval[0].node_name.as_ref(), "d");
+        assert_eq!(nval[0].span(), &Span(18, 21));
+        assert_eq!(*nval[0].arguments[0].literal.span(), Span(20, 21));
+    }
+
     #[test]
     fn parse_node_whitespace() {
         let nval = single(parse(nodes(), "hello  {   }"));
@@ -1353,6 +1849,12 @@ mod test {
                    Literal::Decimal(Decimal("+123.555E-17".into())));
         assert_eq!(parse(number(), "123e+555").unwrap(),
                    Literal::Decimal(Decimal("123e+555".into())));
+        assert_eq!(parse(number(), "1.5e-9").unwrap(),
+                   Literal::Decimal(Decimal("1.5e-9".into())));
+        assert_eq!(parse(number(), "6.022E23").unwrap(),
+                   Literal::Decimal(Decimal("6.022E23".into())));
+        assert_eq!(parse(number(), "-00.5e-0_3").unwrap(),
+                   Literal::Decimal(Decimal("-00.5e-03".into())));
     }
 
     #[test]
@@ -1371,6 +1873,65 @@ mod test {
                    Literal::Int(Integer(Radix::Bin, "1010101".into())));
     }
 
+    #[test]
+    fn parse_radix_number_span() {
+        let nval = parse(nodes(), "node 0xff -0b1_01").unwrap();
+        let args = &nval[0].arguments;
+        assert_eq!(&*args[0].literal,
+                   &Literal::Int(Integer(Radix::Hex, "ff".into())));
+        assert_eq!(*args[0].literal.span(), Span(5, 9));
+        assert_eq!(*args[1].literal.span(), Span(10, 17));
+    }
+
+    #[test]
+    fn parse_radix_number_err() {
+        err_eq!(parse(nodes(), "node 0x"), r#"{
+            "message": "error parsing KDL",
+            "severity": "error",
+            "labels": [],
+            "related": [{
+                "message": "found end of input, expected hexadecimal digit",
+                "severity": "error",
+                "filename": "<test>",
+                "labels": [
+                    {"label": "unexpected token",
+                    "span": {"offset": 7, "length": 0}}
+                ],
+                "related": []
+            }]
+        }"#);
+        err_eq!(parse(nodes(), "node 0xG"), r#"{
+            "message": "error parsing KDL",
+            "severity": "error",
+            "labels": [],
+            "related": [{
+                "message": "found `G`, expected hexadecimal digit",
+                "severity": "error",
+                "filename": "<test>",
+                "labels": [
+                    {"label": "unexpected token",
+                    "span": {"offset": 7, "length": 1}}
+                ],
+                "related": []
+            }]
+        }"#);
+        err_eq!(parse(nodes(), "node 0o_1"), r#"{
+            "message": "error parsing KDL",
+            "severity": "error",
+            "labels": [],
+            "related": [{
+                "message": "found `_`, expected octal digit",
+                "severity": "error",
+                "filename": "<test>",
+                "labels": [
+                    {"label": "unexpected token",
+                    "span": {"offset": 7, "length": 1}}
+                ],
+                "related": []
+            }]
+        }"#);
+    }
+
     #[test]
     fn parse_dashes() {
         let nval = parse(nodes(), "-").unwrap();
@@ -1,22 +1,43 @@
 #![doc = include_str!("../README.md")]
 #![warn(missing_docs)]
 #![warn(missing_debug_implementations)]
+#![forbid(unsafe_code)]
+// lints that disagree with the style of the existing code
+#![allow(clippy::needless_return, clippy::from_over_into)]
+#![allow(clippy::single_match, clippy::unused_unit)]
+#![allow(clippy::for_kv_map, clippy::option_map_unit_fn)]
+#![allow(clippy::implied_bounds_in_impls, clippy::is_digit_ascii_radix)]
+#![allow(clippy::needless_borrow, clippy::useless_conversion)]
+#![allow(clippy::needless_lifetimes)]
 
+mod ast_ref;
 mod containers;
 mod convert;
 mod convert_ast;
 mod grammar;
+mod stream;
 mod wrappers;
 
 pub mod ast;
 pub mod decode;
+pub mod encode;
 pub mod errors;
 pub mod span;
+pub mod string;
 pub mod traits;
 
 #[cfg(feature="derive")]
-pub use knuffel_derive::{Decode, DecodeScalar};
+pub use knuffel_derive::{Decode, DecodeScalar, Encode, EncodeScalar};
 
-pub use wrappers::{parse_ast, parse, parse_with_context};
+#[cfg(feature="serde")]
+#[doc(hidden)]
+pub use serde;
+
+pub use wrappers::{parse_ast, parse_ast_ref, parse, parse_str};
+pub use wrappers::{parse_with_context, parse_with_options, parse_with_span};
+pub use wrappers::ParseOptions;
+pub use stream::{iter_nodes, NodeIter};
 pub use traits::{Decode, DecodeScalar, DecodeChildren};
+pub use traits::{Encode, EncodeScalar, EncodeChildren};
+pub use encode::{to_string, to_string_pretty, to_string_with_options};
 pub use errors::Error;
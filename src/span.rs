@@ -1,7 +1,9 @@
-//! Knuffel supports to kinds of the span for parsing
+//! Knuffel supports three kinds of the span for parsing
 //!
 //! 1. [`Span`] which only tracks byte offset from the start of the source code
 //! 2. [`LineSpan`] which also track line numbers
+//! 3. [`LineColSpan`] which tracks line numbers and columns counted in
+//!    characters, as most editors do
 //!
 //! This distinction is important during parsing stage as [`Span`] is normally
 //! faster. And [`LineSpan`] is still faster than find out line/column number
@@ -66,19 +68,37 @@ pub struct LineSpan(
     pub LinePos,
 );
 
+/// Span with line and column number, where column is a number of characters
+///
+/// Unlike [`LineSpan`], which measures columns in the display width (wide
+/// characters take two columns, tabs take eight), every character including
+/// a tab is a single column here. Use
+/// [`Error::to_diagnostics_with_tab_width`](crate::Error::to_diagnostics_with_tab_width)
+/// if tabs should be expanded in error positions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature="minicbor", derive(minicbor::Encode, minicbor::Decode))]
+pub struct LineColSpan(
+    #[cfg_attr(feature="minicbor", n(0))]
+    pub LinePos,
+    #[cfg_attr(feature="minicbor", n(1))]
+    pub LinePos,
+);
+
 #[allow(missing_debug_implementations)]
 mod sealed {
+    use std::marker::PhantomData;
 
     pub struct OffsetTracker {
         pub(crate) offset: usize,
     }
 
-    #[cfg(feature="line-numbers")]
-    pub struct LineTracker {
+    pub struct LineTracker<S> {
         pub(crate) offset: usize,
         pub(crate) caret_return: bool,
         pub(crate) line: usize,
         pub(crate) column: usize,
+        pub(crate) width: fn(char) -> usize,
+        pub(crate) span: PhantomData<S>,
     }
 
 }
@@ -89,6 +109,18 @@ impl Span {
     pub fn length(&self) -> usize {
         self.1.saturating_sub(self.0)
     }
+    /// Same as `stream()` but for the text that starts at `offset` bytes
+    /// into the source code
+    pub(crate) fn stream_at(text: &str, offset: usize)
+        -> traits::sealed::Stream<'_, Span, sealed::OffsetTracker>
+    {
+        let end = offset + text.len();
+        chumsky::Stream::from_iter(
+            Span(end, end),
+            traits::sealed::Map(text.chars(),
+                                sealed::OffsetTracker { offset }),
+        )
+    }
 }
 
 impl Into<ErrorSpan> for Span {
@@ -165,13 +197,29 @@ impl chumsky::Span for LineSpan {
     fn end(&self) -> LinePos { self.1 }
 }
 
-#[cfg(feature="line-numbers")]
-impl traits::sealed::SpanTracker for sealed::LineTracker {
-    type Span = LineSpan;
-    fn next_span(&mut self, c: char) -> LineSpan {
-        let offset = self.offset;
-        let line = self.line;
-        let column = self.column;
+impl<S> sealed::LineTracker<S> {
+    fn new(width: fn(char) -> usize) -> sealed::LineTracker<S> {
+        sealed::LineTracker {
+            caret_return: false,
+            offset: 0,
+            line: 0,
+            column: 0,
+            width,
+            span: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<S> traits::sealed::SpanTracker for sealed::LineTracker<S>
+    where S: chumsky::Span<Context=(), Offset=LinePos>,
+{
+    type Span = S;
+    fn next_span(&mut self, c: char) -> S {
+        let start = LinePos {
+            offset: self.offset,
+            line: self.line,
+            column: self.column,
+        };
         self.offset += c.len_utf8();
         match c {
             '\n' if self.caret_return => {}
@@ -179,108 +227,120 @@ impl traits::sealed::SpanTracker for sealed::LineTracker {
                 self.line += 1;
                 self.column = 0;
             }
-            '\t' => self.column += 8,
-            c => {
-                self.column += unicode_width::UnicodeWidthChar::width(c)
-                    .unwrap_or(0);  // treat control chars as zero-length
-            }
+            c => self.column += (self.width)(c),
         }
         self.caret_return = c == '\r';
-        LineSpan(
-            LinePos {
-                line,
-                column,
-                offset,
-            },
-            LinePos {
-                line: self.line,
-                column: self.column,
-                offset: self.offset,
-            },
-        )
+        S::new((), start..LinePos {
+            offset: self.offset,
+            line: self.line,
+            column: self.column,
+        })
     }
 }
 
+/// Display width of the character: wide characters take two columns, tabs
+/// take eight
 #[cfg(feature="line-numbers")]
-impl traits::sealed::Sealed for LineSpan {
-    type Tracker = sealed::LineTracker;
-    /// Note assuming ascii, single-width, non-newline chars here
-    fn at_start(&self, chars: usize) -> Self {
-        LineSpan(self.0, LinePos {
-            offset: self.0.offset + chars,
-            column: self.0.column + chars,
-            .. self.0
-        })
+fn display_width(c: char) -> usize {
+    match c {
+        '\t' => 8,
+        c => unicode_width::UnicodeWidthChar::width(c)
+            .unwrap_or(0),  // treat control chars as zero-length
     }
+}
 
-    fn at_end(&self) -> Self {
-        LineSpan(self.1, self.1)
-    }
+fn char_width(_: char) -> usize {
+    1
+}
 
-    /// Note assuming ascii, single-width, non-newline chars here
-    fn before_start(&self, chars: usize) -> Self {
-        LineSpan(LinePos {
-            offset: self.0.offset.saturating_sub(chars),
-            column: self.0.column.saturating_sub(chars),
-            .. self.0
-        }, self.0)
-    }
+macro_rules! impl_line_span {
+    ($typ: ident, $width: expr) => {
+        impl traits::sealed::Sealed for $typ {
+            type Tracker = sealed::LineTracker<$typ>;
+            /// Note assuming ascii, single-width, non-newline chars here
+            fn at_start(&self, chars: usize) -> Self {
+                $typ(self.0, LinePos {
+                    offset: self.0.offset + chars,
+                    column: self.0.column + chars,
+                    .. self.0
+                })
+            }
 
-    fn length(&self) -> usize {
-        self.1.offset.saturating_sub(self.0.offset)
-    }
+            fn at_end(&self) -> Self {
+                $typ(self.1, self.1)
+            }
 
-    fn stream(text: &str) -> traits::sealed::Stream<'_, Self, Self::Tracker>
-        where Self: chumsky::Span
-    {
-        let mut caret_return = false;
-        let mut line = 0;
-        let mut last_line = text;
-        let mut iter = text.chars();
-        while let Some(c) = iter.next() {
-            match c {
-                '\n' if caret_return => {}
-                '\r'|'\n'|'\x0C'|'\u{0085}'|'\u{2028}'|'\u{2029}' => {
-                    line += 1;
-                    last_line = iter.as_str();
-                }
-                _ => {}
+            /// Note assuming ascii, single-width, non-newline chars here
+            fn before_start(&self, chars: usize) -> Self {
+                $typ(LinePos {
+                    offset: self.0.offset.saturating_sub(chars),
+                    column: self.0.column.saturating_sub(chars),
+                    .. self.0
+                }, self.0)
+            }
+
+            fn length(&self) -> usize {
+                self.1.offset.saturating_sub(self.0.offset)
+            }
+
+            fn stream(text: &str)
+                -> traits::sealed::Stream<'_, Self, Self::Tracker>
+                where Self: chumsky::Span
+            {
+                let mut tracker = sealed::LineTracker::new($width);
+                let eoi = text.chars()
+                    .map(|c| {
+                        let span: $typ = traits::sealed::SpanTracker
+                            ::next_span(&mut tracker, c);
+                        span.1
+                    })
+                    .last()
+                    .unwrap_or(LinePos { offset: 0, line: 0, column: 0 });
+                chumsky::Stream::from_iter(
+                    $typ(eoi, eoi),
+                    traits::sealed::Map(
+                        text.chars(),
+                        sealed::LineTracker::new($width),
+                    ),
+                )
+            }
+        }
+
+        impl traits::DecodeSpan<$typ> for Span {
+            fn decode_span(span: &$typ, _: &mut Context<$typ>) -> Self {
+                Span(span.0.offset, span.1.offset)
             }
-            caret_return = c == '\r';
         }
-        let column = unicode_width::UnicodeWidthStr::width(last_line);
-        let eoi = LinePos {
-            line,
-            column,
-            offset: text.len(),
-        };
-        chumsky::Stream::from_iter(
-            LineSpan(eoi, eoi),
-            traits::sealed::Map(
-                text.chars(),
-                sealed::LineTracker {
-                    caret_return: false,
-                    offset: 0,
-                    line: 0,
-                    column: 0,
-                },
-            ),
-        )
     }
 }
 
 #[cfg(feature="line-numbers")]
-impl traits::Span for LineSpan {}
+impl_line_span!(LineSpan, display_width);
 
 #[cfg(feature="line-numbers")]
-impl traits::DecodeSpan<LineSpan> for Span {
-    fn decode_span(span: &LineSpan, _: &mut Context<LineSpan>)
-        -> Self
-    {
-        Span(span.0.offset, span.1.offset)
+impl traits::Span for LineSpan {}
+
+impl Into<ErrorSpan> for LineColSpan {
+    fn into(self) -> ErrorSpan {
+        (self.0.offset, self.1.offset.saturating_sub(self.0.offset)).into()
+    }
+}
+
+impl chumsky::Span for LineColSpan {
+    type Context = ();
+    type Offset = LinePos;
+    fn new(_context: (), range: std::ops::Range<LinePos>) -> Self {
+        LineColSpan(range.start, range.end)
     }
+    fn context(&self) -> () { () }
+    fn start(&self) -> LinePos { self.0 }
+    fn end(&self) -> LinePos { self.1 }
 }
 
+impl_line_span!(LineColSpan, char_width);
+
+impl traits::Span for LineColSpan {}
+
 impl<T, S> Spanned<T, S> {
     /// Converts value but keeps the same span attached
     pub fn map<R>(self, f: impl FnOnce(T) -> R) -> Spanned<R, S> {
@@ -391,6 +451,22 @@ impl fmt::Display for Span {
     }
 }
 
+/// Formats the position as one-based `line:column`
+impl fmt::Display for LinePos {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line + 1, self.column + 1)
+    }
+}
+
+impl fmt::Display for LineColSpan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)?;
+        "..".fmt(f)?;
+        self.1.fmt(f)?;
+        Ok(())
+    }
+}
+
 impl From<Range<usize>> for Span {
     fn from(r: Range<usize>) -> Span {
         Span(r.start, r.end)
@@ -0,0 +1,406 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+
+use chumsky::Parser;
+use miette::{NamedSource, MietteError, MietteSpanContents};
+use miette::{SourceCode, SourceSpan, SpanContents};
+
+use crate::ast::SpannedNode;
+use crate::errors::{Error, ParseError};
+use crate::grammar;
+use crate::span::Span;
+
+
+/// Iterator over top-level nodes of a KDL document read from a stream
+///
+/// Created by [`iter_nodes`]. Each item is either a node or an error. Errors
+/// don't stop the iteration: the text of the failed node is skipped, and
+/// parsing continues on the next line, so the caller can decide whether
+/// to continue or stop at the first error.
+///
+/// Spans of nodes and errors are byte offsets from the start of the stream.
+/// The source code attached to an error only contains the failed node, so
+/// the memory used is proportional to the largest top-level node rather
+/// than to the whole document.
+#[derive(Debug)]
+pub struct NodeIter<R> {
+    reader: R,
+    file_name: String,
+    /// Text of the node that is not complete yet
+    buf: String,
+    /// Byte offset of `buf` from the start of the stream
+    offset: usize,
+    /// Line number of the start of `buf`
+    line: usize,
+    scanner: Scanner,
+    ready: VecDeque<SpannedNode<Span>>,
+    done: bool,
+}
+
+/// Lexical state of the text read so far
+///
+/// Only tracks what's needed to find out whether the node can end at the end
+/// of the line, so every line is scanned once regardless of the node size.
+#[derive(Debug, Default)]
+struct Scanner {
+    /// Nesting of curly braces
+    depth: usize,
+    state: State,
+    /// The last line ends with a line continuation `\`
+    continued: bool,
+    /// Whether the previous character can't be a part of an identifier,
+    /// i.e. a raw string can start here
+    boundary: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    String,
+    RawString { hashes: usize },
+    LineComment,
+    BlockComment { nesting: usize },
+}
+
+impl Default for State {
+    fn default() -> State {
+        State::Normal
+    }
+}
+
+impl Scanner {
+    fn new() -> Scanner {
+        Scanner { boundary: true, ..Default::default() }
+    }
+    fn scan(&mut self, line: &str) {
+        // continuation only matters at the end of the line
+        self.continued = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            let boundary = self.boundary;
+            self.boundary = false;
+            match self.state {
+                State::Normal => match c {
+                    '"' => self.state = State::String,
+                    'r' if boundary && matches!(chars.peek(), Some('#' | '"'))
+                    => {
+                        let mut hashes = 0;
+                        while chars.next_if_eq(&'#').is_some() {
+                            hashes += 1;
+                        }
+                        if chars.next_if_eq(&'"').is_some() {
+                            self.state = State::RawString { hashes };
+                        }
+                    }
+                    '/' if chars.next_if_eq(&'/').is_some() => {
+                        self.state = State::LineComment;
+                    }
+                    '/' if chars.next_if_eq(&'*').is_some() => {
+                        self.state = State::BlockComment { nesting: 1 };
+                    }
+                    '{' => self.depth += 1,
+                    '}' => self.depth = self.depth.saturating_sub(1),
+                    '\\' => self.continued = true,
+                    c if c.is_whitespace() || "(){}=;".contains(c) => {
+                        self.boundary = true;
+                    }
+                    _ => self.continued = false,
+                },
+                State::String => match c {
+                    '\\' => { chars.next(); }
+                    '"' => self.state = State::Normal,
+                    _ => {}
+                },
+                State::RawString { hashes } => {
+                    if c == '"' {
+                        let mut found = 0;
+                        while found < hashes &&
+                            chars.next_if_eq(&'#').is_some()
+                        {
+                            found += 1;
+                        }
+                        if found == hashes {
+                            self.state = State::Normal;
+                        }
+                    }
+                }
+                State::LineComment => if c == '\n' {
+                    self.state = State::Normal;
+                    self.boundary = true;
+                },
+                State::BlockComment { nesting } => {
+                    if c == '*' && chars.next_if_eq(&'/').is_some() {
+                        self.state = if nesting == 1 {
+                            self.boundary = true;
+                            State::Normal
+                        } else {
+                            State::BlockComment { nesting: nesting - 1 }
+                        };
+                    } else if c == '/' && chars.next_if_eq(&'*').is_some() {
+                        self.state = State::BlockComment {
+                            nesting: nesting + 1,
+                        };
+                    }
+                }
+            }
+        }
+    }
+    /// Whether the text scanned so far may be a complete node
+    fn complete(&self) -> bool {
+        self.depth == 0 && self.state == State::Normal && !self.continued
+    }
+}
+
+/// Text of a single node which resolves spans relative to the whole stream
+struct Chunk {
+    offset: usize,
+    line: usize,
+    text: String,
+}
+
+/// Parse KDL from the `reader` yielding top-level nodes one by one
+///
+/// The input is read line by line, and every top-level node is returned as
+/// soon as it's complete, so the whole document never has to be in memory.
+/// Nodes can be decoded one at a time using
+/// [`Decode::decode_node`](crate::Decode::decode_node).
+///
+/// ```rust
+/// # fn main() -> Result<(), knuffel::Error> {
+/// let text = "entry 1\nentry 2 {\n    tag \"x\"\n}\n";
+/// for node in knuffel::iter_nodes("log.kdl", text.as_bytes()) {
+///     let node = node?;
+///     assert_eq!(&**node.node_name, "entry");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn iter_nodes<R: BufRead>(file_name: &str, reader: R) -> NodeIter<R> {
+    NodeIter {
+        reader,
+        file_name: file_name.into(),
+        buf: String::new(),
+        offset: 0,
+        line: 0,
+        scanner: Scanner::new(),
+        ready: VecDeque::new(),
+        done: false,
+    }
+}
+
+fn parse_chunk(text: &str, offset: usize)
+    -> Result<Vec<SpannedNode<Span>>, Vec<ParseError<Span>>>
+{
+    grammar::document()
+        .parse(Span::stream_at(text, offset))
+        .map(|doc| doc.nodes)
+}
+
+impl<R: BufRead> NodeIter<R> {
+    fn consume(&mut self) -> Chunk {
+        let text = std::mem::take(&mut self.buf);
+        self.scanner = Scanner::new();
+        let chunk = Chunk { offset: self.offset, line: self.line, text };
+        self.offset += chunk.text.len();
+        self.line += chunk.text.matches('\n').count();
+        chunk
+    }
+    fn error(&self, errors: Vec<miette::Report>, chunk: Chunk) -> Error {
+        Error {
+            source_code: NamedSource::new(&self.file_name, chunk),
+            errors,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for NodeIter<R> {
+    type Item = Result<SpannedNode<Span>, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.ready.pop_front() {
+                return Some(Ok(node));
+            }
+            if self.done {
+                return None;
+            }
+            let start = self.buf.len();
+            let eof = match self.reader.read_line(&mut self.buf) {
+                Ok(bytes) => bytes == 0,
+                Err(e) => {
+                    self.done = true;
+                    let chunk = self.consume();
+                    let error = miette::Report::msg(e.to_string());
+                    return Some(Err(self.error(vec![error], chunk)));
+                }
+            };
+            let result = if eof {
+                self.done = true;
+                parse_chunk(&self.buf, self.offset)
+            } else {
+                // The text is only parsed when the line ends outside of any
+                // braces, strings and comments, and not with a line
+                // continuation (`\`), so the node is parsed once
+                self.scanner.scan(&self.buf[start..]);
+                if !self.scanner.complete() {
+                    continue;
+                }
+                match parse_chunk(&self.buf, self.offset) {
+                    Err(e) if e.iter().any(|e| e.is_at_end()) => continue,
+                    result => result,
+                }
+            };
+            let chunk = self.consume();
+            match result {
+                Ok(nodes) => self.ready.extend(nodes),
+                Err(errors) => {
+                    let errors = errors.into_iter().map(Into::into).collect();
+                    return Some(Err(self.error(errors, chunk)));
+                }
+            }
+        }
+    }
+}
+
+impl SourceCode for Chunk {
+    fn read_span<'a>(&'a self, span: &SourceSpan,
+                     context_lines_before: usize, context_lines_after: usize)
+        -> Result<Box<dyn SpanContents<'a> + 'a>, MietteError>
+    {
+        let start = span.offset().checked_sub(self.offset)
+            .ok_or(MietteError::OutOfBounds)?;
+        let local = SourceSpan::new(start.into(), span.len().into());
+        let contents = self.text.read_span(&local,
+            context_lines_before, context_lines_after)?;
+        let outer = contents.span();
+        Ok(Box::new(MietteSpanContents::new(
+            contents.data(),
+            SourceSpan::new((outer.offset() + self.offset).into(),
+                            outer.len().into()),
+            contents.line() + self.line,
+            contents.column(),
+            contents.line_count(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{self, BufReader, Read};
+
+    use crate::span::Span;
+    use super::iter_nodes;
+
+    fn names(text: &str) -> Vec<Result<(String, Span), String>> {
+        iter_nodes("<test>", text.as_bytes())
+            .map(|item| item
+                .map(|node| (node.node_name.to_string(), node.span().clone()))
+                .map_err(|e| e.to_diagnostics().into_iter()
+                    .map(|d| d.message).collect::<Vec<_>>().join("\n")))
+            .collect()
+    }
+
+    #[test]
+    fn nodes() {
+        assert_eq!(names("a 1\nb; c\n\n// comment\nd"), vec![
+            Ok(("a".into(), Span(0, 4))),
+            Ok(("b".into(), Span(4, 6))),
+            Ok(("c".into(), Span(7, 9))),
+            Ok(("d".into(), Span(21, 22))),
+        ]);
+        assert_eq!(names(""), vec![]);
+    }
+
+    #[test]
+    fn multiline() {
+        let text = "a {\n    b \"x\ny\"\n}\nc 1 \\\n  2\n/*\nd\n*/ e\n";
+        let nodes = iter_nodes("<test>", text.as_bytes())
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0].span(), &Span(0, 18));
+        assert_eq!(nodes[0].children.as_ref().unwrap().len(), 1);
+        assert_eq!(&**nodes[1].node_name, "c");
+        assert_eq!(nodes[1].arguments.len(), 2);
+        assert_eq!(&**nodes[2].node_name, "e");
+        assert_eq!(nodes[2].span(), &Span(36, 38));
+    }
+
+    #[test]
+    fn skip_errors() {
+        assert_eq!(names("a 1\nb )\nc {\n  d\n}\n"), vec![
+            Ok(("a".into(), Span(0, 4))),
+            Err("found `)`, expected `\"`, `(`, `+`, `-`, `0`, `;`, `\\`, \
+                 `r`, `{`, `false`, `null`, `true`, letter, newline, \
+                 whitespace or end of input".into()),
+            Ok(("c".into(), Span(8, 18))),
+        ]);
+        let errors = iter_nodes("<test>", "a 1\nb {\n  c\n".as_bytes())
+            .filter_map(Result::err)
+            .map(|e| e.to_diagnostics())
+            .collect::<Vec<_>>();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0][0].message, "unclosed curly braces `{`");
+        assert_eq!(errors[0][0].span_start, Some(6));
+    }
+
+    #[test]
+    fn render_error() {
+        let text = "a 1\nb 2\nc )\n";
+        let err = iter_nodes("<test>", text.as_bytes())
+            .find_map(Result::err).unwrap();
+        let mut out = String::new();
+        miette::GraphicalReportHandler::new_themed(
+                miette::GraphicalTheme::unicode_nocolor())
+            .render_report(&mut out, &err).unwrap();
+        assert!(out.contains("[<test>:3:1]"), "{}", out);
+        assert!(out.contains("3 │ c )"), "{}", out);
+        assert!(out.contains("·   ┬"), "{}", out);
+    }
+
+    #[test]
+    fn large_node() {
+        use std::fmt::Write;
+        use std::time::{Duration, Instant};
+
+        let mut text = String::from("root {\n");
+        for i in 0..5000 {
+            writeln!(&mut text, "    child {} \"{{\" r#\"}}\"# /* }} */",
+                     i).unwrap();
+        }
+        text.push_str("}\nlast\n");
+        let start = Instant::now();
+        let nodes = iter_nodes("<test>", text.as_bytes())
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(10),
+                "took {:?}", start.elapsed());
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(nodes[0].children.as_ref().unwrap().len(), 5000);
+        assert_eq!(&**nodes[1].node_name, "last");
+    }
+
+    #[test]
+    fn delimiters() {
+        let text = "a \"}\n\" r##\"\"#{\"##\nb /* { /* */ */ // {\nc\n";
+        let nodes = iter_nodes("<test>", text.as_bytes())
+            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(nodes.iter().map(|n| n.node_name.to_string())
+                   .collect::<Vec<_>>(), ["a", "b", "c"]);
+    }
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk on fire"))
+        }
+    }
+
+    #[test]
+    fn io_error() {
+        let reader = BufReader::new(io::Cursor::new(b"a\n").chain(Failing));
+        let items = iter_nodes("<test>", reader).collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        let err = items[1].as_ref().unwrap_err();
+        assert_eq!(err.to_diagnostics()[0].message, "disk on fire");
+    }
+}
@@ -0,0 +1,113 @@
+//! Escaping and unescaping of KDL strings
+//!
+//! Useful for tools that manipulate KDL text directly. Both functions work
+//! on the body of the string literal, i.e. without the surrounding quotes.
+use std::fmt::Write;
+
+use chumsky::Parser;
+use miette::NamedSource;
+
+use crate::errors::Error;
+use crate::grammar;
+use crate::span::Span;
+use crate::traits::sealed::Sealed;
+
+
+/// Decodes escape sequences in the body of the KDL string literal
+///
+/// Returns an error on invalid escape sequences and unescaped quotes.
+pub fn unescape(text: &str) -> Result<String, Error> {
+    grammar::unescape::<Span>()
+    .parse(Span::stream(text))
+    .map(String::from)
+    .map_err(|errors| {
+        Error {
+            source_code: NamedSource::new("<string>", text.to_string()),
+            errors: errors.into_iter().map(Into::into).collect(),
+        }
+    })
+}
+
+/// Escapes text to be used as a body of the KDL string literal
+///
+/// Only quotes, backslashes and control characters are escaped, everything
+/// else is kept as is.
+pub fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\u{0008}' => result.push_str("\\b"),
+            '\u{000C}' => result.push_str("\\f"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => {
+                write!(&mut result, "\\u{{{:x}}}", c as u32).unwrap();
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, unescape};
+
+    fn unescape_err(text: &str) -> String {
+        let err = unescape(text).unwrap_err();
+        err.errors.iter()
+            .map(|e| e.to_string()).collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn control_chars() {
+        assert_eq!(unescape(r#"a\nb\tc\r\b\f"#).unwrap(),
+                   "a\nb\tc\r\u{0008}\u{000C}");
+        assert_eq!(escape("a\nb\tc\r\u{0008}\u{000C}"),
+                   r#"a\nb\tc\r\b\f"#);
+        assert_eq!(escape("\u{0000}\u{001B}\u{007F}"),
+                   r#"\u{0}\u{1b}\u{7f}"#);
+        assert_eq!(unescape(r#"\u{0}\u{1b}\u{7f}"#).unwrap(),
+                   "\u{0000}\u{001B}\u{007F}");
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(unescape(r#"say \"hi\" \\ \/"#).unwrap(),
+                   r#"say "hi" \ /"#);
+        assert_eq!(escape(r#"say "hi" \ /"#),
+                   r#"say \"hi\" \\ /"#);
+        assert_eq!(unescape_err(r#"say "hi""#),
+                   "found `\"`, expected `\\` or end of input");
+    }
+
+    #[test]
+    fn unicode() {
+        assert_eq!(unescape(r#"\u{1F600} \u{e9}"#).unwrap(), "😀 é");
+        assert_eq!(escape("😀 é"), "😀 é");
+        assert_eq!(unescape_err(r#"\u{110000}"#),
+                   "converted integer out of range for `char`");
+        assert_eq!(unescape_err(r#"\u{D800}"#),
+                   "converted integer out of range for `char`");
+        assert_eq!(unescape_err(r#"\q"#),
+                   "found `q`, expected `\"`, `/`, `\\`, `b`, `f`, `n`, \
+                    `r`, `t` or `u`");
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in [
+            "", "plain", r#"with \"quotes\""#, r#"back\\slash"#,
+            r#"line\nbreak"#, r#"\u{1b}[0m"#, "unicode ☃",
+        ] {
+            assert_eq!(escape(&unescape(text).unwrap()), text);
+        }
+        for text in ["\u{0}\u{1}\u{2}", "\"\\\n\r\t", "mixed \"☃\"\n"] {
+            assert_eq!(unescape(&escape(text)).unwrap(), text);
+        }
+    }
+}
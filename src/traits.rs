@@ -10,6 +10,7 @@ use crate::ast::{SpannedNode, Literal, Value, TypeName};
 use crate::span::Spanned;
 use crate::errors::DecodeError;
 use crate::decode::Context;
+use crate::encode::Writer;
 
 
 /// Trait to decode KDL node from the AST
@@ -81,6 +82,36 @@ pub trait DecodeScalar<S: ErrorSpan>: Sized {
 }
 
 
+/// Trait to encode a value as a KDL node
+///
+/// This is the reverse of [`Decode`], usually implemented by
+/// `#[derive(knuffel::Encode)]`.
+pub trait Encode {
+    /// Writes the node to the writer
+    ///
+    /// The `name` is the node name expected by the parent (i.e. the name
+    /// of the `child` field). When it's `None` the type picks the name
+    /// itself, the same way it's matched when decoding.
+    fn encode_node(&self, name: Option<&str>, writer: &mut Writer);
+}
+
+/// Trait to encode a value as a list of KDL nodes, mostly used for root
+/// document
+///
+/// This is the reverse of [`DecodeChildren`].
+pub trait EncodeChildren {
+    /// Writes every node to the writer
+    fn encode_children(&self, writer: &mut Writer);
+}
+
+/// The trait that encodes a value as a KDL scalar
+///
+/// This is the reverse of [`DecodeScalar`].
+pub trait EncodeScalar {
+    /// Returns the literal representing the value
+    fn encode_scalar(&self) -> Literal;
+}
+
 /// The trait that decodes span into the final structure
 pub trait DecodeSpan<S: ErrorSpan>: Sized {
     /// Decode span
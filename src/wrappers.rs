@@ -1,7 +1,7 @@
 use chumsky::Parser;
 use miette::NamedSource;
 
-use crate::ast::Document;
+use crate::ast::{Document, DocumentRef};
 use crate::decode::Context;
 use crate::errors::Error;
 use crate::grammar;
@@ -9,7 +9,36 @@ use crate::span::{Span};
 use crate::traits::{self, DecodeChildren};
 
 
+/// Options for [`parse_with_options`]
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Accept input produced by sloppy editors and tools
+    ///
+    /// Currently a single trailing NUL byte is ignored. A leading UTF-8 BOM
+    /// (U+FEFF) is accepted regardless of this option, as it is a whitespace
+    /// character in KDL. Nothing else is relaxed.
+    ///
+    /// Ignored characters are only stripped from the end of the text, so
+    /// spans in the AST and in errors are offsets into the original text.
+    pub tolerant: bool,
+}
+
+impl ParseOptions {
+    fn preprocess<'a>(&self, text: &'a str) -> &'a str {
+        if self.tolerant {
+            if let Some(prefix) = text.strip_suffix('\0') {
+                return prefix;
+            }
+        }
+        text
+    }
+}
+
 /// Parse KDL text and return AST
+///
+/// As required by the KDL spec, when a property is repeated in a node the
+/// rightmost value is kept. Derived decoders report repeated properties
+/// that correspond to property fields as errors.
 pub fn parse_ast<S: traits::Span>(file_name: &str, text: &str)
     -> Result<Document<S>, Error>
 {
@@ -23,6 +52,24 @@ pub fn parse_ast<S: traits::Span>(file_name: &str, text: &str)
     })
 }
 
+/// Parse KDL text and return AST borrowing strings from the `text`
+///
+/// See [`DocumentRef`] for when strings are borrowed. Only the resulting
+/// document avoids copying the strings, parser itself still uses temporary
+/// buffers while parsing.
+pub fn parse_ast_ref<'a, S: traits::Span>(file_name: &str, text: &'a str)
+    -> Result<DocumentRef<'a, S>, Error>
+{
+    grammar::document_ref(text)
+    .parse(S::stream(text))
+    .map_err(|errors| {
+        Error {
+            source_code: NamedSource::new(file_name, text.to_string()),
+            errors: errors.into_iter().map(Into::into).collect(),
+        }
+    })
+}
+
 /// Parse KDL text and decode Rust object
 pub fn parse<T>(file_name: &str, text: &str) -> Result<T, Error>
     where T: DecodeChildren<Span>,
@@ -30,6 +77,35 @@ pub fn parse<T>(file_name: &str, text: &str) -> Result<T, Error>
     parse_with_context(file_name, text, |_| {})
 }
 
+/// Parse KDL text which doesn't come from a file and decode Rust object
+///
+/// Same as [`parse`], errors refer to the text as `<string>`.
+pub fn parse_str<T>(text: &str) -> Result<T, Error>
+    where T: DecodeChildren<Span>,
+{
+    parse("<string>", text)
+}
+
+/// Parse KDL text and decode Rust object using the specified span type
+///
+/// Spans decoded into the object (i.e. `span` fields) are of type `S`, e.g.
+/// [`LineColSpan`](crate::span::LineColSpan) for line and column numbers.
+pub fn parse_with_span<S, T>(file_name: &str, text: &str) -> Result<T, Error>
+    where T: DecodeChildren<S>,
+          S: traits::Span,
+{
+    parse_with_context(file_name, text, |_| {})
+}
+
+/// Parse KDL text and decode Rust object using the specified options
+pub fn parse_with_options<T>(file_name: &str, text: &str,
+                             options: &ParseOptions)
+    -> Result<T, Error>
+    where T: DecodeChildren<Span>,
+{
+    parse_with_context(file_name, options.preprocess(text), |_| {})
+}
+
 /// Parse KDL text and decode Rust object providing extra context for the
 /// decoder
 pub fn parse_with_context<T, S, F>(file_name: &str, text: &str, set_ctx: F)
@@ -52,10 +128,10 @@ pub fn parse_with_context<T, S, F>(file_name: &str, text: &str, set_ctx: F)
         }
         Ok(v) => return Ok(v)
     };
-    return Err(Error {
+    Err(Error {
         source_code: NamedSource::new(file_name, text.to_string()),
         errors: errors.into_iter().map(Into::into).collect(),
-    });
+    })
 }
 
 #[test]